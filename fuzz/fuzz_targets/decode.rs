@@ -0,0 +1,20 @@
+#![no_main]
+
+use chipate::core::cpu::Instruction;
+use libfuzzer_sys::fuzz_target;
+
+// decoding never panics and anything that decodes comes back the same after being encoded again
+fuzz_target!(|data: &[u8]| {
+    for chunk in data.chunks_exact(2) {
+        let op_code = u16::from_be_bytes([chunk[0], chunk[1]]);
+
+        if let Some(instruction) = Instruction::from_op_code(op_code) {
+            let _ = instruction.to_string();
+
+            assert_eq!(
+                Instruction::from_op_code(instruction.to_op_code()),
+                Some(instruction)
+            );
+        }
+    }
+});
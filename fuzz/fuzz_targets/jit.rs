@@ -0,0 +1,19 @@
+#![no_main]
+
+use chipate::core::{bench::Bench, cpu::Mode, Program};
+use libfuzzer_sys::fuzz_target;
+
+// whatever the rom does the jit leaves the machine exactly as the interpreter would, a rom that
+// does not fit in memory or that the interpreter runs off the end of memory is not interesting
+fuzz_target!(|data: &[u8]| {
+    let Ok(program) = Program::new(String::from("fuzz"), data.to_vec()) else {
+        return;
+    };
+    let mode = Mode::default();
+
+    if let Ok(bench) = Bench::run(&program, &mode, 10_000) {
+        if let Err(e) = bench.with_jit(&program, &mode) {
+            panic!("{:#}", e);
+        }
+    }
+});
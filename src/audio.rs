@@ -0,0 +1,149 @@
+use std::{io::Write, str::FromStr};
+
+// what plays the sound when no sink has been set, sdl plays a tone through the audio device and
+// the bell rings the terminal bell
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AudioBackend {
+    #[cfg(feature = "sdl")]
+    #[default]
+    Sdl,
+    #[cfg_attr(not(feature = "sdl"), default)]
+    Bell,
+}
+
+impl std::fmt::Display for AudioBackend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            #[cfg(feature = "sdl")]
+            AudioBackend::Sdl => write!(f, "sdl"),
+            AudioBackend::Bell => write!(f, "bell"),
+        }
+    }
+}
+
+impl FromStr for AudioBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            #[cfg(feature = "sdl")]
+            "sdl" => Ok(AudioBackend::Sdl),
+            "bell" => Ok(AudioBackend::Bell),
+            #[cfg(not(feature = "sdl"))]
+            "sdl" => Err(String::from(
+                "audio backend 'sdl' needs chipate built with the sdl feature",
+            )),
+            _ => Err(format!(
+                "invalid audio backend '{}': expected sdl or bell",
+                s
+            )),
+        }
+    }
+}
+
+// notified when the sound timer becomes nonzero and when it reaches zero again, closures taking
+// whether the sound is playing can be used as a sink as well
+pub trait AudioSink: Send {
+    fn sound_started(&mut self);
+    fn sound_stopped(&mut self);
+}
+
+impl<F: FnMut(bool) + Send> AudioSink for F {
+    fn sound_started(&mut self) {
+        self(true)
+    }
+    fn sound_stopped(&mut self) {
+        self(false)
+    }
+}
+
+// rings the terminal bell once every time a sound starts
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Bell;
+
+impl AudioSink for Bell {
+    fn sound_started(&mut self) {
+        print!("\u{7}");
+        let _ = std::io::stdout().flush();
+    }
+    fn sound_stopped(&mut self) {}
+}
+
+pub(crate) struct Audio {
+    sink: Box<dyn AudioSink>,
+    // the sink is the one audio started with until one is set, running swaps it for the backend
+    default_sink: bool,
+    // notified alongside the sink, the rumble of a game controller
+    companion: Option<Box<dyn AudioSink>>,
+    playing: bool,
+}
+
+impl Audio {
+    pub(crate) fn new(sink: Box<dyn AudioSink>) -> Self {
+        Self {
+            sink,
+            default_sink: true,
+            companion: None,
+            playing: false,
+        }
+    }
+    pub(crate) fn set_sink(&mut self, sink: Box<dyn AudioSink>) {
+        self.stop();
+        self.sink = sink;
+        self.default_sink = false;
+    }
+    #[cfg_attr(not(feature = "sdl"), allow(dead_code))]
+    pub(crate) fn has_default_sink(&self) -> bool {
+        self.default_sink
+    }
+    // the backend only stands in for the default sink so the next run opens it again
+    #[cfg_attr(not(feature = "sdl"), allow(dead_code))]
+    pub(crate) fn set_backend_sink(&mut self, sink: Box<dyn AudioSink>) {
+        self.stop();
+        self.sink = sink;
+    }
+    // a sound already playing is stopped on the companion it replaces and started on the new one
+    #[cfg_attr(not(feature = "sdl"), allow(dead_code))]
+    pub(crate) fn set_companion(&mut self, companion: Option<Box<dyn AudioSink>>) {
+        if let Some(old) = self.companion.as_mut().filter(|_| self.playing) {
+            old.sound_stopped();
+        }
+
+        self.companion = companion;
+
+        if let Some(new) = self.companion.as_mut().filter(|_| self.playing) {
+            new.sound_started();
+        }
+    }
+    // only transitions are passed on to the sink
+    pub(crate) fn update(&mut self, playing: bool) {
+        if playing == self.playing {
+            return;
+        }
+
+        self.playing = playing;
+
+        for sink in std::iter::once(&mut self.sink).chain(self.companion.as_mut()) {
+            if playing {
+                sink.sound_started();
+            } else {
+                sink.sound_stopped();
+            }
+        }
+    }
+    pub(crate) fn is_playing(&self) -> bool {
+        self.playing
+    }
+    pub(crate) fn stop(&mut self) {
+        self.update(false);
+    }
+}
+
+impl std::fmt::Debug for Audio {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Audio")
+            .field("playing", &self.playing)
+            .finish_non_exhaustive()
+    }
+}
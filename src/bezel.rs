@@ -0,0 +1,484 @@
+// A png drawn behind the display by the sdl frontend, the window takes the size of the image and
+// the display is scaled into the viewport, a rectangle of the image given in its pixels:
+//
+//   --bezel vip.png --bezel-viewport 96,80,640,320
+//
+// Without a viewport the display covers the whole image. Only 8 bit, non interlaced images are
+// read, which is what image editors write unless asked otherwise.
+
+use crate::error::{bail, Context, EmuError, Result};
+
+use std::{path::Path, str::FromStr};
+
+// larger images are more likely a mistake than a bezel
+const MAX_SIZE: u32 = 8192;
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Viewport {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl FromStr for Viewport {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts = s
+            .split(',')
+            .map(|p| p.trim().parse::<u32>())
+            .collect::<Result<Vec<u32>, _>>()
+            .map_err(|e| format!("invalid viewport '{}': {}", s, e))?;
+
+        match parts.as_slice() {
+            [x, y, width, height] if *width > 0 && *height > 0 => Ok(Self {
+                x: *x,
+                y: *y,
+                width: *width,
+                height: *height,
+            }),
+            _ => Err(format!(
+                "invalid viewport '{}': expected X,Y,W,H with a nonzero size",
+                s
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for Viewport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{},{},{},{}", self.x, self.y, self.width, self.height)
+    }
+}
+
+#[derive(Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Bezel {
+    pub width: u32,
+    pub height: u32,
+    pub viewport: Viewport,
+    // four bytes a pixel, red, green, blue and alpha, row after row
+    #[cfg_attr(not(feature = "sdl"), allow(dead_code))]
+    pub(crate) rgba: Vec<u8>,
+}
+
+impl Bezel {
+    pub fn open(path: impl AsRef<Path>, viewport: Option<Viewport>) -> Result<Self> {
+        let path = path.as_ref();
+        let bytes = std::fs::read(path).context(format!("read {}", path.to_string_lossy()))?;
+
+        let (width, height, rgba) =
+            decode(&bytes).context(format!("decode {}", path.to_string_lossy()))?;
+
+        let viewport = viewport.unwrap_or(Viewport {
+            x: 0,
+            y: 0,
+            width,
+            height,
+        });
+        if viewport.x + viewport.width > width || viewport.y + viewport.height > height {
+            bail!(
+                "viewport {} does not fit in the {}x{} bezel",
+                viewport,
+                width,
+                height
+            );
+        }
+
+        Ok(Self {
+            width,
+            height,
+            viewport,
+            rgba,
+        })
+    }
+}
+
+impl std::fmt::Debug for Bezel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Bezel")
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .field("viewport", &self.viewport)
+            .finish_non_exhaustive()
+    }
+}
+
+// the image as rgba whatever color type it was saved with
+fn decode(bytes: &[u8]) -> Result<(u32, u32, Vec<u8>)> {
+    if !bytes.starts_with(&SIGNATURE) {
+        bail!("not a png");
+    }
+
+    let mut header = None;
+    let mut palette: &[u8] = &[];
+    let mut transparency: &[u8] = &[];
+    let mut data = Vec::new();
+
+    let mut rest = &bytes[SIGNATURE.len()..];
+    while rest.len() >= 12 {
+        let len = u32::from_be_bytes(rest[..4].try_into()?) as usize;
+        let kind = &rest[4..8];
+        let Some(chunk) = rest.get(8..8 + len) else {
+            bail!("png is truncated");
+        };
+        // the checksum follows the chunk
+        rest = rest.get(12 + len..).unwrap_or_default();
+
+        match kind {
+            b"IHDR" if chunk.len() == 13 => header = Some(chunk),
+            b"PLTE" => palette = chunk,
+            b"tRNS" => transparency = chunk,
+            b"IDAT" => data.extend_from_slice(chunk),
+            b"IEND" => break,
+            _ => {}
+        }
+    }
+
+    let Some(header) = header else {
+        bail!("png has no header");
+    };
+
+    let width = u32::from_be_bytes(header[..4].try_into()?);
+    let height = u32::from_be_bytes(header[4..8].try_into()?);
+    let (depth, color, interlace) = (header[8], header[9], header[12]);
+
+    if width == 0 || height == 0 || width > MAX_SIZE || height > MAX_SIZE {
+        bail!(
+            "png is {}x{}, expected at most {}x{}",
+            width,
+            height,
+            MAX_SIZE,
+            MAX_SIZE
+        );
+    }
+    if depth != 8 || interlace != 0 {
+        return Err(EmuError::Unsupported(format!(
+            "png has {} bit{} color, only 8 bit non interlaced images are supported",
+            depth,
+            if interlace != 0 { " interlaced" } else { "" }
+        )));
+    }
+
+    let channels = match color {
+        0 | 3 => 1,
+        4 => 2,
+        2 => 3,
+        6 => 4,
+        _ => bail!("png has unknown color type {}", color),
+    };
+    if color == 3 && palette.is_empty() {
+        bail!("png has a palette color type but no palette");
+    }
+
+    let stride = width as usize * channels;
+    let pixels = unfilter(&inflate(&data)?, stride, height as usize, channels)?;
+
+    let rgba = pixels
+        .chunks_exact(channels)
+        .flat_map(|p| match color {
+            0 => [p[0], p[0], p[0], 0xFF],
+            4 => [p[0], p[0], p[0], p[1]],
+            2 => [p[0], p[1], p[2], 0xFF],
+            6 => [p[0], p[1], p[2], p[3]],
+            // indices past the palette are drawn black
+            _ => {
+                let idx = p[0] as usize;
+                let rgb = palette.get(idx * 3..idx * 3 + 3).unwrap_or(&[0, 0, 0]);
+                let alpha = transparency.get(idx).copied().unwrap_or(0xFF);
+                [rgb[0], rgb[1], rgb[2], alpha]
+            }
+        })
+        .collect();
+
+    Ok((width, height, rgba))
+}
+
+// every row starts with how it was filtered against the row above and the pixel to the left
+fn unfilter(data: &[u8], stride: usize, height: usize, bpp: usize) -> Result<Vec<u8>> {
+    if data.len() < (stride + 1) * height {
+        bail!("png image data is truncated");
+    }
+
+    let mut pixels = vec![0; stride * height];
+
+    for (row, line) in data.chunks_exact(stride + 1).take(height).enumerate() {
+        let (filter, line) = (line[0], &line[1..]);
+        let (previous, current) = pixels.split_at_mut(row * stride);
+        let above = row
+            .checked_sub(1)
+            .map(|_| &previous[previous.len() - stride..]);
+        let current = &mut current[..stride];
+
+        for i in 0..stride {
+            let a = if i >= bpp { current[i - bpp] } else { 0 };
+            let b = above.map_or(0, |above| above[i]);
+            let c = match above {
+                Some(above) if i >= bpp => above[i - bpp],
+                _ => 0,
+            };
+
+            let predicted = match filter {
+                0 => 0,
+                1 => a,
+                2 => b,
+                3 => ((a as u16 + b as u16) / 2) as u8,
+                4 => paeth(a, b, c),
+                _ => bail!("png has unknown filter {} on row {}", filter, row),
+            };
+            current[i] = line[i].wrapping_add(predicted);
+        }
+    }
+
+    Ok(pixels)
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let (pa, pb, pc) = (
+        (p - a as i16).abs(),
+        (p - b as i16).abs(),
+        (p - c as i16).abs(),
+    );
+
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+// the order the lengths of the code length code are stored in
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+// a zlib stream, the adler checksum at the end is not checked
+fn inflate(data: &[u8]) -> Result<Vec<u8>> {
+    match data {
+        [cmf, flg, ..] if cmf & 0x0F == 8 && (*cmf as u16 * 256 + *flg as u16) % 31 == 0 => {
+            if flg & 0x20 != 0 {
+                bail!("png image data uses a preset dictionary");
+            }
+        }
+        _ => bail!("png image data is not a zlib stream"),
+    }
+
+    let mut bits = Bits {
+        bytes: &data[2..],
+        pos: 0,
+        buffer: 0,
+        count: 0,
+    };
+    let mut out = Vec::new();
+
+    loop {
+        let last = bits.take(1)? == 1;
+
+        match bits.take(2)? {
+            0 => {
+                bits.align();
+                let len = bits.take(16)? as usize;
+                let inverse = bits.take(16)? as usize;
+                if len != !inverse & 0xFFFF {
+                    bail!("png image data has a corrupt stored block");
+                }
+                for _ in 0..len {
+                    out.push(bits.take(8)? as u8);
+                }
+            }
+            1 => {
+                let mut lengths = [0; 288];
+                lengths[..144].fill(8);
+                lengths[144..256].fill(9);
+                lengths[256..280].fill(7);
+                lengths[280..].fill(8);
+
+                let literals = Huffman::new(&lengths);
+                let distances = Huffman::new(&[5; 30]);
+                inflate_block(&mut bits, &mut out, &literals, &distances)?;
+            }
+            2 => {
+                let (literals, distances) = dynamic_codes(&mut bits)?;
+                inflate_block(&mut bits, &mut out, &literals, &distances)?;
+            }
+            _ => bail!("png image data has an invalid block type"),
+        }
+
+        if last {
+            return Ok(out);
+        }
+    }
+}
+
+fn dynamic_codes(bits: &mut Bits) -> Result<(Huffman, Huffman)> {
+    let literal_count = bits.take(5)? as usize + 257;
+    let distance_count = bits.take(5)? as usize + 1;
+    let code_length_count = bits.take(4)? as usize + 4;
+
+    let mut code_lengths = [0; 19];
+    for idx in &CODE_LENGTH_ORDER[..code_length_count] {
+        code_lengths[*idx] = bits.take(3)? as u8;
+    }
+    let code_lengths = Huffman::new(&code_lengths);
+
+    let mut lengths = Vec::with_capacity(literal_count + distance_count);
+    while lengths.len() < literal_count + distance_count {
+        let (length, repeat) = match code_lengths.decode(bits)? {
+            symbol @ 0..=15 => (symbol as u8, 1),
+            16 => match lengths.last() {
+                Some(previous) => (*previous, 3 + bits.take(2)?),
+                None => bail!("png image data repeats a code length before the first"),
+            },
+            17 => (0, 3 + bits.take(3)?),
+            _ => (0, 11 + bits.take(7)?),
+        };
+        lengths.extend(std::iter::repeat(length).take(repeat as usize));
+    }
+
+    if lengths.len() > literal_count + distance_count {
+        bail!("png image data has too many code lengths");
+    }
+
+    let (literals, distances) = lengths.split_at(literal_count);
+    Ok((Huffman::new(literals), Huffman::new(distances)))
+}
+
+fn inflate_block(
+    bits: &mut Bits,
+    out: &mut Vec<u8>,
+    literals: &Huffman,
+    distances: &Huffman,
+) -> Result<()> {
+    loop {
+        let symbol = literals.decode(bits)? as usize;
+
+        match symbol {
+            0..=255 => out.push(symbol as u8),
+            256 => return Ok(()),
+            _ => {
+                let idx = symbol - 257;
+                let (Some(base), Some(extra)) = (LENGTH_BASE.get(idx), LENGTH_EXTRA.get(idx))
+                else {
+                    bail!("png image data has an invalid length");
+                };
+                let len = *base as usize + bits.take(*extra as u32)? as usize;
+
+                let idx = distances.decode(bits)? as usize;
+                let (Some(base), Some(extra)) = (DISTANCE_BASE.get(idx), DISTANCE_EXTRA.get(idx))
+                else {
+                    bail!("png image data has an invalid distance");
+                };
+                let distance = *base as usize + bits.take(*extra as u32)? as usize;
+
+                if distance > out.len() {
+                    bail!("png image data refers back before its start");
+                }
+
+                // the copy can overlap what it is copying
+                let start = out.len() - distance;
+                for i in 0..len {
+                    out.push(out[start + i]);
+                }
+            }
+        }
+    }
+}
+
+// read least significant bit first
+struct Bits<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    buffer: u32,
+    count: u32,
+}
+
+impl Bits<'_> {
+    fn take(&mut self, count: u32) -> Result<u32> {
+        while self.count < count {
+            let Some(byte) = self.bytes.get(self.pos) else {
+                bail!("png image data is truncated");
+            };
+            self.buffer |= (*byte as u32) << self.count;
+            self.pos += 1;
+            self.count += 8;
+        }
+
+        let value = self.buffer & ((1 << count) - 1);
+        self.buffer >>= count;
+        self.count -= count;
+
+        Ok(value)
+    }
+    // stored blocks start on a byte
+    fn align(&mut self) {
+        self.buffer = 0;
+        self.count = 0;
+    }
+}
+
+// a canonical huffman code as the number of codes of each length and the symbols in code order
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Self {
+        let mut counts = [0; 16];
+        for length in lengths {
+            counts[*length as usize] += 1;
+        }
+        counts[0] = 0;
+
+        let mut symbols: Vec<u16> = (0..lengths.len() as u16)
+            .filter(|symbol| lengths[*symbol as usize] != 0)
+            .collect();
+        symbols.sort_by_key(|symbol| lengths[*symbol as usize]);
+
+        Self { counts, symbols }
+    }
+    fn decode(&self, bits: &mut Bits) -> Result<u16> {
+        let (mut code, mut first, mut idx) = (0, 0, 0);
+
+        for count in &self.counts[1..] {
+            code |= bits.take(1)? as i32;
+            let count = *count as i32;
+            if code - first < count {
+                if let Some(symbol) = self.symbols.get((idx + code - first) as usize) {
+                    return Ok(*symbol);
+                }
+            }
+            idx += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+
+        bail!("png image data has an invalid code")
+    }
+}
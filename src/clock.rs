@@ -0,0 +1,47 @@
+use std::time::{Duration, Instant};
+
+// more ticks than this being due means the loop was blocked, e.g. on the debugger prompt, and
+// the ticks are dropped instead of being run in a burst
+pub(crate) const MAX_CATCH_UP: u64 = 4;
+
+// counts whole periods from a fixed start so a loop that wakes up late catches up on the ticks it
+// missed instead of drifting further behind every time
+#[derive(Clone, Debug)]
+pub(crate) struct Ticker {
+    start: Instant,
+    period: Duration,
+    ticks: u64,
+}
+
+impl Ticker {
+    pub(crate) fn new(hz: u32, now: Instant) -> Self {
+        Self {
+            start: now,
+            period: Duration::from_secs(1) / hz,
+            ticks: 0,
+        }
+    }
+    // the number of ticks that came due since the last call
+    pub(crate) fn due(&mut self, now: Instant) -> u64 {
+        let elapsed = now.saturating_duration_since(self.start);
+        let total = (elapsed.as_nanos() / self.period.as_nanos()) as u64;
+
+        let due = total.saturating_sub(self.ticks);
+        if due > MAX_CATCH_UP {
+            self.ticks = total;
+            return 1;
+        }
+
+        self.ticks += due;
+
+        due
+    }
+    // starts counting again from now, used when resuming so the time spent paused is not owed
+    pub(crate) fn reset(&mut self, now: Instant) {
+        self.start = now;
+        self.ticks = 0;
+    }
+    pub(crate) fn next(&self) -> Instant {
+        self.start + self.period * (self.ticks + 1) as u32
+    }
+}
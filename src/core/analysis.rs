@@ -0,0 +1,195 @@
+use crate::{
+    core::{
+        cpu::Instruction,
+        disasm::{read_op_code, trace_code},
+        Program,
+    },
+    error::{Context, Result},
+};
+
+use std::{collections::BTreeSet, ops::RangeInclusive, path::Path};
+
+#[derive(Clone, Debug)]
+pub struct DataRegion {
+    pub range: RangeInclusive<u16>,
+    pub referenced: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct Analysis {
+    origin: u16,
+    len: usize,
+    code: BTreeSet<u16>,
+    index_targets: BTreeSet<u16>,
+    executed: Option<BTreeSet<u16>>,
+}
+
+impl Analysis {
+    pub fn new(program: &Program, origin: u16) -> Self {
+        let data = program.data();
+        let code = trace_code(data, origin);
+
+        let index_targets = code
+            .iter()
+            .filter_map(|address| {
+                let op_code = read_op_code(data, (address - origin) as usize);
+                match Instruction::from_op_code(op_code) {
+                    Some(Instruction::SetIndex { value }) => Some(value),
+                    _ => None,
+                }
+            })
+            .collect();
+
+        Self {
+            origin,
+            len: data.len(),
+            code,
+            index_targets,
+            executed: None,
+        }
+    }
+    pub fn with_trace(mut self, executed: BTreeSet<u16>) -> Self {
+        self.executed = Some(executed);
+        self
+    }
+    pub fn unexecuted_code(&self) -> Vec<RangeInclusive<u16>> {
+        let Some(executed) = &self.executed else {
+            return Vec::new();
+        };
+
+        let bytes = self
+            .code
+            .iter()
+            .filter(|address| !executed.contains(address))
+            .flat_map(|address| [*address, address + 1]);
+
+        to_ranges(bytes)
+    }
+    // every byte that static traversal never reached, a region counts as referenced when an
+    // instruction points the index register somewhere inside of it
+    pub fn data_regions(&self) -> Vec<DataRegion> {
+        let code_bytes: BTreeSet<u16> = self
+            .code
+            .iter()
+            .flat_map(|address| [*address, address + 1])
+            .collect();
+
+        let end = self.origin + self.len as u16;
+        let bytes = (self.origin..end).filter(|address| !code_bytes.contains(address));
+
+        to_ranges(bytes)
+            .into_iter()
+            .map(|range| DataRegion {
+                referenced: self.index_targets.iter().any(|t| range.contains(t)),
+                range,
+            })
+            .collect()
+    }
+}
+
+impl std::fmt::Display for Analysis {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let code_bytes = self.code.len() * 2;
+        writeln!(
+            f,
+            "{} of {} bytes reachable as code",
+            code_bytes.min(self.len),
+            self.len
+        )?;
+
+        if let Some(executed) = &self.executed {
+            let unexecuted = self.unexecuted_code();
+            let executed_count = self.code.iter().filter(|a| executed.contains(a)).count();
+
+            writeln!(
+                f,
+                "\n{} of {} reachable instructions executed, never executed code:",
+                executed_count,
+                self.code.len()
+            )?;
+
+            if unexecuted.is_empty() {
+                writeln!(f, "  none")?;
+            }
+
+            for range in unexecuted {
+                writeln!(f, "  {}", format_range(&range))?;
+            }
+        }
+
+        let regions = self.data_regions();
+
+        writeln!(f, "\nunused data:")?;
+        if !regions.iter().any(|r| !r.referenced) {
+            writeln!(f, "  none")?;
+        }
+
+        for region in regions.iter().filter(|r| !r.referenced) {
+            writeln!(f, "  {}", format_range(&region.range))?;
+        }
+
+        writeln!(f, "\nreferenced data:")?;
+        if !regions.iter().any(|r| r.referenced) {
+            writeln!(f, "  none")?;
+        }
+
+        for region in regions.iter().filter(|r| r.referenced) {
+            writeln!(f, "  {}", format_range(&region.range))?;
+        }
+
+        Ok(())
+    }
+}
+
+// reads the program counter of every executed instruction from a trace file, the first token
+// of each line is taken as a hex address and blank lines or lines starting with `#` are skipped
+pub fn read_trace(path: impl AsRef<Path>) -> Result<BTreeSet<u16>> {
+    let text = std::fs::read_to_string(path.as_ref())
+        .context(format!("read file {}", path.as_ref().to_string_lossy()))?;
+
+    let mut executed = BTreeSet::new();
+
+    for (idx, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let token = line.split_whitespace().next().unwrap_or_default();
+        let digits = token.trim_end_matches(':');
+        let digits = digits.strip_prefix("0x").unwrap_or(digits);
+
+        let address = u16::from_str_radix(digits, 16).context(format!(
+            "invalid address on line {}: {}",
+            idx + 1,
+            token
+        ))?;
+
+        executed.insert(address);
+    }
+
+    Ok(executed)
+}
+
+fn to_ranges(addresses: impl Iterator<Item = u16>) -> Vec<RangeInclusive<u16>> {
+    let mut ranges: Vec<RangeInclusive<u16>> = Vec::new();
+
+    for address in addresses {
+        match ranges.last_mut() {
+            Some(range) if *range.end() + 1 == address => *range = *range.start()..=address,
+            Some(range) if range.contains(&address) => {}
+            _ => ranges.push(address..=address),
+        }
+    }
+
+    ranges
+}
+
+fn format_range(range: &RangeInclusive<u16>) -> String {
+    format!(
+        "{:#05x}-{:#05x} ({} bytes)",
+        range.start(),
+        range.end(),
+        range.end() - range.start() + 1
+    )
+}
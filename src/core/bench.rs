@@ -0,0 +1,272 @@
+use crate::{
+    core::{
+        cpu::{Instruction, Mode, Quirks},
+        machine::Machine,
+        memory::RAM_SIZE,
+        Font, Program,
+    },
+    error::{Context, EmuError, Result},
+    DisplayState, Resolution,
+};
+
+#[cfg(feature = "jit")]
+use crate::core::jit::Jit;
+
+use std::{
+    cmp::Reverse,
+    collections::BTreeMap,
+    time::{Duration, Instant},
+};
+
+// timers tick once for every this many instructions, which is the default speed of 700
+// instructions a second against a 60hz timer
+const INSTRUCTIONS_PER_TIMER_TICK: u64 = 12;
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct OpTiming {
+    pub count: u64,
+    pub total: Duration,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct JitRun {
+    pub elapsed: Duration,
+    pub compiled: usize,
+    pub invalidated: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct Bench {
+    instructions: u64,
+    elapsed: Duration,
+    cached_elapsed: Duration,
+    jit: Option<JitRun>,
+    op_timings: BTreeMap<&'static str, OpTiming>,
+}
+
+impl Bench {
+    // executes the program three times, first timing every instruction which is too slow to count
+    // towards the throughput but warms up the caches so neither of the untimed passes measuring
+    // throughput without and with the decode cache has an advantage
+    pub fn run(program: &Program, mode: &Mode, instructions: u64) -> Result<Self> {
+        let mut op_timings: BTreeMap<&'static str, OpTiming> = BTreeMap::new();
+        let mut machine = boot(program, mode);
+        for executed in 0..instructions {
+            let mnemonic = next_mnemonic(&machine);
+
+            let start = Instant::now();
+            step(&mut machine, executed)?;
+            let duration = start.elapsed();
+
+            let timing = op_timings.entry(mnemonic).or_default();
+            timing.count += 1;
+            timing.total += duration;
+        }
+
+        let elapsed = Self::throughput(boot(program, mode), instructions)?;
+
+        let mut machine = boot(program, mode);
+        machine.cpu.set_decode_cache(true);
+        let cached_elapsed = Self::throughput(machine, instructions)?;
+
+        Ok(Self {
+            instructions,
+            elapsed,
+            cached_elapsed,
+            jit: None,
+            op_timings,
+        })
+    }
+    // the jit pass includes the time spent compiling, the machine it leaves behind has to match
+    // the interpreter exactly or the jit is wrong
+    #[cfg(feature = "jit")]
+    pub fn with_jit(mut self, program: &Program, mode: &Mode) -> Result<Self> {
+        let mut interpreter = boot(program, mode);
+        for executed in 0..self.instructions {
+            step(&mut interpreter, executed)?;
+        }
+
+        let mut machine = boot(program, mode);
+        let mut jit = Jit::new(*machine.cpu.quirks())?;
+
+        let start = Instant::now();
+        let mut executed = 0;
+        while executed < self.instructions {
+            executed += step_jit(
+                &mut machine,
+                &mut jit,
+                executed,
+                self.instructions - executed,
+            )?;
+        }
+        let elapsed = start.elapsed();
+
+        if let Some(difference) = difference(&machine, &interpreter) {
+            return Err(EmuError::Jit(format!(
+                "diverged from the interpreter after {} instructions, {}",
+                self.instructions, difference
+            )));
+        }
+
+        self.jit = Some(JitRun {
+            elapsed,
+            compiled: jit.compiled(),
+            invalidated: jit.invalidated(),
+        });
+
+        Ok(self)
+    }
+    fn throughput(mut machine: Machine, instructions: u64) -> Result<Duration> {
+        let start = Instant::now();
+        for executed in 0..instructions {
+            step(&mut machine, executed)?;
+        }
+
+        Ok(start.elapsed())
+    }
+    pub fn instructions_per_sec(&self) -> f64 {
+        self.instructions as f64 / self.elapsed.as_secs_f64()
+    }
+    pub fn cached_instructions_per_sec(&self) -> f64 {
+        self.instructions as f64 / self.cached_elapsed.as_secs_f64()
+    }
+    pub fn jit(&self) -> Option<&JitRun> {
+        self.jit.as_ref()
+    }
+    pub fn op_timings(&self) -> &BTreeMap<&'static str, OpTiming> {
+        &self.op_timings
+    }
+}
+
+impl std::fmt::Display for Bench {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "executed {} instructions in {:.3}s, {:.0} instructions/second",
+            self.instructions,
+            self.elapsed.as_secs_f64(),
+            self.instructions_per_sec()
+        )?;
+        writeln!(
+            f,
+            "with the decode cache in {:.3}s, {:.0} instructions/second, {:.2}x the speed",
+            self.cached_elapsed.as_secs_f64(),
+            self.cached_instructions_per_sec(),
+            self.cached_instructions_per_sec() / self.instructions_per_sec()
+        )?;
+        if let Some(jit) = self.jit.as_ref() {
+            let per_sec = self.instructions as f64 / jit.elapsed.as_secs_f64();
+            writeln!(
+                f,
+                "with the jit in {:.3}s, {:.0} instructions/second, {:.2}x the speed, {} runs compiled and {} invalidated",
+                jit.elapsed.as_secs_f64(),
+                per_sec,
+                per_sec / self.instructions_per_sec(),
+                jit.compiled,
+                jit.invalidated
+            )?;
+        }
+
+        writeln!(
+            f,
+            "\n{:<14}{:>12}{:>14}{:>12}",
+            "instruction", "count", "total", "average"
+        )?;
+
+        // slowest overall first since that is where time is worth saving
+        let mut timings: Vec<_> = self.op_timings.iter().collect();
+        timings.sort_by_key(|(_, timing)| Reverse(timing.total));
+
+        for (mnemonic, timing) in timings {
+            writeln!(
+                f,
+                "{:<14}{:>12}{:>12.3}ms{:>10}ns",
+                mnemonic,
+                timing.count,
+                timing.total.as_secs_f64() * 1000.0,
+                timing.total.as_nanos() / timing.count as u128
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+fn boot(program: &Program, mode: &Mode) -> Machine {
+    let mut machine = Machine::new(Font::default());
+    machine.load(program);
+    machine.display = DisplayState::with_height(Resolution::detect(program).height());
+
+    // a fixed seed keeps both passes executing the same instructions
+    machine.cpu.set_quirks(Quirks::from(mode));
+    machine.cpu.seed_rng(0);
+
+    machine
+}
+
+fn next_mnemonic(machine: &Machine) -> &'static str {
+    Instruction::from_op_code(machine.memory.read_u16(machine.cpu.prog_counter()))
+        .map_or("unknown", |i| i.mnemonic())
+}
+
+fn step(machine: &mut Machine, executed: u64) -> Result<()> {
+    let address = machine.cpu.prog_counter();
+    if address as usize + 1 >= RAM_SIZE {
+        return Err(EmuError::MemoryFault { address })
+            .context(format!("after {} instructions", executed));
+    }
+
+    machine.step();
+
+    if executed % INSTRUCTIONS_PER_TIMER_TICK == 0 {
+        machine.vblank();
+    }
+
+    Ok(())
+}
+
+// the interpreter takes over for one instruction wherever the jit has nothing compiled
+#[cfg(feature = "jit")]
+fn step_jit(machine: &mut Machine, jit: &mut Jit, executed: u64, remaining: u64) -> Result<u64> {
+    let max = usize::try_from(remaining).unwrap_or(usize::MAX);
+    let ran = jit.execute(&mut machine.cpu, &machine.memory, max)? as u64;
+    if ran == 0 {
+        step(machine, executed)?;
+        return Ok(1);
+    }
+
+    // compiled runs never touch the timers so only how many ticks happen matters
+    for executed in executed..executed + ran {
+        if executed % INSTRUCTIONS_PER_TIMER_TICK == 0 {
+            machine.vblank();
+        }
+    }
+
+    Ok(ran)
+}
+
+#[cfg(feature = "jit")]
+fn difference(machine: &Machine, interpreter: &Machine) -> Option<String> {
+    if machine.cpu.state() != interpreter.cpu.state() {
+        return Some(format!(
+            "cpu {:?} where the interpreter has {:?}",
+            machine.cpu.state(),
+            interpreter.cpu.state()
+        ));
+    }
+
+    if let Some((address, (a, b))) = machine
+        .memory
+        .iter()
+        .zip(interpreter.memory.iter())
+        .enumerate()
+        .find(|(_, (a, b))| a != b)
+    {
+        return Some(format!(
+            "memory at {:#05x} is {:#04x} where the interpreter has {:#04x}",
+            address, a, b
+        ));
+    }
+
+    (machine.display != interpreter.display).then(|| String::from("the display differs"))
+}
@@ -0,0 +1,112 @@
+// Assembles instructions and data into a program so a rom can be put together in rust rather than
+// kept as a binary file. Jumps, calls and loads of i can name a label instead of an address, the
+// label may come before or after them and is resolved when the program is built.
+
+use crate::{
+    core::{cpu::Instruction, Program, MAX_PROGRAM_SIZE},
+    error::{bail, Result},
+    PROGRAM_START_ADDR,
+};
+
+use std::collections::HashMap;
+
+// an instruction whose address is filled in once the label it refers to is known
+#[derive(Clone, Debug)]
+struct Fixup {
+    offset: usize,
+    label: String,
+    instruction: fn(u16) -> Instruction,
+}
+
+#[derive(Clone, Debug)]
+pub struct ProgramBuilder {
+    name: String,
+    bytes: Vec<u8>,
+    labels: Vec<(String, usize)>,
+    fixups: Vec<Fixup>,
+}
+
+impl ProgramBuilder {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: String::from(name),
+            bytes: Vec::new(),
+            labels: Vec::new(),
+            fixups: Vec::new(),
+        }
+    }
+    // the address the next instruction or data is placed at
+    pub fn address(&self) -> u16 {
+        PROGRAM_START_ADDR + self.bytes.len() as u16
+    }
+    pub fn label(&mut self, name: &str) -> &mut Self {
+        self.labels.push((String::from(name), self.bytes.len()));
+        self
+    }
+    pub fn instruction(&mut self, instruction: Instruction) -> &mut Self {
+        self.bytes
+            .extend_from_slice(&instruction.to_op_code().to_be_bytes());
+        self
+    }
+    pub fn instructions(
+        &mut self,
+        instructions: impl IntoIterator<Item = Instruction>,
+    ) -> &mut Self {
+        for instruction in instructions {
+            self.instruction(instruction);
+        }
+        self
+    }
+    // data is placed inline, code has to jump over it or end before it
+    pub fn data(&mut self, bytes: &[u8]) -> &mut Self {
+        self.bytes.extend_from_slice(bytes);
+        self
+    }
+    pub fn jump(&mut self, label: &str) -> &mut Self {
+        self.fixup(label, |address| Instruction::Jump { address })
+    }
+    pub fn call(&mut self, label: &str) -> &mut Self {
+        self.fixup(label, |address| Instruction::SubroutineCall { address })
+    }
+    pub fn set_index(&mut self, label: &str) -> &mut Self {
+        self.fixup(label, |value| Instruction::SetIndex { value })
+    }
+    pub fn build(&self) -> Result<Program> {
+        if self.bytes.len() > MAX_PROGRAM_SIZE {
+            bail!(
+                "program is {} bytes, at most {} fit in memory",
+                self.bytes.len(),
+                MAX_PROGRAM_SIZE
+            );
+        }
+
+        let mut labels = HashMap::new();
+        for (name, offset) in self.labels.iter() {
+            if labels.insert(name.as_str(), *offset).is_some() {
+                bail!("label '{}' is defined more than once", name);
+            }
+        }
+
+        let mut bytes = self.bytes.clone();
+        for fixup in self.fixups.iter() {
+            let Some(offset) = labels.get(fixup.label.as_str()) else {
+                bail!("label '{}' is not defined", fixup.label);
+            };
+
+            let op_code = (fixup.instruction)(PROGRAM_START_ADDR + *offset as u16).to_op_code();
+            bytes[fixup.offset..fixup.offset + 2].copy_from_slice(&op_code.to_be_bytes());
+        }
+
+        Program::new(self.name.clone(), bytes)
+    }
+
+    // the instruction is written with a placeholder address until the program is built
+    fn fixup(&mut self, label: &str, instruction: fn(u16) -> Instruction) -> &mut Self {
+        self.fixups.push(Fixup {
+            offset: self.bytes.len(),
+            label: String::from(label),
+            instruction,
+        });
+        self.instruction(instruction(0))
+    }
+}
@@ -0,0 +1,76 @@
+use crate::core::{
+    cpu::CPU,
+    memory::{RAM, RAM_SIZE},
+    symbols::SymbolTable,
+};
+
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CheatTarget {
+    Memory(u16),
+    Register(usize),
+}
+
+impl CheatTarget {
+    // registers are named v0 through vf, anything else is resolved as a symbol or hex address
+    pub fn parse(value: &str, symbols: &SymbolTable) -> Option<Self> {
+        let lower = value.to_ascii_lowercase();
+
+        match lower.strip_prefix('v') {
+            Some(idx) if idx.len() == 1 => usize::from_str_radix(idx, 16)
+                .ok()
+                .map(CheatTarget::Register),
+            _ => symbols
+                .resolve(value)
+                .filter(|address| (*address as usize) < RAM_SIZE)
+                .map(CheatTarget::Memory),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Cheat {
+    pub target: CheatTarget,
+    pub value: u8,
+}
+
+impl Cheat {
+    pub fn apply(&self, cpu: &mut CPU, memory: &mut RAM) {
+        match self.target {
+            CheatTarget::Memory(address) => memory.write(address, self.value),
+            CheatTarget::Register(idx) => cpu.set_v(idx, self.value),
+        }
+    }
+}
+
+impl FromStr for Cheat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (target, value) = s
+            .split_once('=')
+            .ok_or_else(|| format!("invalid cheat '{}': expected ADDRESS=VALUE", s))?;
+
+        let target = CheatTarget::parse(target.trim(), &SymbolTable::default())
+            .ok_or_else(|| format!("invalid cheat '{}': unknown address or register", s))?;
+
+        let value = parse_value(value.trim())
+            .ok_or_else(|| format!("invalid cheat '{}': value must be a byte", s))?;
+
+        Ok(Self { target, value })
+    }
+}
+
+// values are decimal unless prefixed with 0x
+pub fn parse_value(value: &str) -> Option<u8> {
+    match value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+    {
+        Some(digits) => u8::from_str_radix(digits, 16).ok(),
+        None => value.parse::<u8>().ok(),
+    }
+}
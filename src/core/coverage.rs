@@ -0,0 +1,105 @@
+// Counts how many times the instruction at every address was fetched during a session. The
+// report is either the executed addresses with their counts one to a line, which the analyze
+// subcommand reads back as a trace, or the disassembly of the program with every line prefixed
+// by its count the way gcov annotates source, `#####` marking code that never ran.
+
+use crate::core::{
+    disasm::{trace_code, Disassembly},
+    memory::RAM_SIZE,
+    symbols::SymbolTable,
+    Program,
+};
+
+use std::{fmt::Write, str::FromStr};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CoverageFormat {
+    #[default]
+    Text,
+    Disasm,
+}
+
+impl FromStr for CoverageFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(CoverageFormat::Text),
+            "disasm" => Ok(CoverageFormat::Disasm),
+            _ => Err(format!(
+                "invalid coverage format '{}': expected text or disasm",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Coverage {
+    counts: Vec<u64>,
+}
+
+impl Default for Coverage {
+    fn default() -> Self {
+        Self {
+            counts: vec![0; RAM_SIZE],
+        }
+    }
+}
+
+impl Coverage {
+    pub fn record(&mut self, address: u16) {
+        if let Some(count) = self.counts.get_mut(address as usize) {
+            *count += 1;
+        }
+    }
+    pub fn count(&self, address: u16) -> u64 {
+        self.counts
+            .get(address as usize)
+            .copied()
+            .unwrap_or_default()
+    }
+    pub fn executed(&self) -> impl Iterator<Item = (u16, u64)> + '_ {
+        self.counts
+            .iter()
+            .enumerate()
+            .filter(|(_, count)| **count > 0)
+            .map(|(address, count)| (address as u16, *count))
+    }
+    // the program is disassembled from every executed address as well as its start so code only
+    // reached through computed jumps is shown as code rather than data
+    pub fn report(
+        &self,
+        format: CoverageFormat,
+        program: &Program,
+        origin: u16,
+        symbols: &SymbolTable,
+    ) -> String {
+        match format {
+            CoverageFormat::Text => {
+                let reachable = trace_code(program.data(), origin);
+                let executed = reachable.iter().filter(|a| self.count(**a) > 0).count();
+
+                let mut text = format!(
+                    "# {} of {} reachable instructions executed\n",
+                    executed,
+                    reachable.len()
+                );
+                for (address, count) in self.executed() {
+                    let _ = writeln!(text, "{:#05x} {}", address, count);
+                }
+
+                text
+            }
+            CoverageFormat::Disasm => {
+                let entries = self.executed().map(|(address, _)| address);
+
+                Disassembly::with_entry_points(program, origin, entries)
+                    .with_symbols(symbols)
+                    .with_coverage(self.clone())
+                    .to_string()
+            }
+        }
+    }
+}
@@ -1,15 +1,21 @@
 use crate::{
-    core::memory::RAM, DisplayState, Font, Key, KeyState, DISPLAY_PIXELS_HEIGHT,
-    DISPLAY_PIXELS_WIDTH,
+    core::{
+        memory::{RAM, RAM_SIZE},
+        symbols::SymbolTable,
+    },
+    DisplayState, Font, Key, KeyState, DISPLAY_PIXELS_HEIGHT,
 };
 
-use rand::{rngs::ThreadRng, Rng};
-use std::collections::VecDeque;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::{collections::VecDeque, str::FromStr, sync::Arc};
 
 const PROGRAM_COUNTER_START: u16 = 0x200;
 
 const MAX_HISTORY_SIZE: usize = 100;
 
+// the original interpreter reserved room for 16 return addresses
+const MAX_STACK_DEPTH: usize = 16;
+
 #[derive(Clone, Debug, Default)]
 struct Registers {
     vs: [u8; 16],
@@ -36,30 +42,213 @@ impl Stack {
     }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+// something a well behaved program never does, only fatal when running in strict mode
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Fault {
+    UnknownOpCode { address: u16, op_code: u16 },
+    StackOverflow { address: u16 },
+    StackUnderflow { address: u16 },
+}
+
+impl std::fmt::Display for Fault {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Fault::UnknownOpCode { address, op_code } => {
+                write!(f, "unknown op code {:04x} at {:#05x}", op_code, address)
+            }
+            Fault::StackOverflow { address } => write!(f, "stack overflow at {:#05x}", address),
+            Fault::StackUnderflow { address } => {
+                write!(f, "return with an empty stack at {:#05x}", address)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Fault {}
+
+// everything about the cpu that instructions read or write, apart from memory and the display
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CpuState {
+    pub vs: [u8; 16],
+    pub i: u16,
+    pub prog_counter: u16,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+    pub stack: Vec<u16>,
+}
+
+// program counters and return addresses are even addresses inside the program area and the
+// stack is never deeper than a real program could make it
+#[cfg(feature = "proptest")]
+impl proptest::arbitrary::Arbitrary for CpuState {
+    type Parameters = ();
+    type Strategy = proptest::strategy::BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: Self::Parameters) -> Self::Strategy {
+        use proptest::prelude::*;
+
+        let address = || (PROGRAM_COUNTER_START..RAM_SIZE as u16).prop_map(|a| a & !1);
+
+        (
+            any::<[u8; 16]>(),
+            0..RAM_SIZE as u16,
+            address(),
+            any::<u8>(),
+            any::<u8>(),
+            prop::collection::vec(address(), 0..=MAX_STACK_DEPTH),
+        )
+            .prop_map(
+                |(vs, i, prog_counter, delay_timer, sound_timer, stack)| CpuState {
+                    vs,
+                    i,
+                    prog_counter,
+                    delay_timer,
+                    sound_timer,
+                    stack,
+                },
+            )
+            .boxed()
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Mode {
     Classic,
+    Chip48,
+    Schip,
+    XoChip,
+    #[default]
     Modern,
 }
 
-impl From<String> for Mode {
-    fn from(value: String) -> Self {
-        if value.as_str() == "classic" {
-            Mode::Classic
-        } else {
-            Mode::Modern
+impl FromStr for Mode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "classic" => Ok(Mode::Classic),
+            "chip48" => Ok(Mode::Chip48),
+            "schip" => Ok(Mode::Schip),
+            "xochip" => Ok(Mode::XoChip),
+            "modern" => Ok(Mode::Modern),
+            _ => Err(format!(
+                "invalid mode '{}': expected classic, chip48, schip, xochip or modern",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Quirks {
+    pub shift_uses_vy: bool,
+    pub memory_increments_i: bool,
+    pub clipping: bool,
+    pub logic_resets_vf: bool,
+    // drawing waits for the next vblank as it did on the cosmac vip, off in every mode so roms
+    // tuned on interpreters that draw straight away keep their speed
+    pub display_waits: bool,
+    // 0NNN calls into the few routines of the vip interpreters that hybrid roms rely on are
+    // emulated rather than ignored
+    pub vip_routines: bool,
+}
+
+impl Quirks {
+    pub const NAMES: [&'static str; 6] = [
+        "shift",
+        "memory",
+        "clipping",
+        "vf_reset",
+        "display_wait",
+        "vip_routines",
+    ];
+
+    pub fn get(&self, name: &str) -> Option<bool> {
+        match name {
+            "shift" => Some(self.shift_uses_vy),
+            "memory" => Some(self.memory_increments_i),
+            "clipping" => Some(self.clipping),
+            "vf_reset" => Some(self.logic_resets_vf),
+            "display_wait" => Some(self.display_waits),
+            "vip_routines" => Some(self.vip_routines),
+            _ => None,
+        }
+    }
+    pub fn set(&mut self, name: &str, value: bool) -> bool {
+        let quirk = match name {
+            "shift" => &mut self.shift_uses_vy,
+            "memory" => &mut self.memory_increments_i,
+            "clipping" => &mut self.clipping,
+            "vf_reset" => &mut self.logic_resets_vf,
+            "display_wait" => &mut self.display_waits,
+            "vip_routines" => &mut self.vip_routines,
+            _ => return false,
+        };
+
+        *quirk = value;
+
+        true
+    }
+}
+
+impl From<&Mode> for Quirks {
+    fn from(value: &Mode) -> Self {
+        match value {
+            Mode::Classic => Self {
+                shift_uses_vy: true,
+                memory_increments_i: true,
+                clipping: true,
+                logic_resets_vf: true,
+                display_waits: false,
+                vip_routines: true,
+            },
+            Mode::Chip48 | Mode::Schip => Self {
+                shift_uses_vy: false,
+                memory_increments_i: false,
+                clipping: true,
+                logic_resets_vf: false,
+                display_waits: false,
+                vip_routines: false,
+            },
+            Mode::XoChip => Self {
+                shift_uses_vy: true,
+                memory_increments_i: true,
+                clipping: false,
+                logic_resets_vf: false,
+                display_waits: false,
+                vip_routines: false,
+            },
+            Mode::Modern => Self {
+                shift_uses_vy: false,
+                memory_increments_i: false,
+                clipping: true,
+                logic_resets_vf: false,
+                display_waits: false,
+                vip_routines: false,
+            },
         }
     }
 }
 
-impl Default for Mode {
+impl Default for Quirks {
     fn default() -> Self {
-        Self::Modern
+        Self::from(&Mode::default())
     }
 }
 
-#[derive(Clone, Debug)]
-enum Instruction {
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Draw {
+    pub x: u8,
+    pub y: u8,
+    pub width: u8,
+    pub height: u8,
+    pub collision: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Instruction {
     Add { vx: usize, vy: usize },
     AddIndex { v: usize },
     AddRegister { v: usize, value: u8 },
@@ -96,8 +285,23 @@ enum Instruction {
     Xor { vx: usize, vy: usize },
 }
 
+// only instructions the decoder can produce, each in its canonical encoding
+#[cfg(feature = "proptest")]
+impl proptest::arbitrary::Arbitrary for Instruction {
+    type Parameters = ();
+    type Strategy = proptest::strategy::BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: Self::Parameters) -> Self::Strategy {
+        use proptest::prelude::*;
+
+        any::<u16>()
+            .prop_filter_map("op code does not decode", Instruction::from_op_code)
+            .boxed()
+    }
+}
+
 impl Instruction {
-    fn from_op_code(op_code: u16) -> Option<Instruction> {
+    pub fn from_op_code(op_code: u16) -> Option<Instruction> {
         // precompute X, Y, N, NN and NNN nibbles
         let x = (op_code & 0x0F00) >> 8;
         let y = (op_code & 0x00F0) >> 4;
@@ -207,75 +411,184 @@ impl Instruction {
             _ => None,
         }
     }
-}
+    // the inverse of from_op_code, giving the canonical encoding for op codes the decoder does not
+    // look at every nibble of (5XY1 decodes the same as 5XY0) and truncating operands wider than
+    // their field
+    pub fn to_op_code(&self) -> u16 {
+        let x = |v: usize| (v as u16 & 0xF) << 8;
+        let xy = |vx: usize, vy: usize| x(vx) | (vy as u16 & 0xF) << 4;
+        let xnn = |v: usize, value: u8| x(v) | value as u16;
 
-impl std::fmt::Display for Instruction {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Instruction::Add { vx, vy } => f.write_str(&format!("add v{} v{}", vx, vy)),
-            Instruction::AddIndex { v } => f.write_str(&format!("add_i v{}", v)),
-            Instruction::AddRegister { v, value } => {
-                f.write_str(&format!("add v{} {:#04x}", v, value))
-            }
-            Instruction::And { vx, vy } => f.write_str(&format!("and v{} v{}", vx, vy)),
-            Instruction::BcdConversion { v } => f.write_str(&format!("bcd_cnv v{}", v)),
-            Instruction::ClearScreen => f.write_str("clear"),
-            Instruction::DelayTimerLoad { v } => f.write_str(&format!("delay_load v{}", v)),
-            Instruction::DelayTimerSet { v } => f.write_str(&format!("delay_set v{}", v)),
+            Instruction::ClearScreen => 0x00E0,
+            Instruction::SubroutineReturn => 0x00EE,
+            Instruction::MachineLanguageRoutine { address } => address & 0x0FFF,
+            Instruction::Jump { address } => 0x1000 | address & 0x0FFF,
+            Instruction::SubroutineCall { address } => 0x2000 | address & 0x0FFF,
+            Instruction::SkipEqual { v, value } => 0x3000 | xnn(*v, *value),
+            Instruction::SkipNotEqual { v, value } => 0x4000 | xnn(*v, *value),
+            Instruction::SkipEqualReg { vx, vy } => 0x5000 | xy(*vx, *vy),
+            Instruction::Set { v, value } => 0x6000 | xnn(*v, *value),
+            Instruction::AddRegister { v, value } => 0x7000 | xnn(*v, *value),
+            Instruction::SetRegister { vx, vy } => 0x8000 | xy(*vx, *vy),
+            Instruction::Or { vx, vy } => 0x8001 | xy(*vx, *vy),
+            Instruction::And { vx, vy } => 0x8002 | xy(*vx, *vy),
+            Instruction::Xor { vx, vy } => 0x8003 | xy(*vx, *vy),
+            Instruction::Add { vx, vy } => 0x8004 | xy(*vx, *vy),
+            Instruction::Subtract { vx, vy } => 0x8005 | xy(*vx, *vy),
+            Instruction::ShiftRight { vx, vy } => 0x8006 | xy(*vx, *vy),
+            Instruction::SubtractRev { vx, vy } => 0x8007 | xy(*vx, *vy),
+            Instruction::ShiftLeft { vx, vy } => 0x800E | xy(*vx, *vy),
+            Instruction::SkipNotEqualReg { vx, vy } => 0x9000 | xy(*vx, *vy),
+            Instruction::SetIndex { value } => 0xA000 | value & 0x0FFF,
+            Instruction::Random { v, value } => 0xC000 | xnn(*v, *value),
             Instruction::Display { vx, vy, pixels } => {
-                f.write_str(&format!("disp v{} v{} {:#04x}", vx, vy, pixels))
+                0xD000 | xy(*vx, *vy) | (*pixels as u16 & 0xF)
             }
-            Instruction::GetKey { v } => f.write_str(&format!("get_key v{}", v)),
-            Instruction::Jump { address } => f.write_str(&format!("jump {:#04x}", address)),
-            Instruction::Load { n } => f.write_str(&format!("load {}", n)),
-            Instruction::LoadFontChar { v } => f.write_str(&format!("load_font_ch v{}", v)),
-            Instruction::MachineLanguageRoutine { address } => {
-                f.write_str(&format!("mlr {:#04x}", address))
-            }
-            Instruction::Or { vx, vy } => f.write_str(&format!("or v{} v{}", vx, vy)),
-            Instruction::Random { v, value } => f.write_str(&format!("rand v{} {:#04x}", v, value)),
-            Instruction::SetIndex { value } => f.write_str(&format!("set i {:#04x}", value)),
-            Instruction::Set { v, value } => f.write_str(&format!("set v{} {:#04x}", v, value)),
-            Instruction::SetRegister { vx, vy } => f.write_str(&format!("set v{} v{}", vx, vy)),
-            Instruction::ShiftLeft { vx, vy } => f.write_str(&format!("shift_l v{} v{}", vx, vy)),
-            Instruction::ShiftRight { vx, vy } => f.write_str(&format!("shift_r v{} v{}", vx, vy)),
-            Instruction::SkipEqual { v, value } => {
-                f.write_str(&format!("skip_eq v{} {:#04x}", v, value))
-            }
-            Instruction::SkipEqualReg { vx, vy } => {
-                f.write_str(&format!("skip_eq_reg v{} v{}", vx, vy))
-            }
-            Instruction::SkipIfKeyNotPressed { v } => f.write_str(&format!("skip_key v{}", v)),
-            Instruction::SkipIfKeyPressed { v } => f.write_str(&format!("skip_not_key v{}", v)),
-            Instruction::SkipNotEqual { v, value } => {
-                f.write_str(&format!("skip_neq v{} {:#04x}", v, value))
-            }
-            Instruction::SkipNotEqualReg { vx, vy } => {
-                f.write_str(&format!("skip_neq_reg v{} v{}", vx, vy))
-            }
-            Instruction::SoundTimerSet { v } => f.write_str(&format!("sound_set v{}", v)),
-            Instruction::Store { n } => f.write_str(&format!("store {}", n)),
-            Instruction::Subtract { vx, vy } => f.write_str(&format!("sub v{} v{}", vx, vy)),
-            Instruction::SubtractRev { vx, vy } => f.write_str(&format!("sub_rev v{} v{}", vx, vy)),
-            Instruction::SubroutineCall { address } => {
-                f.write_str(&format!("sub_call {:#04x}", address))
+            Instruction::SkipIfKeyPressed { v } => 0xE09E | x(*v),
+            Instruction::SkipIfKeyNotPressed { v } => 0xE0A1 | x(*v),
+            Instruction::DelayTimerLoad { v } => 0xF007 | x(*v),
+            Instruction::GetKey { v } => 0xF00A | x(*v),
+            Instruction::DelayTimerSet { v } => 0xF015 | x(*v),
+            Instruction::SoundTimerSet { v } => 0xF018 | x(*v),
+            Instruction::AddIndex { v } => 0xF01E | x(*v),
+            Instruction::LoadFontChar { v } => 0xF029 | x(*v),
+            Instruction::BcdConversion { v } => 0xF033 | x(*v),
+            Instruction::Store { n } => 0xF055 | x(*n),
+            Instruction::Load { n } => 0xF065 | x(*n),
+        }
+    }
+}
+
+impl Instruction {
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            Instruction::Add { .. } | Instruction::AddRegister { .. } => "add",
+            Instruction::AddIndex { .. } => "add_i",
+            Instruction::And { .. } => "and",
+            Instruction::BcdConversion { .. } => "bcd_cnv",
+            Instruction::ClearScreen => "clear",
+            Instruction::DelayTimerLoad { .. } => "delay_load",
+            Instruction::DelayTimerSet { .. } => "delay_set",
+            Instruction::Display { .. } => "disp",
+            Instruction::GetKey { .. } => "get_key",
+            Instruction::Jump { .. } => "jump",
+            Instruction::Load { .. } => "load",
+            Instruction::LoadFontChar { .. } => "load_font_ch",
+            Instruction::MachineLanguageRoutine { .. } => "mlr",
+            Instruction::Or { .. } => "or",
+            Instruction::Random { .. } => "rand",
+            Instruction::SetIndex { .. } => "set i",
+            Instruction::Set { .. } | Instruction::SetRegister { .. } => "set",
+            Instruction::ShiftLeft { .. } => "shift_l",
+            Instruction::ShiftRight { .. } => "shift_r",
+            Instruction::SkipEqual { .. } => "skip_eq",
+            Instruction::SkipEqualReg { .. } => "skip_eq_reg",
+            Instruction::SkipIfKeyNotPressed { .. } => "skip_not_key",
+            Instruction::SkipIfKeyPressed { .. } => "skip_key",
+            Instruction::SkipNotEqual { .. } => "skip_neq",
+            Instruction::SkipNotEqualReg { .. } => "skip_neq_reg",
+            Instruction::SoundTimerSet { .. } => "sound_set",
+            Instruction::Store { .. } => "store",
+            Instruction::Subtract { .. } => "sub",
+            Instruction::SubtractRev { .. } => "sub_rev",
+            Instruction::SubroutineCall { .. } => "sub_call",
+            Instruction::SubroutineReturn => "sub_ret",
+            Instruction::Xor { .. } => "xor",
+        }
+    }
+    // displays address operands that have a symbol as the symbol name
+    pub(crate) fn with_symbols<'a>(&'a self, symbols: &'a SymbolTable) -> WithSymbols<'a> {
+        WithSymbols {
+            instruction: self,
+            symbols,
+        }
+    }
+    fn fmt_operands(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Instruction::Add { vx, vy }
+            | Instruction::And { vx, vy }
+            | Instruction::Or { vx, vy }
+            | Instruction::SetRegister { vx, vy }
+            | Instruction::ShiftLeft { vx, vy }
+            | Instruction::ShiftRight { vx, vy }
+            | Instruction::SkipEqualReg { vx, vy }
+            | Instruction::SkipNotEqualReg { vx, vy }
+            | Instruction::Subtract { vx, vy }
+            | Instruction::SubtractRev { vx, vy }
+            | Instruction::Xor { vx, vy } => write!(f, " v{} v{}", vx, vy),
+            Instruction::AddIndex { v }
+            | Instruction::BcdConversion { v }
+            | Instruction::DelayTimerLoad { v }
+            | Instruction::DelayTimerSet { v }
+            | Instruction::GetKey { v }
+            | Instruction::LoadFontChar { v }
+            | Instruction::SkipIfKeyNotPressed { v }
+            | Instruction::SkipIfKeyPressed { v }
+            | Instruction::SoundTimerSet { v } => write!(f, " v{}", v),
+            Instruction::AddRegister { v, value }
+            | Instruction::Random { v, value }
+            | Instruction::Set { v, value }
+            | Instruction::SkipEqual { v, value }
+            | Instruction::SkipNotEqual { v, value } => write!(f, " v{} {:#04x}", v, value),
+            Instruction::Display { vx, vy, pixels } => {
+                write!(f, " v{} v{} {:#04x}", vx, vy, pixels)
             }
-            Instruction::SubroutineReturn => f.write_str("sub_ret"),
-            Instruction::Xor { vx, vy } => f.write_str(&format!("xor v{} v{}", vx, vy)),
+            Instruction::Jump { address }
+            | Instruction::MachineLanguageRoutine { address }
+            | Instruction::SubroutineCall { address }
+            | Instruction::SetIndex { value: address } => write!(f, " {:#04x}", address),
+            Instruction::Load { n } | Instruction::Store { n } => write!(f, " {}", n),
+            Instruction::ClearScreen | Instruction::SubroutineReturn => Ok(()),
+        }
+    }
+}
+
+impl std::fmt::Display for Instruction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.mnemonic())?;
+        self.fmt_operands(f)
+    }
+}
+
+pub(crate) struct WithSymbols<'a> {
+    instruction: &'a Instruction,
+    symbols: &'a SymbolTable,
+}
+
+impl std::fmt::Display for WithSymbols<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let address = match self.instruction {
+            Instruction::Jump { address }
+            | Instruction::MachineLanguageRoutine { address }
+            | Instruction::SubroutineCall { address }
+            | Instruction::SetIndex { value: address } => Some(*address),
+            _ => None,
+        };
+
+        match address.and_then(|address| self.symbols.name(address)) {
+            Some(name) => write!(f, "{} {}", self.instruction.mnemonic(), name),
+            None => write!(f, "{}", self.instruction),
         }
     }
 }
 
 #[derive(Clone, Debug)]
 pub struct CPU {
-    mode: Mode,
+    quirks: Quirks,
     registers: Registers,
     prog_counter: u16,
     stack: Stack,
     delay_timer: u8,
     sound_timer: u8,
-    history: VecDeque<Instruction>,
-    rand_gen: ThreadRng,
+    // raw op codes are cheaper to record than decoded instructions, they are decoded when read
+    history: VecDeque<(u16, u16)>,
+    decoded: DecodeCache,
+    rand_gen: StdRng,
+    symbols: Arc<SymbolTable>,
+    fault: Option<Fault>,
+    // set by a draw with the display_wait quirk, nothing executes until the next vblank
+    waiting_for_vblank: bool,
 }
 
 impl CPU {
@@ -288,13 +601,112 @@ impl CPU {
         display: &mut DisplayState,
         font: &Font,
         keyboard: &KeyState,
-    ) {
+    ) -> Option<Draw> {
+        if self.waiting_for_vblank {
+            return None;
+        }
+
+        let address = self.prog_counter;
         let op_code = self.fetch(memory);
 
-        match Instruction::from_op_code(op_code) {
-            None => tracing::warn!("unknown op code: {:#04x}", op_code),
-            Some(instruction) => self.execute(instruction, memory, display, font, keyboard),
+        metrics::counter!("chipate_instructions_total").increment(1);
+
+        let draw = match self.decoded.decode(memory, address, op_code) {
+            None => {
+                tracing::warn!("unknown op code: {:#04x}", op_code);
+                metrics::counter!("chipate_unknown_op_codes_total").increment(1);
+                self.fault = Some(Fault::UnknownOpCode { address, op_code });
+                None
+            }
+            Some(instruction) => {
+                if self.history.len() == MAX_HISTORY_SIZE {
+                    self.history.pop_front();
+                }
+                self.history.push_back((address, op_code));
+
+                self.execute(instruction, memory, display, font, keyboard)
+            }
+        };
+
+        if draw.is_some() {
+            metrics::counter!("chipate_draws_total").increment(1);
         }
+
+        draw
+    }
+    // returns the cpu to its power on state while keeping the configured quirks and symbols
+    pub fn reset(&mut self) {
+        self.registers = Registers::default();
+        self.prog_counter = PROGRAM_COUNTER_START;
+        self.stack = Stack::default();
+        self.delay_timer = 0;
+        self.sound_timer = 0;
+        self.history.clear();
+        self.fault = None;
+        self.waiting_for_vblank = false;
+    }
+    // true while nothing can change until the timers tick or a key is pressed, that is while
+    // halted, waiting on FX0A or spinning in a loop that polls the delay timer
+    pub fn is_idle(&self, memory: &RAM, keyboard: &KeyState) -> bool {
+        if self.waiting_for_vblank {
+            return true;
+        }
+
+        let instruction_at = |address: u16| {
+            if address as usize + 1 >= RAM_SIZE {
+                return None;
+            }
+
+            Instruction::from_op_code(memory.read_u16(address))
+        };
+
+        match instruction_at(self.prog_counter) {
+            Some(Instruction::GetKey { .. }) => return keyboard.get_pressed_key().is_none(),
+            // a jump to itself is how most programs halt
+            Some(Instruction::Jump { address }) if address == self.prog_counter => return true,
+            _ => {}
+        }
+
+        if self.delay_timer == 0 {
+            return false;
+        }
+
+        // the loop is `FX07; 3X00; 1NNN` jumping back to the load, the program counter can be
+        // on any of the three instructions
+        (0..3).any(|idx| {
+            let Some(start) = self.prog_counter.checked_sub(idx * 2) else {
+                return false;
+            };
+
+            match (
+                instruction_at(start),
+                instruction_at(start + 2),
+                instruction_at(start + 4),
+            ) {
+                (
+                    Some(Instruction::DelayTimerLoad { v }),
+                    Some(Instruction::SkipEqual {
+                        v: skip_v,
+                        value: 0,
+                    }),
+                    Some(Instruction::Jump { address }),
+                ) => v == skip_v && address == start,
+                _ => false,
+            }
+        })
+    }
+    // the fault raised by the last tick, if any
+    pub fn take_fault(&mut self) -> Option<Fault> {
+        self.fault.take()
+    }
+    // the boundary between two frames, sixty times a second the timers tick and a draw waiting on
+    // the display goes ahead
+    pub fn vblank(&mut self) {
+        self.dec_timers();
+        self.waiting_for_vblank = false;
+    }
+    pub fn is_waiting_for_vblank(&self) -> bool {
+        self.waiting_for_vblank
     }
     pub fn dec_timers(&mut self) {
         if self.delay_timer > 0 {
@@ -308,13 +720,93 @@ impl CPU {
     pub fn is_sound_playable(&self) -> bool {
         self.sound_timer > 0
     }
+    pub fn quirks(&self) -> &Quirks {
+        &self.quirks
+    }
+    pub fn quirks_mut(&mut self) -> &mut Quirks {
+        &mut self.quirks
+    }
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
+    pub fn seed_rng(&mut self, seed: u64) {
+        self.rand_gen = StdRng::seed_from_u64(seed);
+    }
+    pub fn set_symbols(&mut self, symbols: Arc<SymbolTable>) {
+        self.symbols = symbols;
+    }
+    pub fn state(&self) -> CpuState {
+        CpuState {
+            vs: self.registers.vs,
+            i: self.registers.i,
+            prog_counter: self.prog_counter,
+            delay_timer: self.delay_timer,
+            sound_timer: self.sound_timer,
+            stack: self.stack.data.clone(),
+        }
+    }
+    pub fn set_state(&mut self, state: &CpuState) {
+        self.registers.vs = state.vs;
+        self.registers.i = state.i;
+        self.prog_counter = state.prog_counter;
+        self.delay_timer = state.delay_timer;
+        self.sound_timer = state.sound_timer;
+        self.stack.data = state.stack.clone();
+    }
+    pub fn prog_counter(&self) -> u16 {
+        self.prog_counter
+    }
+    pub fn set_prog_counter(&mut self, address: u16) {
+        self.prog_counter = address;
+    }
+    pub fn index(&self) -> u16 {
+        self.registers.i
+    }
+    pub fn set_index(&mut self, value: u16) {
+        self.registers.i = value;
+    }
+    pub fn stack(&self) -> &[u16] {
+        &self.stack.data
+    }
+    // off by default, the bench subcommand runs a rom with and without it to show the speedup
+    pub fn set_decode_cache(&mut self, enabled: bool) {
+        self.decoded.enabled = enabled;
+    }
+    // the address and instruction of the most recently executed instructions, oldest first
+    pub fn history(&self) -> impl Iterator<Item = (u16, Instruction)> + '_ {
+        self.history.iter().filter_map(|(address, op_code)| {
+            Instruction::from_op_code(*op_code).map(|instruction| (*address, instruction))
+        })
+    }
+    #[cfg(feature = "jit")]
+    pub(crate) fn registers_mut(&mut self) -> (&mut [u8; 16], &mut u16) {
+        (&mut self.registers.vs, &mut self.registers.i)
+    }
+    pub fn set_stack(&mut self, addresses: &[u16]) {
+        self.stack.data = addresses.to_vec();
+    }
+    pub fn v(&self, idx: usize) -> u8 {
+        self.registers.vs[idx]
+    }
+    pub fn set_v(&mut self, idx: usize, value: u8) {
+        self.registers.vs[idx] = value;
+    }
+    pub fn delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
+    pub fn sound_timer(&self) -> u8 {
+        self.sound_timer
+    }
+    pub fn set_timers(&mut self, delay: u8, sound: u8) {
+        self.delay_timer = delay;
+        self.sound_timer = sound;
+    }
     fn fetch(&mut self, memory: &mut RAM) -> u16 {
-        let high = memory.read(self.prog_counter) as u16;
-        let low = memory.read(self.prog_counter + 1) as u16;
+        let op_code = memory.read_u16(self.prog_counter);
 
-        self.prog_counter += 2;
+        self.prog_counter = self.prog_counter.wrapping_add(2);
 
-        (high << 8) | low
+        op_code
     }
     fn execute(
         &mut self,
@@ -323,8 +815,15 @@ impl CPU {
         display: &mut DisplayState,
         font: &Font,
         keyboard: &KeyState,
-    ) {
-        tracing::debug!("executing instruction '{}'", instruction);
+    ) -> Option<Draw> {
+        if tracing::enabled!(tracing::Level::DEBUG) {
+            tracing::debug!(
+                "executing instruction '{}'",
+                instruction.with_symbols(&self.symbols)
+            );
+        }
+
+        let mut draw = None;
 
         match instruction {
             Instruction::Add { vx, vy } => {
@@ -340,7 +839,7 @@ impl CPU {
                 }
             }
             Instruction::AddIndex { v } => {
-                self.registers.i += self.registers.vs[v] as u16;
+                self.registers.i = self.registers.i.wrapping_add(self.registers.vs[v] as u16);
                 if self.registers.i >= 0x1000 {
                     self.registers.set_f(1);
                 }
@@ -349,19 +848,26 @@ impl CPU {
                 let (result, _) = self.registers.vs[v].overflowing_add(value);
                 self.registers.vs[v] = result;
             }
-            Instruction::And { vx, vy } => self.registers.vs[vx] &= self.registers.vs[vy],
+            Instruction::And { vx, vy } => {
+                self.registers.vs[vx] &= self.registers.vs[vy];
+                if self.quirks.logic_resets_vf {
+                    self.registers.set_f(0);
+                }
+            }
             Instruction::BcdConversion { v } => {
                 let value = self.registers.vs[v];
 
                 memory.write(self.registers.i, value / 100);
-                memory.write(self.registers.i + 1, (value % 100) / 10);
-                memory.write(self.registers.i + 2, value % 10);
+                memory.write(self.registers.i.wrapping_add(1), (value % 100) / 10);
+                memory.write(self.registers.i.wrapping_add(2), value % 10);
+                self.decoded.invalidate(memory, self.registers.i, 3);
             }
             Instruction::ClearScreen => display.clear(),
-            Instruction::DelayTimerLoad { v } => self.delay_timer = self.registers.vs[v],
+            Instruction::DelayTimerLoad { v } => self.registers.vs[v] = self.delay_timer,
             Instruction::DelayTimerSet { v } => self.delay_timer = self.registers.vs[v],
             Instruction::Display { vx, vy, pixels } => {
-                self.display(memory, display, vx, vy, pixels)
+                draw = Some(self.display(memory, display, vx, vy, pixels));
+                self.waiting_for_vblank = self.quirks.display_waits;
             }
             Instruction::GetKey { v } => {
                 if let Some(key) = keyboard.get_pressed_key() {
@@ -370,36 +876,53 @@ impl CPU {
                     self.prog_counter -= 2;
                 }
             }
-            Instruction::Jump { address } => self.prog_counter = address,
-            Instruction::Load { n } => match self.mode {
-                Mode::Classic => {
+            Instruction::Jump { address } => {
+                // hi-res roms open with a jump into the routines of the vip hi-res interpreter,
+                // emulators take that as the start of the program at 0x2c0 instead
+                self.prog_counter = if address == 0x260
+                    && self.prog_counter == PROGRAM_COUNTER_START + 2
+                    && display.height() > DISPLAY_PIXELS_HEIGHT
+                {
+                    0x2C0
+                } else {
+                    address
+                }
+            }
+            Instruction::Load { n } => {
+                if self.quirks.memory_increments_i {
                     for i in 0..=n {
                         self.registers.vs[i] = memory.read(self.registers.i);
-                        self.registers.i += 1;
+                        self.registers.i = self.registers.i.wrapping_add(1);
                     }
-                }
-                Mode::Modern => {
+                } else {
                     for i in 0..=n {
-                        self.registers.vs[i] = memory.read(self.registers.i + i as u16);
+                        self.registers.vs[i] = memory.read(self.registers.i.wrapping_add(i as u16));
                     }
                 }
-            },
+            }
             Instruction::LoadFontChar { v } => {
                 let char = self.registers.vs[v];
                 self.registers.i = font.char_addr(char);
             }
-            Instruction::MachineLanguageRoutine { .. } => {
-                tracing::info!("machine routine instruction not supported")
+            Instruction::MachineLanguageRoutine { address } => {
+                if !vip_routine(address, self.quirks.vip_routines, display) {
+                    tracing::info!("machine routine instruction not supported")
+                }
+            }
+            Instruction::Or { vx, vy } => {
+                self.registers.vs[vx] |= self.registers.vs[vy];
+                if self.quirks.logic_resets_vf {
+                    self.registers.set_f(0);
+                }
             }
-            Instruction::Or { vx, vy } => self.registers.vs[vx] |= self.registers.vs[vy],
             Instruction::Random { v, value } => {
-                self.registers.vs[v] = self.rand_gen.gen_range(0..value) & value
+                self.registers.vs[v] = self.rand_gen.gen::<u8>() & value
             }
             Instruction::SetIndex { value } => self.registers.i = value,
             Instruction::Set { v, value } => self.registers.vs[v] = value,
             Instruction::SetRegister { vx, vy } => self.registers.vs[vx] = self.registers.vs[vy],
             Instruction::ShiftLeft { vx, vy } => {
-                if self.mode == Mode::Classic {
+                if self.quirks.shift_uses_vy {
                     self.registers.vs[vx] = self.registers.vs[vy];
                 }
 
@@ -415,7 +938,7 @@ impl CPU {
                 };
             }
             Instruction::ShiftRight { vx, vy } => {
-                if self.mode == Mode::Classic {
+                if self.quirks.shift_uses_vy {
                     self.registers.vs[vx] = self.registers.vs[vy];
                 }
 
@@ -441,14 +964,16 @@ impl CPU {
                 }
             }
             Instruction::SkipIfKeyNotPressed { v } => {
-                let key = Key::from(v);
+                // only the low nibble of the register names a key
+                let key = Key::from(self.registers.vs[v] as usize & 0xF);
 
                 if !keyboard.is_key_pressed(key) {
                     self.prog_counter += 2;
                 }
             }
             Instruction::SkipIfKeyPressed { v } => {
-                let key = Key::from(v);
+                // only the low nibble of the register names a key
+                let key = Key::from(self.registers.vs[v] as usize & 0xF);
 
                 if keyboard.is_key_pressed(key) {
                     self.prog_counter += 2;
@@ -465,19 +990,25 @@ impl CPU {
                 }
             }
             Instruction::SoundTimerSet { v } => self.sound_timer = self.registers.vs[v],
-            Instruction::Store { n } => match self.mode {
-                Mode::Classic => {
+            Instruction::Store { n } => {
+                let start = self.registers.i;
+
+                if self.quirks.memory_increments_i {
                     for i in 0..=n {
                         memory.write(self.registers.i, self.registers.vs[i]);
-                        self.registers.i += 1;
+                        self.registers.i = self.registers.i.wrapping_add(1);
                     }
-                }
-                Mode::Modern => {
+                } else {
                     for i in 0..=n {
-                        memory.write(self.registers.i + i as u16, self.registers.vs[i]);
+                        memory.write(
+                            self.registers.i.wrapping_add(i as u16),
+                            self.registers.vs[i],
+                        );
                     }
                 }
-            },
+
+                self.decoded.invalidate(memory, start, n as u16 + 1);
+            }
             Instruction::Subtract { vx, vy } => {
                 let minuend = self.registers.vs[vx];
                 let subtrahend = self.registers.vs[vy];
@@ -507,21 +1038,34 @@ impl CPU {
                 }
             }
             Instruction::SubroutineCall { address } => {
+                if self.stack.data.len() >= MAX_STACK_DEPTH {
+                    tracing::warn!("call nested deeper than {} levels", MAX_STACK_DEPTH);
+                    self.fault = Some(Fault::StackOverflow {
+                        address: self.prog_counter - 2,
+                    });
+                }
+
                 self.stack.push(self.prog_counter);
                 self.prog_counter = address;
             }
             Instruction::SubroutineReturn => match self.stack.pop() {
                 Some(address) => self.prog_counter = address,
-                None => tracing::warn!("attempted to pop off of empty stack"),
+                None => {
+                    tracing::warn!("attempted to pop off of empty stack");
+                    self.fault = Some(Fault::StackUnderflow {
+                        address: self.prog_counter - 2,
+                    });
+                }
             },
-            Instruction::Xor { vx, vy } => self.registers.vs[vx] ^= self.registers.vs[vy],
-        }
-
-        if self.history.len() == MAX_HISTORY_SIZE {
-            self.history.pop_front();
+            Instruction::Xor { vx, vy } => {
+                self.registers.vs[vx] ^= self.registers.vs[vy];
+                if self.quirks.logic_resets_vf {
+                    self.registers.set_f(0);
+                }
+            }
         }
 
-        self.history.push_back(instruction);
+        draw
     }
     fn display(
         &mut self,
@@ -530,52 +1074,403 @@ impl CPU {
         vx: usize,
         vy: usize,
         pixels: u8,
-    ) {
-        let mut x = self.registers.vs[vx] % DISPLAY_PIXELS_WIDTH;
-        let mut y = self.registers.vs[vy] % DISPLAY_PIXELS_HEIGHT;
+    ) -> Draw {
+        let (display_width, display_height) = (display.width(), display.height());
+
+        let x = self.registers.vs[vx] % display_width;
+        let y = self.registers.vs[vy] % display_height;
+
+        // sprites either stop at the edges of the display or wrap around to the other side
+        let (width, height) = if self.quirks.clipping {
+            (
+                u8::min(8, display_width - x),
+                u8::min(pixels, display_height - y),
+            )
+        } else {
+            (8, pixels)
+        };
+
+        let mut draw = Draw {
+            x,
+            y,
+            width,
+            height,
+            collision: false,
+        };
 
         self.registers.set_f(0);
 
-        'rows: for i in 0..pixels {
-            let b = memory.read(self.registers.i + i as u16);
+        for i in 0..height {
+            let b = memory.read(self.registers.i.wrapping_add(i as u16));
+            let py = (y + i) % display_height;
 
-            'cols: for j in 0..8 {
+            for j in 0..width {
                 let px = b & (0x1 << (7 - j));
-                let idx = y as u16 * DISPLAY_PIXELS_WIDTH as u16 + x as u16;
+                let px_x = (x + j) % display_width;
+                let idx = py as u16 * display_width as u16 + px_x as u16;
 
                 let px_current = display.read_pixel(idx);
                 display.write_pixel(idx, px_current ^ (px != 0));
-                if px_current && ((px != 0) ^ px_current) {
+                if px_current && px != 0 {
                     self.registers.set_f(1);
-                }
-
-                x += 1;
-                if x >= DISPLAY_PIXELS_WIDTH {
-                    break 'cols;
+                    draw.collision = true;
                 }
             }
+        }
 
-            y += 1;
-            if y >= DISPLAY_PIXELS_HEIGHT {
-                break 'rows;
-            }
+        draw
+    }
+}
+
+// the routines of the vip interpreters that hybrid roms call, false for any other address
+fn vip_routine(address: u16, enabled: bool, display: &mut DisplayState) -> bool {
+    match address {
+        // the hi-res interpreters clear their taller display with a routine at 0x230, the taller
+        // display already means that interpreter is emulated so it does not need the quirk
+        0x230 if display.height() > DISPLAY_PIXELS_HEIGHT => display.clear(),
+        // chip-8x cycles the background color, there is only the one so nothing changes
+        0x2A0 if enabled => {}
+        _ => return false,
+    }
+
+    true
+}
+
+// decoded instructions by address. FX33 and FX55 drop the instructions they write over, any other
+// write to memory, by a cheat, the debugger or a loaded state, changes the version of memory and
+// drops them all
+#[derive(Default)]
+struct DecodeCache {
+    enabled: bool,
+    version: u64,
+    entries: Vec<Option<Instruction>>,
+}
 
-            x = self.registers.vs[vx] % DISPLAY_PIXELS_WIDTH;
+impl DecodeCache {
+    fn decode(&mut self, memory: &RAM, address: u16, op_code: u16) -> Option<Instruction> {
+        if !self.enabled {
+            return Instruction::from_op_code(op_code);
         }
+
+        if self.entries.is_empty() || self.version != memory.version() {
+            self.entries.clear();
+            self.entries.resize(RAM_SIZE, None);
+            self.version = memory.version();
+        }
+
+        let entry = &mut self.entries[address as usize % RAM_SIZE];
+        if entry.is_none() {
+            *entry = Instruction::from_op_code(op_code);
+        }
+
+        entry.clone()
+    }
+    // the instruction starting the byte before the first one written is written over as well
+    fn invalidate(&mut self, memory: &RAM, start: u16, len: u16) {
+        if self.entries.is_empty() {
+            return;
+        }
+
+        for offset in 0..=len {
+            let address = start.wrapping_sub(1).wrapping_add(offset);
+            self.entries[address as usize % RAM_SIZE] = None;
+        }
+        self.version = memory.version();
+    }
+}
+
+// a copy starts out empty, the cpu is cloned for every rewind frame and copying the cache there
+// would cost more than decoding again
+impl Clone for DecodeCache {
+    fn clone(&self) -> Self {
+        Self {
+            enabled: self.enabled,
+            version: 0,
+            entries: Vec::new(),
+        }
+    }
+}
+
+impl std::fmt::Debug for DecodeCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DecodeCache")
+            .field("enabled", &self.enabled)
+            .field("entries", &self.entries.len())
+            .finish()
     }
 }
 
 impl Default for CPU {
     fn default() -> Self {
         Self {
-            mode: Mode::default(),
+            quirks: Quirks::default(),
             registers: Registers::default(),
             prog_counter: PROGRAM_COUNTER_START,
             stack: Stack::default(),
             delay_timer: 0,
             sound_timer: 0,
             history: VecDeque::with_capacity(MAX_HISTORY_SIZE),
-            rand_gen: ThreadRng::default(),
+            decoded: DecodeCache::default(),
+            rand_gen: StdRng::from_entropy(),
+            symbols: Arc::default(),
+            fault: None,
+            waiting_for_vblank: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(cpu: &mut CPU, memory: &mut RAM, display: &mut DisplayState, ticks: usize) {
+        for _ in 0..ticks {
+            cpu.tick(memory, display, &Font::default(), &KeyState::default());
+        }
+    }
+
+    fn load(memory: &mut RAM, op_codes: &[u16]) {
+        let bytes: Vec<u8> = op_codes.iter().flat_map(|op| op.to_be_bytes()).collect();
+        memory.write_block(PROGRAM_COUNTER_START, &bytes);
+    }
+
+    #[test]
+    fn drawing_over_a_lit_pixel_is_a_collision() {
+        // draws the row 0xd0 three times in the same place, then the row 0x01 that only lights
+        // pixels which are off, both rows are bytes of the program
+        let mut memory = RAM::new();
+        load(
+            &mut memory,
+            &[0xA20A, 0xD001, 0xD001, 0xD001, 0xA20B, 0xD001, 0x8000],
+        );
+
+        let mut cpu = CPU::new();
+        let mut display = DisplayState::default();
+
+        run(&mut cpu, &mut memory, &mut display, 2);
+        assert_eq!(cpu.v(0xF), 0);
+        run(&mut cpu, &mut memory, &mut display, 1);
+        assert_eq!(cpu.v(0xF), 1);
+        run(&mut cpu, &mut memory, &mut display, 1);
+        assert_eq!(cpu.v(0xF), 0);
+        run(&mut cpu, &mut memory, &mut display, 2);
+        assert_eq!(cpu.v(0xF), 0);
+    }
+
+    #[test]
+    fn random_numbers_are_masked() {
+        let mut memory = RAM::new();
+        load(&mut memory, &[0xC000, 0xC10F, 0xC2F0]);
+
+        let mut cpu = CPU::new();
+        let mut display = DisplayState::default();
+
+        for _ in 0..64 {
+            cpu.prog_counter = PROGRAM_COUNTER_START;
+            run(&mut cpu, &mut memory, &mut display, 3);
+            assert_eq!(cpu.v(0), 0);
+            assert_eq!(cpu.v(1) & 0xF0, 0);
+            assert_eq!(cpu.v(2) & 0x0F, 0);
+        }
+    }
+
+    #[test]
+    fn delay_timer_loads_into_a_register() {
+        let mut memory = RAM::new();
+        load(&mut memory, &[0x6020, 0xF015, 0xF107]);
+
+        let mut cpu = CPU::new();
+        let mut display = DisplayState::default();
+
+        run(&mut cpu, &mut memory, &mut display, 3);
+        assert_eq!(cpu.delay_timer(), 0x20);
+        assert_eq!(cpu.v(1), 0x20);
+    }
+
+    #[test]
+    fn index_addresses_wrap_around_the_end_of_memory() {
+        // stores v0..v2 from 0xffe, reads v3..v4 back from 0xfff then writes 123 as bcd at 0xfff
+        let mut memory = RAM::new();
+        load(
+            &mut memory,
+            &[
+                0x6011, 0x6122, 0x6233, 0xAFFE, 0xF255, 0xAFFF, 0xF165, 0x8300, 0x8410, 0x607B,
+                0xAFFF, 0xF033,
+            ],
+        );
+
+        let mut cpu = CPU::new();
+        let mut display = DisplayState::default();
+
+        run(&mut cpu, &mut memory, &mut display, 5);
+        assert_eq!(memory.read(0xFFE), 0x11);
+        assert_eq!(memory.read(0xFFF), 0x22);
+        assert_eq!(memory.read(0x000), 0x33);
+
+        run(&mut cpu, &mut memory, &mut display, 4);
+        assert_eq!((cpu.v(3), cpu.v(4)), (0x22, 0x33));
+
+        run(&mut cpu, &mut memory, &mut display, 3);
+        assert_eq!(memory.read(0xFFF), 1);
+        assert_eq!(memory.read(0x000), 2);
+        assert_eq!(memory.read(0x001), 3);
+    }
+
+    #[test]
+    fn decoded_instructions_encode_back_to_themselves() {
+        for op_code in 0..=u16::MAX {
+            if let Some(instruction) = Instruction::from_op_code(op_code) {
+                assert_eq!(
+                    Instruction::from_op_code(instruction.to_op_code()),
+                    Some(instruction),
+                    "{:04x}",
+                    op_code
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn every_op_code_decodes_to_its_family() {
+        for op_code in 0..=u16::MAX {
+            let Some(instruction) = Instruction::from_op_code(op_code) else {
+                continue;
+            };
+
+            // the high nibble picks the family, 8XY_ also looks at the low nibble and EX__ and FX__
+            // at the low byte
+            let family = match op_code & 0xF000 {
+                0x8000 => 0xF00F,
+                0xE000 | 0xF000 => 0xF0FF,
+                _ => 0xF000,
+            };
+            let encoded = instruction.to_op_code();
+            assert_eq!(encoded & family, op_code & family, "{:04x}", op_code);
+
+            match op_code & 0xF000 {
+                0x0000 => assert!(matches!(
+                    instruction,
+                    Instruction::ClearScreen
+                        | Instruction::SubroutineReturn
+                        | Instruction::MachineLanguageRoutine { .. }
+                )),
+                0x1000 => assert!(matches!(instruction, Instruction::Jump { .. })),
+                0x2000 => assert!(matches!(instruction, Instruction::SubroutineCall { .. })),
+                0xD000 => assert!(matches!(instruction, Instruction::Display { .. })),
+                _ => {}
+            }
+        }
+    }
+
+    #[test]
+    fn canonical_op_codes_round_trip() {
+        for op_code in 0..=u16::MAX {
+            let Some(instruction) = Instruction::from_op_code(op_code) else {
+                continue;
+            };
+
+            // 5XYN and 9XYN decode the same whatever N is, only N = 0 is canonical
+            let canonical = !matches!(op_code & 0xF000, 0x5000 | 0x9000) || op_code & 0xF == 0;
+            assert_eq!(
+                instruction.to_op_code() == op_code,
+                canonical,
+                "{:04x}",
+                op_code
+            );
+        }
+    }
+
+    #[test]
+    fn decode_cache_sees_instructions_the_program_stores_over() {
+        // adds one to v2, then stores 7205 over the add and jumps back to it
+        let program = [0x7201, 0xA200, 0x6072, 0x6105, 0xF155, 0x1200];
+
+        for cached in [false, true] {
+            let mut cpu = CPU::new();
+            cpu.set_decode_cache(cached);
+            let mut memory = RAM::new();
+            load(&mut memory, &program);
+
+            let mut display = DisplayState::default();
+            run(&mut cpu, &mut memory, &mut display, program.len() + 1);
+            assert_eq!(cpu.v(2), 6, "cached {}", cached);
+        }
+    }
+
+    #[test]
+    fn decode_cache_sees_writes_from_outside_the_cpu() {
+        let mut cpu = CPU::new();
+        cpu.set_decode_cache(true);
+        let mut memory = RAM::new();
+        load(&mut memory, &[0x7201]);
+
+        let mut display = DisplayState::default();
+        run(&mut cpu, &mut memory, &mut display, 1);
+        memory.write(PROGRAM_COUNTER_START + 1, 0x05);
+        cpu.set_prog_counter(PROGRAM_COUNTER_START);
+        run(&mut cpu, &mut memory, &mut display, 1);
+
+        assert_eq!(cpu.v(2), 6);
+    }
+
+    #[test]
+    fn decode_cache_wraps_past_the_end_of_memory() {
+        for address in [RAM_SIZE as u16 - 2, u16::MAX - 1] {
+            let mut cpu = CPU::new();
+            cpu.set_decode_cache(true);
+            cpu.set_prog_counter(address);
+
+            run(&mut cpu, &mut RAM::new(), &mut DisplayState::default(), 2);
+        }
+    }
+
+    #[test]
+    fn key_skips_test_the_key_in_the_register() {
+        // v0 holds key 5 while the held key is 0, the index of v0
+        let mut memory = RAM::new();
+        load(
+            &mut memory,
+            &[0x6005, 0xE09E, 0x6101, 0x6202, 0xE0A1, 0x6303],
+        );
+
+        let mut cpu = CPU::new();
+        let mut display = DisplayState::default();
+        let mut keyboard = KeyState::default();
+        keyboard.key_pressed(Key::Num5);
+        for _ in 0..5 {
+            cpu.tick(&mut memory, &mut display, &Font::default(), &keyboard);
+        }
+
+        assert_eq!((cpu.v(1), cpu.v(2), cpu.v(3)), (0, 2, 3));
+    }
+}
+
+#[cfg(all(test, feature = "proptest"))]
+mod proptests {
+    use super::*;
+    use proptest::prelude::*;
+
+    proptest! {
+        #[test]
+        fn register_copies_leave_everything_else_alone(
+            state in any::<CpuState>(),
+            vx in 0..16_usize,
+            vy in 0..16_usize,
+        ) {
+            let mut memory = RAM::new();
+            let op_code = Instruction::SetRegister { vx, vy }.to_op_code();
+            memory.write_block(state.prog_counter, &op_code.to_be_bytes());
+            let checksum = memory.checksum();
+
+            let mut cpu = CPU::new();
+            cpu.set_state(&state);
+            cpu.tick(&mut memory, &mut DisplayState::default(), &Font::default(), &KeyState::default());
+
+            let mut expected = state.clone();
+            expected.vs[vx] = state.vs[vy];
+            expected.prog_counter += 2;
+            prop_assert_eq!(cpu.state(), expected);
+            prop_assert_eq!(memory.checksum(), checksum);
         }
     }
 }
@@ -0,0 +1,88 @@
+use crate::{
+    core::{
+        cpu::Instruction,
+        test_pattern::{assemble, draw, wait},
+        Program,
+    },
+    DISPLAY_PIXELS_WIDTH, PROGRAM_START_ADDR,
+};
+
+const LOGO: &str = "CHIPATE";
+
+const USAGE: [&str; 2] = ["RUN WITH", "-R ROM"];
+
+// glyphs for the letters the demo writes, four pixels wide like the built in font
+const GLYPHS: [(char, [u8; 5]); 14] = [
+    ('A', [0x60, 0x90, 0xF0, 0x90, 0x90]),
+    ('C', [0x70, 0x80, 0x80, 0x80, 0x70]),
+    ('E', [0xF0, 0x80, 0xE0, 0x80, 0xF0]),
+    ('H', [0x90, 0x90, 0xF0, 0x90, 0x90]),
+    ('I', [0xE0, 0x40, 0x40, 0x40, 0xE0]),
+    ('M', [0x90, 0xF0, 0xF0, 0x90, 0x90]),
+    ('N', [0x90, 0xD0, 0xB0, 0x90, 0x90]),
+    ('O', [0x60, 0x90, 0x90, 0x90, 0x60]),
+    ('P', [0xE0, 0x90, 0xE0, 0x80, 0x80]),
+    ('R', [0xE0, 0x90, 0xE0, 0xA0, 0x90]),
+    ('T', [0xF0, 0x40, 0x40, 0x40, 0x40]),
+    ('U', [0x90, 0x90, 0x90, 0x90, 0x60]),
+    ('W', [0x90, 0x90, 0xF0, 0xF0, 0x90]),
+    ('-', [0x00, 0x00, 0xF0, 0x00, 0x00]),
+];
+
+// fast enough to redraw the logo within a frame so the animation does not flicker
+pub const INSTRUCTIONS_PER_SEC: u16 = 10_000;
+
+// rows the logo drops through before it settles
+const LOGO_DROP: u8 = 6;
+
+// booted when chipate is started without a rom, the logo drops in and the usage blinks under it
+pub fn program() -> Program {
+    let data: Vec<u8> = GLYPHS.iter().flat_map(|(_, glyph)| *glyph).collect();
+
+    let start = PROGRAM_START_ADDR + 2 + data.len() as u16;
+
+    let mut code = vec![Instruction::ClearScreen];
+    for y in 0..=LOGO_DROP {
+        code.extend(text(LOGO, y));
+        code.extend(wait(start, &code, 3));
+        if y < LOGO_DROP {
+            code.extend(text(LOGO, y));
+        }
+    }
+
+    let blink = start + 2 * code.len() as u16;
+    for _ in 0..2 {
+        for (line, usage) in USAGE.iter().enumerate() {
+            code.extend(text(usage, 17 + line as u8 * 7));
+        }
+        code.extend(wait(start, &code, 30));
+    }
+    code.push(Instruction::Jump { address: blink });
+
+    assemble("demo", data, code)
+}
+
+// draws a line of text centred horizontally, drawing it again erases it
+fn text(line: &str, y: u8) -> Vec<Instruction> {
+    // glyphs are four pixels wide with a pixel between them
+    let width = line.len() as u8 * 5 - 1;
+    let x = (DISPLAY_PIXELS_WIDTH - width) / 2;
+
+    line.chars()
+        .enumerate()
+        .filter(|(_, c)| *c != ' ')
+        .flat_map(|(idx, c)| {
+            let glyph = GLYPHS
+                .iter()
+                .position(|(g, _)| *g == c)
+                .expect("demo text only uses known glyphs");
+
+            // the glyphs are the only data so they start right after the jump over them
+            let address = PROGRAM_START_ADDR + 2 + glyph as u16 * 5;
+
+            let mut code = vec![Instruction::SetIndex { value: address }];
+            code.extend(draw(x + idx as u8 * 5, y, 5));
+            code
+        })
+        .collect()
+}
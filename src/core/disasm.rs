@@ -0,0 +1,569 @@
+use crate::core::{
+    coverage::Coverage, cpu::Instruction, regions::RegionMap, symbols::SymbolTable, Program,
+};
+
+use std::collections::{BTreeMap, BTreeSet};
+
+const DATA_BYTES_PER_LINE: usize = 8;
+
+#[derive(Clone, Debug)]
+enum Line {
+    Code {
+        address: u16,
+        op_code: u16,
+        instruction: Instruction,
+    },
+    Data {
+        address: u16,
+        bytes: Vec<u8>,
+    },
+}
+
+#[derive(Clone, Debug)]
+pub struct Disassembly {
+    lines: Vec<Line>,
+    labels: SymbolTable,
+    regions: RegionMap,
+    coverage: Option<Coverage>,
+    annotate: bool,
+}
+
+impl Disassembly {
+    pub fn new(program: &Program, origin: u16) -> Self {
+        Self::with_entry_points(program, origin, [])
+    }
+    // control flow is followed from the entry points as well as the origin
+    pub fn with_entry_points(
+        program: &Program,
+        origin: u16,
+        entries: impl IntoIterator<Item = u16>,
+    ) -> Self {
+        let data = program.data();
+        let code = trace_code_from(data, origin, entries);
+
+        let mut lines = Vec::new();
+        let mut offset = 0;
+
+        while offset < data.len() {
+            let address = origin + offset as u16;
+
+            if code.contains(&address) {
+                let op_code = read_op_code(data, offset);
+                let instruction = Instruction::from_op_code(op_code)
+                    .expect("traced addresses always decode to an instruction");
+
+                lines.push(Line::Code {
+                    address,
+                    op_code,
+                    instruction,
+                });
+
+                offset += 2;
+            } else {
+                let mut bytes = Vec::new();
+
+                while offset < data.len()
+                    && bytes.len() < DATA_BYTES_PER_LINE
+                    && !code.contains(&(origin + offset as u16))
+                {
+                    bytes.push(data[offset]);
+                    offset += 1;
+                }
+
+                lines.push(Line::Data { address, bytes });
+            }
+        }
+
+        let labels = infer_labels(&lines, &code);
+
+        Self {
+            lines,
+            labels,
+            regions: RegionMap::new(),
+            coverage: None,
+            annotate: false,
+        }
+    }
+    pub fn with_symbols(mut self, symbols: &SymbolTable) -> Self {
+        self.labels.merge(symbols);
+        self
+    }
+    // a comment naming the region goes before the line it starts on
+    pub fn with_regions(mut self, regions: RegionMap) -> Self {
+        self.regions = regions;
+        self
+    }
+    pub fn with_coverage(mut self, coverage: Coverage) -> Self {
+        self.coverage = Some(coverage);
+        self
+    }
+    // every instruction gets a comment describing what it does and every call or jump target a
+    // comment listing where it is reached from
+    pub fn with_annotations(mut self) -> Self {
+        self.annotate = true;
+        self
+    }
+    pub fn label(&self, address: u16) -> Option<&str> {
+        self.labels.name(address)
+    }
+}
+
+// the listing is octo source that assembles back into the program, it opens with main so no jump
+// to it is needed and instructions octo has no statement for are written as their bytes
+impl std::fmt::Display for Disassembly {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let references = match self.annotate {
+            true => self.references(),
+            false => BTreeMap::new(),
+        };
+
+        // a label is only used where it is written, one naming the middle of an instruction or
+        // an address outside the program is left as a number
+        let mut starts = BTreeSet::new();
+        for line in &self.lines {
+            match line {
+                Line::Code { address, .. } => {
+                    starts.insert(*address);
+                }
+                Line::Data { address, bytes } => {
+                    starts.extend((0..bytes.len() as u16).map(|offset| address + offset));
+                }
+            }
+        }
+        let target = |address: u16| match self.label(address) {
+            Some(label) if starts.contains(&address) => String::from(label),
+            _ => format!("{:#05x}", address),
+        };
+
+        let mut main = self.labels.address("main").is_none();
+
+        for line in &self.lines {
+            match line {
+                Line::Code {
+                    address,
+                    op_code,
+                    instruction,
+                } => {
+                    self.write_header(f, *address, 2, &references, &mut main)?;
+                    self.write_count(f, self.coverage.as_ref().map(|c| c.count(*address)))?;
+
+                    let text = match instruction.to_op_code() == *op_code {
+                        true => statement(instruction, target),
+                        false => bytes(&op_code.to_be_bytes()),
+                    };
+
+                    write!(f, "    {:<23} # {:#05x}: {:04x}", text, address, op_code)?;
+                    match self.annotate {
+                        true => writeln!(f, " - {}", describe(instruction))?,
+                        false => writeln!(f)?,
+                    }
+                }
+                Line::Data {
+                    address,
+                    bytes: data,
+                } => {
+                    // a run of data is split where a label names a byte in it
+                    let mut start = 0;
+                    while start < data.len() {
+                        let end = (start + 1..data.len())
+                            .find(|offset| self.label(address + *offset as u16).is_some())
+                            .unwrap_or(data.len());
+                        let address = address + start as u16;
+
+                        self.write_header(
+                            f,
+                            address,
+                            (end - start) as u16,
+                            &references,
+                            &mut main,
+                        )?;
+                        self.write_count(f, None)?;
+                        writeln!(f, "    {:<23} # {:#05x}", bytes(&data[start..end]), address)?;
+
+                        start = end;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Disassembly {
+    // the regions starting within the line, then its labels and what reaches it
+    fn write_header(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+        address: u16,
+        len: u16,
+        references: &BTreeMap<u16, Vec<(u16, bool)>>,
+        main: &mut bool,
+    ) -> std::fmt::Result {
+        for region in self
+            .regions
+            .iter()
+            .filter(|region| (address..address + len).contains(&region.start))
+        {
+            self.write_count(f, None)?;
+            writeln!(f, "# {}", region)?;
+        }
+
+        if std::mem::take(main) {
+            self.write_count(f, None)?;
+            writeln!(f, ": main")?;
+        }
+
+        if let Some(label) = self.label(address) {
+            self.write_count(f, None)?;
+            writeln!(f, ": {}", label)?;
+        }
+
+        if let Some(references) = references.get(&address) {
+            self.write_count(f, None)?;
+            writeln!(f, "    # {}", describe_references(references))?;
+        }
+
+        Ok(())
+    }
+    // with coverage every line starts with how many times it ran, lines that are not code get a
+    // dash and code that never ran gets hashes
+    fn write_count(&self, f: &mut std::fmt::Formatter<'_>, count: Option<u64>) -> std::fmt::Result {
+        if self.coverage.is_none() {
+            return Ok(());
+        }
+
+        match count {
+            Some(0) => write!(f, "{:>10}  ", "#####"),
+            Some(count) => write!(f, "{:>10}  ", count),
+            None => write!(f, "{:>10}  ", "-"),
+        }
+    }
+    // the addresses of the calls and jumps to every target, whether or not the target is code
+    fn references(&self) -> BTreeMap<u16, Vec<(u16, bool)>> {
+        let mut references: BTreeMap<u16, Vec<(u16, bool)>> = BTreeMap::new();
+
+        for line in &self.lines {
+            let Line::Code {
+                address,
+                instruction,
+                ..
+            } = line
+            else {
+                continue;
+            };
+
+            match instruction {
+                Instruction::SubroutineCall { address: target } => references
+                    .entry(*target)
+                    .or_default()
+                    .push((*address, true)),
+                Instruction::Jump { address: target } => references
+                    .entry(*target)
+                    .or_default()
+                    .push((*address, false)),
+                _ => {}
+            }
+        }
+
+        references
+    }
+}
+
+fn bytes(bytes: &[u8]) -> String {
+    let bytes: Vec<String> = bytes.iter().map(|b| format!("{:#04x}", b)).collect();
+    bytes.join(" ")
+}
+
+// the octo statement assembling to the instruction, a skip is the if ... then whose condition
+// does not hold when the instruction skips
+fn statement(instruction: &Instruction, target: impl Fn(u16) -> String) -> String {
+    match *instruction {
+        Instruction::Add { vx, vy } => format!("v{:x} += v{:x}", vx, vy),
+        Instruction::AddIndex { v } => format!("i += v{:x}", v),
+        Instruction::AddRegister { v, value } => format!("v{:x} += {:#04x}", v, value),
+        Instruction::And { vx, vy } => format!("v{:x} &= v{:x}", vx, vy),
+        Instruction::BcdConversion { v } => format!("bcd v{:x}", v),
+        Instruction::ClearScreen => String::from("clear"),
+        Instruction::DelayTimerLoad { v } => format!("v{:x} := delay", v),
+        Instruction::DelayTimerSet { v } => format!("delay := v{:x}", v),
+        Instruction::Display { vx, vy, pixels } => {
+            format!("sprite v{:x} v{:x} {}", vx, vy, pixels)
+        }
+        Instruction::GetKey { v } => format!("v{:x} := key", v),
+        Instruction::Jump { address } => format!("jump {}", target(address)),
+        Instruction::Load { n } => format!("load v{:x}", n),
+        Instruction::LoadFontChar { v } => format!("i := hex v{:x}", v),
+        Instruction::MachineLanguageRoutine { address } => format!("native {:#05x}", address),
+        Instruction::Or { vx, vy } => format!("v{:x} |= v{:x}", vx, vy),
+        Instruction::Random { v, value } => format!("v{:x} := random {:#04x}", v, value),
+        Instruction::SetIndex { value } => format!("i := {}", target(value)),
+        Instruction::Set { v, value } => format!("v{:x} := {:#04x}", v, value),
+        Instruction::SetRegister { vx, vy } => format!("v{:x} := v{:x}", vx, vy),
+        Instruction::ShiftLeft { vx, vy } => format!("v{:x} <<= v{:x}", vx, vy),
+        Instruction::ShiftRight { vx, vy } => format!("v{:x} >>= v{:x}", vx, vy),
+        Instruction::SkipEqual { v, value } => format!("if v{:x} != {:#04x} then", v, value),
+        Instruction::SkipEqualReg { vx, vy } => format!("if v{:x} != v{:x} then", vx, vy),
+        Instruction::SkipIfKeyNotPressed { v } => format!("if v{:x} key then", v),
+        Instruction::SkipIfKeyPressed { v } => format!("if v{:x} -key then", v),
+        Instruction::SkipNotEqual { v, value } => format!("if v{:x} == {:#04x} then", v, value),
+        Instruction::SkipNotEqualReg { vx, vy } => format!("if v{:x} == v{:x} then", vx, vy),
+        Instruction::SoundTimerSet { v } => format!("buzzer := v{:x}", v),
+        Instruction::Store { n } => format!("save v{:x}", n),
+        Instruction::Subtract { vx, vy } => format!("v{:x} -= v{:x}", vx, vy),
+        Instruction::SubtractRev { vx, vy } => format!("v{:x} =- v{:x}", vx, vy),
+        Instruction::SubroutineCall { address } => format!(":call {}", target(address)),
+        Instruction::SubroutineReturn => String::from("return"),
+        Instruction::Xor { vx, vy } => format!("v{:x} ^= v{:x}", vx, vy),
+    }
+}
+
+fn describe_references(references: &[(u16, bool)]) -> String {
+    let from = |call: bool| {
+        let addresses: Vec<String> = references
+            .iter()
+            .filter(|(_, c)| *c == call)
+            .map(|(address, _)| format!("{:#05x}", address))
+            .collect();
+        addresses.join(", ")
+    };
+
+    let (calls, jumps) = (from(true), from(false));
+    match (calls.is_empty(), jumps.is_empty()) {
+        (false, true) => format!("called from {}", calls),
+        (true, false) => format!("jumped to from {}", jumps),
+        _ => format!("called from {}, jumped to from {}", calls, jumps),
+    }
+}
+
+// what an instruction does in pseudo code, instructions whose behavior depends on a quirk say so
+fn describe(instruction: &Instruction) -> String {
+    match *instruction {
+        Instruction::Add { vx, vy } => format!("V{:X} += V{:X}, VF = carry", vx, vy),
+        Instruction::AddIndex { v } => format!("I += V{:X}", v),
+        Instruction::AddRegister { v, value } => {
+            format!("V{:X} += {:#04x} (no carry flag)", v, value)
+        }
+        Instruction::And { vx, vy } => {
+            format!("V{:X} &= V{:X} (VF = 0 with the vf_reset quirk)", vx, vy)
+        }
+        Instruction::BcdConversion { v } => {
+            format!("memory[I..I+3] = hundreds, tens and ones of V{:X}", v)
+        }
+        Instruction::ClearScreen => String::from("clear the display"),
+        Instruction::DelayTimerLoad { v } => format!("V{:X} = delay timer", v),
+        Instruction::DelayTimerSet { v } => format!("delay timer = V{:X}", v),
+        // schip draws a 16x16 sprite for no rows, this cpu has no such sprites
+        Instruction::Display { vx, vy, pixels: 0 } => format!(
+            "draw no rows at (V{:X}, V{:X}), VF = 0 (waits for the vblank with the display_wait \
+             quirk)",
+            vx, vy
+        ),
+        Instruction::Display { vx, vy, pixels } => format!(
+            "draw the {} byte sprite at I at (V{:X}, V{:X}), VF = collision (waits for the \
+             vblank with the display_wait quirk)",
+            pixels, vx, vy
+        ),
+        Instruction::GetKey { v } => format!("wait for a key press, V{:X} = key", v),
+        Instruction::Jump { address } => format!("goto {:#05x}", address),
+        Instruction::Load { n } => format!(
+            "V0..=V{:X} = memory[I..], I moves past them with the memory quirk",
+            n
+        ),
+        Instruction::LoadFontChar { v } => format!("I = font sprite of the digit in V{:X}", v),
+        Instruction::MachineLanguageRoutine { address } => {
+            format!(
+                "run machine code at {:#05x}, ignored unless the vip_routines quirk knows it",
+                address
+            )
+        }
+        Instruction::Or { vx, vy } => {
+            format!("V{:X} |= V{:X} (VF = 0 with the vf_reset quirk)", vx, vy)
+        }
+        Instruction::Random { v, value } => format!("V{:X} = random & {:#04x}", v, value),
+        Instruction::SetIndex { value } => format!("I = {:#05x}", value),
+        Instruction::Set { v, value } => format!("V{:X} = {:#04x}", v, value),
+        Instruction::SetRegister { vx, vy } => format!("V{:X} = V{:X}", vx, vy),
+        Instruction::ShiftLeft { vx, vy } => format!(
+            "V{:X} = V{:X} << 1 (V{:X} <<= 1 with the shift quirk), VF = bit shifted out",
+            vx, vy, vx
+        ),
+        Instruction::ShiftRight { vx, vy } => format!(
+            "V{:X} = V{:X} >> 1 (V{:X} >>= 1 with the shift quirk), VF = bit shifted out",
+            vx, vy, vx
+        ),
+        Instruction::SkipEqual { v, value } => format!("skip next if V{:X} == {:#04x}", v, value),
+        Instruction::SkipEqualReg { vx, vy } => format!("skip next if V{:X} == V{:X}", vx, vy),
+        Instruction::SkipIfKeyNotPressed { v } => {
+            format!("skip next if the key in V{:X} is not pressed", v)
+        }
+        Instruction::SkipIfKeyPressed { v } => {
+            format!("skip next if the key in V{:X} is pressed", v)
+        }
+        Instruction::SkipNotEqual { v, value } => {
+            format!("skip next if V{:X} != {:#04x}", v, value)
+        }
+        Instruction::SkipNotEqualReg { vx, vy } => format!("skip next if V{:X} != V{:X}", vx, vy),
+        Instruction::SoundTimerSet { v } => format!("sound timer = V{:X}", v),
+        Instruction::Store { n } => format!(
+            "memory[I..] = V0..=V{:X}, I moves past them with the memory quirk",
+            n
+        ),
+        Instruction::Subtract { vx, vy } => format!("V{:X} -= V{:X}, VF = no borrow", vx, vy),
+        Instruction::SubtractRev { vx, vy } => {
+            format!("V{:X} = V{:X} - V{:X}, VF = no borrow", vx, vy, vx)
+        }
+        Instruction::SubroutineCall { address } => format!("call {:#05x}", address),
+        Instruction::SubroutineReturn => String::from("return from the subroutine"),
+        Instruction::Xor { vx, vy } => {
+            format!("V{:X} ^= V{:X} (VF = 0 with the vf_reset quirk)", vx, vy)
+        }
+    }
+}
+
+pub(crate) fn read_op_code(data: &[u8], offset: usize) -> u16 {
+    (data[offset] as u16) << 8 | data[offset + 1] as u16
+}
+
+// recursively follow control flow from the entry point, returning the start address of every
+// instruction that can be reached
+pub(crate) fn trace_code(data: &[u8], origin: u16) -> BTreeSet<u16> {
+    trace_code_from(data, origin, [])
+}
+
+pub(crate) fn trace_code_from(
+    data: &[u8],
+    origin: u16,
+    entries: impl IntoIterator<Item = u16>,
+) -> BTreeSet<u16> {
+    let end = origin as usize + data.len();
+
+    let mut code = BTreeSet::new();
+    let mut pending = vec![origin];
+    pending.extend(entries);
+
+    while let Some(address) = pending.pop() {
+        if (address as usize) < origin as usize
+            || address as usize + 1 >= end
+            || code.contains(&address)
+        {
+            continue;
+        }
+
+        let op_code = read_op_code(data, (address - origin) as usize);
+        let Some(instruction) = Instruction::from_op_code(op_code) else {
+            continue;
+        };
+
+        code.insert(address);
+
+        match instruction {
+            Instruction::Jump { address: target } => pending.push(target),
+            Instruction::SubroutineCall { address: target } => {
+                pending.push(target);
+                pending.push(address + 2);
+            }
+            Instruction::SubroutineReturn => {}
+            Instruction::SkipEqual { .. }
+            | Instruction::SkipEqualReg { .. }
+            | Instruction::SkipIfKeyNotPressed { .. }
+            | Instruction::SkipIfKeyPressed { .. }
+            | Instruction::SkipNotEqual { .. }
+            | Instruction::SkipNotEqualReg { .. } => {
+                pending.push(address + 2);
+                pending.push(address + 4);
+            }
+            _ => pending.push(address + 2),
+        }
+    }
+
+    code
+}
+
+// name call targets as subroutines and jump targets as loops or labels depending on direction,
+// only targets that begin a traced instruction are named
+fn infer_labels(lines: &[Line], code: &BTreeSet<u16>) -> SymbolTable {
+    let mut labels = SymbolTable::new();
+
+    for line in lines {
+        if let Line::Code {
+            instruction: Instruction::SubroutineCall { address: target },
+            ..
+        } = line
+        {
+            if code.contains(target) {
+                labels.insert(&format!("sub_{:03X}", target), *target);
+            }
+        }
+    }
+
+    for line in lines {
+        if let Line::Code {
+            address,
+            instruction: Instruction::Jump { address: target },
+            ..
+        } = line
+        {
+            if code.contains(target) && labels.name(*target).is_none() {
+                let prefix = if target <= address { "loop" } else { "label" };
+                labels.insert(&format!("{}_{:03X}", prefix, target), *target);
+            }
+        }
+    }
+
+    labels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        core::{demo, octo, test_pattern},
+        PROGRAM_START_ADDR,
+    };
+
+    fn round_trip(program: &Program, symbols: &SymbolTable) {
+        let listing = Disassembly::new(program, PROGRAM_START_ADDR)
+            .with_symbols(symbols)
+            .with_annotations()
+            .to_string();
+
+        let assembled =
+            octo::assemble("listing", &listing).unwrap_or_else(|e| panic!("{}\n{}", e, listing));
+        assert_eq!(assembled.data(), program.data(), "\n{}", listing);
+    }
+
+    #[test]
+    fn listings_assemble_back_into_the_program() {
+        for program in [
+            demo::program(),
+            test_pattern::display(32),
+            test_pattern::keypad(),
+            test_pattern::sound(),
+        ] {
+            round_trip(&program, &SymbolTable::new());
+        }
+    }
+
+    #[test]
+    fn every_instruction_assembles_back_into_itself() {
+        // every instruction, two that only decode, a load of i and a jump outside the program,
+        // a jump into the middle of an instruction and one back, then data
+        let op_codes: [u16; 41] = [
+            0x00E0, 0x0123, 0x2210, 0x3A12, 0x4B34, 0x5AB0, 0x9AB0, 0x6C56, 0x7D78, 0x8010, 0x8121,
+            0x8232, 0x8343, 0x8454, 0x8565, 0x8676, 0x877E, 0xA257, 0xC9FF, 0xD125, 0xD120, 0xE19E,
+            0xE2A1, 0xF307, 0xF40A, 0xF515, 0xF618, 0xF71E, 0xF829, 0xF933, 0xFA55, 0xFB65, 0x5AB1,
+            0x9AB2, 0xA050, 0x3000, 0x1203, 0x3000, 0x1050, 0x120C, 0x00EE,
+        ];
+        let mut bytes: Vec<u8> = op_codes.iter().flat_map(|op| op.to_be_bytes()).collect();
+        bytes.extend([0xF0, 0x90, 0xF0, 0x90, 0xF0, 0x01, 0x02, 0x03]);
+        let program = Program::new(String::from("all"), bytes).unwrap();
+
+        // one symbol names a byte in the middle of the data and one an address outside it
+        let mut symbols = SymbolTable::new();
+        symbols.insert("digits", 0x257);
+        symbols.insert("font", 0x050);
+
+        round_trip(&program, &symbols);
+    }
+}
@@ -0,0 +1,38 @@
+// Memory dumps are the bytes of memory behind a small header so they open in any hex editor with
+// the data at a fixed offset, every number is big endian:
+//   "C8MD" followed by a version byte
+//   the program counter and index when the dump was taken as u16
+//   the address of the first byte dumped and the number of bytes as u16
+//   the bytes, starting at offset 13
+
+use crate::{
+    core::{cpu::CPU, memory::RAM},
+    error::{Context, Result},
+};
+
+use std::path::Path;
+
+const MAGIC: [u8; 4] = *b"C8MD";
+
+const VERSION: u8 = 1;
+
+// stops at the end of memory so the dump may be shorter than requested
+pub fn encode(cpu: &CPU, memory: &RAM, start: u16, len: usize) -> Vec<u8> {
+    let block = memory.read_block(start, len);
+
+    let mut bytes = Vec::with_capacity(13 + block.len());
+    bytes.extend_from_slice(&MAGIC);
+    bytes.push(VERSION);
+    bytes.extend_from_slice(&cpu.prog_counter().to_be_bytes());
+    bytes.extend_from_slice(&cpu.index().to_be_bytes());
+    bytes.extend_from_slice(&start.to_be_bytes());
+    bytes.extend_from_slice(&(block.len() as u16).to_be_bytes());
+    bytes.extend_from_slice(block);
+
+    bytes
+}
+
+pub fn save(path: impl AsRef<Path>, cpu: &CPU, memory: &RAM, start: u16, len: usize) -> Result<()> {
+    std::fs::write(path.as_ref(), encode(cpu, memory, start, len))
+        .context(format!("write {}", path.as_ref().to_string_lossy()))
+}
@@ -0,0 +1,214 @@
+// Runs generated programs headless looking for any that make the interpreter panic. A program is
+// random bytes, random instructions with their addresses aimed inside the program, or one of the
+// seed roms with a few bytes flipped, replaced, inserted or removed. Each runs in a random mode
+// and resolution with random keys held until it faults, runs off the end of memory or reaches the
+// instruction limit, none of which are crashes; only a panic is.
+//
+// Everything is drawn from one seeded generator so a seed and the same seed roms give the same
+// programs, which is how a crash found by an earlier session is reproduced.
+
+use crate::{
+    core::{
+        cpu::{Instruction, Mode, Quirks},
+        machine::Machine,
+        memory::RAM_SIZE,
+        Font, Program, MAX_PROGRAM_SIZE,
+    },
+    DisplayState, Key, DISPLAY_PIXELS_HEIGHT, MAX_DISPLAY_PIXELS_HEIGHT, PROGRAM_START_ADDR,
+};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::panic::{self, AssertUnwindSafe};
+
+const MODES: [Mode; 5] = [
+    Mode::Classic,
+    Mode::Chip48,
+    Mode::Schip,
+    Mode::XoChip,
+    Mode::Modern,
+];
+
+// timers tick once for every this many instructions as they do at the default speed
+const INSTRUCTIONS_PER_TIMER_TICK: u64 = 12;
+
+#[derive(Clone, Debug)]
+pub struct FuzzConfig {
+    pub runs: u64,
+    pub instructions: u64,
+    pub seed: u64,
+    // every run picks a mode at random when none is given
+    pub mode: Option<Mode>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Crash {
+    pub run: u64,
+    pub mode: Mode,
+    pub message: String,
+    pub program: Vec<u8>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct FuzzReport {
+    pub runs: u64,
+    pub instructions: u64,
+    pub faults: u64,
+    pub crashes: Vec<Crash>,
+}
+
+pub fn run(config: &FuzzConfig, seeds: &[Program]) -> FuzzReport {
+    let mut rng = StdRng::seed_from_u64(config.seed);
+    let mut report = FuzzReport::default();
+
+    // the default hook would print every panic as it is caught, the message is kept instead
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+
+    for run in 0..config.runs {
+        let program = match rng.gen_range(0..3) {
+            0 if !seeds.is_empty() => {
+                let seed = &seeds[rng.gen_range(0..seeds.len())];
+                mutate(&mut rng, seed.data())
+            }
+            0 | 1 => random_instructions(&mut rng),
+            _ => random_bytes(&mut rng),
+        };
+        let mode = config
+            .mode
+            .clone()
+            .unwrap_or_else(|| MODES[rng.gen_range(0..MODES.len())].clone());
+        let height = [DISPLAY_PIXELS_HEIGHT, 48, MAX_DISPLAY_PIXELS_HEIGHT][rng.gen_range(0..3)];
+        let run_seed = rng.gen();
+
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            execute(&program, &mode, height, run_seed, config.instructions)
+        }));
+
+        report.runs += 1;
+        match result {
+            Ok((executed, faulted)) => {
+                report.instructions += executed;
+                report.faults += faulted as u64;
+            }
+            Err(payload) => {
+                let message = payload
+                    .downcast_ref::<&str>()
+                    .map(|s| String::from(*s))
+                    .or_else(|| payload.downcast_ref::<String>().cloned())
+                    .unwrap_or_else(|| String::from("panicked without a message"));
+
+                report.crashes.push(Crash {
+                    run,
+                    mode,
+                    message,
+                    program,
+                });
+            }
+        }
+    }
+
+    panic::set_hook(hook);
+
+    report
+}
+
+// returns how many instructions ran and whether the program faulted
+fn execute(program: &[u8], mode: &Mode, height: u8, seed: u64, instructions: u64) -> (u64, bool) {
+    let mut machine = Machine::new(Font::default());
+    let program = Program::new(String::from("fuzz"), program.to_vec())
+        .expect("generated programs always fit in memory");
+    machine.load(&program);
+    machine.display = DisplayState::with_height(height);
+
+    machine.cpu.set_quirks(Quirks::from(mode));
+    machine.cpu.seed_rng(seed);
+
+    let mut rng = StdRng::seed_from_u64(seed);
+
+    for executed in 0..instructions {
+        if machine.cpu.prog_counter() as usize + 1 >= RAM_SIZE {
+            return (executed, false);
+        }
+
+        // keys change now and then so roms waiting on one get past it
+        if rng.gen_ratio(1, 64) {
+            let key = Key::from(rng.gen_range(0..16_usize));
+            match rng.gen() {
+                true => machine.keyboard.key_pressed(key),
+                false => machine.keyboard.key_released(key),
+            }
+        }
+
+        machine.step();
+        if machine.cpu.take_fault().is_some() {
+            return (executed + 1, true);
+        }
+
+        if executed % INSTRUCTIONS_PER_TIMER_TICK == 0 {
+            machine.vblank();
+        }
+    }
+
+    (instructions, false)
+}
+
+fn random_bytes(rng: &mut StdRng) -> Vec<u8> {
+    let len = rng.gen_range(2..=MAX_PROGRAM_SIZE);
+    (0..len).map(|_| rng.gen()).collect()
+}
+
+// jumps, calls and loads of i mostly land inside the program, as they would in a real rom,
+// rather than anywhere in memory
+fn random_instructions(rng: &mut StdRng) -> Vec<u8> {
+    let count = rng.gen_range(1..=MAX_PROGRAM_SIZE / 2);
+    let end = PROGRAM_START_ADDR + count as u16 * 2;
+
+    let mut program = Vec::with_capacity(count * 2);
+    while program.len() < count * 2 {
+        let Some(instruction) = Instruction::from_op_code(rng.gen()) else {
+            continue;
+        };
+
+        let inside = rng.gen_range(PROGRAM_START_ADDR..end) & !1;
+        let instruction = match instruction {
+            Instruction::Jump { .. } if rng.gen_ratio(7, 8) => {
+                Instruction::Jump { address: inside }
+            }
+            Instruction::SubroutineCall { .. } if rng.gen_ratio(7, 8) => {
+                Instruction::SubroutineCall { address: inside }
+            }
+            Instruction::SetIndex { .. } if rng.gen_ratio(7, 8) => {
+                Instruction::SetIndex { value: inside }
+            }
+            instruction => instruction,
+        };
+
+        program.extend_from_slice(&instruction.to_op_code().to_be_bytes());
+    }
+
+    program
+}
+
+fn mutate(rng: &mut StdRng, seed: &[u8]) -> Vec<u8> {
+    let mut program = seed.to_vec();
+    program.truncate(MAX_PROGRAM_SIZE);
+
+    for _ in 0..rng.gen_range(1..=8) {
+        if program.is_empty() {
+            program.push(rng.gen());
+            continue;
+        }
+
+        let at = rng.gen_range(0..program.len());
+        match rng.gen_range(0..4) {
+            0 => program[at] ^= 1 << rng.gen_range(0..8),
+            1 => program[at] = rng.gen(),
+            2 if program.len() < MAX_PROGRAM_SIZE => program.insert(at, rng.gen()),
+            _ => {
+                program.remove(at);
+            }
+        }
+    }
+
+    program
+}
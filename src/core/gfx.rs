@@ -0,0 +1,152 @@
+// The display the cpu draws to and the font it draws digits with. A display is 64 pixels wide so
+// each row is kept as the bits of a u64 with the leftmost pixel in the top bit, the same way round
+// as the bits of a sprite byte.
+
+use crate::{
+    core::memory::RAM, DISPLAY_PIXELS_HEIGHT, DISPLAY_PIXELS_WIDTH, MAX_DISPLAY_PIXELS_HEIGHT,
+};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DisplayState {
+    #[cfg_attr(feature = "serde", serde(with = "serde_big_array::BigArray"))]
+    rows: [u64; MAX_DISPLAY_PIXELS_HEIGHT as usize],
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "deserialize_height"))]
+    height: u8,
+}
+
+// the rows are sized for the tallest display so a taller one would read past them
+#[cfg(feature = "serde")]
+fn deserialize_height<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<u8, D::Error> {
+    let height = <u8 as serde::Deserialize>::deserialize(deserializer)?;
+    if height > MAX_DISPLAY_PIXELS_HEIGHT {
+        return Err(serde::de::Error::custom(format!(
+            "invalid display height {}: expected at most {}",
+            height, MAX_DISPLAY_PIXELS_HEIGHT
+        )));
+    }
+
+    Ok(height)
+}
+
+impl DisplayState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn with_height(height: u8) -> Self {
+        Self {
+            rows: [0; MAX_DISPLAY_PIXELS_HEIGHT as usize],
+            height: u8::min(height, MAX_DISPLAY_PIXELS_HEIGHT),
+        }
+    }
+    pub fn width(&self) -> u8 {
+        DISPLAY_PIXELS_WIDTH
+    }
+    pub fn height(&self) -> u8 {
+        self.height
+    }
+    pub fn num_pixels(&self) -> u16 {
+        self.width() as u16 * self.height as u16
+    }
+    pub fn clear(&mut self) {
+        self.rows.fill(0);
+    }
+    // pixels are numbered left to right along each row from the top row down
+    pub fn read_pixel(&self, idx: u16) -> bool {
+        let (x, y) = Self::position(idx);
+        self.pixel(x, y)
+    }
+    pub fn write_pixel(&mut self, idx: u16, value: bool) {
+        let (x, y) = Self::position(idx);
+        self.set_pixel(x, y, value);
+    }
+    pub fn pixel(&self, x: u8, y: u8) -> bool {
+        self.rows[y as usize] & Self::mask(x) != 0
+    }
+    pub fn set_pixel(&mut self, x: u8, y: u8, value: bool) {
+        match value {
+            true => self.rows[y as usize] |= Self::mask(x),
+            false => self.rows[y as usize] &= !Self::mask(x),
+        }
+    }
+    pub fn row(&self, y: u8) -> u64 {
+        self.rows[y as usize]
+    }
+    pub fn set_row(&mut self, y: u8, bits: u64) {
+        self.rows[y as usize] = bits;
+    }
+    pub fn rows(&self) -> &[u64] {
+        &self.rows[..self.height as usize]
+    }
+    pub fn lit_pixels(&self) -> u32 {
+        self.rows().iter().map(|row| row.count_ones()).sum()
+    }
+
+    fn position(idx: u16) -> (u8, u8) {
+        let width = DISPLAY_PIXELS_WIDTH as u16;
+        ((idx % width) as u8, (idx / width) as u8)
+    }
+    fn mask(x: u8) -> u64 {
+        1 << (DISPLAY_PIXELS_WIDTH - 1 - x)
+    }
+}
+
+impl Default for DisplayState {
+    fn default() -> Self {
+        Self::with_height(DISPLAY_PIXELS_HEIGHT)
+    }
+}
+
+pub(crate) const FONT_START_ADDR: u16 = 0x050;
+
+// the font has a glyph for each hex digit, every glyph is four pixels wide and five tall
+pub const FONT_GLYPHS: u8 = 16;
+
+pub const GLYPH_HEIGHT: u8 = 5;
+
+pub const GLYPH_WIDTH: u8 = 4;
+
+const DEFAULT_FONT_DATA: [u8; 80] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, 0x20, 0x60, 0x20, 0x20, 0x70, 0xF0, 0x10, 0xF0, 0x80, 0xF0, 0xF0,
+    0x10, 0xF0, 0x10, 0xF0, 0x90, 0x90, 0xF0, 0x10, 0x10, 0xF0, 0x80, 0xF0, 0x10, 0xF0, 0xF0, 0x80,
+    0xF0, 0x90, 0xF0, 0xF0, 0x10, 0x20, 0x40, 0x40, 0xF0, 0x90, 0xF0, 0x90, 0xF0, 0xF0, 0x90, 0xF0,
+    0x10, 0xF0, 0xF0, 0x90, 0xF0, 0x90, 0x90, 0xE0, 0x90, 0xE0, 0x90, 0xE0, 0xF0, 0x80, 0x80, 0x80,
+    0xF0, 0xE0, 0x90, 0x90, 0x90, 0xE0, 0xF0, 0x80, 0xF0, 0x80, 0xF0, 0xF0, 0x80, 0xF0, 0x80, 0x80,
+];
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Font {
+    pub name: String,
+    #[cfg_attr(feature = "serde", serde(with = "serde_big_array::BigArray"))]
+    data: [u8; 80],
+}
+
+impl Font {
+    pub fn new(name: String, data: [u8; 80]) -> Self {
+        Self { name, data }
+    }
+    pub fn load(&self, memory: &mut RAM) {
+        memory.write_block(FONT_START_ADDR, &self.data);
+    }
+    pub fn char_addr(&self, char: u8) -> u16 {
+        FONT_START_ADDR + (GLYPH_HEIGHT as u16 * char as u16)
+    }
+    // the rows of the glyph as sprite bytes, top row first, only the low nibble picks the glyph
+    pub fn glyph(&self, char: u8) -> &[u8] {
+        let start = GLYPH_HEIGHT as usize * (char & 0xF) as usize;
+        &self.data[start..start + GLYPH_HEIGHT as usize]
+    }
+    // how many columns from the left the glyph lights, a font may draw narrower digits than the
+    // four columns the built in one uses
+    pub fn glyph_width(&self, char: u8) -> u8 {
+        let bits = self.glyph(char).iter().fold(0, |bits, row| bits | row);
+        8 - bits.trailing_zeros() as u8
+    }
+}
+
+impl Default for Font {
+    fn default() -> Self {
+        Self::new(String::from("Default"), DEFAULT_FONT_DATA)
+    }
+}
@@ -0,0 +1,372 @@
+// An experimental just in time compiler, a playground for seeing how fast the interpreter could
+// be rather than something the emulator runs on. Runs of instructions that only touch the v
+// registers and i are compiled with cranelift into a native function, anything that reads or
+// writes memory, the display, the timers, the keypad or the program counter ends the run and is
+// left to the interpreter.
+//
+// Compiled runs are keyed by their address along with the op codes they were compiled from and
+// are thrown away and compiled again when memory no longer holds those op codes, so programs that
+// modify themselves keep working. The code of a discarded run is not freed until the jit is
+// dropped.
+
+use crate::{
+    core::{
+        cpu::{Instruction, Quirks, CPU},
+        memory::{RAM, RAM_SIZE},
+    },
+    error::{Context, EmuError, Result},
+};
+
+use cranelift_codegen::{
+    ir::{condcodes::IntCC, types, AbiParam, InstBuilder, MemFlags, Value},
+    settings::{self, Configurable},
+};
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext, Variable};
+use cranelift_jit::{JITBuilder, JITModule};
+use cranelift_module::{default_libcall_names, Linkage, Module};
+
+// runs of a single instruction cost more to call into than to interpret
+const MIN_RUN_LEN: usize = 2;
+
+const MAX_RUN_LEN: usize = 64;
+
+const VF: usize = 0xF;
+
+type CompiledRun = unsafe extern "C" fn(vs: *mut u8, i: *mut u16);
+
+struct Run {
+    op_codes: Vec<u8>,
+    len: usize,
+    code: Option<CompiledRun>,
+}
+
+pub struct Jit {
+    module: JITModule,
+    builder_context: FunctionBuilderContext,
+    quirks: Quirks,
+    runs: Vec<Option<Run>>,
+    compiled: usize,
+    invalidated: usize,
+}
+
+impl Jit {
+    pub fn new(quirks: Quirks) -> Result<Self> {
+        let mut flags = settings::builder();
+        flags.set("opt_level", "speed").map_err(jit_error)?;
+        flags
+            .set("use_colocated_libcalls", "false")
+            .map_err(jit_error)?;
+        flags.set("is_pic", "true").map_err(jit_error)?;
+
+        let isa = cranelift_native::builder()
+            .map_err(|e| EmuError::Jit(format!("this machine is not supported: {}", e)))?
+            .finish(settings::Flags::new(flags))
+            .map_err(jit_error)
+            .context("create jit target")?;
+
+        Ok(Self {
+            module: JITModule::new(JITBuilder::with_isa(isa, default_libcall_names())),
+            builder_context: FunctionBuilderContext::new(),
+            quirks,
+            runs: std::iter::repeat_with(|| None).take(RAM_SIZE).collect(),
+            compiled: 0,
+            invalidated: 0,
+        })
+    }
+    // executes the run at the program counter when one is compiled there and is no longer than
+    // max instructions, returns how many instructions were executed so zero means the next
+    // instruction is for the interpreter
+    pub fn execute(&mut self, cpu: &mut CPU, memory: &RAM, max: usize) -> Result<usize> {
+        if *cpu.quirks() != self.quirks {
+            return Err(EmuError::Jit(String::from(
+                "compiled for different quirks than the cpu has",
+            )));
+        }
+
+        // the interpreter sits out the wait for the vblank
+        if cpu.is_waiting_for_vblank() {
+            return Ok(0);
+        }
+
+        let address = cpu.prog_counter();
+        let slot = address as usize % RAM_SIZE;
+
+        let run = match self.runs[slot].take() {
+            Some(run) if memory.read_block(address, run.op_codes.len()) == run.op_codes => run,
+            stale => {
+                if stale.is_some() {
+                    self.invalidated += 1;
+                }
+                self.compile(address, memory)?
+            }
+        };
+        let run = self.runs[slot].insert(run);
+        let Some(code) = run.code.filter(|_| run.len <= max) else {
+            return Ok(0);
+        };
+
+        let (vs, i) = cpu.registers_mut();
+        // the run only touches the sixteen v registers and i it is handed
+        unsafe { code(vs.as_mut_ptr(), i) };
+
+        cpu.set_prog_counter(address + 2 * run.len as u16);
+
+        Ok(run.len)
+    }
+    pub fn compiled(&self) -> usize {
+        self.compiled
+    }
+    pub fn invalidated(&self) -> usize {
+        self.invalidated
+    }
+    fn compile(&mut self, address: u16, memory: &RAM) -> Result<Run> {
+        let mut instructions = Vec::new();
+        let mut next = address as usize;
+        while instructions.len() < MAX_RUN_LEN && next + 1 < RAM_SIZE {
+            match Instruction::from_op_code(memory.read_u16(next as u16)) {
+                Some(instruction) if is_compilable(&instruction) => instructions.push(instruction),
+                _ => break,
+            }
+            next += 2;
+        }
+
+        // a run too short to compile still remembers its op codes so it is looked at again once
+        // memory changes
+        let op_codes = memory
+            .read_block(address, 2 * usize::max(instructions.len(), 1))
+            .to_vec();
+
+        if instructions.len() < MIN_RUN_LEN {
+            return Ok(Run {
+                op_codes,
+                len: instructions.len(),
+                code: None,
+            });
+        }
+
+        let code = self
+            .build(address, &instructions)
+            .context(format!("compile run at {:#05x}", address))?;
+        self.compiled += 1;
+
+        Ok(Run {
+            op_codes,
+            len: instructions.len(),
+            code: Some(code),
+        })
+    }
+    fn build(&mut self, address: u16, instructions: &[Instruction]) -> Result<CompiledRun> {
+        let pointer = self.module.target_config().pointer_type();
+
+        let mut context = self.module.make_context();
+        context.func.signature.params.push(AbiParam::new(pointer));
+        context.func.signature.params.push(AbiParam::new(pointer));
+
+        // every run gets a new name since the run it replaces keeps its code
+        let name = format!("run_{:03x}_{}", address, self.compiled);
+        let id = self
+            .module
+            .declare_function(&name, Linkage::Local, &context.func.signature)
+            .map_err(jit_error)?;
+
+        let mut builder = FunctionBuilder::new(&mut context.func, &mut self.builder_context);
+        let entry = builder.create_block();
+        builder.append_block_params_for_function_params(entry);
+        builder.switch_to_block(entry);
+        builder.seal_block(entry);
+
+        let vs_ptr = builder.block_params(entry)[0];
+        let i_ptr = builder.block_params(entry)[1];
+        let flags = MemFlags::trusted();
+
+        // the registers live in variables for the length of the run and are stored once at the end
+        let vs: Vec<Variable> = (0..16).map(Variable::from_u32).collect();
+        let i = Variable::from_u32(16);
+        for (idx, var) in vs.iter().enumerate() {
+            builder.declare_var(*var, types::I8);
+            let value = builder.ins().load(types::I8, flags, vs_ptr, idx as i32);
+            builder.def_var(*var, value);
+        }
+        builder.declare_var(i, types::I16);
+        let value = builder.ins().load(types::I16, flags, i_ptr, 0);
+        builder.def_var(i, value);
+
+        let mut emitter = Emitter {
+            builder,
+            vs,
+            i,
+            quirks: self.quirks,
+        };
+        for instruction in instructions {
+            emitter.emit(instruction);
+        }
+
+        let Emitter {
+            mut builder, vs, i, ..
+        } = emitter;
+        for (idx, var) in vs.iter().enumerate() {
+            let value = builder.use_var(*var);
+            builder.ins().store(flags, value, vs_ptr, idx as i32);
+        }
+        let value = builder.use_var(i);
+        builder.ins().store(flags, value, i_ptr, 0);
+        builder.ins().return_(&[]);
+        builder.finalize();
+
+        self.module
+            .define_function(id, &mut context)
+            .map_err(jit_error)?;
+        self.module.clear_context(&mut context);
+        self.module.finalize_definitions().map_err(jit_error)?;
+
+        let code = self.module.get_finalized_function(id);
+
+        // the signature declared above
+        Ok(unsafe { std::mem::transmute::<*const u8, CompiledRun>(code) })
+    }
+}
+
+impl std::fmt::Debug for Jit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Jit")
+            .field("quirks", &self.quirks)
+            .field("runs", &self.runs.iter().flatten().count())
+            .field("compiled", &self.compiled)
+            .field("invalidated", &self.invalidated)
+            .finish()
+    }
+}
+
+fn jit_error(e: impl std::fmt::Display) -> EmuError {
+    EmuError::Jit(e.to_string())
+}
+
+fn is_compilable(instruction: &Instruction) -> bool {
+    matches!(
+        instruction,
+        Instruction::Add { .. }
+            | Instruction::AddIndex { .. }
+            | Instruction::AddRegister { .. }
+            | Instruction::And { .. }
+            | Instruction::Or { .. }
+            | Instruction::Set { .. }
+            | Instruction::SetIndex { .. }
+            | Instruction::SetRegister { .. }
+            | Instruction::ShiftLeft { .. }
+            | Instruction::ShiftRight { .. }
+            | Instruction::Subtract { .. }
+            | Instruction::SubtractRev { .. }
+            | Instruction::Xor { .. }
+    )
+}
+
+// each instruction does exactly what the interpreter does, including the order vf is written in
+// when it is also the destination
+struct Emitter<'a> {
+    builder: FunctionBuilder<'a>,
+    vs: Vec<Variable>,
+    i: Variable,
+    quirks: Quirks,
+}
+
+impl Emitter<'_> {
+    fn emit(&mut self, instruction: &Instruction) {
+        match *instruction {
+            Instruction::Add { vx, vy } => {
+                let (x, y) = (self.v(vx), self.v(vy));
+                let sum = self.builder.ins().iadd(x, y);
+                let carry = self.builder.ins().icmp(IntCC::UnsignedLessThan, sum, x);
+                self.set_v(vx, sum);
+                self.set_v(VF, carry);
+            }
+            Instruction::AddIndex { v } => {
+                let value = self.v(v);
+                let value = self.builder.ins().uextend(types::I16, value);
+                let i = self.builder.use_var(self.i);
+                let i = self.builder.ins().iadd(i, value);
+                self.builder.def_var(self.i, i);
+
+                let past_memory =
+                    self.builder
+                        .ins()
+                        .icmp_imm(IntCC::UnsignedGreaterThanOrEqual, i, 0x1000);
+                let (one, vf) = (self.builder.ins().iconst(types::I8, 1), self.v(VF));
+                let vf = self.builder.ins().select(past_memory, one, vf);
+                self.set_v(VF, vf);
+            }
+            Instruction::AddRegister { v, value } => {
+                let x = self.v(v);
+                let sum = self.builder.ins().iadd_imm(x, value as i64);
+                self.set_v(v, sum);
+            }
+            Instruction::And { vx, vy } => {
+                let (x, y) = (self.v(vx), self.v(vy));
+                let value = self.builder.ins().band(x, y);
+                self.logic(vx, value);
+            }
+            Instruction::Or { vx, vy } => {
+                let (x, y) = (self.v(vx), self.v(vy));
+                let value = self.builder.ins().bor(x, y);
+                self.logic(vx, value);
+            }
+            Instruction::Xor { vx, vy } => {
+                let (x, y) = (self.v(vx), self.v(vy));
+                let value = self.builder.ins().bxor(x, y);
+                self.logic(vx, value);
+            }
+            Instruction::Set { v, value } => {
+                let value = self.builder.ins().iconst(types::I8, value as i64);
+                self.set_v(v, value);
+            }
+            Instruction::SetIndex { value } => {
+                let value = self.builder.ins().iconst(types::I16, value as i64);
+                self.builder.def_var(self.i, value);
+            }
+            Instruction::SetRegister { vx, vy } => {
+                let value = self.v(vy);
+                self.set_v(vx, value);
+            }
+            Instruction::ShiftLeft { vx, vy } => {
+                let value = self.v(if self.quirks.shift_uses_vy { vy } else { vx });
+                let shifted = self.builder.ins().ishl_imm(value, 1);
+                let flag = self.builder.ins().ushr_imm(value, 7);
+                self.set_v(vx, shifted);
+                self.set_v(VF, flag);
+            }
+            Instruction::ShiftRight { vx, vy } => {
+                let value = self.v(if self.quirks.shift_uses_vy { vy } else { vx });
+                let shifted = self.builder.ins().ushr_imm(value, 1);
+                let flag = self.builder.ins().band_imm(value, 1);
+                self.set_v(vx, shifted);
+                self.set_v(VF, flag);
+            }
+            Instruction::Subtract { vx, vy } => self.subtract(vx, vx, vy),
+            Instruction::SubtractRev { vx, vy } => self.subtract(vx, vy, vx),
+            _ => unreachable!("only compilable instructions are emitted"),
+        }
+    }
+    fn v(&mut self, idx: usize) -> Value {
+        self.builder.use_var(self.vs[idx])
+    }
+    fn set_v(&mut self, idx: usize, value: Value) {
+        self.builder.def_var(self.vs[idx], value);
+    }
+    fn logic(&mut self, vx: usize, value: Value) {
+        self.set_v(vx, value);
+        if self.quirks.logic_resets_vf {
+            let zero = self.builder.ins().iconst(types::I8, 0);
+            self.set_v(VF, zero);
+        }
+    }
+    // vf is set when there was no borrow
+    fn subtract(&mut self, vx: usize, minuend: usize, subtrahend: usize) {
+        let (minuend, subtrahend) = (self.v(minuend), self.v(subtrahend));
+        let value = self.builder.ins().isub(minuend, subtrahend);
+        let no_borrow =
+            self.builder
+                .ins()
+                .icmp(IntCC::UnsignedGreaterThanOrEqual, minuend, subtrahend);
+        self.set_v(vx, value);
+        self.set_v(VF, no_borrow);
+    }
+}
@@ -0,0 +1,84 @@
+use crate::{
+    core::{
+        cpu::{Draw, CPU},
+        memory::RAM,
+        Program,
+    },
+    DisplayState, Font, KeyState,
+};
+
+// everything that makes up a running chip-8 without a window, timing or sound, a frontend drives
+// it by calling step for each instruction and vblank at 60hz
+#[derive(Clone, Debug)]
+pub struct Machine {
+    pub(crate) cpu: CPU,
+    pub(crate) memory: RAM,
+    pub(crate) display: DisplayState,
+    pub(crate) keyboard: KeyState,
+    pub(crate) font: Font,
+}
+
+impl Machine {
+    pub fn new(font: Font) -> Self {
+        let mut memory = RAM::new();
+
+        font.load(&mut memory);
+        tracing::debug!("loaded {} font into memory", font.name);
+
+        Self {
+            cpu: CPU::default(),
+            memory,
+            display: DisplayState::default(),
+            keyboard: KeyState::default(),
+            font,
+        }
+    }
+    pub fn step(&mut self) -> Option<Draw> {
+        self.cpu.tick(
+            &mut self.memory,
+            &mut self.display,
+            &self.font,
+            &self.keyboard,
+        )
+    }
+    pub fn vblank(&mut self) {
+        self.cpu.vblank();
+    }
+    // the cpu keeps its quirks and symbols, memory is cleared back to just the font
+    pub fn reset(&mut self) {
+        self.cpu.reset();
+        self.memory = RAM::new();
+        self.display.clear();
+        self.keyboard.reset();
+
+        self.font.load(&mut self.memory);
+    }
+    pub fn load(&mut self, program: &Program) {
+        program.load(&mut self.memory);
+        tracing::debug!("loaded {} program into memory", program.name);
+    }
+    pub fn cpu(&self) -> &CPU {
+        &self.cpu
+    }
+    pub fn cpu_mut(&mut self) -> &mut CPU {
+        &mut self.cpu
+    }
+    pub fn memory(&self) -> &RAM {
+        &self.memory
+    }
+    pub fn memory_mut(&mut self) -> &mut RAM {
+        &mut self.memory
+    }
+    pub fn display(&self) -> &DisplayState {
+        &self.display
+    }
+    pub fn keyboard(&self) -> &KeyState {
+        &self.keyboard
+    }
+    pub fn keyboard_mut(&mut self) -> &mut KeyState {
+        &mut self.keyboard
+    }
+    pub fn font(&self) -> &Font {
+        &self.font
+    }
+}
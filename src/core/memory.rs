@@ -1,28 +1,113 @@
+use std::{
+    ops::{Bound, RangeBounds},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+pub const RAM_SIZE: usize = 4096;
+
+// shared by every instance so two memories only have the same version when one is a copy of the
+// other that has not been written to since
+static VERSION: AtomicU64 = AtomicU64::new(1);
+
+fn next_version() -> u64 {
+    VERSION.fetch_add(1, Ordering::Relaxed)
+}
+
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct RAM {
-    data: [u8; 4096],
+    #[cfg_attr(feature = "serde", serde(with = "serde_big_array::BigArray"))]
+    data: [u8; RAM_SIZE],
+    #[cfg_attr(feature = "serde", serde(skip, default = "next_version"))]
+    version: u64,
 }
 
 impl RAM {
     pub fn new() -> Self {
         Self::default()
     }
+    // addresses wrap around at the end of memory so an index register left near the end reads
+    // and writes the start of memory rather than past the end
     pub fn read(&self, address: u16) -> u8 {
-        self.data[address as usize]
+        self.data[address as usize % RAM_SIZE]
+    }
+    // big endian, which is how op codes are stored
+    pub fn read_u16(&self, address: u16) -> u16 {
+        (self.read(address) as u16) << 8 | self.read(address.wrapping_add(1)) as u16
+    }
+    // stops at the end of memory so the block may be shorter than requested
+    pub fn read_block(&self, start_addr: u16, len: usize) -> &[u8] {
+        self.view(start_addr as usize..start_addr as usize + len)
+    }
+    // the part of memory within the range, clamped to the end of memory
+    pub fn view(&self, range: impl RangeBounds<usize>) -> &[u8] {
+        let start = match range.start_bound() {
+            Bound::Included(start) => *start,
+            Bound::Excluded(start) => start + 1,
+            Bound::Unbounded => 0,
+        };
+
+        let end = match range.end_bound() {
+            Bound::Included(end) => end + 1,
+            Bound::Excluded(end) => *end,
+            Bound::Unbounded => RAM_SIZE,
+        };
+
+        let end = usize::min(end, RAM_SIZE);
+        &self.data[usize::min(start, end)..end]
+    }
+    pub fn as_slice(&self) -> &[u8] {
+        &self.data
+    }
+    pub fn iter(&self) -> impl Iterator<Item = u8> + '_ {
+        self.data.iter().copied()
+    }
+    pub fn snapshot(&self) -> RAM {
+        self.clone()
+    }
+    // every address whose byte differs, along with the byte here and the byte in other
+    pub fn diff(&self, other: &RAM) -> Vec<(u16, u8, u8)> {
+        self.data
+            .iter()
+            .zip(other.data.iter())
+            .enumerate()
+            .filter(|(_, (old, new))| old != new)
+            .map(|(address, (old, new))| (address as u16, *old, *new))
+            .collect()
+    }
+    pub fn checksum(&self) -> u32 {
+        checksum(&self.data)
+    }
+    // changes with every write, what was worked out from the contents of memory holds for as
+    // long as the version stays the same
+    pub fn version(&self) -> u64 {
+        self.version
     }
     pub fn write(&mut self, address: u16, byte: u8) {
-        self.data[address as usize] = byte;
+        self.data[address as usize % RAM_SIZE] = byte;
+        self.version = next_version();
     }
     pub fn write_block(&mut self, start_addr: u16, bytes: &[u8]) {
         let dest_start = start_addr as usize;
         let dest_end = start_addr as usize + bytes.len();
 
         self.data[dest_start..dest_end].copy_from_slice(&bytes[0..bytes.len()]);
+        self.version = next_version();
     }
 }
 
+// FNV-1a, fast and good enough to tell whether two blocks of memory are the same
+pub fn checksum(data: &[u8]) -> u32 {
+    data.iter().fold(0x811C9DC5_u32, |hash, byte| {
+        (hash ^ *byte as u32).wrapping_mul(0x01000193)
+    })
+}
+
 impl Default for RAM {
     fn default() -> Self {
-        Self { data: [0; 4096] }
+        Self {
+            data: [0; RAM_SIZE],
+            version: next_version(),
+        }
     }
 }
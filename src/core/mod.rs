@@ -1,22 +1,58 @@
-use crate::{core::memory::RAM, PROGRAM_START_ADDR};
+use crate::{
+    core::memory::{RAM, RAM_SIZE},
+    error::{bail, Context, EmuError, Result},
+    PROGRAM_START_ADDR,
+};
 
-use anyhow::Context;
 use std::path::Path;
 
+pub mod analysis;
+pub mod bench;
+pub mod builder;
+pub mod cheat;
+pub mod coverage;
 pub mod cpu;
+pub mod demo;
+pub mod disasm;
+pub mod dump;
+pub mod fuzz;
+pub mod gfx;
+#[cfg(feature = "jit")]
+pub mod jit;
+pub mod machine;
 pub mod memory;
+pub mod octo;
+pub mod patch;
+pub mod profile;
+pub mod regions;
+pub mod symbols;
+pub mod test_pattern;
+pub mod trace;
 
-#[derive(Clone, Debug)]
+pub use gfx::Font;
+
+// the most a rom can hold, everything from where programs are loaded to the end of memory
+pub const MAX_PROGRAM_SIZE: usize = RAM_SIZE - PROGRAM_START_ADDR as usize;
+
+#[derive(Clone, Debug, Default)]
 pub struct Program {
     pub name: String,
     data: Vec<u8>,
 }
 
 impl Program {
-    pub fn new(name: String, data: Vec<u8>) -> Self {
-        Self { name, data }
+    pub fn new(name: String, data: Vec<u8>) -> Result<Self> {
+        if data.len() > MAX_PROGRAM_SIZE {
+            bail!(
+                "rom is {} bytes, only {} fit in memory",
+                data.len(),
+                MAX_PROGRAM_SIZE
+            );
+        }
+
+        Ok(Self { name, data })
     }
-    pub fn from_file(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
         tracing::debug!("loading program from path: {:?}", path.as_ref());
 
         let name = path
@@ -25,46 +61,44 @@ impl Program {
             .and_then(|s| s.to_str().map(String::from))
             .unwrap_or_else(|| String::from("Unknown"));
 
-        let data = std::fs::read(path.as_ref())
-            .context(format!("read file {}", path.as_ref().to_string_lossy()))?;
+        let data = std::fs::read(path.as_ref()).map_err(|error| EmuError::RomLoad {
+            path: path.as_ref().to_string_lossy().into_owned(),
+            error,
+        })?;
 
-        Ok(Self::new(name, data))
+        Self::new(name, data)
     }
-    pub fn load(&self, memory: &mut RAM) {
-        memory.write_block(PROGRAM_START_ADDR, &self.data);
-    }
-}
+    // the patched rom keeps the name of the original
+    pub fn patch(self, path: impl AsRef<Path>) -> Result<Self> {
+        tracing::debug!("applying patch from path: {:?}", path.as_ref());
 
-const FONT_START_ADDR: u16 = 0x050;
+        let bytes = std::fs::read(path.as_ref())
+            .context(format!("read file {}", path.as_ref().to_string_lossy()))?;
+        let data = patch::apply(&self.data, &bytes)
+            .context(format!("patch {}", path.as_ref().to_string_lossy()))?;
 
-const DEFAULT_FONT_DATA: [u8; 80] = [
-    0xF0, 0x90, 0x90, 0x90, 0xF0, 0x20, 0x60, 0x20, 0x20, 0x70, 0xF0, 0x10, 0xF0, 0x80, 0xF0, 0xF0,
-    0x10, 0xF0, 0x10, 0xF0, 0x90, 0x90, 0xF0, 0x10, 0x10, 0xF0, 0x80, 0xF0, 0x10, 0xF0, 0xF0, 0x80,
-    0xF0, 0x90, 0xF0, 0xF0, 0x10, 0x20, 0x40, 0x40, 0xF0, 0x90, 0xF0, 0x90, 0xF0, 0xF0, 0x90, 0xF0,
-    0x10, 0xF0, 0xF0, 0x90, 0xF0, 0x90, 0x90, 0xE0, 0x90, 0xE0, 0x90, 0xE0, 0xF0, 0x80, 0x80, 0x80,
-    0xF0, 0xE0, 0x90, 0x90, 0x90, 0xE0, 0xF0, 0x80, 0xF0, 0x80, 0xF0, 0xF0, 0x80, 0xF0, 0x80, 0x80,
-];
+        let program = Self::new(self.name, data)
+            .context(format!("patch {}", path.as_ref().to_string_lossy()))?;
 
-#[derive(Clone, Debug)]
-pub struct Font {
-    pub name: String,
-    data: [u8; 80],
-}
+        tracing::info!("applied patch {}", path.as_ref().to_string_lossy());
 
-impl Font {
-    pub fn new(name: String, data: [u8; 80]) -> Self {
-        Self { name, data }
+        Ok(program)
     }
-    pub fn load(&self, memory: &mut RAM) {
-        memory.write_block(FONT_START_ADDR, &self.data);
+    pub fn data(&self) -> &[u8] {
+        &self.data
     }
-    pub fn char_addr(&self, char: u8) -> u16 {
-        FONT_START_ADDR + (5 * char as u16)
+    pub fn load(&self, memory: &mut RAM) {
+        memory.write_block(PROGRAM_START_ADDR, &self.data);
     }
 }
 
-impl Default for Font {
-    fn default() -> Self {
-        Self::new(String::from("Default"), DEFAULT_FONT_DATA)
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roms_larger_than_memory_are_rejected() {
+        assert!(Program::new(String::new(), vec![0; MAX_PROGRAM_SIZE]).is_ok());
+        assert!(Program::new(String::new(), vec![0; MAX_PROGRAM_SIZE + 1]).is_err());
     }
 }
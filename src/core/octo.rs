@@ -0,0 +1,706 @@
+// Assembles Octo source, the language of the Octo ide, into a program. The program starts with a
+// jump to the label named main, unless the source opens with main and it is already at the start.
+// The statements for the instructions this cpu runs are supported:
+//
+//   : name  :const name value  :alias name vx  :unpack n label  :org address  numbers as bytes
+//   clear  return  ;  jump a  :call a  name  native a  sprite vx vy n
+//   i := a  i := hex vx  i += vx  bcd vx  save vx  load vx  delay := vx  buzzer := vx
+//   vx := n  vx := vy  vx := delay  vx := key  vx := random n
+//   vx += n  vx -= n  and += -= =- |= &= ^= >>= <<= with a register
+//   if cond then  if cond begin ... else ... end  loop ... while cond ... again
+//
+// where a condition is vx followed by ==, !=, <, >, <= or >= and a number or register, or by key
+// or -key. A name on its own calls the label. The less and greater comparisons go through vf the
+// way Octo expands them. Macros, :calc, strings and the schip and xo-chip statements are not.
+
+use crate::{
+    core::{cpu::Instruction, Program, MAX_PROGRAM_SIZE},
+    error::{bail, Context, Result},
+    PROGRAM_START_ADDR,
+};
+
+use std::{collections::HashMap, path::Path};
+
+const FLAG: usize = 0xF;
+
+const OPERATORS: [&str; 9] = [":=", "+=", "-=", "=-", "|=", "&=", "^=", ">>=", "<<="];
+
+pub fn assemble(name: &str, source: &str) -> Result<Program> {
+    let tokens: Vec<Token> = source
+        .lines()
+        .enumerate()
+        .flat_map(|(idx, line)| {
+            let code = line.split('#').next().unwrap_or_default();
+            code.split_whitespace().map(move |text| Token {
+                text,
+                line: idx + 1,
+            })
+        })
+        .collect();
+
+    let mut assembler = Assembler::new(&tokens);
+    match assembler.assemble() {
+        Ok(bytes) => Program::new(String::from(name), bytes),
+        Err(Error { line, message }) => bail!("{}:{}: {}", name, line, message),
+    }
+}
+
+// the program is named after the file like one loaded from a rom
+pub fn assemble_file(path: impl AsRef<Path>) -> Result<Program> {
+    let name = path
+        .as_ref()
+        .file_name()
+        .and_then(|s| s.to_str())
+        .unwrap_or("Unknown");
+
+    let source = std::fs::read_to_string(path.as_ref())
+        .context(format!("read file {}", path.as_ref().to_string_lossy()))?;
+
+    assemble(name, &source)
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Token<'a> {
+    text: &'a str,
+    line: usize,
+}
+
+#[derive(Debug)]
+struct Error {
+    line: usize,
+    message: String,
+}
+
+// an address only known once the label it names is defined
+#[derive(Clone, Debug)]
+struct Fixup<'a> {
+    offset: usize,
+    label: Token<'a>,
+    kind: FixupKind,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum FixupKind {
+    Instruction(fn(u16) -> Instruction),
+    // v0 gets the nibble and the top of the address, v1 the rest
+    Unpack(u8),
+}
+
+// the statements opened by begin and loop that are waiting for their end and again
+#[derive(Clone, Debug)]
+enum Block {
+    If {
+        jump: usize,
+        line: usize,
+    },
+    Else {
+        jump: usize,
+        line: usize,
+    },
+    Loop {
+        start: u16,
+        breaks: Vec<usize>,
+        line: usize,
+    },
+}
+
+// instructions that skip the next one when the condition holds and when it does not, after the
+// ones that work the condition out
+#[derive(Clone, Debug)]
+struct Condition {
+    prelude: Vec<Instruction>,
+    skip_if_true: Instruction,
+    skip_if_false: Instruction,
+}
+
+struct Assembler<'a> {
+    tokens: &'a [Token<'a>],
+    pos: usize,
+    line: usize,
+    bytes: Vec<u8>,
+    labels: HashMap<&'a str, u16>,
+    constants: HashMap<&'a str, u16>,
+    aliases: HashMap<&'a str, usize>,
+    fixups: Vec<Fixup<'a>>,
+    blocks: Vec<Block>,
+}
+
+impl<'a> Assembler<'a> {
+    fn new(tokens: &'a [Token<'a>]) -> Self {
+        Self {
+            tokens,
+            pos: 0,
+            line: 1,
+            bytes: Vec::new(),
+            labels: HashMap::new(),
+            constants: HashMap::new(),
+            aliases: HashMap::new(),
+            fixups: Vec::new(),
+            blocks: Vec::new(),
+        }
+    }
+    fn assemble(&mut self) -> Result<Vec<u8>, Error> {
+        let opens_with_main = matches!(
+            self.tokens,
+            [colon, name, ..] if colon.text == ":" && name.text == "main"
+        );
+        if !opens_with_main {
+            let main = Token {
+                text: "main",
+                line: 1,
+            };
+            self.emit(Instruction::Jump { address: 0 });
+            self.fixups.push(Fixup {
+                offset: 0,
+                label: main,
+                kind: FixupKind::Instruction(|address| Instruction::Jump { address }),
+            });
+        }
+
+        while self.pos < self.tokens.len() {
+            self.statement().map_err(|message| Error {
+                line: self.line,
+                message,
+            })?;
+        }
+
+        if let Some(block) = self.blocks.last() {
+            let (line, message) = match block {
+                Block::If { line, .. } | Block::Else { line, .. } => {
+                    (*line, "begin is never closed with end")
+                }
+                Block::Loop { line, .. } => (*line, "loop is never closed with again"),
+            };
+            return Err(Error {
+                line,
+                message: String::from(message),
+            });
+        }
+
+        if self.bytes.len() > MAX_PROGRAM_SIZE {
+            return Err(Error {
+                line: self.line,
+                message: format!(
+                    "program is {} bytes, at most {} fit in memory",
+                    self.bytes.len(),
+                    MAX_PROGRAM_SIZE
+                ),
+            });
+        }
+
+        for fixup in self.fixups.iter() {
+            let Some(address) = self.labels.get(fixup.label.text) else {
+                let message = match fixup.label.text {
+                    "main" => String::from("no main label to start the program at"),
+                    label => format!("label '{}' is not defined", label),
+                };
+                return Err(Error {
+                    line: fixup.label.line,
+                    message,
+                });
+            };
+
+            match fixup.kind {
+                FixupKind::Instruction(instruction) => {
+                    let op_code = instruction(*address).to_op_code();
+                    self.bytes[fixup.offset..fixup.offset + 2]
+                        .copy_from_slice(&op_code.to_be_bytes());
+                }
+                FixupKind::Unpack(nibble) => {
+                    self.bytes[fixup.offset + 1] = nibble << 4 | (*address >> 8) as u8;
+                    self.bytes[fixup.offset + 3] = *address as u8;
+                }
+            }
+        }
+
+        Ok(std::mem::take(&mut self.bytes))
+    }
+    fn statement(&mut self) -> Result<(), String> {
+        let token = self.next()?;
+
+        match token.text {
+            ":" => {
+                let name = self.name()?;
+                if self.labels.insert(name, self.address()).is_some() {
+                    return Err(format!("label '{}' is defined more than once", name));
+                }
+            }
+            ":const" => {
+                let name = self.name()?;
+                let value = self.next()?;
+                let value = self.value(value)?;
+                self.constants.insert(name, value);
+            }
+            ":alias" => {
+                let name = self.name()?;
+                let register = self.next()?;
+                let register = self.register(register)?;
+                self.aliases.insert(name, register);
+            }
+            ":unpack" => {
+                let nibble = self.next()?;
+                let nibble = self.value(nibble)?;
+                if nibble > 0xF {
+                    return Err(format!("invalid nibble {}: expected 0 to 15", nibble));
+                }
+                let label = self.next()?;
+                self.fixups.push(Fixup {
+                    offset: self.bytes.len(),
+                    label,
+                    kind: FixupKind::Unpack(nibble as u8),
+                });
+                self.emit(Instruction::Set { v: 0, value: 0 });
+                self.emit(Instruction::Set { v: 1, value: 0 });
+            }
+            ":org" => {
+                let address = self.next()?;
+                let address = self.value(address)?;
+                if address < self.address() {
+                    return Err(format!(
+                        "can not :org back to {:#05X}, the program is already at {:#05X}",
+                        address,
+                        self.address()
+                    ));
+                }
+                self.bytes
+                    .resize((address - PROGRAM_START_ADDR) as usize, 0);
+            }
+            ":call" => {
+                let label = self.next()?;
+                self.emit_address(label, |address| Instruction::SubroutineCall { address })?;
+            }
+            "clear" => self.emit(Instruction::ClearScreen),
+            "return" | ";" => self.emit(Instruction::SubroutineReturn),
+            "jump" => {
+                let label = self.next()?;
+                self.emit_address(label, |address| Instruction::Jump { address })?;
+            }
+            "native" => {
+                let label = self.next()?;
+                self.emit_address(label, |address| Instruction::MachineLanguageRoutine {
+                    address,
+                })?;
+            }
+            "sprite" => {
+                let vx = self.next()?;
+                let vy = self.next()?;
+                let pixels = self.next()?;
+                let instruction = Instruction::Display {
+                    vx: self.register(vx)?,
+                    vy: self.register(vy)?,
+                    pixels: self.value(pixels)? as u8 & 0xF,
+                };
+                self.emit(instruction);
+            }
+            "bcd" | "save" | "load" => {
+                let v = self.next()?;
+                let v = self.register(v)?;
+                self.emit(match token.text {
+                    "bcd" => Instruction::BcdConversion { v },
+                    "save" => Instruction::Store { n: v },
+                    _ => Instruction::Load { n: v },
+                });
+            }
+            "delay" | "buzzer" => {
+                self.expect(":=")?;
+                let v = self.next()?;
+                let v = self.register(v)?;
+                self.emit(match token.text {
+                    "delay" => Instruction::DelayTimerSet { v },
+                    _ => Instruction::SoundTimerSet { v },
+                });
+            }
+            "i" => self.index()?,
+            "if" => {
+                let condition = self.condition()?;
+                let form = self.next()?;
+                self.emit_all(&condition.prelude);
+                match form.text {
+                    "then" => self.emit(condition.skip_if_false),
+                    "begin" => {
+                        self.emit(condition.skip_if_true);
+                        let jump = self.emit_jump();
+                        self.blocks.push(Block::If {
+                            jump,
+                            line: token.line,
+                        });
+                    }
+                    text => return Err(format!("expected then or begin, got '{}'", text)),
+                }
+            }
+            "else" => match self.blocks.pop() {
+                Some(Block::If { jump, line }) => {
+                    let end = self.emit_jump();
+                    self.patch_jump(jump);
+                    self.blocks.push(Block::Else { jump: end, line });
+                }
+                _ => return Err(String::from("else without if ... begin")),
+            },
+            "end" => match self.blocks.pop() {
+                Some(Block::If { jump, .. } | Block::Else { jump, .. }) => self.patch_jump(jump),
+                _ => return Err(String::from("end without if ... begin")),
+            },
+            "loop" => self.blocks.push(Block::Loop {
+                start: self.address(),
+                breaks: Vec::new(),
+                line: token.line,
+            }),
+            "while" => {
+                let condition = self.condition()?;
+                self.emit_all(&condition.prelude);
+                self.emit(condition.skip_if_true);
+                let jump = self.emit_jump();
+                match self
+                    .blocks
+                    .iter_mut()
+                    .rev()
+                    .find(|block| matches!(block, Block::Loop { .. }))
+                {
+                    Some(Block::Loop { breaks, .. }) => breaks.push(jump),
+                    _ => return Err(String::from("while outside of a loop")),
+                }
+            }
+            "again" => match self.blocks.pop() {
+                Some(Block::Loop { start, breaks, .. }) => {
+                    self.emit(Instruction::Jump { address: start });
+                    for jump in breaks {
+                        self.patch_jump(jump);
+                    }
+                }
+                _ => return Err(String::from("again without loop")),
+            },
+            text if self.is_register(text) => self.assignment(token)?,
+            text if is_number(text) || self.constants.contains_key(text) => {
+                let value = self.byte(token)?;
+                self.bytes.push(value);
+            }
+            text if OPERATORS.contains(&text) => {
+                return Err(format!("'{}' needs a register on the left", text));
+            }
+            text if text.starts_with(':') => {
+                return Err(format!("unsupported directive '{}'", text));
+            }
+            // a misspelled register would otherwise be taken for a call
+            text if self
+                .tokens
+                .get(self.pos)
+                .is_some_and(|next| OPERATORS.contains(&next.text)) =>
+            {
+                return Err(format!("expected a register, got '{}'", text));
+            }
+            _ => self.emit_address(token, |address| Instruction::SubroutineCall { address })?,
+        }
+
+        Ok(())
+    }
+    fn index(&mut self) -> Result<(), String> {
+        let op = self.next()?;
+        match op.text {
+            ":=" => {
+                let value = self.next()?;
+                if value.text == "hex" {
+                    let v = self.next()?;
+                    let v = self.register(v)?;
+                    self.emit(Instruction::LoadFontChar { v });
+                } else {
+                    self.emit_address(value, |value| Instruction::SetIndex { value })?;
+                }
+            }
+            "+=" => {
+                let v = self.next()?;
+                let v = self.register(v)?;
+                self.emit(Instruction::AddIndex { v });
+            }
+            text => return Err(format!("expected := or += after i, got '{}'", text)),
+        }
+
+        Ok(())
+    }
+    fn assignment(&mut self, target: Token<'a>) -> Result<(), String> {
+        let vx = self.register(target)?;
+        let op = self.next()?;
+        let operand = self.next()?;
+
+        let instruction = match (op.text, operand.text) {
+            (":=", "delay") => Instruction::DelayTimerLoad { v: vx },
+            (":=", "key") => Instruction::GetKey { v: vx },
+            (":=", "random") => {
+                let mask = self.next()?;
+                Instruction::Random {
+                    v: vx,
+                    value: self.byte(mask)?,
+                }
+            }
+            (op, text) if self.is_register(text) => {
+                let vy = self.register(operand)?;
+                match op {
+                    ":=" => Instruction::SetRegister { vx, vy },
+                    "+=" => Instruction::Add { vx, vy },
+                    "-=" => Instruction::Subtract { vx, vy },
+                    "=-" => Instruction::SubtractRev { vx, vy },
+                    "|=" => Instruction::Or { vx, vy },
+                    "&=" => Instruction::And { vx, vy },
+                    "^=" => Instruction::Xor { vx, vy },
+                    ">>=" => Instruction::ShiftRight { vx, vy },
+                    "<<=" => Instruction::ShiftLeft { vx, vy },
+                    _ => return Err(format!("unknown operator '{}'", op)),
+                }
+            }
+            (":=", _) => Instruction::Set {
+                v: vx,
+                value: self.byte(operand)?,
+            },
+            ("+=", _) => Instruction::AddRegister {
+                v: vx,
+                value: self.byte(operand)?,
+            },
+            // there is no instruction subtracting a number, adding its negation wraps the same
+            ("-=", _) => Instruction::AddRegister {
+                v: vx,
+                value: self.byte(operand)?.wrapping_neg(),
+            },
+            (op, _) => return Err(format!("'{}' needs a register on the right", op)),
+        };
+        self.emit(instruction);
+
+        Ok(())
+    }
+    fn condition(&mut self) -> Result<Condition, String> {
+        let left = self.next()?;
+        let v = self.register(left)?;
+        let op = self.next()?;
+
+        match op.text {
+            "key" => {
+                return Ok(Condition {
+                    prelude: Vec::new(),
+                    skip_if_true: Instruction::SkipIfKeyPressed { v },
+                    skip_if_false: Instruction::SkipIfKeyNotPressed { v },
+                })
+            }
+            "-key" => {
+                return Ok(Condition {
+                    prelude: Vec::new(),
+                    skip_if_true: Instruction::SkipIfKeyNotPressed { v },
+                    skip_if_false: Instruction::SkipIfKeyPressed { v },
+                })
+            }
+            _ => {}
+        }
+
+        let right = self.next()?;
+        let right = match self.is_register(right.text) {
+            true => Operand::Register(self.register(right)?),
+            false => Operand::Byte(self.byte(right)?),
+        };
+
+        match op.text {
+            "==" => Ok(equal(v, right)),
+            "!=" => {
+                let condition = equal(v, right);
+                Ok(Condition {
+                    prelude: condition.prelude,
+                    skip_if_true: condition.skip_if_false,
+                    skip_if_false: condition.skip_if_true,
+                })
+            }
+            "<" | ">" | "<=" | ">=" => {
+                if v == FLAG || matches!(right, Operand::Register(FLAG)) {
+                    return Err(format!("vf can not be compared with '{}'", op.text));
+                }
+
+                // vf := b, vf -= a leaves vf 1 when a <= b and 0 when a > b, the subtraction
+                // needs a in a register so a number n is compared with n - 1 the other way round
+                let (a, b, holds_when_flag) = match (op.text, right) {
+                    (">", _) => (v, right, 0),
+                    ("<=", _) => (v, right, 1),
+                    ("<", Operand::Register(vy)) => (vy, Operand::Register(v), 0),
+                    (_, Operand::Register(vy)) => (vy, Operand::Register(v), 1),
+                    (op, Operand::Byte(0)) => {
+                        return Err(format!(
+                            "'{} {} 0' is {} true",
+                            left.text,
+                            op,
+                            if op == "<" { "never" } else { "always" }
+                        ))
+                    }
+                    ("<", Operand::Byte(n)) => (v, Operand::Byte(n - 1), 1),
+                    (_, Operand::Byte(n)) => (v, Operand::Byte(n - 1), 0),
+                };
+
+                let mut condition = equal(FLAG, Operand::Byte(holds_when_flag));
+                condition.prelude = vec![
+                    match b {
+                        Operand::Register(vy) => Instruction::SetRegister { vx: FLAG, vy },
+                        Operand::Byte(value) => Instruction::Set { v: FLAG, value },
+                    },
+                    Instruction::Subtract { vx: FLAG, vy: a },
+                ];
+                Ok(condition)
+            }
+            text => Err(format!("unknown comparison '{}'", text)),
+        }
+    }
+    fn register(&self, token: Token) -> Result<usize, String> {
+        if let Some(register) = self.aliases.get(token.text) {
+            return Ok(*register);
+        }
+
+        token
+            .text
+            .strip_prefix(['v', 'V'])
+            .filter(|digit| digit.len() == 1)
+            .and_then(|digit| usize::from_str_radix(digit, 16).ok())
+            .ok_or_else(|| format!("expected a register, got '{}'", token.text))
+    }
+    fn is_register(&self, text: &str) -> bool {
+        self.register(Token { text, line: 0 }).is_ok()
+    }
+    fn value(&self, token: Token) -> Result<u16, String> {
+        if let Some(value) = self.constants.get(token.text) {
+            return Ok(*value);
+        }
+        if let Some(address) = self.labels.get(token.text) {
+            return Ok(*address);
+        }
+
+        parse_number(token.text)
+            .map(|n| n as u16)
+            .ok_or_else(|| format!("unknown name '{}'", token.text))
+    }
+    // negative numbers are the two's complement byte
+    fn byte(&self, token: Token) -> Result<u8, String> {
+        let value = match parse_number(token.text) {
+            Some(n) => n,
+            None => self.value(token)? as i32,
+        };
+
+        match value {
+            -128..=255 => Ok(value as u8),
+            _ => Err(format!("invalid byte {}: expected -128 to 255", value)),
+        }
+    }
+    // a name that has not been seen yet, labels may be used before they are defined
+    fn name(&mut self) -> Result<&'a str, String> {
+        let token = self.next()?;
+        if self.is_register(token.text) || is_number(token.text) {
+            return Err(format!("'{}' can not be used as a name", token.text));
+        }
+
+        Ok(token.text)
+    }
+    fn next(&mut self) -> Result<Token<'a>, String> {
+        let token = self
+            .tokens
+            .get(self.pos)
+            .copied()
+            .ok_or_else(|| String::from("source ends in the middle of a statement"))?;
+        self.pos += 1;
+        self.line = token.line;
+
+        Ok(token)
+    }
+    fn expect(&mut self, text: &str) -> Result<(), String> {
+        let token = self.next()?;
+        match token.text == text {
+            true => Ok(()),
+            false => Err(format!("expected '{}', got '{}'", text, token.text)),
+        }
+    }
+    fn address(&self) -> u16 {
+        PROGRAM_START_ADDR + self.bytes.len() as u16
+    }
+    fn emit(&mut self, instruction: Instruction) {
+        self.bytes
+            .extend_from_slice(&instruction.to_op_code().to_be_bytes());
+    }
+    fn emit_all(&mut self, instructions: &[Instruction]) {
+        for instruction in instructions {
+            self.emit(instruction.clone());
+        }
+    }
+    // numbers and constants are used as they are, labels are filled in once they are all known
+    fn emit_address(
+        &mut self,
+        token: Token<'a>,
+        instruction: fn(u16) -> Instruction,
+    ) -> Result<(), String> {
+        let address = match self.constants.get(token.text) {
+            Some(value) => Some(*value as i32),
+            None => parse_number(token.text),
+        };
+
+        match address {
+            Some(address @ 0..=0xFFF) => self.emit(instruction(address as u16)),
+            Some(address) => {
+                return Err(format!("invalid address {}: expected 0 to 0xFFF", address))
+            }
+            None => {
+                self.fixups.push(Fixup {
+                    offset: self.bytes.len(),
+                    label: token,
+                    kind: FixupKind::Instruction(instruction),
+                });
+                self.emit(instruction(0));
+            }
+        }
+
+        Ok(())
+    }
+    // a jump whose address is patched in once the end of its block is reached
+    fn emit_jump(&mut self) -> usize {
+        let offset = self.bytes.len();
+        self.emit(Instruction::Jump { address: 0 });
+        offset
+    }
+    fn patch_jump(&mut self, offset: usize) {
+        let op_code = Instruction::Jump {
+            address: self.address(),
+        }
+        .to_op_code();
+        self.bytes[offset..offset + 2].copy_from_slice(&op_code.to_be_bytes());
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Operand {
+    Register(usize),
+    Byte(u8),
+}
+
+fn equal(v: usize, right: Operand) -> Condition {
+    match right {
+        Operand::Register(vy) => Condition {
+            prelude: Vec::new(),
+            skip_if_true: Instruction::SkipEqualReg { vx: v, vy },
+            skip_if_false: Instruction::SkipNotEqualReg { vx: v, vy },
+        },
+        Operand::Byte(value) => Condition {
+            prelude: Vec::new(),
+            skip_if_true: Instruction::SkipEqual { v, value },
+            skip_if_false: Instruction::SkipNotEqual { v, value },
+        },
+    }
+}
+
+fn is_number(text: &str) -> bool {
+    text.trim_start_matches('-')
+        .starts_with(|c: char| c.is_ascii_digit())
+}
+
+fn parse_number(text: &str) -> Option<i32> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text),
+    };
+
+    let value = if let Some(hex) = digits.strip_prefix("0x") {
+        i32::from_str_radix(hex, 16).ok()?
+    } else if let Some(binary) = digits.strip_prefix("0b") {
+        i32::from_str_radix(binary, 2).ok()?
+    } else {
+        digits.parse().ok()?
+    };
+
+    Some(if negative { -value } else { value })
+}
@@ -0,0 +1,335 @@
+// Applies the two patch formats rom hacks are usually distributed in, told apart by their magic:
+//   IPS  "PATCH", then records of a u24 offset and u16 length followed by that many bytes, or a
+//        zero length followed by a u16 count and a byte to repeat, until "EOF" and an optional
+//        u24 length to truncate the rom to, numbers are big endian
+//   BPS  "BPS1", then variable length numbers for the source, target and metadata sizes, the
+//        metadata, the actions building the target and the crc32 of the source, target and patch
+//
+// BPS patches carry the checksum of the rom they were made for so applying one to the wrong rom
+// fails instead of producing garbage, IPS patches have no way to tell.
+
+use crate::{
+    core::MAX_PROGRAM_SIZE,
+    error::{bail, Context, Result},
+};
+
+const IPS_MAGIC: &[u8] = b"PATCH";
+
+const IPS_END: &[u8] = b"EOF";
+
+const BPS_MAGIC: &[u8] = b"BPS1";
+
+pub fn apply(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>> {
+    if let Some(records) = patch.strip_prefix(IPS_MAGIC) {
+        apply_ips(rom, records).context("apply ips patch")
+    } else if patch.starts_with(BPS_MAGIC) {
+        apply_bps(rom, patch).context("apply bps patch")
+    } else {
+        bail!("not an ips or bps patch")
+    }
+}
+
+fn apply_ips(rom: &[u8], records: &[u8]) -> Result<Vec<u8>> {
+    let mut target = rom.to_vec();
+    let mut reader = Reader { bytes: records };
+
+    loop {
+        let offset = reader.take(3)?;
+        if offset == IPS_END {
+            break;
+        }
+
+        let offset = u32::from_be_bytes([0, offset[0], offset[1], offset[2]]) as usize;
+        let bytes = match reader.u16()? {
+            0 => {
+                let count = reader.u16()? as usize;
+                vec![reader.take(1)?[0]; count]
+            }
+            len => reader.take(len as usize)?.to_vec(),
+        };
+
+        // records past the end of the rom grow it
+        let end = offset + bytes.len();
+        if target.len() < end {
+            target.resize(end, 0);
+        }
+        target[offset..end].copy_from_slice(&bytes);
+    }
+
+    if let Ok(len) = reader.take(3) {
+        target.truncate(u32::from_be_bytes([0, len[0], len[1], len[2]]) as usize);
+    }
+
+    Ok(target)
+}
+
+fn apply_bps(source: &[u8], patch: &[u8]) -> Result<Vec<u8>> {
+    if patch.len() < BPS_MAGIC.len() + 12 {
+        bail!("patch is truncated");
+    }
+
+    let (body, footer) = patch.split_at(patch.len() - 12);
+    let footer_u32 =
+        |idx: usize| u32::from_le_bytes(footer[idx * 4..idx * 4 + 4].try_into().unwrap());
+
+    if crc32(&patch[..patch.len() - 4]) != footer_u32(2) {
+        bail!("patch is corrupt, its checksum does not match");
+    }
+    if crc32(source) != footer_u32(0) {
+        bail!("patch is for a different rom, the rom checksum does not match");
+    }
+
+    let mut reader = Reader {
+        bytes: &body[BPS_MAGIC.len()..],
+    };
+
+    let source_size = reader.number()?;
+    let target_size = reader.number()?;
+    let metadata_size = reader.number()?;
+    reader.take(metadata_size)?;
+
+    if source_size != source.len() {
+        bail!("patch is for a different rom, the rom size does not match");
+    }
+    // checked before anything is allocated for it
+    if target_size > MAX_PROGRAM_SIZE {
+        bail!(
+            "patched rom would be {} bytes, only {} fit in memory",
+            target_size,
+            MAX_PROGRAM_SIZE
+        );
+    }
+
+    let mut target = Vec::with_capacity(target_size);
+    let mut source_offset = 0;
+    let mut target_offset = 0;
+
+    while !reader.bytes.is_empty() {
+        let action = reader.number()?;
+        let len = (action >> 2) + 1;
+
+        match action & 3 {
+            // source read, the bytes at the same offset in the rom
+            0 => {
+                let start = target.len();
+                let bytes = start
+                    .checked_add(len)
+                    .and_then(|end| source.get(start..end))
+                    .context("patch reads past the end of the rom")?;
+                target.extend_from_slice(bytes);
+            }
+            // target read, bytes stored in the patch
+            1 => target.extend_from_slice(reader.take(len)?),
+            // source copy, bytes from anywhere in the rom
+            2 => {
+                source_offset = reader.offset(source_offset)?;
+                let bytes = source_offset
+                    .checked_add(len)
+                    .and_then(|end| source.get(source_offset..end))
+                    .context("patch copies past the end of the rom")?;
+                target.extend_from_slice(bytes);
+                source_offset += len;
+            }
+            // target copy, bytes already written which may overlap the bytes being written
+            _ => {
+                target_offset = reader.offset(target_offset)?;
+                for _ in 0..len {
+                    if target.len() >= target_size {
+                        bail!("patch writes past the size of the patched rom");
+                    }
+
+                    let byte = *target
+                        .get(target_offset)
+                        .context("patch copies past the end of the output")?;
+                    target.push(byte);
+                    target_offset += 1;
+                }
+            }
+        }
+
+        if target.len() > target_size {
+            bail!("patch writes past the size of the patched rom");
+        }
+    }
+
+    if target.len() != target_size || crc32(&target) != footer_u32(1) {
+        bail!("patched rom does not match the checksum in the patch");
+    }
+
+    Ok(target)
+}
+
+// the crc32 used by zip and png, which bps uses for its checksums
+fn crc32(data: &[u8]) -> u32 {
+    let crc = data.iter().fold(0xFFFFFFFF_u32, |crc, byte| {
+        (0..8).fold(crc ^ *byte as u32, |crc, _| {
+            (crc >> 1) ^ (0xEDB88320 & (crc & 1).wrapping_neg())
+        })
+    });
+
+    !crc
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.bytes.len() < len {
+            bail!("patch is truncated");
+        }
+
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+
+        Ok(taken)
+    }
+    fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_be_bytes(self.take(2)?.try_into()?))
+    }
+    // seven bits a byte, least significant first, with the high bit marking the last byte and
+    // each continuation adding one so every number has a single encoding
+    fn number(&mut self) -> Result<usize> {
+        let mut number: usize = 0;
+        let mut shift: usize = 1;
+
+        loop {
+            let byte = self.take(1)?[0];
+            number = (byte as usize & 0x7F)
+                .checked_mul(shift)
+                .and_then(|n| n.checked_add(number))
+                .context("patch number is too large")?;
+
+            if byte & 0x80 != 0 {
+                return Ok(number);
+            }
+
+            shift = shift
+                .checked_mul(0x80)
+                .context("patch number is too large")?;
+            number = number
+                .checked_add(shift)
+                .context("patch number is too large")?;
+        }
+    }
+    // copies move relative to where the last one of the same kind ended, the lowest bit is the sign
+    fn offset(&mut self, from: usize) -> Result<usize> {
+        let data = self.number()?;
+        let delta = data >> 1;
+
+        let offset = if data & 1 == 1 {
+            from.checked_sub(delta)
+        } else {
+            from.checked_add(delta)
+        };
+
+        offset.context("patch copies from before the start")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn number(mut value: usize) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        loop {
+            let low = (value & 0x7F) as u8;
+            value >>= 7;
+            if value == 0 {
+                bytes.push(low | 0x80);
+                return bytes;
+            }
+            bytes.push(low);
+            value -= 1;
+        }
+    }
+
+    // a source read of the common prefix followed by a target read of the rest
+    fn bps(source: &[u8], target: &[u8], target_size: usize) -> Vec<u8> {
+        let prefix = source
+            .iter()
+            .zip(target)
+            .take_while(|(a, b)| a == b)
+            .count();
+
+        let mut patch = BPS_MAGIC.to_vec();
+        patch.extend(number(source.len()));
+        patch.extend(number(target_size));
+        patch.extend(number(0));
+        if prefix > 0 {
+            patch.extend(number((prefix - 1) << 2));
+        }
+        if target.len() > prefix {
+            patch.extend(number(((target.len() - prefix - 1) << 2) | 1));
+            patch.extend_from_slice(&target[prefix..]);
+        }
+        patch.extend(crc32(source).to_le_bytes());
+        patch.extend(crc32(target).to_le_bytes());
+        patch.extend(crc32(&patch).to_le_bytes());
+
+        patch
+    }
+
+    #[test]
+    fn ips_records_write_grow_and_truncate() {
+        let rom = [1, 2, 3, 4];
+
+        let mut patch = IPS_MAGIC.to_vec();
+        patch.extend([0, 0, 1, 0, 1, 9]);
+        patch.extend([0, 0, 5, 0, 0, 0, 2, 7]);
+        patch.extend(IPS_END);
+        assert_eq!(apply(&rom, &patch).unwrap(), vec![1, 9, 3, 4, 0, 7, 7]);
+
+        patch.extend([0, 0, 3]);
+        assert_eq!(apply(&rom, &patch).unwrap(), vec![1, 9, 3]);
+    }
+
+    #[test]
+    fn bps_round_trips() {
+        let source = [0x60, 0x01, 0x61, 0x02, 0x12, 0x00];
+        let target = [0x60, 0x01, 0x61, 0x03, 0x12, 0x00, 0x00, 0xE0];
+
+        let patch = bps(&source, &target, target.len());
+        assert_eq!(apply(&source, &patch).unwrap(), target);
+    }
+
+    #[test]
+    fn bps_with_a_bad_checksum_is_rejected() {
+        let source = [0x60, 0x01];
+        let mut patch = bps(&source, &[0x60, 0x02], 2);
+        let last = patch.len() - 1;
+        patch[last] ^= 0xFF;
+
+        assert!(apply(&source, &patch).is_err());
+        assert!(apply(&[0x60, 0x03], &bps(&source, &[0x60, 0x02], 2)).is_err());
+    }
+
+    #[test]
+    fn bps_target_larger_than_memory_is_rejected() {
+        let source = [0x60, 0x01];
+
+        assert!(apply(&source, &bps(&source, &[0x60, 0x02], 1 << 60)).is_err());
+        assert!(apply(&source, &bps(&source, &[0x60, 0x02], MAX_PROGRAM_SIZE + 1)).is_err());
+    }
+
+    #[test]
+    fn bps_target_copy_stops_at_the_target_size() {
+        let source = [0x60];
+        let target = [0x60, 0x60];
+
+        let mut patch = BPS_MAGIC.to_vec();
+        patch.extend(number(source.len()));
+        patch.extend(number(target.len()));
+        patch.extend(number(0));
+        patch.extend(number(0));
+        patch.extend(number(((1 << 40) << 2) | 3));
+        patch.extend(number(0));
+        patch.extend(crc32(&source).to_le_bytes());
+        patch.extend(crc32(&target).to_le_bytes());
+        patch.extend(crc32(&patch).to_le_bytes());
+
+        assert!(apply(&source, &patch).is_err());
+    }
+}
@@ -0,0 +1,162 @@
+use crate::{
+    core::{
+        cpu::Mode,
+        disasm::{read_op_code, trace_code},
+        Program,
+    },
+    Resolution, PROGRAM_START_ADDR,
+};
+
+use std::{collections::BTreeSet, str::FromStr};
+
+// a platform the rom was written for, expanding to the quirks, display and speed it had
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Profile {
+    Vip,
+    Chip48,
+    Schip,
+    XoChip,
+    #[default]
+    Modern,
+}
+
+impl Profile {
+    pub fn mode(&self) -> Mode {
+        match self {
+            Profile::Vip => Mode::Classic,
+            Profile::Chip48 => Mode::Chip48,
+            Profile::Schip => Mode::Schip,
+            Profile::XoChip => Mode::XoChip,
+            Profile::Modern => Mode::Modern,
+        }
+    }
+    pub fn instructions_per_sec(&self) -> u16 {
+        match self {
+            Profile::Vip => 540,
+            Profile::Chip48 => 900,
+            Profile::Schip => 1800,
+            Profile::XoChip => 3000,
+            Profile::Modern => 700,
+        }
+    }
+    // only the vip had the hi-res interpreters so only it detects their roms, the others always
+    // use the standard display until their own display modes are supported
+    pub fn resolution(&self) -> Option<Resolution> {
+        match self {
+            Profile::Vip | Profile::Modern => None,
+            _ => Some(Resolution::Standard),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Detection {
+    pub profile: Profile,
+    pub reason: String,
+}
+
+impl Profile {
+    // guesses the platform from instructions only some of them have and from habits of programs
+    // written for them, none means nothing in the rom gave it away
+    pub fn detect(program: &Program) -> Option<Detection> {
+        let data = program.data();
+        let detection = |profile, reason: String| Some(Detection { profile, reason });
+
+        if data.starts_with(&[0x12, 0x60]) {
+            return detection(
+                Profile::Vip,
+                String::from("starts by jumping into the vip hi-res interpreter"),
+            );
+        }
+
+        // tracing stops at op codes the cpu does not decode, which is where the extended
+        // instructions are, so the instruction after every traced one is checked as well
+        let code = trace_code(data, PROGRAM_START_ADDR);
+        let addresses: BTreeSet<u16> = code.iter().flat_map(|a| [*a, a + 2]).collect();
+        let op_codes: Vec<(u16, u16)> = addresses
+            .into_iter()
+            .map(|address| ((address - PROGRAM_START_ADDR) as usize, address))
+            .filter(|(offset, _)| offset + 1 < data.len())
+            .map(|(offset, address)| (address, read_op_code(data, offset)))
+            .collect();
+
+        let xochip = op_codes.iter().find(|(_, op)| {
+            *op == 0xF000
+                || *op == 0xF002
+                || matches!(op & 0xF00F, 0x5002 | 0x5003)
+                || op & 0xF0FF == 0xF001
+                || op & 0xFFF0 == 0x00D0
+        });
+        if let Some((address, op)) = xochip {
+            return detection(
+                Profile::XoChip,
+                format!("xo-chip instruction {:04x} at {:#05x}", op, address),
+            );
+        }
+
+        let schip = op_codes.iter().find(|(_, op)| {
+            matches!(op, 0x00FB..=0x00FF)
+                || op & 0xFFF0 == 0x00C0
+                || matches!(op & 0xF0FF, 0xF030 | 0xF075 | 0xF085)
+                || op & 0xF00F == 0xD000
+        });
+        if let Some((address, op)) = schip {
+            return detection(
+                Profile::Schip,
+                format!("super-chip instruction {:04x} at {:#05x}", op, address),
+            );
+        }
+
+        // BNNN adds v0 on the vip but BXNN adds vx on the chip-48, a program that never sets v0
+        // can only mean the latter
+        let sets_v0 = op_codes.iter().any(|(_, op)| op & 0xFF00 == 0x6000);
+        let jump = op_codes
+            .iter()
+            .find(|(_, op)| op & 0xF000 == 0xB000 && op & 0x0F00 != 0);
+        if let Some((address, op)) = jump.filter(|_| !sets_v0) {
+            return detection(
+                Profile::Chip48,
+                format!(
+                    "{:04x} at {:#05x} jumps with vx while v0 is never set",
+                    op, address
+                ),
+            );
+        }
+
+        // back to back loads or stores only work when each one moves the index past what it
+        // touched, which only the vip did
+        let is_load_store = |op: u16| matches!(op & 0xF0FF, 0xF055 | 0xF065);
+        let loop_start = op_codes.windows(2).find(|pair| {
+            pair[1].0 == pair[0].0 + 2 && is_load_store(pair[0].1) && is_load_store(pair[1].1)
+        });
+        if let Some([(address, _), _]) = loop_start {
+            return detection(
+                Profile::Vip,
+                format!(
+                    "consecutive loads or stores at {:#05x} rely on the index advancing",
+                    address
+                ),
+            );
+        }
+
+        None
+    }
+}
+
+impl FromStr for Profile {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "vip" => Ok(Profile::Vip),
+            "chip48" => Ok(Profile::Chip48),
+            "schip" => Ok(Profile::Schip),
+            "xochip" => Ok(Profile::XoChip),
+            "modern" => Ok(Profile::Modern),
+            _ => Err(format!(
+                "invalid profile '{}': expected vip, chip48, schip, xochip or modern",
+                s
+            )),
+        }
+    }
+}
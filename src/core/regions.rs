@@ -0,0 +1,164 @@
+// Named ranges of memory so the debugger and disassembler can say what an address is part of. The
+// font and the loaded program are always known, a regions file adds ranges of its own one to a
+// line as a name and the first and last address, which are included:
+//
+//   # anything after a # or ; is a comment
+//   sprites 0x300 0x33f
+//   level-map = 0x340-0x3bf
+//
+// An address inside more than one region belongs to the smallest, so a region of the file within
+// the program is named rather than the program.
+
+use crate::{
+    core::{
+        gfx::{FONT_GLYPHS, FONT_START_ADDR, GLYPH_HEIGHT},
+        memory::RAM_SIZE,
+    },
+    error::{bail, Context, Result},
+    PROGRAM_START_ADDR,
+};
+
+use std::path::Path;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Region {
+    pub name: String,
+    pub start: u16,
+    pub end: u16,
+}
+
+impl Region {
+    pub fn contains(&self, address: u16) -> bool {
+        (self.start..=self.end).contains(&address)
+    }
+    fn len(&self) -> u16 {
+        self.end - self.start
+    }
+}
+
+impl std::fmt::Display for Region {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {:#05x}-{:#05x}", self.name, self.start, self.end)
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RegionMap {
+    regions: Vec<Region>,
+}
+
+impl RegionMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    // the font and a program of the given length, nothing is loaded at zero
+    pub fn builtin(program_len: usize) -> Self {
+        let mut regions = Self::new();
+
+        let font_len = FONT_GLYPHS as u16 * GLYPH_HEIGHT as u16;
+        regions.insert("font", FONT_START_ADDR, FONT_START_ADDR + font_len - 1);
+
+        let program_len = program_len.min(RAM_SIZE - PROGRAM_START_ADDR as usize) as u16;
+        if program_len > 0 {
+            regions.insert(
+                "program",
+                PROGRAM_START_ADDR,
+                PROGRAM_START_ADDR + program_len - 1,
+            );
+        }
+
+        regions
+    }
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        tracing::debug!("loading regions from path: {:?}", path.as_ref());
+
+        let text = std::fs::read_to_string(path.as_ref())
+            .context(format!("read file {}", path.as_ref().to_string_lossy()))?;
+
+        Self::parse(&text)
+    }
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut regions = Self::new();
+
+        for (idx, line) in text.lines().enumerate() {
+            let line = line.split(['#', ';']).next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let parts: Vec<&str> = line
+                .split(|c: char| c.is_whitespace() || c == '=')
+                .filter(|p| !p.is_empty())
+                .collect();
+
+            let (name, start, end) = match parts.as_slice() {
+                [name, range] => match range.split_once('-') {
+                    Some((start, end)) => (*name, start, end),
+                    None => bail!("invalid region on line {}: {}", idx + 1, line),
+                },
+                [name, start, end] => (*name, *start, *end),
+                _ => bail!("invalid region on line {}: {}", idx + 1, line),
+            };
+
+            match (parse_address(start), parse_address(end)) {
+                (Some(start), Some(end)) if start <= end && (end as usize) < RAM_SIZE => {
+                    regions.insert(name, start, end)
+                }
+                _ => bail!(
+                    "invalid region on line {}: {}, expected a name and the first and last address",
+                    idx + 1,
+                    line
+                ),
+            }
+        }
+
+        Ok(regions)
+    }
+    // a region with the same name is replaced
+    pub fn insert(&mut self, name: &str, start: u16, end: u16) {
+        self.regions.retain(|region| region.name != name);
+        self.regions.push(Region {
+            name: String::from(name),
+            start,
+            end,
+        });
+        self.regions.sort_by_key(|region| region.start);
+    }
+    pub fn merge(&mut self, other: &RegionMap) {
+        for region in &other.regions {
+            self.insert(&region.name, region.start, region.end);
+        }
+    }
+    pub fn is_empty(&self) -> bool {
+        self.regions.is_empty()
+    }
+    pub fn iter(&self) -> impl Iterator<Item = &Region> {
+        self.regions.iter()
+    }
+    pub fn find(&self, address: u16) -> Option<&Region> {
+        self.regions
+            .iter()
+            .filter(|region| region.contains(address))
+            .min_by_key(|region| region.len())
+    }
+    // the name of the region with how far into it the address is, e.g. sprites+0x04
+    pub fn describe(&self, address: u16) -> Option<String> {
+        self.find(address)
+            .map(|region| match address - region.start {
+                0 => region.name.clone(),
+                offset => format!("{}+{:#04x}", region.name, offset),
+            })
+    }
+}
+
+fn parse_address(value: &str) -> Option<u16> {
+    match value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+    {
+        Some(digits) => u16::from_str_radix(digits, 16).ok(),
+        None => value.parse::<u16>().ok(),
+    }
+}
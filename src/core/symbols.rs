@@ -0,0 +1,108 @@
+use crate::error::{bail, Context, Result};
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::Path,
+};
+
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SymbolTable {
+    names: BTreeMap<u16, String>,
+    addresses: HashMap<String, u16>,
+}
+
+impl SymbolTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        tracing::debug!("loading symbols from path: {:?}", path.as_ref());
+
+        let text = std::fs::read_to_string(path.as_ref())
+            .context(format!("read file {}", path.as_ref().to_string_lossy()))?;
+
+        Self::parse(&text)
+    }
+    // accepts Octo style `name = 0x2A4` lines as well as whitespace separated `name 0x2A4` or
+    // `0x2A4 name` pairs, addresses are either 0x prefixed hex or decimal and anything after a
+    // `#` or `;` is treated as a comment
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut symbols = Self::new();
+
+        for (idx, line) in text.lines().enumerate() {
+            let line = line.split(['#', ';']).next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let parts: Vec<&str> = line
+                .split(|c: char| c.is_whitespace() || c == '=' || c == ':')
+                .filter(|p| !p.is_empty())
+                .collect();
+
+            let (name, address) = match parts.as_slice() {
+                [a, b] => match (parse_symbol_address(a), parse_symbol_address(b)) {
+                    (None, Some(address)) => (*a, address),
+                    (Some(address), None) => (*b, address),
+                    _ => bail!("invalid symbol on line {}: {}", idx + 1, line),
+                },
+                _ => bail!("invalid symbol on line {}: {}", idx + 1, line),
+            };
+
+            symbols.insert(name, address);
+        }
+
+        Ok(symbols)
+    }
+    pub fn insert(&mut self, name: &str, address: u16) {
+        if let Some(previous) = self.names.insert(address, String::from(name)) {
+            self.addresses.remove(&previous);
+        }
+
+        self.addresses.insert(String::from(name), address);
+    }
+    pub fn merge(&mut self, other: &SymbolTable) {
+        for (address, name) in &other.names {
+            self.insert(name, *address);
+        }
+    }
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+    pub fn name(&self, address: u16) -> Option<&str> {
+        self.names.get(&address).map(String::as_str)
+    }
+    pub fn address(&self, name: &str) -> Option<u16> {
+        self.addresses.get(name).copied()
+    }
+    // resolves either a symbol name or a hex address, with or without the 0x prefix
+    pub fn resolve(&self, value: &str) -> Option<u16> {
+        self.address(value).or_else(|| parse_number(value))
+    }
+    pub fn format_address(&self, address: u16) -> String {
+        match self.name(address) {
+            Some(name) => format!("{} ({:#05x})", name, address),
+            None => format!("{:#05x}", address),
+        }
+    }
+}
+
+fn parse_symbol_address(value: &str) -> Option<u16> {
+    match value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+    {
+        Some(digits) => u16::from_str_radix(digits, 16).ok(),
+        None => value.parse::<u16>().ok(),
+    }
+}
+
+fn parse_number(value: &str) -> Option<u16> {
+    let digits = value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+        .unwrap_or(value);
+
+    u16::from_str_radix(digits, 16).ok()
+}
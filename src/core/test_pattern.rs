@@ -0,0 +1,211 @@
+use crate::{
+    core::{cpu::Instruction, Program},
+    DISPLAY_PIXELS_WIDTH, PROGRAM_START_ADDR,
+};
+
+// frames each still pattern is shown for
+const HOLD_FRAMES: u8 = 120;
+
+const CHECKER: [u8; 8] = [0xAA, 0x55, 0xAA, 0x55, 0xAA, 0x55, 0xAA, 0x55];
+
+const BOX: [u8; 8] = [0xFF, 0x81, 0xBD, 0xA5, 0xA5, 0xBD, 0x81, 0xFF];
+
+const LINE: [u8; 1] = [0xFF];
+
+const LEFT_EDGE: [u8; 8] = [0x80; 8];
+
+const RIGHT_EDGE: [u8; 8] = [0x01; 8];
+
+const KEY_CAP: [u8; 7] = [0xFF; 7];
+
+// keys in the order they appear on the cosmac vip keypad
+const KEYPAD_LAYOUT: [[u8; 4]; 4] = [
+    [0x1, 0x2, 0x3, 0xC],
+    [0x4, 0x5, 0x6, 0xD],
+    [0x7, 0x8, 0x9, 0xE],
+    [0xA, 0x0, 0xB, 0xF],
+];
+
+// frames the sound plays for, frames of silence after it and how often that repeats, from single
+// frame clicks through to a long tone and rapid toggling
+const SOUND_PATTERNS: [(u8, u8, u8); 4] = [(1, 29, 4), (10, 30, 4), (60, 60, 1), (2, 2, 15)];
+
+// the keypad is redrawn from scratch every frame so it has to be drawn well within one frame
+pub const KEYPAD_INSTRUCTIONS_PER_SEC: u16 = 30_000;
+
+// builds a rom that loops through a checkerboard, a border around the display and a box moving
+// corner to corner, the rom is generated for the display height since it fills the whole display
+pub fn display(height: u8) -> Program {
+    let mut data = Vec::new();
+    let mut sprite = |bytes: &[u8]| {
+        let address = PROGRAM_START_ADDR + 2 + data.len() as u16;
+        data.extend_from_slice(bytes);
+        address
+    };
+
+    let checker = sprite(&CHECKER);
+    let boxed = sprite(&BOX);
+    let line = sprite(&LINE);
+    let left_edge = sprite(&LEFT_EDGE);
+    let right_edge = sprite(&RIGHT_EDGE);
+
+    let start = PROGRAM_START_ADDR + 2 + data.len() as u16;
+
+    let mut code = vec![
+        Instruction::ClearScreen,
+        Instruction::SetIndex { value: checker },
+    ];
+    for y in (0..height).step_by(8) {
+        for x in (0..DISPLAY_PIXELS_WIDTH).step_by(8) {
+            code.extend(draw(x, y, 8));
+        }
+    }
+    code.extend(wait(start, &code, HOLD_FRAMES));
+
+    code.extend([
+        Instruction::ClearScreen,
+        Instruction::SetIndex { value: line },
+    ]);
+    for x in (0..DISPLAY_PIXELS_WIDTH).step_by(8) {
+        code.extend(draw(x, 0, 1));
+        code.extend(draw(x, height - 1, 1));
+    }
+    for (address, x) in [(left_edge, 0), (right_edge, DISPLAY_PIXELS_WIDTH - 8)] {
+        code.push(Instruction::SetIndex { value: address });
+        for y in (1..height - 1).step_by(8) {
+            code.extend(draw(x, y, u8::min(8, height - 1 - y)));
+        }
+    }
+    code.extend(wait(start, &code, HOLD_FRAMES));
+
+    // each position is drawn, held for a frame and drawn again to erase it
+    code.extend([
+        Instruction::ClearScreen,
+        Instruction::SetIndex { value: boxed },
+    ]);
+    let steps = DISPLAY_PIXELS_WIDTH - 8;
+    for step in 0..=steps {
+        let y = (step as u16 * (height - 8) as u16 / steps as u16) as u8;
+        code.extend(draw(step, y, 8));
+        code.extend(wait(start, &code, 1));
+        code.push(Instruction::Display {
+            vx: 0,
+            vy: 1,
+            pixels: 8,
+        });
+    }
+
+    code.push(Instruction::Jump { address: start });
+
+    assemble("display test", data, code)
+}
+
+// builds a rom that shows the keypad every frame with the keys that are held drawn inverted
+pub fn keypad() -> Program {
+    let key_cap = PROGRAM_START_ADDR + 2;
+    let data = KEY_CAP.to_vec();
+
+    let start = PROGRAM_START_ADDR + 2 + data.len() as u16;
+
+    let mut code = vec![Instruction::ClearScreen];
+    for (row, keys) in KEYPAD_LAYOUT.iter().enumerate() {
+        for (col, key) in keys.iter().enumerate() {
+            let x = col as u8 * 16;
+            let y = row as u8 * 8;
+
+            code.extend([
+                Instruction::Set { v: 2, value: *key },
+                Instruction::LoadFontChar { v: 2 },
+            ]);
+            code.extend(draw(x + 6, y + 1, 5));
+            code.extend([
+                Instruction::Set { v: 3, value: x + 4 },
+                Instruction::Set { v: 4, value: y },
+                Instruction::SetIndex { value: key_cap },
+                Instruction::SkipIfKeyNotPressed { v: 2 },
+                Instruction::Display {
+                    vx: 3,
+                    vy: 4,
+                    pixels: KEY_CAP.len() as u8,
+                },
+            ]);
+        }
+    }
+    code.extend(wait(start, &code, 1));
+    code.push(Instruction::Jump { address: start });
+
+    assemble("input test", data, code)
+}
+
+// builds a rom that plays each sound pattern in turn with the number of the pattern on screen
+pub fn sound() -> Program {
+    let start = PROGRAM_START_ADDR + 2;
+
+    let mut code = Vec::new();
+    for (idx, (on, off, repeat)) in SOUND_PATTERNS.iter().enumerate() {
+        code.extend([
+            Instruction::ClearScreen,
+            Instruction::Set {
+                v: 2,
+                value: idx as u8 + 1,
+            },
+            Instruction::LoadFontChar { v: 2 },
+        ]);
+        code.extend(draw(30, 13, 5));
+
+        for _ in 0..*repeat {
+            code.extend([
+                Instruction::Set { v: 2, value: *on },
+                Instruction::SoundTimerSet { v: 2 },
+            ]);
+            code.extend(wait(start, &code, on + off));
+        }
+    }
+    code.push(Instruction::Jump { address: start });
+
+    assemble("sound test", Vec::new(), code)
+}
+
+// the rom starts with a jump over the sprite data to the code
+pub(super) fn assemble(name: &str, data: Vec<u8>, code: Vec<Instruction>) -> Program {
+    let start = PROGRAM_START_ADDR + 2 + data.len() as u16;
+
+    let mut bytes = Instruction::Jump { address: start }
+        .to_op_code()
+        .to_be_bytes()
+        .to_vec();
+    bytes.extend(data);
+    for instruction in code {
+        bytes.extend_from_slice(&instruction.to_op_code().to_be_bytes());
+    }
+
+    Program::new(String::from(name), bytes).expect("test patterns always fit in memory")
+}
+
+pub(super) fn draw(x: u8, y: u8, rows: u8) -> [Instruction; 3] {
+    [
+        Instruction::Set { v: 0, value: x },
+        Instruction::Set { v: 1, value: y },
+        Instruction::Display {
+            vx: 0,
+            vy: 1,
+            pixels: rows,
+        },
+    ]
+}
+
+// spins on the delay timer, the loop starts after the two instructions that set the timer
+pub(super) fn wait(start: u16, code: &[Instruction], frames: u8) -> [Instruction; 5] {
+    let address = start + 2 * (code.len() as u16 + 2);
+
+    [
+        Instruction::Set {
+            v: 2,
+            value: frames,
+        },
+        Instruction::DelayTimerSet { v: 2 },
+        Instruction::DelayTimerLoad { v: 2 },
+        Instruction::SkipEqual { v: 2, value: 0 },
+        Instruction::Jump { address },
+    ]
+}
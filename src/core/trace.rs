@@ -0,0 +1,285 @@
+// Runs a rom headless for a number of frames and writes a line for every instruction executed,
+// its address, op code and disassembly followed by the registers as they were before it ran:
+//
+//   # frame 0
+//   200 00e0 clear                    v=00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 i=000 dt=00 st=00 sp=0
+//
+// A frame is the speed divided by the timer rate worth of instructions followed by a timer tick,
+// the same as under netplay, and no keys are ever pressed, so the same rom, mode, speed and seed
+// always give the same trace and two traces can be diffed line by line. Lines starting with #
+// mark where each frame begins and the analyze subcommand reads the addresses back as a trace of
+// executed code.
+//
+// Two traces are diffed by lining their instructions up one for one, the first line that differs
+// is where they diverge. Since every line holds the registers before its instruction runs, the
+// instruction before the divergence is what behaved differently and what it is points at the
+// likely cause, e.g. a shift points at the shift quirk and a key skip at different input.
+
+use crate::{
+    core::{
+        cpu::{Instruction, Mode, Quirks, CPU},
+        machine::Machine,
+        memory::{RAM, RAM_SIZE},
+        Font, Program,
+    },
+    error::{Context, EmuError, Result},
+    DisplayState, Resolution,
+};
+
+use std::io::Write;
+
+#[derive(Clone, Debug)]
+pub struct TraceConfig {
+    pub mode: Mode,
+    pub instructions_per_sec: u16,
+    pub timer_hz: u16,
+    pub seed: u64,
+    pub frames: u64,
+}
+
+// returns how many instructions were traced
+pub fn run(program: &Program, config: &TraceConfig, out: &mut impl Write) -> Result<u64> {
+    let mut machine = Machine::new(Font::default());
+    machine.load(program);
+    machine.display = DisplayState::with_height(Resolution::detect(program).height());
+
+    machine.cpu.set_quirks(Quirks::from(&config.mode));
+    machine.cpu.seed_rng(config.seed);
+
+    let per_frame = u16::max(1, config.instructions_per_sec / config.timer_hz);
+    let mut executed = 0;
+
+    for frame in 0..config.frames {
+        writeln!(out, "# frame {}", frame)?;
+
+        for _ in 0..per_frame {
+            let address = machine.cpu.prog_counter();
+            if address as usize + 1 >= RAM_SIZE {
+                return Err(EmuError::MemoryFault { address })
+                    .context(format!("after {} instructions", executed));
+            }
+
+            write_line(out, &machine.cpu, &machine.memory)?;
+            machine.step();
+            executed += 1;
+        }
+
+        machine.vblank();
+    }
+
+    out.flush()?;
+
+    Ok(executed)
+}
+
+fn write_line(out: &mut impl Write, cpu: &CPU, memory: &RAM) -> std::io::Result<()> {
+    let state = cpu.state();
+    let op_code = memory.read_u16(state.prog_counter);
+    let text = Instruction::from_op_code(op_code).map_or_else(
+        || format!("unknown {:04x}", op_code),
+        |instruction| instruction.to_string(),
+    );
+
+    let vs: Vec<String> = state.vs.iter().map(|v| format!("{:02x}", v)).collect();
+
+    writeln!(
+        out,
+        "{:03x} {:04x} {:<24} v={} i={:03x} dt={:02x} st={:02x} sp={}",
+        state.prog_counter,
+        op_code,
+        text,
+        vs.join(" "),
+        state.i,
+        state.delay_timer,
+        state.sound_timer,
+        state.stack.len()
+    )
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Step {
+    frame: u64,
+    line: String,
+    prog_counter: u16,
+    op_code: u16,
+    // everything after the disassembly, the registers before the instruction runs
+    state: String,
+    vf: u8,
+    i: u16,
+}
+
+fn parse(text: &str) -> Result<Vec<Step>> {
+    let mut steps = Vec::new();
+    let mut frame = 0;
+
+    for (idx, line) in text.lines().enumerate() {
+        if let Some(marker) = line.strip_prefix("# frame ") {
+            frame = marker.trim().parse().context(format!(
+                "line {}: invalid frame '{}'",
+                idx + 1,
+                marker
+            ))?;
+            continue;
+        }
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let step = parse_step(frame, line).ok_or_else(|| {
+            EmuError::Invalid(format!("line {}: invalid trace line '{}'", idx + 1, line))
+        })?;
+        steps.push(step);
+    }
+
+    Ok(steps)
+}
+
+fn parse_step(frame: u64, line: &str) -> Option<Step> {
+    let mut fields = line.split_whitespace();
+    let prog_counter = u16::from_str_radix(fields.next()?, 16).ok()?;
+    let op_code = u16::from_str_radix(fields.next()?, 16).ok()?;
+
+    let state = &line[line.find(" v=")? + 1..];
+    let vs = state.strip_prefix("v=")?.split_whitespace();
+    let vf = u8::from_str_radix(vs.clone().nth(15)?, 16).ok()?;
+    let i = state
+        .split_whitespace()
+        .find_map(|field| field.strip_prefix("i="))
+        .and_then(|i| u16::from_str_radix(i, 16).ok())?;
+
+    Some(Step {
+        frame,
+        line: String::from(line),
+        prog_counter,
+        op_code,
+        state: String::from(state),
+        vf,
+        i,
+    })
+}
+
+// instructions run before the first timer tick, which is the same in every frame of a trace
+fn per_frame(steps: &[Step]) -> usize {
+    steps.iter().take_while(|step| step.frame == 0).count()
+}
+
+// a report of where the traces diverge and why, none when they are the same
+pub fn diff(a: &str, b: &str, context: usize) -> Result<Option<String>> {
+    let a = parse(a).context("parse the first trace")?;
+    let b = parse(b).context("parse the second trace")?;
+
+    let Some(idx) = (0..usize::max(a.len(), b.len())).find(|idx| {
+        let (a, b) = (a.get(*idx), b.get(*idx));
+        a.map(|s| (s.frame, &s.line)) != b.map(|s| (s.frame, &s.line))
+    }) else {
+        return Ok(None);
+    };
+
+    let mut report = match (a.get(idx), b.get(idx)) {
+        (Some(step), _) | (None, Some(step)) => format!(
+            "traces diverge at instruction {}, frame {}\n",
+            idx, step.frame
+        ),
+        (None, None) => unreachable!("the traces differ at an index one of them has"),
+    };
+
+    // lines are prefixed with their frame as lines that only differ in it would look the same
+    for step in &a[idx.saturating_sub(context)..idx] {
+        report.push_str(&format!("  {:>5} {}\n", step.frame, step.line));
+    }
+    for (sign, steps) in [('-', &a), ('+', &b)] {
+        match steps.get(idx..) {
+            Some(rest) if !rest.is_empty() => {
+                for step in rest.iter().take(context + 1) {
+                    report.push_str(&format!("{} {:>5} {}\n", sign, step.frame, step.line));
+                }
+            }
+            _ => report.push_str(&format!("{} <end of trace>\n", sign)),
+        }
+    }
+
+    report.push_str(&format!(
+        "likely cause: {}\n",
+        likely_cause(
+            idx.checked_sub(1).map(|idx| &a[idx]),
+            a.get(idx),
+            b.get(idx)
+        )
+    ));
+
+    let (a_per_frame, b_per_frame) = (per_frame(&a), per_frame(&b));
+    if a_per_frame != b_per_frame {
+        report.push_str(&format!(
+            "note: the first trace runs {} instructions a frame and the second {}, they were \
+             made at different speeds or timer rates\n",
+            a_per_frame, b_per_frame
+        ));
+    }
+
+    Ok(Some(report))
+}
+
+fn likely_cause(previous: Option<&Step>, a: Option<&Step>, b: Option<&Step>) -> String {
+    let (Some(a), Some(b)) = (a, b) else {
+        return String::from("one trace is shorter, it stopped early or was made for fewer frames");
+    };
+
+    if a.frame != b.frame {
+        return String::from(
+            "the timers ticked after a different number of instructions, the speed or timer rate \
+             differs",
+        );
+    }
+
+    let Some(previous) = previous else {
+        return String::from("the traces differ from the start, the roms or modes differ");
+    };
+
+    if a.prog_counter == b.prog_counter && a.op_code != b.op_code {
+        return format!(
+            "the code at {:#05x} differs, the roms differ or the code was modified differently",
+            a.prog_counter
+        );
+    }
+
+    let Some(instruction) = Instruction::from_op_code(previous.op_code) else {
+        return format!(
+            "the unknown op code {:04x} behaved differently",
+            previous.op_code
+        );
+    };
+
+    let at = format!("{} at {:#05x}", instruction, previous.prog_counter);
+    let vf_differs = a.vf != b.vf;
+
+    match instruction {
+        Instruction::ShiftLeft { .. } | Instruction::ShiftRight { .. } => {
+            format!("{} shifted differently, the shift quirk differs", at)
+        }
+        Instruction::Load { .. } | Instruction::Store { .. } if a.i != b.i => {
+            format!("{} left i differently, the memory quirk differs", at)
+        }
+        Instruction::Or { .. } | Instruction::And { .. } | Instruction::Xor { .. }
+            if vf_differs =>
+        {
+            format!("{} set vf differently, the vf_reset quirk differs", at)
+        }
+        Instruction::Display { .. } if vf_differs => format!(
+            "{} collided differently, the clipping quirk or what was on the display differs",
+            at
+        ),
+        Instruction::Random { .. } => format!("{} drew a different number, the seeds differ", at),
+        Instruction::SkipIfKeyPressed { .. }
+        | Instruction::SkipIfKeyNotPressed { .. }
+        | Instruction::GetKey { .. } => format!("{} saw different keys, the input differs", at),
+        Instruction::DelayTimerLoad { .. } => format!(
+            "{} read a different delay timer, the speed or timer rate differs",
+            at
+        ),
+        _ => format!(
+            "{} behaved differently, likely reading memory an earlier instruction wrote \
+             differently under other quirks",
+            at
+        ),
+    }
+}
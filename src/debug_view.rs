@@ -0,0 +1,301 @@
+// What the debug window shows, drawn as an image so a frontend only has to put it on screen. Memory
+// is a heatmap of every byte, bytes glow when they are executed or written and fade over a few
+// frames. Next to it are the registers and the instructions around the program counter, written
+// with a three by five pixel font.
+
+use crate::{
+    core::{
+        cpu::{CpuState, Instruction, CPU},
+        memory::{RAM, RAM_SIZE},
+        symbols::SymbolTable,
+    },
+    palette::{Color, Palette},
+};
+
+// heat added for each instruction executed at or byte written to an address, and how much of it is
+// left after a frame
+const HEAT_EXECUTED: u8 = 64;
+
+const HEAT_WRITTEN: u8 = 192;
+
+const HEAT_KEPT: u16 = 15;
+
+const HEAT_OUT_OF: u16 = 16;
+
+// instructions shown before and after the one at the program counter
+const DISASSEMBLY_BEFORE: u16 = 4;
+
+const DISASSEMBLY_AFTER: u16 = 8;
+
+// memory is 64 bytes to a row with each byte a two pixel square
+const HEATMAP_COLUMNS: usize = 64;
+
+const HEATMAP_CELL: usize = 2;
+
+const MARGIN: usize = 4;
+
+const GLYPH_WIDTH: usize = 3;
+
+const GLYPH_HEIGHT: usize = 5;
+
+const CHAR_ADVANCE: usize = GLYPH_WIDTH + 1;
+
+const LINE_ADVANCE: usize = GLYPH_HEIGHT + 2;
+
+// the text column is wide enough for the longest disassembly line, longer lines are cut off
+const TEXT_COLUMNS: usize = 28;
+
+// rows of three pixels with the leftmost pixel in the highest bit, lowercase letters are drawn as
+// uppercase and anything else as a question mark
+const FONT: [(char, [u8; 5]); 52] = [
+    ('A', [0b010, 0b101, 0b111, 0b101, 0b101]),
+    ('B', [0b110, 0b101, 0b110, 0b101, 0b110]),
+    ('C', [0b011, 0b100, 0b100, 0b100, 0b011]),
+    ('D', [0b110, 0b101, 0b101, 0b101, 0b110]),
+    ('E', [0b111, 0b100, 0b110, 0b100, 0b111]),
+    ('F', [0b111, 0b100, 0b110, 0b100, 0b100]),
+    ('G', [0b011, 0b100, 0b101, 0b101, 0b011]),
+    ('H', [0b101, 0b101, 0b111, 0b101, 0b101]),
+    ('I', [0b111, 0b010, 0b010, 0b010, 0b111]),
+    ('J', [0b001, 0b001, 0b001, 0b101, 0b010]),
+    ('K', [0b101, 0b101, 0b110, 0b101, 0b101]),
+    ('L', [0b100, 0b100, 0b100, 0b100, 0b111]),
+    ('M', [0b101, 0b111, 0b111, 0b101, 0b101]),
+    ('N', [0b110, 0b101, 0b101, 0b101, 0b101]),
+    ('O', [0b010, 0b101, 0b101, 0b101, 0b010]),
+    ('P', [0b110, 0b101, 0b110, 0b100, 0b100]),
+    ('Q', [0b010, 0b101, 0b101, 0b110, 0b011]),
+    ('R', [0b110, 0b101, 0b110, 0b101, 0b101]),
+    ('S', [0b011, 0b100, 0b010, 0b001, 0b110]),
+    ('T', [0b111, 0b010, 0b010, 0b010, 0b010]),
+    ('U', [0b101, 0b101, 0b101, 0b101, 0b111]),
+    ('V', [0b101, 0b101, 0b101, 0b101, 0b010]),
+    ('W', [0b101, 0b101, 0b111, 0b111, 0b101]),
+    ('X', [0b101, 0b101, 0b010, 0b101, 0b101]),
+    ('Y', [0b101, 0b101, 0b010, 0b010, 0b010]),
+    ('Z', [0b111, 0b001, 0b010, 0b100, 0b111]),
+    ('0', [0b111, 0b101, 0b101, 0b101, 0b111]),
+    ('1', [0b010, 0b110, 0b010, 0b010, 0b111]),
+    ('2', [0b110, 0b001, 0b010, 0b100, 0b111]),
+    ('3', [0b110, 0b001, 0b010, 0b001, 0b110]),
+    ('4', [0b101, 0b101, 0b111, 0b001, 0b001]),
+    ('5', [0b111, 0b100, 0b110, 0b001, 0b110]),
+    ('6', [0b011, 0b100, 0b111, 0b101, 0b111]),
+    ('7', [0b111, 0b001, 0b010, 0b010, 0b010]),
+    ('8', [0b111, 0b101, 0b111, 0b101, 0b111]),
+    ('9', [0b111, 0b101, 0b111, 0b001, 0b110]),
+    (' ', [0b000, 0b000, 0b000, 0b000, 0b000]),
+    (':', [0b000, 0b010, 0b000, 0b010, 0b000]),
+    ('-', [0b000, 0b000, 0b111, 0b000, 0b000]),
+    ('.', [0b000, 0b000, 0b000, 0b000, 0b010]),
+    (',', [0b000, 0b000, 0b000, 0b010, 0b100]),
+    ('#', [0b101, 0b111, 0b101, 0b111, 0b101]),
+    ('[', [0b110, 0b100, 0b100, 0b100, 0b110]),
+    (']', [0b011, 0b001, 0b001, 0b001, 0b011]),
+    ('(', [0b010, 0b100, 0b100, 0b100, 0b010]),
+    (')', [0b010, 0b001, 0b001, 0b001, 0b010]),
+    ('+', [0b000, 0b010, 0b111, 0b010, 0b000]),
+    ('=', [0b000, 0b111, 0b000, 0b111, 0b000]),
+    ('>', [0b100, 0b010, 0b001, 0b010, 0b100]),
+    ('<', [0b001, 0b010, 0b100, 0b010, 0b001]),
+    ('_', [0b000, 0b000, 0b000, 0b000, 0b111]),
+    ('?', [0b110, 0b001, 0b010, 0b000, 0b010]),
+];
+
+// how recently each byte of memory was executed or written
+#[derive(Clone, Debug)]
+pub(crate) struct Heatmap {
+    heat: Vec<u8>,
+    // memory as the last frame left it, a byte that differs from it was written since
+    memory: Vec<u8>,
+}
+
+impl Heatmap {
+    pub(crate) fn new(memory: &RAM) -> Self {
+        Self {
+            heat: vec![0; RAM_SIZE],
+            memory: memory.read_block(0, RAM_SIZE).to_vec(),
+        }
+    }
+    pub(crate) fn executed(&mut self, address: u16) {
+        for address in [address, address.wrapping_add(1)] {
+            let heat = &mut self.heat[address as usize % RAM_SIZE];
+            *heat = heat.saturating_add(HEAT_EXECUTED);
+        }
+    }
+    // writes are found by comparing memory once a frame rather than on every write
+    pub(crate) fn frame(&mut self, memory: &RAM) {
+        let current = memory.read_block(0, RAM_SIZE);
+        for (idx, heat) in self.heat.iter_mut().enumerate() {
+            *heat = (*heat as u16 * HEAT_KEPT / HEAT_OUT_OF) as u8;
+            if current[idx] != self.memory[idx] {
+                *heat = heat.saturating_add(HEAT_WRITTEN);
+            }
+        }
+
+        self.memory.copy_from_slice(current);
+    }
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct DebugView {
+    state: CpuState,
+    heat: Vec<u8>,
+    disassembly: Vec<(u16, String)>,
+}
+
+impl DebugView {
+    pub(crate) fn new(cpu: &CPU, memory: &RAM, heatmap: &Heatmap, symbols: &SymbolTable) -> Self {
+        let state = cpu.state();
+
+        let start = state
+            .prog_counter
+            .saturating_sub(2 * DISASSEMBLY_BEFORE)
+            .max(0x200);
+        let disassembly = (0..=DISASSEMBLY_BEFORE + DISASSEMBLY_AFTER)
+            .map(|idx| start + 2 * idx)
+            .take_while(|address| (*address as usize) + 1 < RAM_SIZE)
+            .map(|address| {
+                let op_code = memory.read_u16(address);
+                let text = match Instruction::from_op_code(op_code) {
+                    Some(instruction) => instruction.with_symbols(symbols).to_string(),
+                    None => format!("db {:#06x}", op_code),
+                };
+                (address, text)
+            })
+            .collect();
+
+        Self {
+            state,
+            heat: heatmap.heat.clone(),
+            disassembly,
+        }
+    }
+    // the registers and disassembly sit to the right of the heatmap
+    #[cfg_attr(not(feature = "sdl"), allow(dead_code))]
+    pub(crate) fn render(&self, palette: Palette) -> Panel {
+        let heatmap_width = HEATMAP_COLUMNS * HEATMAP_CELL;
+        let heatmap_height = RAM_SIZE / HEATMAP_COLUMNS * HEATMAP_CELL;
+        let text_left = MARGIN + heatmap_width + 2 * MARGIN;
+
+        let lines = self.lines();
+        let width = text_left + TEXT_COLUMNS * CHAR_ADVANCE + MARGIN;
+        let height = usize::max(
+            MARGIN + LINE_ADVANCE + heatmap_height,
+            MARGIN + lines.len() * LINE_ADVANCE,
+        ) + MARGIN;
+
+        let mut panel = Panel::new(width, height, palette.background());
+
+        panel.text(MARGIN, MARGIN, "memory", palette.foreground());
+        let top = MARGIN + LINE_ADVANCE;
+        for (address, heat) in self.heat.iter().enumerate() {
+            let executing = address as u16 == self.state.prog_counter
+                || address as u16 == self.state.prog_counter.wrapping_add(1);
+            let color = match executing {
+                true => palette.accent(),
+                false => blend(palette.background(), palette.foreground(), *heat),
+            };
+
+            let x = MARGIN + address % HEATMAP_COLUMNS * HEATMAP_CELL;
+            let y = top + address / HEATMAP_COLUMNS * HEATMAP_CELL;
+            panel.fill(x, y, HEATMAP_CELL, HEATMAP_CELL, color);
+        }
+
+        for (idx, (line, current)) in lines.iter().enumerate() {
+            let color = match current {
+                true => palette.accent(),
+                false => palette.foreground(),
+            };
+            panel.text(text_left, MARGIN + idx * LINE_ADVANCE, line, color);
+        }
+
+        panel
+    }
+
+    #[cfg_attr(not(feature = "sdl"), allow(dead_code))]
+    fn lines(&self) -> Vec<(String, bool)> {
+        let state = &self.state;
+
+        let mut lines = vec![(
+            format!("pc {:03x}  i {:03x}", state.prog_counter, state.i),
+            false,
+        )];
+        for (row, vs) in state.vs.chunks(4).enumerate() {
+            let registers: Vec<String> = vs
+                .iter()
+                .enumerate()
+                .map(|(idx, v)| format!("v{:x} {:02x}", row * 4 + idx, v))
+                .collect();
+            lines.push((registers.join(" "), false));
+        }
+        lines.push((
+            format!("dt {:02x}  st {:02x}", state.delay_timer, state.sound_timer),
+            false,
+        ));
+        let stack: Vec<String> = state.stack.iter().map(|a| format!("{:03x}", a)).collect();
+        lines.push((format!("stack {}", stack.join(" ")), false));
+        lines.push((String::new(), false));
+
+        for (address, text) in &self.disassembly {
+            let current = *address == state.prog_counter;
+            let marker = if current { '>' } else { ' ' };
+            lines.push((format!("{} {:03x} {}", marker, address, text), current));
+        }
+
+        lines
+    }
+}
+
+// an image for the frontend to scale up
+#[cfg_attr(not(feature = "sdl"), allow(dead_code))]
+pub(crate) struct Panel {
+    pub(crate) width: usize,
+    pub(crate) height: usize,
+    pub(crate) pixels: Vec<Color>,
+}
+
+#[cfg_attr(not(feature = "sdl"), allow(dead_code))]
+impl Panel {
+    fn new(width: usize, height: usize, background: Color) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![background; width * height],
+        }
+    }
+    fn fill(&mut self, x: usize, y: usize, width: usize, height: usize, color: Color) {
+        for row in y..y + height {
+            let start = row * self.width + x;
+            self.pixels[start..start + width].fill(color);
+        }
+    }
+    fn text(&mut self, x: usize, y: usize, text: &str, color: Color) {
+        let columns = (self.width - x) / CHAR_ADVANCE;
+        for (idx, char) in text.chars().take(columns).enumerate() {
+            let char = char.to_ascii_uppercase();
+            let glyph = FONT
+                .iter()
+                .find(|(c, _)| *c == char)
+                .or_else(|| FONT.iter().find(|(c, _)| *c == '?'))
+                .map(|(_, glyph)| glyph);
+
+            let left = x + idx * CHAR_ADVANCE;
+            for (row, bits) in glyph.into_iter().flatten().enumerate() {
+                for column in 0..GLYPH_WIDTH {
+                    if bits & (1 << (GLYPH_WIDTH - 1 - column)) != 0 {
+                        self.pixels[(y + row) * self.width + left + column] = color;
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg_attr(not(feature = "sdl"), allow(dead_code))]
+fn blend(from: Color, to: Color, amount: u8) -> Color {
+    let mix = |from: u8, to: u8| {
+        ((from as u32 * (255 - amount as u32) + to as u32 * amount as u32) / 255) as u8
+    };
+    Color::rgb(mix(from.r, to.r), mix(from.g, to.g), mix(from.b, to.b))
+}
@@ -0,0 +1,747 @@
+use crate::{
+    core::{
+        cheat::{self, Cheat, CheatTarget},
+        cpu::{Draw, Quirks, CPU},
+        dump,
+        memory::{RAM, RAM_SIZE},
+        regions::RegionMap,
+        symbols::SymbolTable,
+    },
+    error::{bail, Context, Result},
+};
+
+use std::{
+    collections::BTreeSet,
+    io::{BufRead, Write},
+    path::Path,
+    str::FromStr,
+    sync::Arc,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ScreenRect {
+    pub x: u8,
+    pub y: u8,
+    pub width: u8,
+    pub height: u8,
+}
+
+impl ScreenRect {
+    pub fn intersects(&self, draw: &Draw) -> bool {
+        let (x, y) = (self.x as u16, self.y as u16);
+        let (w, h) = (self.width as u16, self.height as u16);
+        let (draw_x, draw_y) = (draw.x as u16, draw.y as u16);
+        let (draw_w, draw_h) = (draw.width as u16, draw.height as u16);
+
+        x < draw_x + draw_w && draw_x < x + w && y < draw_y + draw_h && draw_y < y + h
+    }
+}
+
+impl FromStr for ScreenRect {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts = s
+            .split(',')
+            .map(|p| p.trim().parse::<u8>())
+            .collect::<Result<Vec<u8>, _>>()
+            .map_err(|e| format!("invalid rect '{}': {}", s, e))?;
+
+        match parts.as_slice() {
+            [x, y, width, height] => Ok(Self {
+                x: *x,
+                y: *y,
+                width: *width,
+                height: *height,
+            }),
+            _ => Err(format!("invalid rect '{}': expected X,Y,W,H", s)),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DrawBreakpoint {
+    pub rect: Option<ScreenRect>,
+    pub collision_only: bool,
+}
+
+impl DrawBreakpoint {
+    pub fn matches(&self, draw: &Draw) -> bool {
+        if self.collision_only && !draw.collision {
+            return false;
+        }
+
+        match &self.rect {
+            Some(rect) => rect.intersects(draw),
+            None => true,
+        }
+    }
+}
+
+// an opcode written the way the docs write them, where x, y and n stand for any digit
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct OpcodePattern {
+    pattern: String,
+    mask: u16,
+    value: u16,
+}
+
+impl OpcodePattern {
+    pub fn matches(&self, op_code: u16) -> bool {
+        op_code & self.mask == self.value
+    }
+}
+
+impl FromStr for OpcodePattern {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let pattern = s.trim().to_uppercase();
+        if pattern.chars().count() != 4 {
+            return Err(format!(
+                "invalid opcode pattern '{}': expected four hex digits or X, Y and N, e.g. FX0A",
+                s
+            ));
+        }
+
+        let mut mask = 0;
+        let mut value = 0;
+        for c in pattern.chars() {
+            mask <<= 4;
+            value <<= 4;
+
+            match c {
+                'X' | 'Y' | 'N' => {}
+                c => match c.to_digit(16) {
+                    Some(digit) => {
+                        mask |= 0xF;
+                        value |= digit as u16;
+                    }
+                    None => {
+                        return Err(format!(
+                            "invalid opcode pattern '{}': expected four hex digits or X, Y and N, e.g. FX0A",
+                            s
+                        ))
+                    }
+                },
+            }
+        }
+
+        Ok(Self {
+            pattern,
+            mask,
+            value,
+        })
+    }
+}
+
+impl std::fmt::Display for OpcodePattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.pattern)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DebuggerConfig {
+    pub break_on_draw: Option<DrawBreakpoint>,
+    pub breakpoints: Vec<String>,
+    pub opcode_breakpoints: Vec<OpcodePattern>,
+    // named alongside the font and the program
+    pub regions: RegionMap,
+    // stops before the first instruction even without a breakpoint there
+    pub break_at_start: bool,
+    // where the session is written when the emulation finishes, along with the symbols and
+    // regions files it was started with
+    pub session: Option<String>,
+    pub symbols_file: Option<String>,
+    pub regions_file: Option<String>,
+}
+
+impl DebuggerConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.break_at_start
+            || self.break_on_draw.is_some()
+            || !self.breakpoints.is_empty()
+            || !self.opcode_breakpoints.is_empty()
+    }
+}
+
+// what a debugging session of a rom was set up with, kept in the data directory of the rom so the
+// next one starts with the same breakpoints and files. one setting to a line, the same words the
+// prompt uses for them:
+//
+//   symbols /home/me/roms/game.sym
+//   regions /home/me/roms/game.regions
+//   break 0x24a
+//   break-op FX0A
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DebugSession {
+    pub symbols: Option<String>,
+    pub regions: Option<String>,
+    pub breakpoints: Vec<String>,
+    pub opcode_breakpoints: Vec<OpcodePattern>,
+}
+
+impl DebugSession {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        tracing::debug!("loading debugger session from path: {:?}", path.as_ref());
+
+        let text = std::fs::read_to_string(path.as_ref())
+            .context(format!("read file {}", path.as_ref().to_string_lossy()))?;
+
+        Self::parse(&text)
+    }
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut session = Self::default();
+
+        for (idx, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            // paths keep any spaces after the first
+            let (key, value) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let value = value.trim();
+            if value.is_empty() {
+                bail!("invalid session on line {}: {}", idx + 1, line);
+            }
+
+            match key {
+                "symbols" => session.symbols = Some(String::from(value)),
+                "regions" => session.regions = Some(String::from(value)),
+                "break" => session.breakpoints.push(String::from(value)),
+                "break-op" => match value.parse() {
+                    Ok(pattern) => session.opcode_breakpoints.push(pattern),
+                    Err(e) => bail!("invalid session on line {}: {}", idx + 1, e),
+                },
+                _ => bail!(
+                    "invalid session on line {}: {}, expected symbols, regions, break or break-op",
+                    idx + 1,
+                    line
+                ),
+            }
+        }
+
+        Ok(session)
+    }
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        std::fs::write(path.as_ref(), self.to_string())
+            .context(format!("write file {}", path.as_ref().to_string_lossy()))
+    }
+}
+
+impl std::fmt::Display for DebugSession {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(path) = self.symbols.as_ref() {
+            writeln!(f, "symbols {}", path)?;
+        }
+        if let Some(path) = self.regions.as_ref() {
+            writeln!(f, "regions {}", path)?;
+        }
+        for breakpoint in &self.breakpoints {
+            writeln!(f, "break {}", breakpoint)?;
+        }
+        for pattern in &self.opcode_breakpoints {
+            writeln!(f, "break-op {}", pattern)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Action {
+    Continue,
+    Quit,
+}
+
+#[derive(Clone, Debug)]
+pub struct Debugger {
+    config: DebuggerConfig,
+    symbols: Arc<SymbolTable>,
+    breakpoints: BTreeSet<u16>,
+    opcode_breakpoints: Vec<OpcodePattern>,
+    regions: RegionMap,
+    stepping: bool,
+    snapshot: Option<RAM>,
+}
+
+impl Debugger {
+    pub fn new(config: DebuggerConfig, symbols: Arc<SymbolTable>) -> Self {
+        let mut breakpoints = BTreeSet::new();
+
+        for breakpoint in &config.breakpoints {
+            match symbols.resolve(breakpoint) {
+                Some(address) => {
+                    breakpoints.insert(address);
+                }
+                None => tracing::warn!("unknown breakpoint address or symbol: {}", breakpoint),
+            }
+        }
+
+        let mut regions = RegionMap::builtin(0);
+        regions.merge(&config.regions);
+
+        Self {
+            opcode_breakpoints: config.opcode_breakpoints.clone(),
+            stepping: config.break_at_start,
+            regions,
+            config,
+            symbols,
+            breakpoints,
+            snapshot: None,
+        }
+    }
+    // breakpoints are kept as addresses, the symbols file names them again when it is restored
+    pub fn session(&self) -> DebugSession {
+        DebugSession {
+            symbols: self.config.symbols_file.clone(),
+            regions: self.config.regions_file.clone(),
+            breakpoints: self
+                .breakpoints
+                .iter()
+                .map(|address| format!("{:#05x}", address))
+                .collect(),
+            opcode_breakpoints: self.opcode_breakpoints.clone(),
+        }
+    }
+    pub fn save_session(&self) -> Result<()> {
+        match self.config.session.as_ref() {
+            Some(path) => self.session().save(path),
+            None => Ok(()),
+        }
+    }
+    // the program region only becomes known once a program is loaded
+    pub fn set_program_len(&mut self, len: usize) {
+        self.regions = RegionMap::builtin(len);
+        self.regions.merge(&self.config.regions);
+    }
+    // like address breakpoints an opcode breaks before the matching instruction executes
+    pub fn should_break(&self, cpu: &CPU, memory: &RAM, draw: Option<&Draw>) -> bool {
+        if self.stepping || self.breakpoints.contains(&cpu.prog_counter()) {
+            return true;
+        }
+
+        if self.matching_opcode(cpu, memory).is_some() {
+            return true;
+        }
+
+        match (&self.config.break_on_draw, draw) {
+            (Some(breakpoint), Some(draw)) => breakpoint.matches(draw),
+            _ => false,
+        }
+    }
+    pub fn prompt(
+        &mut self,
+        cpu: &mut CPU,
+        memory: &mut RAM,
+        freezes: &mut Vec<Cheat>,
+        draw: Option<&Draw>,
+    ) -> Action {
+        if let Some(draw) = draw {
+            println!(
+                "break on draw at ({}, {}) {}x{} collision={}",
+                draw.x, draw.y, draw.width, draw.height, draw.collision
+            );
+        } else if self.breakpoints.contains(&cpu.prog_counter()) {
+            println!("break at {}", self.format_address(cpu.prog_counter()));
+        } else if let Some(pattern) = self.matching_opcode(cpu, memory) {
+            println!(
+                "break on {} at {} ({:04x})",
+                pattern,
+                self.format_address(cpu.prog_counter()),
+                memory.read_u16(cpu.prog_counter())
+            );
+        }
+
+        print_registers(cpu, |address| self.format_address(address));
+
+        let stdin = std::io::stdin();
+        let mut line = String::new();
+
+        loop {
+            print!("(chipate) ");
+            let _ = std::io::stdout().flush();
+
+            line.clear();
+            match stdin.lock().read_line(&mut line) {
+                Ok(0) => return Action::Quit,
+                Ok(_) => {}
+                Err(e) => {
+                    tracing::error!("failed to read debugger command: {}", e);
+                    return Action::Quit;
+                }
+            }
+
+            let mut parts = line.split_whitespace();
+            let cmd = parts.next().unwrap_or_default();
+            let arg = parts.next();
+            let arg2 = parts.next();
+            let arg3 = parts.next();
+
+            match cmd {
+                "c" | "continue" => {
+                    self.stepping = false;
+                    return Action::Continue;
+                }
+                "s" | "step" => {
+                    self.stepping = true;
+                    return Action::Continue;
+                }
+                "r" | "regs" => print_registers(cpu, |address| self.format_address(address)),
+                "b" | "break" => match arg.and_then(|a| self.symbols.resolve(a)) {
+                    Some(address) => {
+                        self.breakpoints.insert(address);
+                        println!("breakpoint set at {}", self.format_address(address));
+                    }
+                    None => println!("break requires an address or symbol"),
+                },
+                "d" | "delete" => match arg.and_then(|a| self.symbols.resolve(a)) {
+                    Some(address) if self.breakpoints.remove(&address) => {
+                        println!("breakpoint removed at {}", self.format_address(address))
+                    }
+                    _ => println!("delete requires an existing breakpoint address or symbol"),
+                },
+                "bo" | "break-op" => match arg.map(str::parse::<OpcodePattern>) {
+                    Some(Ok(pattern)) => {
+                        println!("breakpoint set on {}", pattern);
+                        if !self.opcode_breakpoints.contains(&pattern) {
+                            self.opcode_breakpoints.push(pattern);
+                        }
+                    }
+                    Some(Err(e)) => println!("{}", e),
+                    None => println!("break-op requires an opcode pattern, e.g. 'bo FX0A'"),
+                },
+                "do" | "delete-op" => match arg.map(str::parse::<OpcodePattern>) {
+                    Some(Ok(pattern)) if self.opcode_breakpoints.contains(&pattern) => {
+                        self.opcode_breakpoints.retain(|p| *p != pattern);
+                        println!("breakpoint removed on {}", pattern);
+                    }
+                    _ => println!("delete-op requires an existing opcode pattern"),
+                },
+                "bl" | "breakpoints" => {
+                    for address in &self.breakpoints {
+                        println!("{}", self.format_address(*address));
+                    }
+                    for pattern in &self.opcode_breakpoints {
+                        println!("{}", pattern);
+                    }
+                }
+                "sp" | "sprite" => match arg.map(str::parse::<u8>) {
+                    None => print_sprite(memory, cpu.index(), next_sprite_rows(cpu, memory)),
+                    Some(Ok(rows)) if (1..=15).contains(&rows) => {
+                        print_sprite(memory, cpu.index(), rows)
+                    }
+                    Some(_) => println!("sprite rows must be a number between 1 and 15"),
+                },
+                "m" | "mem" => match arg.and_then(|a| self.symbols.resolve(a)) {
+                    Some(address) => {
+                        let len = arg2.and_then(|a| a.parse::<u16>().ok()).unwrap_or(16);
+                        print_memory(memory, address, len, &self.regions);
+                    }
+                    None => println!("mem requires an address or symbol"),
+                },
+                // all of memory unless an address and optionally a length are given
+                "dump" => {
+                    let start = match arg2 {
+                        Some(a) => self.symbols.resolve(a),
+                        None => Some(0),
+                    };
+                    let len = match arg3 {
+                        Some(a) => a.parse::<usize>().ok(),
+                        None => Some(RAM_SIZE),
+                    };
+
+                    match (arg, start, len) {
+                        (Some(path), Some(start), Some(len)) => {
+                            match dump::save(path, cpu, memory, start, len) {
+                                Ok(_) => println!("memory dumped to {}", path),
+                                Err(e) => println!("dump failed: {:#}", e),
+                            }
+                        }
+                        _ => println!(
+                            "dump requires a path and optionally an address or symbol and a length"
+                        ),
+                    }
+                }
+                "rg" | "regions" => {
+                    for region in self.regions.iter() {
+                        println!("{}", region);
+                    }
+                }
+                "snap" | "snapshot" => {
+                    self.snapshot = Some(memory.snapshot());
+                    println!("memory snapshot taken, checksum {:08x}", memory.checksum());
+                }
+                "diff" => match &self.snapshot {
+                    Some(snapshot) => {
+                        print_diff(snapshot, memory, |address| self.format_address(address))
+                    }
+                    None => println!("diff requires a snapshot, take one with 'snap'"),
+                },
+                "p" | "poke" => {
+                    let target = arg.and_then(|a| CheatTarget::parse(a, &self.symbols));
+                    let value = arg2.and_then(cheat::parse_value);
+
+                    match (target, value) {
+                        (Some(target), Some(value)) => {
+                            let cheat = Cheat { target, value };
+                            cheat.apply(cpu, memory);
+                            println!("{}", self.format_cheat(&cheat));
+                        }
+                        _ => println!(
+                            "poke requires an address, symbol or register and a byte value"
+                        ),
+                    }
+                }
+                "set" => match (arg.map(str::to_ascii_lowercase).as_deref(), arg2) {
+                    (Some("pc"), Some(value)) => match self.symbols.resolve(value) {
+                        Some(address) if (address as usize) < RAM_SIZE => {
+                            cpu.set_prog_counter(address);
+                            print_registers(cpu, |address| self.format_address(address));
+                        }
+                        _ => println!("set pc requires an address or symbol"),
+                    },
+                    (Some("i"), Some(value)) => match self.symbols.resolve(value) {
+                        Some(address) => {
+                            cpu.set_index(address);
+                            print_registers(cpu, |address| self.format_address(address));
+                        }
+                        _ => println!("set i requires an address or symbol"),
+                    },
+                    (Some(timer @ ("dt" | "st")), Some(value)) => match cheat::parse_value(value) {
+                        Some(value) => {
+                            let (delay, sound) = match timer {
+                                "dt" => (value, cpu.sound_timer()),
+                                _ => (cpu.delay_timer(), value),
+                            };
+                            cpu.set_timers(delay, sound);
+                            print_registers(cpu, |address| self.format_address(address));
+                        }
+                        None => println!("set {} requires a byte value", timer),
+                    },
+                    (Some(register), Some(value)) => {
+                        let idx = register
+                            .strip_prefix('v')
+                            .filter(|idx| idx.len() == 1)
+                            .and_then(|idx| usize::from_str_radix(idx, 16).ok());
+
+                        match (idx, cheat::parse_value(value)) {
+                            (Some(idx), Some(value)) => {
+                                cpu.set_v(idx, value);
+                                print_registers(cpu, |address| self.format_address(address));
+                            }
+                            _ => println!("set requires one of v0-vf, i, pc, dt or st and a value"),
+                        }
+                    }
+                    _ => println!("set requires one of v0-vf, i, pc, dt or st and a value"),
+                },
+                "f" | "freeze" => match (arg, arg2) {
+                    (None, _) => {
+                        for freeze in freezes.iter() {
+                            println!("{}", self.format_cheat(freeze));
+                        }
+                    }
+                    (Some(target), value) => {
+                        let target = CheatTarget::parse(target, &self.symbols);
+                        let value = value.and_then(cheat::parse_value);
+
+                        match (target, value) {
+                            (Some(target), Some(value)) => {
+                                let freeze = Cheat { target, value };
+                                freeze.apply(cpu, memory);
+                                println!("frozen {}", self.format_cheat(&freeze));
+
+                                freezes.retain(|f| f.target != target);
+                                freezes.push(freeze);
+                            }
+                            _ => println!(
+                                "freeze requires an address, symbol or register and a byte value"
+                            ),
+                        }
+                    }
+                },
+                "uf" | "unfreeze" => match arg.and_then(|a| CheatTarget::parse(a, &self.symbols)) {
+                    Some(target) if freezes.iter().any(|f| f.target == target) => {
+                        freezes.retain(|f| f.target != target);
+                        println!("unfrozen {}", self.format_target(target));
+                    }
+                    _ => println!("unfreeze requires a frozen address, symbol or register"),
+                },
+                "quirk" | "quirks" => match (arg, arg2) {
+                    (None, _) => print_quirks(cpu.quirks()),
+                    (Some(name), value) => {
+                        let value = match value {
+                            Some("on") => cpu.quirks().get(name).map(|_| true),
+                            Some("off") => cpu.quirks().get(name).map(|_| false),
+                            Some(_) => None,
+                            None => cpu.quirks().get(name).map(|current| !current),
+                        };
+
+                        match value {
+                            Some(value) => {
+                                cpu.quirks_mut().set(name, value);
+                                println!("{} = {}", name, if value { "on" } else { "off" });
+                            }
+                            None => println!(
+                                "quirk requires one of {} and optionally on or off",
+                                Quirks::NAMES.join(", ")
+                            ),
+                        }
+                    }
+                },
+                "q" | "quit" => return Action::Quit,
+                "h" | "help" => print_help(),
+                "" => {}
+                cmd => println!(
+                    "unknown command '{}', type 'help' for a list of commands",
+                    cmd
+                ),
+            }
+        }
+    }
+    // the symbol of the address and the region it is in when they are known
+    fn format_address(&self, address: u16) -> String {
+        let formatted = self.symbols.format_address(address);
+
+        match self.regions.describe(address) {
+            Some(region) => format!("{} [{}]", formatted, region),
+            None => formatted,
+        }
+    }
+    fn format_target(&self, target: CheatTarget) -> String {
+        match target {
+            CheatTarget::Memory(address) => self.format_address(address),
+            CheatTarget::Register(idx) => format!("v{:x}", idx),
+        }
+    }
+    fn format_cheat(&self, cheat: &Cheat) -> String {
+        format!(
+            "{} = {:#04x}",
+            self.format_target(cheat.target),
+            cheat.value
+        )
+    }
+    fn matching_opcode(&self, cpu: &CPU, memory: &RAM) -> Option<&OpcodePattern> {
+        if self.opcode_breakpoints.is_empty() {
+            return None;
+        }
+
+        let op_code = memory.read_u16(cpu.prog_counter());
+        self.opcode_breakpoints
+            .iter()
+            .find(|pattern| pattern.matches(op_code))
+    }
+}
+
+fn print_registers(cpu: &CPU, format_address: impl Fn(u16) -> String) {
+    println!(
+        "pc={} i={} dt={:#04x} st={:#04x}",
+        format_address(cpu.prog_counter()),
+        format_address(cpu.index()),
+        cpu.delay_timer(),
+        cpu.sound_timer()
+    );
+
+    for row in 0..2 {
+        let regs: Vec<String> = (0..8)
+            .map(|col| row * 8 + col)
+            .map(|idx| format!("v{:x}={:#04x}", idx, cpu.v(idx)))
+            .collect();
+
+        println!("{}", regs.join(" "));
+    }
+}
+
+// use the row count of the DXYN at the program counter when there is one
+fn next_sprite_rows(cpu: &CPU, memory: &RAM) -> u8 {
+    let pc = cpu.prog_counter();
+    let op_code = memory.read_u16(pc);
+
+    match (op_code & 0xF000, op_code & 0x000F) {
+        (0xD000, n) if n > 0 => n as u8,
+        _ => 15,
+    }
+}
+
+fn print_sprite(memory: &RAM, address: u16, rows: u8) {
+    println!("sprite at i={:#05x} ({} rows)", address, rows);
+
+    for (row, byte) in memory.read_block(address, rows as usize).iter().enumerate() {
+        let addr = address + row as u16;
+
+        let pixels: String = (0..8)
+            .map(|bit| {
+                if byte & (0x80 >> bit) != 0 {
+                    "██"
+                } else {
+                    "··"
+                }
+            })
+            .collect();
+
+        println!("{:#05x} {:#04x} {}", addr, byte, pixels);
+    }
+}
+
+// every row is followed by the region its first byte is in
+fn print_memory(memory: &RAM, address: u16, len: u16, regions: &RegionMap) {
+    let block = memory.read_block(address, len as usize);
+
+    for (idx, row) in block.chunks(16).enumerate() {
+        let start = address as usize + idx * 16;
+        let bytes: Vec<String> = row.iter().map(|byte| format!("{:02x}", byte)).collect();
+
+        match regions.describe(start as u16) {
+            Some(region) => println!("{:#05x}: {:<47}  {}", start, bytes.join(" "), region),
+            None => println!("{:#05x}: {}", start, bytes.join(" ")),
+        }
+    }
+}
+
+fn print_diff(snapshot: &RAM, memory: &RAM, format_address: impl Fn(u16) -> String) {
+    let changes = snapshot.diff(memory);
+    if changes.is_empty() {
+        println!("memory is unchanged since the snapshot");
+    }
+
+    for (address, old, new) in changes {
+        println!("{} {:#04x} -> {:#04x}", format_address(address), old, new);
+    }
+}
+
+fn print_quirks(quirks: &Quirks) {
+    for name in Quirks::NAMES {
+        let enabled = quirks.get(name).unwrap_or_default();
+        println!("{:<10} {}", name, if enabled { "on" } else { "off" });
+    }
+}
+
+fn print_help() {
+    println!("c, continue  resume execution");
+    println!("s, step      execute a single instruction");
+    println!("r, regs      print registers");
+    println!("b, break     set a breakpoint at an address or symbol");
+    println!("d, delete    remove the breakpoint at an address or symbol");
+    println!("bo, break-op break on any instruction matching a pattern, e.g. 'bo FX0A'");
+    println!("do, delete-op remove the breakpoint on an opcode pattern");
+    println!("bl           list breakpoints");
+    println!("sp, sprite   draw the sprite at i, optionally with a row count");
+    println!("m, mem       dump memory at an address, optionally with a length");
+    println!("dump         write memory to a file, optionally from an address with a length");
+    println!("rg, regions  list the named regions of memory");
+    println!("snap         remember the current contents of memory");
+    println!("diff         list the bytes that changed since the last snap");
+    println!("p, poke      write a byte to an address or register, e.g. 'poke v3 5'");
+    println!("set          write v0-vf, i, pc, dt or st, e.g. 'set pc 0x2a0' or 'set dt 60'");
+    println!("f, freeze    keep an address or register at a byte value, or list the frozen ones");
+    println!("uf, unfreeze stop keeping an address or register at its frozen value");
+    println!("quirk        list quirks or toggle one, e.g. 'quirk shift on'");
+    println!("q, quit      exit the emulator");
+    println!("h, help      print this message");
+}
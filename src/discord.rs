@@ -0,0 +1,271 @@
+// Shows the rom being played, for how long and whether it is paused in Discord Rich Presence.
+//
+// The Discord client listens on a local socket, discord-ipc-0 through discord-ipc-9 in the runtime
+// or temp directory on unix and a named pipe of the same name on windows. Every message is framed
+// by a little endian u32 op code and payload length followed by the json payload, a handshake
+// naming the application comes first and every activity after it is a SET_ACTIVITY command.
+//
+// The socket is written from a thread of its own so a missing or slow client never holds up the
+// emulation, when Discord is not running it is looked for again every RECONNECT_INTERVAL.
+
+use crate::{
+    error::{bail, Context, Result},
+    json::json_string,
+};
+
+use std::{
+    io::{Read, Write},
+    sync::mpsc::{self, Receiver, RecvTimeoutError, Sender},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+const HANDSHAKE: u32 = 0;
+
+const FRAME: u32 = 1;
+
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(15);
+
+// replies are only read to keep the socket drained, a client that does not answer is dropped
+const READ_TIMEOUT: Duration = Duration::from_secs(2);
+
+// larger payloads are errors about the activity, nothing that needs reading
+const MAX_PAYLOAD_LEN: usize = 64 * 1024;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Activity {
+    rom: String,
+    paused: bool,
+}
+
+#[derive(Debug)]
+pub struct Presence {
+    updates: Sender<Activity>,
+    last: Option<Activity>,
+}
+
+impl Presence {
+    // the client id is that of an application registered in the Discord developer portal, its
+    // name is what Discord shows as being played
+    pub fn start(client_id: &str) -> Result<Self> {
+        let (updates, activities) = mpsc::channel();
+        let client_id = String::from(client_id);
+
+        std::thread::Builder::new()
+            .name(String::from("discord"))
+            .spawn(move || publish(&client_id, activities))
+            .context("spawn discord thread")?;
+
+        Ok(Self {
+            updates,
+            last: None,
+        })
+    }
+    pub(crate) fn update(&mut self, rom: &str, paused: bool) {
+        if self
+            .last
+            .as_ref()
+            .is_some_and(|last| last.rom == rom && last.paused == paused)
+        {
+            return;
+        }
+
+        let activity = Activity {
+            rom: String::from(rom),
+            paused,
+        };
+        let _ = self.updates.send(activity.clone());
+        self.last = Some(activity);
+    }
+}
+
+trait Ipc: Read + Write + Send {}
+
+impl<T: Read + Write + Send> Ipc for T {}
+
+// play time only counts while running, a paused rom shows no timer and picks up where it left off
+#[derive(Default)]
+struct PlayTime {
+    rom: String,
+    played: Duration,
+    resumed: Option<SystemTime>,
+}
+
+impl PlayTime {
+    fn apply(&mut self, activity: &Activity) {
+        let now = SystemTime::now();
+
+        if activity.rom != self.rom {
+            self.rom = activity.rom.clone();
+            self.played = Duration::ZERO;
+            self.resumed = None;
+        }
+
+        match (self.resumed, activity.paused) {
+            (Some(resumed), true) => {
+                self.played += now.duration_since(resumed).unwrap_or_default();
+                self.resumed = None;
+            }
+            (None, false) => self.resumed = Some(now),
+            _ => {}
+        }
+    }
+    // the unix time the rom would have been started at to have been played for as long as it has
+    fn start(&self) -> Option<u64> {
+        let resumed = self.resumed?.checked_sub(self.played)?;
+        resumed
+            .duration_since(UNIX_EPOCH)
+            .ok()
+            .map(|start| start.as_secs())
+    }
+}
+
+fn publish(client_id: &str, activities: Receiver<Activity>) {
+    let mut ipc: Option<Box<dyn Ipc>> = None;
+    let mut play_time = PlayTime::default();
+    let mut pending = None;
+    let mut nonce = 0u64;
+
+    loop {
+        match activities.recv_timeout(RECONNECT_INTERVAL) {
+            Ok(activity) => {
+                play_time.apply(&activity);
+                pending = Some(activity);
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return,
+        }
+
+        if ipc.is_none() {
+            ipc = match connect(client_id) {
+                Ok(connected) => {
+                    tracing::info!("connected to discord");
+                    // a new connection starts without an activity
+                    pending = pending.or_else(|| {
+                        (!play_time.rom.is_empty()).then(|| Activity {
+                            rom: play_time.rom.clone(),
+                            paused: play_time.resumed.is_none(),
+                        })
+                    });
+                    Some(connected)
+                }
+                Err(e) => {
+                    tracing::debug!("discord is not available: {:#}", e);
+                    None
+                }
+            };
+        }
+
+        let (Some(connected), Some(activity)) = (ipc.as_mut(), pending.as_ref()) else {
+            continue;
+        };
+
+        nonce += 1;
+        let payload = set_activity(activity, play_time.start(), nonce);
+        match send(connected.as_mut(), FRAME, &payload).and_then(|_| receive(connected.as_mut())) {
+            Ok(_) => pending = None,
+            Err(e) => {
+                tracing::warn!("lost the connection to discord: {:#}", e);
+                ipc = None;
+            }
+        }
+    }
+}
+
+fn connect(client_id: &str) -> Result<Box<dyn Ipc>> {
+    let mut ipc = open_socket()?;
+
+    let handshake = format!("{{\"v\":1,\"client_id\":{}}}", json_string(client_id));
+    send(ipc.as_mut(), HANDSHAKE, &handshake)?;
+    receive(ipc.as_mut()).context("read discord handshake reply")?;
+
+    Ok(ipc)
+}
+
+#[cfg(unix)]
+fn open_socket() -> Result<Box<dyn Ipc>> {
+    use std::os::unix::net::UnixStream;
+
+    let dirs: Vec<String> = ["XDG_RUNTIME_DIR", "TMPDIR", "TMP", "TEMP"]
+        .into_iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .chain([String::from("/tmp")])
+        .collect();
+
+    for dir in &dirs {
+        for idx in 0..10 {
+            let path = format!("{}/discord-ipc-{}", dir.trim_end_matches('/'), idx);
+            if let Ok(stream) = UnixStream::connect(&path) {
+                stream
+                    .set_read_timeout(Some(READ_TIMEOUT))
+                    .context("set discord socket timeout")?;
+                return Ok(Box::new(stream));
+            }
+        }
+    }
+
+    bail!("no discord-ipc socket in {}", dirs.join(", "))
+}
+
+#[cfg(windows)]
+fn open_socket() -> Result<Box<dyn Ipc>> {
+    for idx in 0..10 {
+        let path = format!(r"\\?\pipe\discord-ipc-{}", idx);
+        if let Ok(pipe) = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&path)
+        {
+            return Ok(Box::new(pipe));
+        }
+    }
+
+    bail!("no discord-ipc pipe")
+}
+
+#[cfg(not(any(unix, windows)))]
+fn open_socket() -> Result<Box<dyn Ipc>> {
+    bail!("discord rich presence is not supported on this platform")
+}
+
+fn send(ipc: &mut dyn Ipc, op: u32, payload: &str) -> Result<()> {
+    let mut frame = Vec::with_capacity(8 + payload.len());
+    frame.extend_from_slice(&op.to_le_bytes());
+    frame.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    frame.extend_from_slice(payload.as_bytes());
+
+    ipc.write_all(&frame).context("write to discord")?;
+    ipc.flush().context("write to discord")
+}
+
+fn receive(ipc: &mut dyn Ipc) -> Result<()> {
+    let mut header = [0; 8];
+    ipc.read_exact(&mut header).context("read from discord")?;
+
+    let len = u32::from_le_bytes([header[4], header[5], header[6], header[7]]) as usize;
+    if len > MAX_PAYLOAD_LEN {
+        bail!("discord sent a {} byte reply", len);
+    }
+
+    let mut payload = vec![0; len];
+    ipc.read_exact(&mut payload).context("read from discord")?;
+
+    Ok(())
+}
+
+fn set_activity(activity: &Activity, start: Option<u64>, nonce: u64) -> String {
+    let state = if activity.paused { "Paused" } else { "Playing" };
+    let timestamps = match start {
+        Some(start) => format!(",\"timestamps\":{{\"start\":{}}}", start),
+        None => String::new(),
+    };
+
+    format!(
+        "{{\"cmd\":\"SET_ACTIVITY\",\"args\":{{\"pid\":{},\"activity\":{{\"details\":{},\
+         \"state\":\"{}\"{}}}}},\"nonce\":\"{}\"}}",
+        std::process::id(),
+        json_string(&activity.rom),
+        state,
+        timestamps,
+        nonce
+    )
+}
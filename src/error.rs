@@ -0,0 +1,140 @@
+// The errors the library returns. The kinds an embedder is likely to act on have their own variant,
+// everything else is either an io error or invalid input along with a message saying what was
+// wrong. Context wraps an error with what was being done at the time the way the binary reports
+// it, root gives back the error underneath so it can be matched on.
+
+use crate::core::cpu::Fault;
+
+use std::fmt::Display;
+
+pub type Result<T, E = EmuError> = std::result::Result<T, E>;
+
+// every message already holds the one of the error it wraps so none of them are given as a source
+// to keep reports from repeating them
+#[derive(Debug, thiserror::Error)]
+pub enum EmuError {
+    #[error("read rom {path}: {error}")]
+    RomLoad { path: String, error: std::io::Error },
+    #[error("sdl: {0}")]
+    Sdl(String),
+    #[error("gpu: {0}")]
+    Gpu(String),
+    #[error("unknown op code {op_code:04x} at {address:#05x}")]
+    IllegalOpcode { address: u16, op_code: u16 },
+    #[error("program counter ran past the end of memory at {address:#05x}")]
+    MemoryFault { address: u16 },
+    #[error("stack overflow at {address:#05x}")]
+    StackOverflow { address: u16 },
+    #[error("return with an empty stack at {address:#05x}")]
+    StackUnderflow { address: u16 },
+    #[error("{0}")]
+    Io(std::io::Error),
+    #[error("{0}")]
+    Network(String),
+    #[error("jit: {0}")]
+    Jit(String),
+    #[error("{0}")]
+    Invalid(String),
+    #[error("{0}")]
+    Unsupported(String),
+    #[error("emulator stopped")]
+    Stopped,
+    #[error("{context}: {error}")]
+    Context {
+        context: String,
+        error: Box<EmuError>,
+    },
+}
+
+impl EmuError {
+    pub fn root(&self) -> &EmuError {
+        match self {
+            EmuError::Context { error, .. } => error.root(),
+            error => error,
+        }
+    }
+}
+
+impl From<Fault> for EmuError {
+    fn from(fault: Fault) -> Self {
+        match fault {
+            Fault::UnknownOpCode { address, op_code } => {
+                EmuError::IllegalOpcode { address, op_code }
+            }
+            Fault::StackOverflow { address } => EmuError::StackOverflow { address },
+            Fault::StackUnderflow { address } => EmuError::StackUnderflow { address },
+        }
+    }
+}
+
+impl From<std::io::Error> for EmuError {
+    fn from(e: std::io::Error) -> Self {
+        EmuError::Io(e)
+    }
+}
+
+// the FromStr impls of the library all fail with a message
+impl From<String> for EmuError {
+    fn from(message: String) -> Self {
+        EmuError::Invalid(message)
+    }
+}
+
+impl From<std::num::ParseIntError> for EmuError {
+    fn from(e: std::num::ParseIntError) -> Self {
+        EmuError::Invalid(e.to_string())
+    }
+}
+
+impl From<std::array::TryFromSliceError> for EmuError {
+    fn from(e: std::array::TryFromSliceError) -> Self {
+        EmuError::Invalid(e.to_string())
+    }
+}
+
+impl From<std::str::Utf8Error> for EmuError {
+    fn from(e: std::str::Utf8Error) -> Self {
+        EmuError::Invalid(e.to_string())
+    }
+}
+
+impl From<std::string::FromUtf8Error> for EmuError {
+    fn from(e: std::string::FromUtf8Error) -> Self {
+        EmuError::Invalid(e.to_string())
+    }
+}
+
+impl From<tungstenite::Error> for EmuError {
+    fn from(e: tungstenite::Error) -> Self {
+        EmuError::Network(e.to_string())
+    }
+}
+
+// the same as the context of anyhow, a missing value becomes invalid input described by the context
+pub(crate) trait Context<T> {
+    fn context(self, context: impl Display) -> Result<T>;
+}
+
+impl<T, E: Into<EmuError>> Context<T> for std::result::Result<T, E> {
+    fn context(self, context: impl Display) -> Result<T> {
+        self.map_err(|e| EmuError::Context {
+            context: context.to_string(),
+            error: Box::new(e.into()),
+        })
+    }
+}
+
+impl<T> Context<T> for Option<T> {
+    fn context(self, context: impl Display) -> Result<T> {
+        self.ok_or_else(|| EmuError::Invalid(context.to_string()))
+    }
+}
+
+// returns early with invalid input, the same as bail of anyhow
+macro_rules! bail {
+    ($($arg:tt)*) => {
+        return Err($crate::error::EmuError::Invalid(format!($($arg)*)))
+    };
+}
+
+pub(crate) use bail;
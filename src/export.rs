@@ -0,0 +1,100 @@
+// Writes every frame that differs from the one before as block characters, two pixels to a
+// character cell, either as an asciinema v2 cast or as plain text frames one after another. Frames
+// are timed by the emulated clock rather than the wall clock so a headless run exports at the
+// speed the program would have run at.
+
+use crate::{
+    error::{Context, Result},
+    json::json_string,
+    DisplayState, ExportFormat,
+};
+
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+#[derive(Debug)]
+pub(crate) struct Exporter {
+    out: BufWriter<File>,
+    format: ExportFormat,
+    last: Option<Vec<String>>,
+}
+
+impl Exporter {
+    pub(crate) fn create(
+        path: impl AsRef<Path>,
+        format: ExportFormat,
+        title: &str,
+        display: &DisplayState,
+    ) -> Result<Self> {
+        let file = File::create(path.as_ref())
+            .context(format!("create {}", path.as_ref().to_string_lossy()))?;
+        let mut out = BufWriter::new(file);
+
+        if format == ExportFormat::Cast {
+            writeln!(
+                out,
+                "{{\"version\":2,\"width\":{},\"height\":{},\"title\":{}}}",
+                display.width(),
+                (display.height() as usize).div_ceil(2),
+                json_string(title)
+            )?;
+        }
+
+        Ok(Self {
+            out,
+            format,
+            last: None,
+        })
+    }
+    pub(crate) fn frame(&mut self, frame: u64, seconds: f64, display: &DisplayState) -> Result<()> {
+        let rows = block_art(display);
+        if self.last.as_ref() == Some(&rows) {
+            return Ok(());
+        }
+
+        match self.format {
+            // the first frame clears the screen, later ones draw over it from the top left
+            ExportFormat::Cast => {
+                let clear = if self.last.is_none() { "\x1b[2J" } else { "" };
+                let text = format!("{}\x1b[H{}", clear, rows.join("\r\n"));
+                writeln!(self.out, "[{:.6},\"o\",{}]", seconds, json_string(&text))?;
+            }
+            ExportFormat::Text => {
+                writeln!(self.out, "frame {} at {:.3}s", frame, seconds)?;
+                for row in &rows {
+                    writeln!(self.out, "{}", row)?;
+                }
+                writeln!(self.out)?;
+            }
+        }
+
+        self.last = Some(rows);
+
+        Ok(())
+    }
+    pub(crate) fn finish(&mut self) -> Result<()> {
+        Ok(self.out.flush()?)
+    }
+}
+
+fn block_art(display: &DisplayState) -> Vec<String> {
+    let (width, height) = (display.width() as usize, display.height() as usize);
+    let pixel = |x: usize, y: usize| y < height && display.read_pixel((y * width + x) as u16);
+
+    (0..height)
+        .step_by(2)
+        .map(|y| {
+            (0..width)
+                .map(|x| match (pixel(x, y), pixel(x, y + 1)) {
+                    (true, true) => '█',
+                    (true, false) => '▀',
+                    (false, true) => '▄',
+                    (false, false) => ' ',
+                })
+                .collect()
+        })
+        .collect()
+}
@@ -0,0 +1,221 @@
+// Draws the display with wgpu through the pixels crate. The frame is uploaded as a texture the
+// size of the display and scaled up on the gpu, which keeps the pixels square whatever size the
+// window is resized to. Pixels of another aspect are made of several texture pixels, e.g. two
+// side by side for 2:1.
+
+use crate::{
+    error::{EmuError, Result},
+    image::Image,
+    keymap::{self, Hotkeys},
+    DisplayState, Input, Output, PixelAspect, Style, VisualBell, DISPLAY_PIXELS_WIDTH,
+};
+
+use pixels::{Pixels, SurfaceTexture};
+use std::{
+    sync::{
+        mpsc::{Receiver, Sender},
+        Arc,
+    },
+    thread::ScopedJoinHandle,
+    time::{Duration, Instant},
+};
+use winit::{
+    dpi::LogicalSize,
+    event::{ElementState, Event, KeyEvent, WindowEvent},
+    event_loop::{ControlFlow, EventLoop, EventLoopWindowTarget},
+    keyboard::{KeyCode, PhysicalKey},
+    window::{Window, WindowBuilder},
+};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+// opens the window up front so a missing gpu is reported before the emulation starts
+pub(crate) struct Gpu {
+    event_loop: EventLoop<()>,
+    window: Arc<Window>,
+    pixels: Pixels<'static>,
+}
+
+impl Gpu {
+    pub(crate) fn open(width: u32, height: u32, scale: u32, aspect: PixelAspect) -> Result<Self> {
+        let event_loop = EventLoop::new().map_err(|e| gpu_error("create event loop", e))?;
+
+        let (pixel_width, pixel_height) =
+            aspect.pixel_size(scale, DISPLAY_PIXELS_WIDTH as u32, height);
+        let window = WindowBuilder::new()
+            .with_title("chipate")
+            .with_inner_size(LogicalSize::new(width * pixel_width, height * pixel_height))
+            .build(&event_loop)
+            .map_err(|e| gpu_error("create window", e))?;
+        let window = Arc::new(window);
+
+        let (texels_wide, texels_tall) = aspect.ratio(DISPLAY_PIXELS_WIDTH as u32, height);
+        let size = window.inner_size();
+        let surface = SurfaceTexture::new(size.width, size.height, Arc::clone(&window));
+        let pixels = Pixels::new(width * texels_wide, height * texels_tall, surface)
+            .map_err(|e| gpu_error("create gpu surface", e))?;
+
+        Ok(Self {
+            event_loop,
+            window,
+            pixels,
+        })
+    }
+    pub(crate) fn present<T>(
+        self,
+        style: Style,
+        hotkeys: &Hotkeys,
+        outputs: &Receiver<Output>,
+        inputs: &Sender<Input>,
+        emulation: &ScopedJoinHandle<T>,
+    ) {
+        let Self {
+            event_loop,
+            window,
+            mut pixels,
+        } = self;
+
+        let mut style = style;
+        let mut hotkeys = hotkeys.clone();
+        let mut drawn: Option<(Vec<DisplayState>, Option<VisualBell>)> = None;
+
+        let result = event_loop.run(|event, target| {
+            let input = match event {
+                Event::WindowEvent {
+                    event: WindowEvent::CloseRequested,
+                    ..
+                } => Some(Input::Quit),
+                Event::WindowEvent {
+                    event:
+                        WindowEvent::KeyboardInput {
+                            event:
+                                KeyEvent {
+                                    physical_key: PhysicalKey::Code(code),
+                                    state,
+                                    repeat: false,
+                                    ..
+                                },
+                            ..
+                        },
+                    ..
+                } => key_name(code).and_then(|name| {
+                    Input::from_host_key(&hotkeys, &name, state == ElementState::Pressed)
+                }),
+                Event::WindowEvent {
+                    event: WindowEvent::Resized(size),
+                    ..
+                } => {
+                    if let Err(e) = pixels.resize_surface(size.width, size.height) {
+                        tracing::error!("resize gpu surface error: {}", e);
+                    }
+                    None
+                }
+                Event::WindowEvent {
+                    event: WindowEvent::RedrawRequested,
+                    ..
+                } => {
+                    if let Err(e) = pixels.render() {
+                        tracing::error!("gpu render error: {}", e);
+                        exit(target, inputs);
+                    }
+                    None
+                }
+                Event::AboutToWait => {
+                    // only the latest frame is worth drawing when the window has fallen behind
+                    let mut frame = None;
+                    for output in outputs.try_iter() {
+                        match output {
+                            Output::Frame(displays, bell) => frame = Some((displays, bell)),
+                            Output::Title(title) => window.set_title(&title),
+                            // only the sdl frontend opens a debug window
+                            Output::Debug(_) => {}
+                            // the frame sent after a reload is drawn even though it has not changed
+                            Output::Reload(reloaded_style, reloaded_hotkeys) => {
+                                style = reloaded_style;
+                                hotkeys = reloaded_hotkeys;
+                                drawn = None;
+                            }
+                        }
+                    }
+
+                    match frame {
+                        Some(frame) if drawn.as_ref() != Some(&frame) => {
+                            let image = Image::new(style.palette, &frame.0, frame.1);
+                            if let Err(e) = fill(&mut pixels, &image, style.pixel_aspect) {
+                                tracing::error!("gpu buffer error: {:#}", e);
+                                exit(target, inputs);
+                            }
+                            window.request_redraw();
+                            drawn = Some(frame);
+                        }
+                        _ if emulation.is_finished() => target.exit(),
+                        _ => {}
+                    }
+
+                    let next_poll = Instant::now() + POLL_INTERVAL;
+                    target.set_control_flow(ControlFlow::WaitUntil(next_poll));
+                    None
+                }
+                _ => None,
+            };
+
+            if let Some(input) = input {
+                if inputs.send(input).is_err() {
+                    target.exit();
+                }
+            }
+        });
+
+        if let Err(e) = result {
+            tracing::error!("event loop error: {}", e);
+            let _ = inputs.send(Input::Quit);
+        }
+    }
+}
+
+// the emulation is asked to stop and the window stays open until it has
+fn exit(target: &EventLoopWindowTarget<()>, inputs: &Sender<Input>) {
+    if inputs.send(Input::Quit).is_err() {
+        target.exit();
+    }
+}
+
+fn fill(pixels: &mut Pixels, image: &Image, aspect: PixelAspect) -> Result<()> {
+    let (texels_wide, texels_tall) = aspect.ratio(DISPLAY_PIXELS_WIDTH as u32, image.height as u32);
+    let (width, height) = (
+        image.width as u32 * texels_wide,
+        image.height as u32 * texels_tall,
+    );
+    let texture = pixels.texture();
+    if (texture.width(), texture.height()) != (width, height) {
+        pixels
+            .resize_buffer(width, height)
+            .map_err(|e| gpu_error("resize gpu buffer", e))?;
+    }
+
+    for (idx, rgba) in pixels.frame_mut().chunks_exact_mut(4).enumerate() {
+        let (x, y) = (idx as u32 % width, idx as u32 / width);
+        let pixel = image.pixel((x / texels_wide) as usize, (y / texels_tall) as usize);
+        let color = image.colors[pixel as usize];
+        rgba.copy_from_slice(&[color.r, color.g, color.b, 0xFF]);
+    }
+
+    Ok(())
+}
+
+// winit names physical keys like KeyA, Digit1, F5 and Escape, physical keys so the keypad keeps
+// the same shape on every keyboard layout
+fn key_name(code: KeyCode) -> Option<String> {
+    let name = format!("{:?}", code);
+    let name = name
+        .strip_prefix("Key")
+        .or(name.strip_prefix("Digit"))
+        .unwrap_or(&name)
+        .to_lowercase();
+
+    keymap::is_host_key(&name).then_some(name)
+}
+
+fn gpu_error(context: &str, e: impl std::fmt::Display) -> EmuError {
+    EmuError::Gpu(format!("{}: {}", context, e))
+}
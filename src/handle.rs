@@ -0,0 +1,91 @@
+use crate::{
+    core::Program,
+    error::{bail, EmuError, Result},
+};
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Sender},
+        Arc,
+    },
+    time::Duration,
+};
+
+// how long a caller waits for the emulation loop, which only handles commands once per frame and
+// not at all while the debugger prompt is open
+const REPLY_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Debug)]
+pub enum Command {
+    Pause,
+    Resume,
+    Reset,
+    LoadRom(Program),
+    SaveState,
+    LoadState,
+    Registers,
+}
+
+// commands are answered with a json body on success or an error message
+#[derive(Debug)]
+pub(crate) struct Request {
+    pub(crate) command: Command,
+    pub(crate) reply: Sender<Result<String, String>>,
+}
+
+// controls a running emu from any thread, every method blocks until the command was handled
+#[derive(Clone, Debug)]
+pub struct EmuHandle {
+    requests: Sender<Request>,
+    stop: Arc<AtomicBool>,
+}
+
+impl EmuHandle {
+    pub(crate) fn new(requests: Sender<Request>, stop: Arc<AtomicBool>) -> Self {
+        Self { requests, stop }
+    }
+    // asks the emulation loop to exit after the current instruction, unlike the other methods
+    // this never blocks so it is safe to call from a signal handler thread
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+    pub fn is_stopping(&self) -> bool {
+        self.stop.load(Ordering::Relaxed)
+    }
+    pub fn pause(&self) -> Result<()> {
+        self.send(Command::Pause).map(|_| ())
+    }
+    pub fn resume(&self) -> Result<()> {
+        self.send(Command::Resume).map(|_| ())
+    }
+    pub fn reset(&self) -> Result<()> {
+        self.send(Command::Reset).map(|_| ())
+    }
+    pub fn load_rom(&self, program: Program) -> Result<()> {
+        self.send(Command::LoadRom(program)).map(|_| ())
+    }
+    pub fn save_state(&self) -> Result<()> {
+        self.send(Command::SaveState).map(|_| ())
+    }
+    pub fn load_state(&self) -> Result<()> {
+        self.send(Command::LoadState).map(|_| ())
+    }
+    // the registers and timers as json
+    pub fn registers(&self) -> Result<String> {
+        self.send(Command::Registers)
+    }
+    // returns the json reply of the emulation loop
+    pub fn send(&self, command: Command) -> Result<String> {
+        let (reply, replies) = mpsc::channel();
+
+        self.requests
+            .send(Request { command, reply })
+            .map_err(|_| EmuError::Stopped)?;
+
+        match replies.recv_timeout(REPLY_TIMEOUT) {
+            Ok(result) => result.map_err(EmuError::Invalid),
+            Err(_) => bail!("emulator did not respond"),
+        }
+    }
+}
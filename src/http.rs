@@ -0,0 +1,140 @@
+// A minimal HTTP/1.1 control API, every response body is json.
+//
+//   POST /pause         stops executing instructions and decrementing timers
+//   POST /resume        continues execution
+//   POST /reset         resets the machine and reloads the current rom
+//   POST /rom           replaces the current rom with the request body and resets
+//   POST /state/save    snapshots the cpu, memory and display
+//   POST /state/load    restores the last snapshot
+//   GET  /registers     returns the program counter, index, v registers and timers
+
+use crate::{
+    core::Program,
+    error::{Context, Result},
+    handle::{Command, EmuHandle},
+};
+
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+    time::Duration,
+};
+
+// the largest rom that fits in memory after the interpreter area
+const MAX_BODY_LEN: usize = 4096 - 0x200;
+
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub fn serve(addr: impl ToSocketAddrs, handle: EmuHandle) -> Result<()> {
+    let listener = TcpListener::bind(addr).context("bind http listener")?;
+    tracing::info!(
+        "serving http control api on {}",
+        listener.local_addr().context("http listener address")?
+    );
+
+    std::thread::Builder::new()
+        .name(String::from("http"))
+        .spawn(move || {
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(stream) => {
+                        if let Err(e) = handle_connection(stream, &handle) {
+                            tracing::debug!("http request error: {}", e);
+                        }
+                    }
+                    Err(e) => tracing::error!("http accept error: {}", e),
+                }
+            }
+        })
+        .context("spawn http thread")?;
+
+    Ok(())
+}
+
+fn handle_connection(mut stream: TcpStream, handle: &EmuHandle) -> Result<()> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+
+    let mut reader = BufReader::new(&stream);
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let path = parts.next().unwrap_or_default().to_string();
+
+    let mut content_len = 0;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+
+        if let Some((name, value)) = header.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                content_len = value.trim().parse().context("invalid content length")?;
+            }
+        }
+    }
+
+    if content_len > MAX_BODY_LEN {
+        return respond(&mut stream, 413, "rom is too large");
+    }
+
+    let mut body = vec![0_u8; content_len];
+    reader.read_exact(&mut body)?;
+
+    let command = match (method.as_str(), path.as_str()) {
+        ("POST", "/pause") => Command::Pause,
+        ("POST", "/resume") => Command::Resume,
+        ("POST", "/reset") => Command::Reset,
+        ("POST", "/rom") if body.is_empty() => {
+            return respond(&mut stream, 400, "request body must contain the rom")
+        }
+        ("POST", "/rom") => match Program::new(String::from("http"), body) {
+            Ok(program) => Command::LoadRom(program),
+            Err(e) => return respond(&mut stream, 400, &e.to_string()),
+        },
+        ("POST", "/state/save") => Command::SaveState,
+        ("POST", "/state/load") => Command::LoadState,
+        ("GET", "/registers") => Command::Registers,
+        (
+            _,
+            "/pause" | "/resume" | "/reset" | "/rom" | "/state/save" | "/state/load" | "/registers",
+        ) => return respond(&mut stream, 405, "method not allowed"),
+        _ => return respond(&mut stream, 404, "not found"),
+    };
+
+    match handle.send(command) {
+        Ok(json) => write_response(&mut stream, 200, &json),
+        Err(e) => respond(&mut stream, 409, &e.to_string()),
+    }
+}
+
+fn respond(stream: &mut TcpStream, status: u16, error: &str) -> Result<()> {
+    let json = format!("{{\"error\":\"{}\"}}", error.replace('"', "'"));
+    write_response(stream, status, &json)
+}
+
+fn write_response(stream: &mut TcpStream, status: u16, json: &str) -> Result<()> {
+    let reason = match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        413 => "Payload Too Large",
+        _ => "Internal Server Error",
+    };
+
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        reason,
+        json.len(),
+        json
+    )?;
+
+    Ok(stream.flush()?)
+}
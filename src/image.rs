@@ -0,0 +1,74 @@
+// The displays side by side with a blank column between them for frontends that draw pixels
+// themselves, every pixel is an index into the colors so a frontend can use a palette where it has
+// one.
+
+use crate::{
+    palette::{Color, Palette},
+    DisplayState, VisualBell,
+};
+
+pub(crate) struct Image {
+    pub(crate) width: usize,
+    pub(crate) height: usize,
+    pub(crate) pixels: Vec<u8>,
+    pub(crate) colors: [Color; 3],
+}
+
+pub(crate) const OFF: u8 = 0;
+
+pub(crate) const ON: u8 = 1;
+
+pub(crate) const ACCENT: u8 = 2;
+
+impl Image {
+    pub(crate) fn new(
+        palette: Palette,
+        displays: &[DisplayState],
+        bell: Option<VisualBell>,
+    ) -> Self {
+        let (off, on) = match bell {
+            Some(VisualBell::Invert) => (palette.foreground(), palette.background()),
+            _ => (palette.background(), palette.foreground()),
+        };
+
+        let width = displays
+            .iter()
+            .map(|d| d.width() as usize + 1)
+            .sum::<usize>()
+            - 1;
+        let height = displays
+            .iter()
+            .map(|d| d.height() as usize)
+            .max()
+            .unwrap_or(0);
+
+        let mut pixels = vec![OFF; width * height];
+        let mut left = 0;
+        for display in displays {
+            let (w, h) = (display.width() as usize, display.height() as usize);
+
+            for y in 0..h {
+                for x in 0..w {
+                    let edge = x == 0 || x == w - 1 || y == 0 || y == h - 1;
+                    pixels[y * width + left + x] = match display.read_pixel((y * w + x) as u16) {
+                        true => ON,
+                        false if edge && bell == Some(VisualBell::Border) => ACCENT,
+                        false => OFF,
+                    };
+                }
+            }
+
+            left += w + 1;
+        }
+
+        Self {
+            width,
+            height,
+            pixels,
+            colors: [off, on, palette.accent()],
+        }
+    }
+    pub(crate) fn pixel(&self, x: usize, y: usize) -> u8 {
+        self.pixels[y * self.width + x]
+    }
+}
@@ -0,0 +1,104 @@
+// Recorded keypad input is a text file of the frame each key changed on, counted from when the rom
+// was loaded, the key in hex and whether it went down or up:
+//
+//   # chipate inputs
+//   120 5 down
+//   126 5 up
+//
+// Frames follow the emulated clock so a recording replays the same however fast the host is,
+// though the rom only behaves the same when it was recorded with the same seed.
+
+use crate::{
+    error::{Context, EmuError, Result},
+    netplay::KeyEvent,
+};
+
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Recording {
+    events: Vec<(u64, KeyEvent)>,
+}
+
+impl Recording {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let text = std::fs::read_to_string(path.as_ref())
+            .context(format!("read {}", path.as_ref().to_string_lossy()))?;
+
+        Self::parse(&text).context(format!("parse {}", path.as_ref().to_string_lossy()))
+    }
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut events = Vec::new();
+
+        for (idx, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let event = parse_event(line).ok_or_else(|| {
+                EmuError::Invalid(format!("line {}: invalid input '{}'", idx + 1, line))
+            })?;
+            events.push(event);
+        }
+
+        // events are replayed in frame order whatever order they were written in
+        events.sort_by_key(|(frame, _)| *frame);
+
+        Ok(Self { events })
+    }
+    // the events of the frames from `from` up to but not including `to`
+    pub fn events(&self, from: u64, to: u64) -> impl Iterator<Item = KeyEvent> + '_ {
+        let start = self.events.partition_point(|(frame, _)| *frame < from);
+        self.events[start..]
+            .iter()
+            .take_while(move |(frame, _)| *frame < to)
+            .map(|(_, event)| *event)
+    }
+}
+
+fn parse_event(line: &str) -> Option<(u64, KeyEvent)> {
+    let mut fields = line.split_whitespace();
+
+    let frame = fields.next()?.parse().ok()?;
+    let key = u8::from_str_radix(fields.next()?, 16)
+        .ok()
+        .filter(|k| *k < 16)?;
+    let pressed = match fields.next()? {
+        "down" => true,
+        "up" => false,
+        _ => return None,
+    };
+
+    fields
+        .next()
+        .is_none()
+        .then_some((frame, KeyEvent { key, pressed }))
+}
+
+#[derive(Debug)]
+pub(crate) struct Recorder {
+    out: BufWriter<File>,
+}
+
+impl Recorder {
+    pub(crate) fn create(path: impl AsRef<Path>) -> Result<Self> {
+        let file = File::create(path.as_ref())
+            .context(format!("create {}", path.as_ref().to_string_lossy()))?;
+        let mut out = BufWriter::new(file);
+        writeln!(out, "# chipate inputs")?;
+
+        Ok(Self { out })
+    }
+    pub(crate) fn record(&mut self, frame: u64, event: KeyEvent) -> Result<()> {
+        let state = if event.pressed { "down" } else { "up" };
+        Ok(writeln!(self.out, "{} {:x} {}", frame, event.key, state)?)
+    }
+    pub(crate) fn finish(&mut self) -> Result<()> {
+        Ok(self.out.flush()?)
+    }
+}
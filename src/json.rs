@@ -0,0 +1,236 @@
+// Just enough of a json reader for the documents chipate reads back in, the ones it writes are put
+// together with format! instead. Numbers are kept as f64 the way javascript keeps them.
+
+use crate::error::{bail, EmuError, Result};
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    pub(crate) fn parse(text: &str) -> Result<Self> {
+        let mut parser = Parser {
+            bytes: text.as_bytes(),
+            pos: 0,
+        };
+
+        let value = parser.value()?;
+        parser.whitespace();
+        if parser.pos < parser.bytes.len() {
+            bail!(
+                "unexpected text after the json value at byte {}",
+                parser.pos
+            );
+        }
+
+        Ok(value)
+    }
+    pub(crate) fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(fields) => fields
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+    // whole numbers only, a fraction is as wrong as a string where a register is expected
+    pub(crate) fn as_u64(&self) -> Option<u64> {
+        match self {
+            Value::Number(n) if n.fract() == 0.0 && *n >= 0.0 && *n <= u64::MAX as f64 => {
+                Some(*n as u64)
+            }
+            _ => None,
+        }
+    }
+    pub(crate) fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+    pub(crate) fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Value::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+}
+
+struct Parser<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn value(&mut self) -> Result<Value> {
+        self.whitespace();
+        match self.peek() {
+            Some(b'{') => self.object(),
+            Some(b'[') => self.array(),
+            Some(b'"') => Ok(Value::String(self.string()?)),
+            Some(b't') => self.literal("true", Value::Bool(true)),
+            Some(b'f') => self.literal("false", Value::Bool(false)),
+            Some(b'n') => self.literal("null", Value::Null),
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            Some(c) => bail!("unexpected '{}' at byte {}", c as char, self.pos),
+            None => bail!("json ends before a value"),
+        }
+    }
+    fn object(&mut self) -> Result<Value> {
+        self.pos += 1;
+        let mut fields = Vec::new();
+
+        self.whitespace();
+        if self.peek() == Some(b'}') {
+            self.pos += 1;
+            return Ok(Value::Object(fields));
+        }
+
+        loop {
+            self.whitespace();
+            if self.peek() != Some(b'"') {
+                bail!("expected a field name at byte {}", self.pos);
+            }
+            let name = self.string()?;
+
+            self.whitespace();
+            self.expect(b':')?;
+            fields.push((name, self.value()?));
+
+            self.whitespace();
+            match self.next() {
+                Some(b',') => continue,
+                Some(b'}') => return Ok(Value::Object(fields)),
+                _ => bail!("expected ',' or '}}' at byte {}", self.pos - 1),
+            }
+        }
+    }
+    fn array(&mut self) -> Result<Value> {
+        self.pos += 1;
+        let mut values = Vec::new();
+
+        self.whitespace();
+        if self.peek() == Some(b']') {
+            self.pos += 1;
+            return Ok(Value::Array(values));
+        }
+
+        loop {
+            values.push(self.value()?);
+
+            self.whitespace();
+            match self.next() {
+                Some(b',') => continue,
+                Some(b']') => return Ok(Value::Array(values)),
+                _ => bail!("expected ',' or ']' at byte {}", self.pos - 1),
+            }
+        }
+    }
+    fn string(&mut self) -> Result<String> {
+        self.pos += 1;
+        let mut bytes = Vec::new();
+
+        loop {
+            match self.next() {
+                Some(b'"') => break,
+                Some(b'\\') => {
+                    let c = match self.next() {
+                        Some(b'"') => '"',
+                        Some(b'\\') => '\\',
+                        Some(b'/') => '/',
+                        Some(b'b') => '\u{8}',
+                        Some(b'f') => '\u{c}',
+                        Some(b'n') => '\n',
+                        Some(b'r') => '\r',
+                        Some(b't') => '\t',
+                        // a surrogate pair is two escapes, neither half is a char on its own
+                        Some(b'u') => char::from_u32(self.hex4()?).unwrap_or('\u{fffd}'),
+                        _ => bail!("invalid escape at byte {}", self.pos - 1),
+                    };
+                    bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+                }
+                Some(byte) => bytes.push(byte),
+                None => bail!("json ends inside a string"),
+            }
+        }
+
+        // the text came from a str and escapes are encoded whole so the bytes are still utf-8
+        Ok(String::from_utf8(bytes)?)
+    }
+    fn hex4(&mut self) -> Result<u32> {
+        let Some(digits) = self.bytes.get(self.pos..self.pos + 4) else {
+            bail!("json ends inside an escape");
+        };
+        self.pos += 4;
+
+        let digits = std::str::from_utf8(digits)?;
+        u32::from_str_radix(digits, 16)
+            .map_err(|_| EmuError::Invalid(format!("invalid escape '\\u{}'", digits)))
+    }
+    fn number(&mut self) -> Result<Value> {
+        let start = self.pos;
+        while matches!(
+            self.peek(),
+            Some(b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9')
+        ) {
+            self.pos += 1;
+        }
+
+        let text = std::str::from_utf8(&self.bytes[start..self.pos])?;
+        match text.parse() {
+            Ok(n) => Ok(Value::Number(n)),
+            Err(_) => bail!("invalid number '{}' at byte {}", text, start),
+        }
+    }
+    fn literal(&mut self, word: &str, value: Value) -> Result<Value> {
+        if !self.bytes[self.pos..].starts_with(word.as_bytes()) {
+            bail!("unexpected text at byte {}", self.pos);
+        }
+        self.pos += word.len();
+
+        Ok(value)
+    }
+    fn expect(&mut self, byte: u8) -> Result<()> {
+        match self.next() {
+            Some(b) if b == byte => Ok(()),
+            _ => bail!("expected '{}' at byte {}", byte as char, self.pos - 1),
+        }
+    }
+    fn whitespace(&mut self) {
+        while matches!(self.peek(), Some(b' ' | b'\t' | b'\n' | b'\r')) {
+            self.pos += 1;
+        }
+    }
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.pos).copied()
+    }
+    fn next(&mut self) -> Option<u8> {
+        let byte = self.peek();
+        self.pos += 1;
+        byte
+    }
+}
+
+pub(crate) fn json_string(text: &str) -> String {
+    let mut json = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            c if (c as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+
+    json
+}
@@ -0,0 +1,320 @@
+// Host keys are named the same way in every frontend: a letter or digit, f1 to f12, escape,
+// backspace, tab, space or enter. The keypad layout, the emulator hotkeys and input macros all map
+// from these names, hotkeys first and macros second so a binding can be moved off a key a rom needs
+// without the two colliding.
+//
+// A macro plays a sequence of keypad presses when its host key is pressed. It is written as steps
+// separated by commas, each the keypad keys held together joined with + and optionally the number
+// of frames they are held for after a colon, or - for a step holding nothing:
+//
+//   5:3, 6            hold 5 for three frames, then 6 for one
+//   4+6:10, -:30, 5   hold 4 and 6 together, wait half a second, then press 5
+//
+// The keys of a step are released a frame before the next step presses its own so the same key in
+// two steps in a row is seen as two presses.
+
+use crate::{netplay::KeyEvent, Key};
+
+use std::str::FromStr;
+
+const NAMED_KEYS: [&str; 5] = ["escape", "backspace", "tab", "space", "enter"];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Hotkey {
+    Quit,
+    Pause,
+    Reset,
+    SaveState,
+    LoadState,
+    Rewind,
+    DumpMemory,
+    PixelGrid,
+    ExportSvg,
+}
+
+impl Hotkey {
+    pub const ALL: [Hotkey; 9] = [
+        Hotkey::Quit,
+        Hotkey::Pause,
+        Hotkey::Reset,
+        Hotkey::SaveState,
+        Hotkey::LoadState,
+        Hotkey::Rewind,
+        Hotkey::DumpMemory,
+        Hotkey::PixelGrid,
+        Hotkey::ExportSvg,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Hotkey::Quit => "quit",
+            Hotkey::Pause => "pause",
+            Hotkey::Reset => "reset",
+            Hotkey::SaveState => "save-state",
+            Hotkey::LoadState => "load-state",
+            Hotkey::Rewind => "rewind",
+            Hotkey::DumpMemory => "dump-memory",
+            Hotkey::PixelGrid => "pixel-grid",
+            Hotkey::ExportSvg => "export-svg",
+        }
+    }
+}
+
+impl FromStr for Hotkey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Hotkey::ALL
+            .into_iter()
+            .find(|hotkey| hotkey.name() == s)
+            .ok_or_else(|| {
+                format!(
+                    "invalid hotkey '{}': expected quit, pause, reset, save-state, load-state, rewind, dump-memory, pixel-grid or export-svg",
+                    s
+                )
+            })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Macro {
+    steps: Vec<(Vec<u8>, u64)>,
+}
+
+impl Macro {
+    // the key events of the macro by the frame they happen on, counted from when it starts
+    pub fn events(&self) -> Vec<(u64, KeyEvent)> {
+        let mut events = Vec::new();
+        let mut frame = 0;
+
+        for (keys, frames) in &self.steps {
+            if keys.is_empty() {
+                frame += frames;
+                continue;
+            }
+
+            for key in keys {
+                events.push((
+                    frame,
+                    KeyEvent {
+                        key: *key,
+                        pressed: true,
+                    },
+                ));
+            }
+            frame += frames;
+            for key in keys {
+                events.push((
+                    frame,
+                    KeyEvent {
+                        key: *key,
+                        pressed: false,
+                    },
+                ));
+            }
+            frame += 1;
+        }
+
+        events
+    }
+}
+
+impl FromStr for Macro {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "invalid macro '{}': expected steps like 5:3, 6 of keypad keys 0 to f joined with + \
+                 and the frames to hold them, or - to hold nothing",
+                s
+            )
+        };
+
+        let mut steps = Vec::new();
+        for step in s.split(',') {
+            let (keys, frames) = match step.split_once(':') {
+                Some((keys, frames)) => (keys.trim(), frames.trim()),
+                None => (step.trim(), "1"),
+            };
+
+            let frames = frames
+                .parse::<u64>()
+                .ok()
+                .filter(|frames| *frames > 0)
+                .ok_or_else(invalid)?;
+
+            let keys = match keys {
+                "-" => Vec::new(),
+                keys => keys
+                    .split('+')
+                    .map(|key| match key.trim() {
+                        key if key.len() == 1 => u8::from_str_radix(key, 16).ok(),
+                        _ => None,
+                    })
+                    .collect::<Option<Vec<u8>>>()
+                    .ok_or_else(invalid)?,
+            };
+
+            steps.push((keys, frames));
+        }
+
+        Ok(Self { steps })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Hotkeys {
+    bindings: Vec<(Hotkey, String)>,
+    macros: Vec<(String, Macro)>,
+}
+
+impl Hotkeys {
+    // none leaves the hotkey unbound
+    pub fn bind(&mut self, hotkey: Hotkey, key: &str) -> Result<(), String> {
+        self.bindings.retain(|(bound, _)| *bound != hotkey);
+
+        if key == "none" {
+            return Ok(());
+        }
+
+        if !is_host_key(key) {
+            return Err(format!(
+                "invalid key '{}': expected a letter, a digit, f1 to f12, escape, backspace, tab, space or enter",
+                key
+            ));
+        }
+
+        if keypad_key(key).is_some() {
+            tracing::warn!(
+                "{} is bound to {}, which is also a keypad key that roms will no longer see",
+                hotkey.name(),
+                key
+            );
+        }
+
+        self.bindings.push((hotkey, String::from(key)));
+        Ok(())
+    }
+    pub fn bind_macro(&mut self, key: &str, steps: Macro) -> Result<(), String> {
+        if !is_host_key(key) {
+            return Err(format!(
+                "invalid key '{}': expected a letter, a digit, f1 to f12, escape, backspace, tab, space or enter",
+                key
+            ));
+        }
+
+        if keypad_key(key).is_some() {
+            tracing::warn!(
+                "a macro is bound to {}, which is also a keypad key that roms will no longer see",
+                key
+            );
+        }
+
+        self.macros.retain(|(bound, _)| bound != key);
+        self.macros.push((String::from(key), steps));
+        Ok(())
+    }
+    // checked once all bindings are made so two hotkeys can swap keys
+    pub fn validate(&self) -> Result<(), String> {
+        for (idx, (hotkey, key)) in self.bindings.iter().enumerate() {
+            if let Some((other, _)) = self.bindings[idx + 1..].iter().find(|(_, k)| k == key) {
+                return Err(format!(
+                    "key '{}' is bound to both {} and {}",
+                    key,
+                    hotkey.name(),
+                    other.name()
+                ));
+            }
+
+            if self.macros.iter().any(|(k, _)| k == key) {
+                return Err(format!(
+                    "key '{}' is bound to both {} and a macro",
+                    key,
+                    hotkey.name()
+                ));
+            }
+        }
+
+        Ok(())
+    }
+    pub fn key(&self, hotkey: Hotkey) -> Option<&str> {
+        self.bindings
+            .iter()
+            .find(|(bound, _)| *bound == hotkey)
+            .map(|(_, key)| key.as_str())
+    }
+    pub fn get(&self, key: &str) -> Option<Hotkey> {
+        self.bindings
+            .iter()
+            .find(|(_, bound)| bound == key)
+            .map(|(hotkey, _)| *hotkey)
+    }
+    pub fn macro_for(&self, key: &str) -> Option<&Macro> {
+        self.macros
+            .iter()
+            .find(|(bound, _)| bound == key)
+            .map(|(_, steps)| steps)
+    }
+}
+
+// none of the defaults are on the keypad
+impl Default for Hotkeys {
+    fn default() -> Self {
+        let bindings = [
+            (Hotkey::Quit, "escape"),
+            (Hotkey::Pause, "p"),
+            (Hotkey::Reset, "f2"),
+            (Hotkey::SaveState, "f5"),
+            (Hotkey::LoadState, "f9"),
+            (Hotkey::Rewind, "backspace"),
+            (Hotkey::DumpMemory, "f12"),
+            (Hotkey::PixelGrid, "f3"),
+            (Hotkey::ExportSvg, "f10"),
+        ];
+
+        Self {
+            bindings: bindings
+                .into_iter()
+                .map(|(hotkey, key)| (hotkey, String::from(key)))
+                .collect(),
+            macros: Vec::new(),
+        }
+    }
+}
+
+pub fn is_host_key(name: &str) -> bool {
+    let single = name.len() == 1
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit());
+    let function = (1..=12).any(|n| name == format!("f{}", n));
+
+    single || function || NAMED_KEYS.contains(&name)
+}
+
+// the keys in the same place on a qwerty keyboard as the cosmac vip keypad
+pub fn keypad_key(name: &str) -> Option<Key> {
+    match name {
+        "1" => Some(Key::Num1),
+        "2" => Some(Key::Num2),
+        "3" => Some(Key::Num3),
+        "4" => Some(Key::C),
+        "q" => Some(Key::Num4),
+        "w" => Some(Key::Num5),
+        "e" => Some(Key::Num6),
+        "r" => Some(Key::D),
+        "a" => Some(Key::Num7),
+        "s" => Some(Key::Num8),
+        "d" => Some(Key::Num9),
+        "f" => Some(Key::E),
+        "z" => Some(Key::A),
+        "x" => Some(Key::Num0),
+        "c" => Some(Key::B),
+        "v" => Some(Key::F),
+        _ => None,
+    }
+}
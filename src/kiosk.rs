@@ -0,0 +1,130 @@
+// A playlist for kiosk mode has a rom on every line, optionally followed by how many seconds it
+// runs for, inputs recorded with --record-inputs to replay and the mode to run it in, each after
+// a |, relative paths are relative to the playlist:
+//
+//   # blank lines and lines starting with # are skipped
+//   roms/Space Invaders.ch8 | seconds=45 | inputs=invaders.inputs
+//   roms/Car Race.ch8 | mode=schip
+//
+// Every rom is loaded up front so a broken playlist fails before anything runs, after the last
+// rom the playlist starts over.
+
+use crate::{
+    core::{cpu::Mode, Program},
+    error::{bail, Context, EmuError, Result},
+    inputs::Recording,
+};
+
+use std::path::Path;
+
+#[derive(Clone, Debug)]
+pub struct Entry {
+    pub program: Program,
+    pub seconds: u64,
+    pub mode: Option<Mode>,
+    pub inputs: Option<Recording>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Playlist {
+    entries: Vec<Entry>,
+}
+
+impl Playlist {
+    pub fn load(path: impl AsRef<Path>, seconds: u64) -> Result<Self> {
+        let path = path.as_ref();
+        let text =
+            std::fs::read_to_string(path).context(format!("read {}", path.to_string_lossy()))?;
+        let dir = path.parent().unwrap_or(Path::new(""));
+
+        let mut entries = Vec::new();
+
+        for (idx, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let entry = parse_entry(line, dir, seconds).context(format!(
+                "{} line {}",
+                path.to_string_lossy(),
+                idx + 1
+            ))?;
+            entries.push(entry);
+        }
+
+        if entries.is_empty() {
+            bail!("playlist {} has no roms", path.to_string_lossy());
+        }
+
+        Ok(Self { entries })
+    }
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+    // wraps around to the start after the last entry
+    pub fn entry(&self, idx: usize) -> &Entry {
+        &self.entries[idx % self.entries.len()]
+    }
+}
+
+fn parse_entry(line: &str, dir: &Path, seconds: u64) -> Result<Entry> {
+    let mut fields = line.split('|').map(str::trim);
+    let rom = fields.next().unwrap_or_default();
+
+    let mut entry = Entry {
+        program: Program::from_file(dir.join(rom)).context("load rom")?,
+        seconds,
+        mode: None,
+        inputs: None,
+    };
+
+    for field in fields {
+        match field.split_once('=').map(|(k, v)| (k.trim(), v.trim())) {
+            Some(("seconds", value)) => {
+                entry.seconds = value
+                    .parse()
+                    .ok()
+                    .filter(|s| *s > 0)
+                    .context(format!("invalid seconds '{}'", value))?
+            }
+            Some(("mode", value)) => {
+                entry.mode = Some(value.parse().map_err(EmuError::Invalid)?);
+            }
+            Some(("inputs", value)) => {
+                entry.inputs = Some(Recording::load(dir.join(value)).context("load inputs")?);
+            }
+            _ => bail!(
+                "invalid option '{}': expected seconds=N, inputs=PATH or mode=MODE",
+                field
+            ),
+        }
+    }
+
+    Ok(entry)
+}
+
+// where kiosk mode is in its playlist
+#[derive(Debug)]
+pub(crate) struct Kiosk {
+    playlist: Playlist,
+    current: usize,
+}
+
+impl Kiosk {
+    pub(crate) fn new(playlist: Playlist) -> Self {
+        Self {
+            playlist,
+            current: 0,
+        }
+    }
+    pub(crate) fn entry(&self) -> &Entry {
+        self.playlist.entry(self.current)
+    }
+    pub(crate) fn advance(&mut self) {
+        self.current = (self.current + 1) % self.playlist.len();
+    }
+}
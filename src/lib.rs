@@ -1,13 +1,87 @@
+pub mod audio;
+pub mod bezel;
+mod clock;
 pub mod core;
+mod debug_view;
+pub mod debugger;
+#[cfg(feature = "discord")]
+pub mod discord;
+pub mod error;
+mod export;
+#[cfg(feature = "pixels")]
+mod gpu;
+pub mod handle;
+#[cfg(feature = "http-api")]
+pub mod http;
+mod image;
+mod inputs;
+mod json;
+pub mod keymap;
+pub mod kiosk;
+pub mod netplay;
+pub mod observer;
+pub mod palette;
+mod rewind;
+#[cfg(feature = "sdl")]
+mod sdl;
+pub mod settings;
+pub mod sink;
+mod state;
+pub mod storage;
+mod svg;
+mod tui;
+pub mod websocket;
 
-use crate::core::{
-    cpu::{Mode, CPU},
-    memory::RAM,
-    Font, Program,
+pub use crate::core::gfx::DisplayState;
+pub use crate::error::{EmuError, Result};
+#[cfg(feature = "sdl")]
+pub use crate::sdl::Embedded;
+
+use crate::{
+    audio::{Audio, AudioBackend, AudioSink, Bell},
+    bezel::Bezel,
+    clock::{Ticker, MAX_CATCH_UP},
+    core::{
+        cheat::Cheat,
+        coverage::{Coverage, CoverageFormat},
+        cpu::{Mode, Quirks, CPU},
+        dump,
+        machine::Machine,
+        memory::RAM_SIZE,
+        symbols::SymbolTable,
+        Font, Program,
+    },
+    debug_view::{DebugView, Heatmap},
+    debugger::{Action, Debugger, DebuggerConfig},
+    error::Context,
+    export::Exporter,
+    handle::{Command, EmuHandle, Request},
+    inputs::Recorder,
+    keymap::{Hotkey, Hotkeys},
+    kiosk::{Kiosk, Playlist},
+    netplay::{KeyEvent, Netplay},
+    observer::{FrameObserver, Observers},
+    palette::Palette,
+    rewind::Rewind,
+    sink::{DisplaySink, Sinks},
+    state::Snapshot,
+    storage::RomData,
+    websocket::DisplayServer,
 };
 
-use sdl2::{event::Event, keyboard::Keycode, pixels::Color, rect::Rect};
-use std::time::Instant;
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Arc,
+    },
+    thread::ScopedJoinHandle,
+    time::{Duration, Instant},
+};
 
 pub const PROGRAM_START_ADDR: u16 = 0x200;
 
@@ -15,43 +89,493 @@ pub const DISPLAY_PIXELS_WIDTH: u8 = 64;
 
 pub const DISPLAY_PIXELS_HEIGHT: u8 = 32;
 
-const NUM_PIXELS: usize = 64 * 32;
+// the two page hi-res variants are the tallest display supported
+pub const MAX_DISPLAY_PIXELS_HEIGHT: u8 = 64;
 
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Config {
     pub mode: Mode,
+    pub quirks: Vec<(String, bool)>,
     pub instructions_per_sec: u16,
+    pub timer_hz: u16,
     pub font: Font,
+    pub symbols: SymbolTable,
+    pub cheats: Vec<Cheat>,
+    pub freezes: Vec<Cheat>,
+    pub debugger: DebuggerConfig,
+    pub debug_window: bool,
+    pub seed: Option<u64>,
+    pub compare: Option<Mode>,
+    pub headless: bool,
+    pub autosave: Option<String>,
+    // the slot the save-state and load-state hotkeys use
+    pub state_slot: u8,
+    pub frame_hashes: Option<String>,
+    pub export: Option<String>,
+    pub export_format: ExportFormat,
+    // the last frame as an svg, written when the emulation finishes
+    pub export_svg: Option<String>,
+    pub coverage: Option<String>,
+    pub coverage_format: CoverageFormat,
+    pub record_inputs: Option<String>,
+    pub exit_after_frames: Option<u64>,
+    pub exit_after_time: Option<Duration>,
+    pub strict: bool,
+    pub visual_bell: Option<VisualBell>,
+    pub audio: AudioBackend,
+    // samples per audio buffer, the backend picks one for the audio driver when not set
+    pub audio_buffer: Option<u16>,
+    // percent of the full strength of the game controller motors run while the sound plays
+    pub rumble: Option<u8>,
+    pub palette: Palette,
+    pub pixel_pattern: bool,
+    pub pixel_aspect: PixelAspect,
+    pub filter: Filter,
+    pub pixel_grid: PixelGrid,
+    pub bezel: Option<Bezel>,
+    pub scale: u32,
+    pub resolution: Option<Resolution>,
+    pub rewind: Option<Duration>,
+    pub renderer: Renderer,
+    pub vsync: bool,
+    pub frontend: Frontend,
+    pub terminal_graphics: TerminalGraphics,
+    // draws every frame in the terminal as well as in the window of the frontend
+    pub terminal_mirror: bool,
+    // frames a key stays down for however briefly it was tapped, a rom polling the keypad once a
+    // frame could miss a shorter press
+    pub min_key_hold: Option<u64>,
+    pub hotkeys: Hotkeys,
+    pub config_file: Option<PathBuf>,
+    pub profile_name: Option<String>,
 }
 
-#[derive(Clone, Debug)]
-pub struct DisplayState {
-    pixels: [bool; NUM_PIXELS],
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Resolution {
+    #[default]
+    Standard,
+    Tall,
+    Hires,
 }
 
-impl DisplayState {
-    pub fn new() -> Self {
-        Self::default()
+impl Resolution {
+    pub fn height(&self) -> u8 {
+        match self {
+            Resolution::Standard => DISPLAY_PIXELS_HEIGHT,
+            Resolution::Tall => 48,
+            Resolution::Hires => MAX_DISPLAY_PIXELS_HEIGHT,
+        }
+    }
+    // roms written for the vip hi-res interpreter start with a jump to 0x260 where the
+    // interpreter kept its own routines
+    pub fn detect(program: &Program) -> Self {
+        if program.data().starts_with(&[0x12, 0x60]) {
+            Resolution::Hires
+        } else {
+            Resolution::Standard
+        }
+    }
+}
+
+impl FromStr for Resolution {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "64x32" => Ok(Resolution::Standard),
+            "64x48" => Ok(Resolution::Tall),
+            "64x64" => Ok(Resolution::Hires),
+            _ => Err(format!(
+                "invalid resolution '{}': expected 64x32, 64x48 or 64x64",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Frontend {
+    #[cfg(feature = "sdl")]
+    #[default]
+    Sdl,
+    #[cfg_attr(not(feature = "sdl"), default)]
+    Tui,
+    #[cfg(feature = "pixels")]
+    Pixels,
+}
+
+impl Frontend {
+    fn reports_refreshes(&self) -> bool {
+        match self {
+            #[cfg(feature = "sdl")]
+            Frontend::Sdl => true,
+            _ => false,
+        }
     }
-    pub fn clear(&mut self) {
-        self.pixels.fill(false);
+    fn opens_debug_window(&self) -> bool {
+        match self {
+            #[cfg(feature = "sdl")]
+            Frontend::Sdl => true,
+            _ => false,
+        }
     }
-    pub fn read_pixel(&self, idx: u16) -> bool {
-        self.pixels[idx as usize]
+    fn is_sdl(&self) -> bool {
+        match self {
+            #[cfg(feature = "sdl")]
+            Frontend::Sdl => true,
+            _ => false,
+        }
     }
-    pub fn write_pixel(&mut self, idx: u16, value: bool) {
-        self.pixels[idx as usize] = value;
+}
+
+impl FromStr for Frontend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            #[cfg(feature = "sdl")]
+            "sdl" => Ok(Frontend::Sdl),
+            "tui" => Ok(Frontend::Tui),
+            #[cfg(feature = "pixels")]
+            "pixels" => Ok(Frontend::Pixels),
+            // left out of this build rather than misspelled
+            s if ["sdl", "pixels"].contains(&s) => Err(format!(
+                "frontend '{}' needs chipate built with the {} feature",
+                s, s
+            )),
+            _ => Err(format!(
+                "invalid frontend '{}': expected sdl, tui or pixels",
+                s
+            )),
+        }
     }
 }
 
-impl Default for DisplayState {
-    fn default() -> Self {
-        Self {
-            pixels: [false; NUM_PIXELS],
+// how the terminal frontend draws pixels, auto picks an image protocol when the terminal
+// advertises one and block characters otherwise
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TerminalGraphics {
+    #[default]
+    Auto,
+    Kitty,
+    Sixel,
+    Blocks,
+}
+
+impl FromStr for TerminalGraphics {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(TerminalGraphics::Auto),
+            "kitty" => Ok(TerminalGraphics::Kitty),
+            "sixel" => Ok(TerminalGraphics::Sixel),
+            "blocks" => Ok(TerminalGraphics::Blocks),
+            _ => Err(format!(
+                "invalid terminal graphics '{}': expected auto, kitty, sixel or blocks",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ExportFormat {
+    #[default]
+    Cast,
+    Text,
+}
+
+impl FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "cast" => Ok(ExportFormat::Cast),
+            "text" => Ok(ExportFormat::Text),
+            _ => Err(format!(
+                "invalid export format '{}': expected cast or text",
+                s
+            )),
+        }
+    }
+}
+
+// auto uses the gpu when a context can be created and falls back to software rendering otherwise,
+// which is what virtual machines and remote x sessions without acceleration end up with
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Renderer {
+    #[default]
+    Auto,
+    Accelerated,
+    Software,
+}
+
+impl FromStr for Renderer {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(Renderer::Auto),
+            "accelerated" => Ok(Renderer::Accelerated),
+            "software" => Ok(Renderer::Software),
+            _ => Err(format!(
+                "invalid renderer '{}': expected auto, accelerated or software",
+                s
+            )),
+        }
+    }
+}
+
+// shown in the window for as long as the sound timer is active
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum VisualBell {
+    Border,
+    Invert,
+}
+
+impl FromStr for VisualBell {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "border" => Ok(VisualBell::Border),
+            "invert" => Ok(VisualBell::Invert),
+            _ => Err(format!(
+                "invalid visual bell '{}': expected border or invert",
+                s
+            )),
+        }
+    }
+}
+
+// the shape of a display pixel as its width to its height, stretch fills a 4:3 screen the way a
+// television did whatever the resolution
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PixelAspect {
+    #[default]
+    Square,
+    Ratio(u8, u8),
+    Stretch,
+}
+
+impl PixelAspect {
+    // the fewest whole screen pixels a display pixel of the right shape can be drawn with
+    pub(crate) fn ratio(&self, display_width: u32, display_height: u32) -> (u32, u32) {
+        let (width, height) = match self {
+            PixelAspect::Square => (1, 1),
+            PixelAspect::Ratio(width, height) => (*width as u32, *height as u32),
+            PixelAspect::Stretch => (4 * display_height, 3 * display_width),
+        };
+
+        let gcd = (1..=u32::min(width, height))
+            .rev()
+            .find(|d| width % d == 0 && height % d == 0)
+            .unwrap_or(1);
+
+        (width / gcd, height / gcd)
+    }
+    // the size in screen pixels of a display pixel, the scale is its shorter side so a stretched
+    // pixel is never smaller than a square one
+    #[cfg_attr(not(any(feature = "sdl", feature = "pixels")), allow(dead_code))]
+    pub(crate) fn pixel_size(
+        &self,
+        scale: u32,
+        display_width: u32,
+        display_height: u32,
+    ) -> (u32, u32) {
+        let (width, height) = self.ratio(display_width, display_height);
+
+        if width >= height {
+            ((scale * width + height / 2) / height, scale)
+        } else {
+            (scale, (scale * height + width / 2) / width)
+        }
+    }
+}
+
+impl std::fmt::Display for PixelAspect {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PixelAspect::Square => write!(f, "square"),
+            PixelAspect::Ratio(width, height) => write!(f, "{}:{}", width, height),
+            PixelAspect::Stretch => write!(f, "stretch"),
+        }
+    }
+}
+
+impl FromStr for PixelAspect {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let ratio = s.split_once(':').and_then(|(width, height)| {
+            let parse = |side: &str| side.parse::<u8>().ok().filter(|n| (1..=8).contains(n));
+            Some((parse(width)?, parse(height)?))
+        });
+
+        match (s, ratio) {
+            ("square", _) => Ok(PixelAspect::Square),
+            ("stretch", _) => Ok(PixelAspect::Stretch),
+            (_, Some((width, height))) if width == height => Ok(PixelAspect::Square),
+            (_, Some((width, height))) => Ok(PixelAspect::Ratio(width, height)),
+            _ => Err(format!(
+                "invalid pixel aspect '{}': expected square, stretch or W:H with each side from 1 \
+                 to 8",
+                s
+            )),
+        }
+    }
+}
+
+// how the display is scaled up to the window, nearest keeps every pixel sharp and linear blends
+// neighbouring pixels into each other
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Filter {
+    #[default]
+    Nearest,
+    Linear,
+}
+
+impl std::fmt::Display for Filter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Filter::Nearest => write!(f, "nearest"),
+            Filter::Linear => write!(f, "linear"),
+        }
+    }
+}
+
+impl FromStr for Filter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "nearest" => Ok(Filter::Nearest),
+            "linear" => Ok(Filter::Linear),
+            _ => Err(format!(
+                "invalid filter '{}': expected nearest or linear",
+                s
+            )),
+        }
+    }
+}
+
+// lines between the pixels of the display for designing sprites, rulers also number every eighth
+// column and row
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PixelGrid {
+    #[default]
+    Off,
+    Lines,
+    Rulers,
+}
+
+impl PixelGrid {
+    // the order the hotkey steps through them in
+    fn next(self) -> Self {
+        match self {
+            PixelGrid::Off => PixelGrid::Lines,
+            PixelGrid::Lines => PixelGrid::Rulers,
+            PixelGrid::Rulers => PixelGrid::Off,
+        }
+    }
+}
+
+impl std::fmt::Display for PixelGrid {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PixelGrid::Off => write!(f, "off"),
+            PixelGrid::Lines => write!(f, "lines"),
+            PixelGrid::Rulers => write!(f, "rulers"),
+        }
+    }
+}
+
+impl FromStr for PixelGrid {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(PixelGrid::Off),
+            "lines" => Ok(PixelGrid::Lines),
+            "rulers" => Ok(PixelGrid::Rulers),
+            _ => Err(format!(
+                "invalid pixel grid '{}': expected off, lines or rulers",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Output {
+    Frame(Vec<DisplayState>, Option<VisualBell>),
+    Title(String),
+    // the config file or a hotkey changed the style, the frame that follows is drawn with it
+    Reload(Style, Hotkeys),
+    // sent after every frame when the debug window is open
+    #[cfg_attr(not(feature = "sdl"), allow(dead_code))]
+    Debug(Box<DebugView>),
+}
+
+#[derive(Clone, Debug)]
+enum Input {
+    Key(Key, bool),
+    Hotkey(Hotkey, bool),
+    // the host key a macro is bound to
+    Macro(String, bool),
+    // the window was presented on a display refresh
+    #[cfg_attr(not(feature = "sdl"), allow(dead_code))]
+    Refresh,
+    Quit,
+}
+
+impl Input {
+    // hotkeys take precedence over macros and both over the keypad
+    fn from_host_key(hotkeys: &Hotkeys, name: &str, pressed: bool) -> Option<Self> {
+        match hotkeys.get(name) {
+            Some(hotkey) => Some(Input::Hotkey(hotkey, pressed)),
+            None if hotkeys.macro_for(name).is_some() => {
+                Some(Input::Macro(String::from(name), pressed))
+            }
+            None => keymap::keypad_key(name).map(|key| Input::Key(key, pressed)),
         }
     }
 }
 
+// how the frontend draws the display, the terminal only uses the palette
+#[derive(Clone, Copy, Debug)]
+struct Style {
+    palette: Palette,
+    #[cfg_attr(not(feature = "sdl"), allow(dead_code))]
+    pixel_pattern: bool,
+    #[cfg_attr(not(feature = "sdl"), allow(dead_code))]
+    scale: u32,
+    #[cfg_attr(not(any(feature = "sdl", feature = "pixels")), allow(dead_code))]
+    pixel_aspect: PixelAspect,
+    #[cfg_attr(not(feature = "sdl"), allow(dead_code))]
+    filter: Filter,
+    #[cfg_attr(not(feature = "sdl"), allow(dead_code))]
+    pixel_grid: PixelGrid,
+}
+
+// the emulation thread side of the channels to the frontend
+#[derive(Debug)]
+struct Channels {
+    outputs: Sender<Output>,
+    inputs: Receiver<Input>,
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Key {
     Num0,
@@ -128,29 +652,8 @@ impl From<Key> for usize {
     }
 }
 
-fn keycode_to_key(value: Keycode) -> Option<Key> {
-    match value {
-        Keycode::Num1 => Some(Key::Num1),
-        Keycode::Num2 => Some(Key::Num2),
-        Keycode::Num3 => Some(Key::Num3),
-        Keycode::Num4 => Some(Key::C),
-        Keycode::Q => Some(Key::Num4),
-        Keycode::W => Some(Key::Num5),
-        Keycode::E => Some(Key::Num6),
-        Keycode::R => Some(Key::D),
-        Keycode::A => Some(Key::Num7),
-        Keycode::S => Some(Key::Num8),
-        Keycode::D => Some(Key::Num9),
-        Keycode::F => Some(Key::E),
-        Keycode::Z => Some(Key::A),
-        Keycode::X => Some(Key::Num0),
-        Keycode::C => Some(Key::B),
-        Keycode::V => Some(Key::F),
-        _ => None,
-    }
-}
-
 #[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct KeyState {
     keys: [bool; 16],
 }
@@ -169,6 +672,15 @@ impl KeyState {
 
         self.keys[idx] = true;
     }
+    pub fn apply(&mut self, event: KeyEvent) {
+        let key = Key::from(event.key as usize);
+
+        if event.pressed {
+            self.key_pressed(key);
+        } else {
+            self.key_released(key);
+        }
+    }
     pub fn key_released(&mut self, key: Key) {
         tracing::debug!("{:?} key released", key);
 
@@ -192,147 +704,1486 @@ impl KeyState {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Debug)]
 pub struct Emu {
     config: Config,
-    cpu: CPU,
-    memory: RAM,
-    display: DisplayState,
-    keyboard: KeyState,
+    machine: Machine,
+    debugger: Option<Debugger>,
+    compare: Option<Box<Emu>>,
+    netplay: Option<Netplay>,
+    websocket: Option<DisplayServer>,
+    #[cfg(feature = "discord")]
+    presence: Option<discord::Presence>,
+    frame: u64,
+    diverged: bool,
+    program: Option<Program>,
+    paused: bool,
+    commands: Receiver<Request>,
+    command_sender: Sender<Request>,
+    stop: Arc<AtomicBool>,
+    last_frame: Option<Instant>,
+    frontend: Option<Channels>,
+    audio: Audio,
+    observers: Observers,
+    sinks: Sinks,
+    heatmap: Option<Heatmap>,
+    // cheats written again after every instruction so the program can not change the value
+    freezes: Vec<Cheat>,
+    rewind: Option<Rewind>,
+    rewinding: bool,
+    refreshes: u64,
+    frame_hashes: Option<BufWriter<File>>,
+    dump_requested: Arc<AtomicBool>,
+    export: Option<Exporter>,
+    coverage: Option<Coverage>,
+    settings: Option<settings::Watcher>,
+    recorder: Option<Recorder>,
+    kiosk: Option<Kiosk>,
+    // key events of the macros being played by the frame they happen on
+    macro_events: Vec<(u64, KeyEvent)>,
+    // the frame each key can be released on with a minimum hold and whether it was let go sooner
+    hold_until: [u64; 16],
+    early_releases: [bool; 16],
+    // the frame the program was last loaded on, recorded inputs count frames from here
+    program_frame: u64,
 }
 
 impl Emu {
     pub fn new(config: Config) -> Self {
-        let mut memory = RAM::new();
+        let mut machine = Machine::new(config.font.clone());
+
+        let symbols = Arc::new(config.symbols.clone());
 
-        config.font.load(&mut memory);
-        tracing::debug!("loaded {} font into memory", config.font.name);
+        // both sides of a comparison share a seed so random numbers do not cause divergence
+        let seed = config.seed.unwrap_or_else(rand::random);
+
+        let (command_sender, commands) = mpsc::channel();
+
+        let cpu = machine.cpu_mut();
+        cpu.set_quirks(quirks(&config.mode, &config.quirks));
+        cpu.set_symbols(Arc::clone(&symbols));
+        cpu.seed_rng(seed);
+
+        let debugger = if config.debugger.is_enabled() {
+            Some(Debugger::new(config.debugger.clone(), symbols))
+        } else {
+            None
+        };
+
+        let compare = config.compare.clone().map(|mode| {
+            Box::new(Emu::new(Config {
+                mode,
+                quirks: Vec::new(),
+                seed: Some(seed),
+                compare: None,
+                debugger: DebuggerConfig::default(),
+                debug_window: false,
+                autosave: None,
+                frame_hashes: None,
+                export: None,
+                coverage: None,
+                record_inputs: None,
+                config_file: None,
+                rewind: None,
+                ..config.clone()
+            }))
+        });
+
+        let freezes = config.freezes.clone();
+        let heatmap = config.debug_window.then(|| Heatmap::new(&machine.memory));
+
+        let rewind = config
+            .rewind
+            .map(|history| Rewind::new((history.as_secs_f64() * config.timer_hz as f64) as usize));
 
         Self {
             config,
-            cpu: CPU::default(),
-            memory,
-            display: DisplayState::default(),
-            keyboard: KeyState::default(),
+            machine,
+            debugger,
+            compare,
+            netplay: None,
+            websocket: None,
+            #[cfg(feature = "discord")]
+            presence: None,
+            frame: 0,
+            diverged: false,
+            program: None,
+            paused: false,
+            commands,
+            command_sender,
+            stop: Arc::new(AtomicBool::new(false)),
+            last_frame: None,
+            frontend: None,
+            audio: Audio::new(Box::new(Bell)),
+            observers: Observers::default(),
+            sinks: Sinks::default(),
+            heatmap,
+            freezes,
+            rewind,
+            rewinding: false,
+            refreshes: 0,
+            frame_hashes: None,
+            dump_requested: Arc::new(AtomicBool::new(false)),
+            export: None,
+            coverage: None,
+            settings: None,
+            recorder: None,
+            kiosk: None,
+            macro_events: Vec::new(),
+            hold_until: [0; 16],
+            early_releases: [false; 16],
+            program_frame: 0,
         }
     }
-    pub fn load_program(&mut self, program: Program) {
-        program.load(&mut self.memory);
-        tracing::debug!("loaded {} program into memory", program.name);
+    pub fn set_audio_sink(&mut self, sink: impl AudioSink + 'static) {
+        self.audio.set_sink(Box::new(sink));
+    }
+    // observers run on the emulation thread so a slow one holds up the emulation
+    pub fn add_frame_observer(&mut self, observer: impl FrameObserver + 'static) {
+        self.observers.add(Box::new(observer));
+    }
+    // sinks run on the emulation thread alongside the frontend, see add_frame_observer
+    pub fn add_display_sink(&mut self, sink: impl DisplaySink + 'static) {
+        self.sinks.add(Box::new(sink));
+    }
+    pub fn handle(&self) -> EmuHandle {
+        EmuHandle::new(self.command_sender.clone(), Arc::clone(&self.stop))
+    }
+    // the signal only sets a flag, the state is logged from the emulation loop
+    #[cfg(unix)]
+    pub fn dump_state_on_sigusr1(&self) -> Result<()> {
+        signal_hook::flag::register(
+            signal_hook::consts::SIGUSR1,
+            Arc::clone(&self.dump_requested),
+        )
+        .context("register SIGUSR1 handler")?;
+
+        Ok(())
+    }
+    pub fn set_netplay(&mut self, netplay: Netplay) {
+        self.netplay = Some(netplay);
+    }
+    // loads the first rom of the playlist in place of any loaded program
+    pub fn set_playlist(&mut self, playlist: Playlist) {
+        self.kiosk = Some(Kiosk::new(playlist));
+        self.play_kiosk_entry();
     }
-    pub fn run(&mut self) -> anyhow::Result<()> {
-        let min_ms_per_tick = 1000_u128 / self.config.instructions_per_sec as u128;
-        let mut last_tick = Instant::now();
+    pub fn set_websocket(&mut self, server: DisplayServer) {
+        self.websocket = Some(server);
+    }
+    #[cfg(feature = "discord")]
+    pub fn set_presence(&mut self, presence: discord::Presence) {
+        self.presence = Some(presence);
+    }
+    // a sink set before running takes the place of the audio backend
+    #[cfg(feature = "sdl")]
+    fn open_tone(&mut self) -> Option<sdl::Tone> {
+        if self.config.audio != AudioBackend::Sdl || !self.audio.has_default_sink() {
+            return None;
+        }
+
+        match sdl::Tone::open(self.config.audio_buffer) {
+            Ok((tone, sink)) => {
+                self.audio.set_backend_sink(Box::new(sink));
+                Some(tone)
+            }
+            Err(e) => {
+                tracing::warn!(
+                    "could not open the audio device, ringing the terminal bell: {:#}",
+                    e
+                );
+                None
+            }
+        }
+    }
+    pub fn load_program(&mut self, program: Program) {
+        if let Some(compare) = self.compare.as_mut() {
+            compare.load_program(program.clone());
+        }
 
-        let min_ms_per_timer_dec = 1000_u128 / 60_u128;
-        let mut last_timer = Instant::now();
+        self.machine.load(&program);
 
-        let sdl_context = match sdl2::init() {
-            Err(msg) => anyhow::bail!(msg),
-            Ok(ctx) => ctx,
-        };
+        if let Some(debugger) = self.debugger.as_mut() {
+            debugger.set_program_len(program.data().len());
+        }
 
-        let video_subsystem = match sdl_context.video() {
-            Err(msg) => anyhow::bail!(msg),
-            Ok(video_subsystem) => video_subsystem,
-        };
+        let resolution = self
+            .config
+            .resolution
+            .unwrap_or_else(|| Resolution::detect(&program));
+        if resolution.height() != self.machine.display.height() {
+            self.machine.display = DisplayState::with_height(resolution.height());
+            tracing::debug!("using {:?} resolution", resolution);
+        }
+
+        for cheat in &self.config.cheats {
+            cheat.apply(&mut self.machine.cpu, &mut self.machine.memory);
+            tracing::debug!("applied cheat {:?}", cheat);
+        }
+
+        for freeze in &self.freezes {
+            freeze.apply(&mut self.machine.cpu, &mut self.machine.memory);
+        }
+
+        // loading the program is not the program writing to memory
+        if let Some(heatmap) = self.heatmap.as_mut() {
+            *heatmap = Heatmap::new(&self.machine.memory);
+        }
+
+        self.program = Some(program);
+        self.program_frame = self.frame;
+    }
+    pub fn reset(&mut self) {
+        self.machine.reset();
 
-        let window = match video_subsystem
-            .window("chipate", 640, 320)
-            .position_centered()
-            .build()
+        if let Some(program) = self.program.take() {
+            if let Some(compare) = self.compare.as_mut() {
+                compare.reset();
+            }
+
+            self.load_program(program);
+        }
+
+        tracing::debug!("reset emulator");
+    }
+    pub fn run(&mut self) -> Result<()> {
+        if self.config.debug_window
+            && (self.config.headless || !self.config.frontend.opens_debug_window())
         {
-            Err(msg) => anyhow::bail!(msg),
-            Ok(window) => window,
-        };
+            return Err(EmuError::Unsupported(String::from(
+                "the debug window needs the sdl frontend",
+            )));
+        }
 
-        let mut canvas = match window.into_canvas().build() {
-            Err(msg) => anyhow::bail!(msg),
-            Ok(canvas) => canvas,
-        };
+        if self.config.terminal_mirror {
+            if self.config.frontend == Frontend::Tui && !self.config.headless {
+                tracing::warn!(
+                    "the terminal frontend already draws in the terminal, not mirroring"
+                );
+            } else {
+                let mirror = tui::Mirror::enter(self.config.terminal_graphics)?;
+                self.sinks.add(Box::new(mirror));
+            }
+        }
 
-        let mut event_pump = match sdl_context.event_pump() {
-            Err(msg) => anyhow::bail!(msg),
-            Ok(event_pump) => event_pump,
-        };
+        if self.config.headless {
+            return self.emulate();
+        }
 
-        'main: loop {
-            let timer_elapsed = last_timer.elapsed();
-            if timer_elapsed.as_millis() >= min_ms_per_timer_dec {
-                self.cpu.dec_timers();
-                if self.cpu.is_sound_playable() {
-                    // TODO: sdl2 audio instead of bell char
-                    print!("\u{7}");
+        if self.config.audio_buffer.is_some() && self.config.audio == AudioBackend::Bell {
+            tracing::warn!("the audio buffer only applies to the sdl audio backend");
+        }
+
+        if self.config.pixel_grid != PixelGrid::Off && !self.config.frontend.is_sdl() {
+            tracing::warn!("the pixel grid needs the sdl frontend, drawing the display without it");
+        }
+
+        if self.config.bezel.is_some() && !self.config.frontend.is_sdl() {
+            tracing::warn!("the bezel needs the sdl frontend, drawing the display without it");
+        }
+
+        if self.config.rumble.is_some() && !self.config.frontend.is_sdl() {
+            tracing::warn!("rumble needs the sdl frontend, game controllers are not opened");
+        }
+
+        // kept open until the emulation has finished
+        #[cfg(feature = "sdl")]
+        let _tone = self.open_tone();
+
+        let style = self.style();
+
+        // only the sdl frontend reports display refreshes, elsewhere the timers would never tick
+        if self.config.vsync && !self.config.frontend.reports_refreshes() {
+            tracing::warn!("vsync needs the sdl frontend, pacing frames with the timer clock");
+            self.config.vsync = false;
+        }
+
+        let hotkeys = self.config.hotkeys.clone();
+
+        match self.config.frontend {
+            #[cfg(feature = "sdl")]
+            Frontend::Sdl => {
+                let mut window = sdl::Window::open(
+                    &self.config,
+                    self.machine.display.width() as u32 * self.num_displays(),
+                    self.machine.display.height() as u32,
+                )?;
+                self.config.vsync = window.vsync;
+
+                if let Some(sink) = window.rumble_sink() {
+                    self.audio.set_companion(Some(Box::new(sink)));
                 }
 
-                last_timer = Instant::now();
-            }
-
-            let tick_elapsed = last_tick.elapsed();
-            if tick_elapsed.as_millis() >= min_ms_per_tick {
-                for event in event_pump.poll_iter() {
-                    match event {
-                        Event::KeyDown {
-                            keycode: Some(keycode),
-                            ..
-                        } => {
-                            if let Some(key) = keycode_to_key(keycode) {
-                                self.keyboard.key_pressed(key);
-                            }
-                        }
-                        Event::Quit { .. }
-                        | Event::KeyUp {
-                            keycode: Some(Keycode::Escape),
-                            ..
-                        } => break 'main,
-                        Event::KeyUp {
-                            keycode: Some(keycode),
-                            ..
-                        } => {
-                            if let Some(key) = keycode_to_key(keycode) {
-                                self.keyboard.key_released(key);
-                            }
-                        }
-                        _ => {}
-                    }
+                let result = self.run_with_frontend(|outputs, inputs, emulation| {
+                    window.present(style, &hotkeys, outputs, inputs, emulation)
+                });
+                self.audio.set_companion(None);
+
+                result
+            }
+            Frontend::Tui => {
+                let mut terminal = tui::Terminal::enter(self.config.terminal_graphics)?;
+                self.run_with_frontend(|outputs, inputs, emulation| {
+                    terminal.present(style, &hotkeys, outputs, inputs, emulation)
+                })
+            }
+            #[cfg(feature = "pixels")]
+            Frontend::Pixels => {
+                // the pixels crate always samples the nearest texel when it scales
+                if self.config.filter != Filter::Nearest {
+                    return Err(EmuError::Unsupported(format!(
+                        "filter '{}' needs the sdl frontend, the pixels frontend only scales with \
+                         nearest",
+                        self.config.filter
+                    )));
                 }
 
-                self.cpu.tick(
-                    &mut self.memory,
-                    &mut self.display,
-                    &self.config.font,
-                    &self.keyboard,
-                );
+                let gpu = gpu::Gpu::open(
+                    (self.machine.display.width() as u32 + 1) * self.num_displays() - 1,
+                    self.machine.display.height() as u32,
+                    self.config.scale,
+                    self.config.pixel_aspect,
+                )?;
+                self.run_with_frontend(|outputs, inputs, emulation| {
+                    gpu.present(style, &hotkeys, outputs, inputs, emulation)
+                })
+            }
+        }
+    }
+    // runs the emulation on its own thread while the host application runs its own sdl window on
+    // this one, the emulation stops when the host returns
+    #[cfg(feature = "sdl")]
+    pub fn run_embedded(&mut self, host: impl FnOnce(&mut Embedded)) -> Result<()> {
+        // the host presents when it likes so frames are paced with the timer clock
+        self.config.vsync = false;
+
+        let style = self.style();
+        let hotkeys = self.config.hotkeys.clone();
+
+        self.run_with_frontend(|outputs, inputs, emulation| {
+            let mut embedded = Embedded::new(style, hotkeys, outputs, inputs, emulation);
+            host(&mut embedded);
+            embedded.quit();
+        })
+    }
+    // runs the emulation on its own thread while the frontend runs on this one until the
+    // emulation has finished
+    fn run_with_frontend(
+        &mut self,
+        frontend: impl FnOnce(&Receiver<Output>, &Sender<Input>, &ScopedJoinHandle<Result<()>>),
+    ) -> Result<()> {
+        let (output_sender, outputs) = mpsc::channel();
+        let (input_sender, inputs) = mpsc::channel();
+
+        let channels = Channels {
+            outputs: output_sender,
+            inputs,
+        };
+
+        std::thread::scope(|scope| {
+            let emulation = std::thread::Builder::new()
+                .name(String::from("emulation"))
+                .spawn_scoped(scope, move || {
+                    self.frontend = Some(channels);
+                    let result = self.emulate();
+                    self.frontend = None;
+                    result
+                })
+                .context("spawn emulation thread")?;
+
+            frontend(&outputs, &input_sender, &emulation);
+
+            match emulation.join() {
+                Ok(result) => result,
+                Err(panic) => std::panic::resume_unwind(panic),
+            }
+        })
+    }
+    fn style(&self) -> Style {
+        Style {
+            palette: self.config.palette,
+            pixel_pattern: self.config.pixel_pattern,
+            scale: self.config.scale,
+            pixel_aspect: self.config.pixel_aspect,
+            filter: self.config.filter,
+            pixel_grid: self.config.pixel_grid,
+        }
+    }
+    fn emulate(&mut self) -> Result<()> {
+        let mut next_tick = Instant::now();
+
+        let mut timer = Ticker::new(self.config.timer_hz as u32, Instant::now());
+
+        self.restore_autosave();
+
+        if let Some(path) = self.config.frame_hashes.as_ref() {
+            let file = File::create(path).context(format!("create frame hash file {}", path))?;
+            self.frame_hashes = Some(BufWriter::new(file));
+        }
+
+        if let Some(path) = self.config.export.as_ref() {
+            let title = self.program.as_ref().map_or("chipate", |p| p.name.as_str());
+            let exporter = Exporter::create(
+                path,
+                self.config.export_format,
+                title,
+                &self.machine.display,
+            )
+            .context("create export")?;
+            self.export = Some(exporter);
+        }
+
+        if self.config.coverage.is_some() {
+            self.coverage = Some(Coverage::default());
+        }
 
-                last_tick = Instant::now();
+        if let Some(path) = self.config.record_inputs.as_ref() {
+            self.recorder = Some(Recorder::create(path).context("create input recording")?);
+        }
+
+        if let Some(path) = self.config.config_file.clone() {
+            let profile = self.config.profile_name.clone();
+            self.settings = Some(settings::Watcher::new(path, profile));
+        }
+
+        // with vsync every display refresh is a frame instead of the timer clock, the clock is
+        // still used to estimate when the next frame is due
+        let vsync = self.config.vsync && self.frontend.is_some();
+
+        let started = Instant::now();
+
+        if self.break_at_start() == Action::Quit {
+            return self.shutdown();
+        }
+
+        'main: loop {
+            // the pause hotkey arrives with the inputs and the pause command with the commands
+            let was_paused = self.paused;
+
+            self.process_inputs();
+
+            if self.stop.load(Ordering::Relaxed) {
+                break 'main;
+            }
+
+            if self.dump_requested.swap(false, Ordering::Relaxed) {
+                tracing::info!("{}", self.state_report());
+            }
+
+            self.reload_settings();
+
+            if self.should_exit(started) {
+                tracing::info!("exiting after {} frames", self.frame);
+                break 'main;
             }
 
-            canvas.set_draw_color(Color::BLACK);
-            canvas.clear();
-            canvas.set_draw_color(Color::WHITE);
+            let now = Instant::now();
 
-            for c in 0..DISPLAY_PIXELS_WIDTH {
-                for r in 0..DISPLAY_PIXELS_HEIGHT {
-                    let idx = (r as i32 * DISPLAY_PIXELS_WIDTH as i32) + c as i32;
+            // the timers run off their own clock so they keep their rate however long the
+            // instructions or drawing in between take
+            let mut timer_ticks = timer.due(now);
+            if vsync {
+                timer_ticks = match std::mem::take(&mut self.refreshes) {
+                    refreshes if refreshes > MAX_CATCH_UP => 1,
+                    refreshes => refreshes,
+                };
+            }
+
+            if timer_ticks > 0 {
+                self.process_commands();
+                #[cfg(feature = "discord")]
+                self.update_presence();
+            }
+
+            // timers and instruction pacing restart together so resuming neither runs a burst of
+            // catch up instructions nor decrements the timers for the paused time
+            if was_paused && !self.paused {
+                timer.reset(now);
+                next_tick = now;
+                continue 'main;
+            }
+
+            if timer_ticks > 0 {
+                for _ in 0..timer_ticks {
+                    if self.paused {
+                        break;
+                    }
 
-                    if self.display.read_pixel(idx as u16) {
-                        // window is a factor of 10 larger than display state grid
-                        let x = (c as i32 % DISPLAY_PIXELS_WIDTH as i32) * 10;
-                        let y = (r as i32 % DISPLAY_PIXELS_HEIGHT as i32) * 10;
+                    if self.rewinding {
+                        self.step_back();
+                        continue;
+                    }
 
-                        let rect = Rect::new(x, y, 10, 10);
-                        if let Err(msg) = canvas.fill_rect(rect) {
-                            tracing::error!("fill rect error: {}", msg);
-                        }
+                    if self.run_frame()? == Action::Quit {
+                        break 'main;
                     }
+
+                    self.on_frame();
+                    self.record_frame();
+                    self.hash_frame()?;
+                    self.export_frame()?;
+                    self.kiosk_frame();
+                    self.macro_frame();
+                    self.key_hold_frame();
                 }
+
+                self.send_frame();
             }
 
-            canvas.present();
-        }
+            let next_timer = timer.next();
 
-        tracing::debug!("exited main loop");
+            if !self.paused && !self.rewinding && self.netplay.is_none() && now >= next_tick {
+                if self.is_idle() {
+                    // nothing can change before the timers tick or a key is pressed
+                    next_tick = next_timer;
+                } else {
+                    if self.step()? == Action::Quit {
+                        break 'main;
+                    }
 
-        Ok(())
-    }
+                    // read every instruction as the speed can change with the config file
+                    let tick_duration =
+                        Duration::from_secs(1) / self.config.instructions_per_sec as u32;
+                    next_tick = (next_tick + tick_duration).max(now);
+                }
+            }
+
+            // sleep until whatever is due next instead of spinning
+            let next = if !self.paused && !self.rewinding && self.netplay.is_none() {
+                next_tick.min(next_timer)
+            } else {
+                next_timer
+            };
+
+            if self.wait_until(next) {
+                next_tick = next_tick.min(Instant::now());
+            }
+        }
+
+        tracing::debug!("exited main loop");
+
+        self.shutdown()
+    }
+    // applies whatever changed in the config file, the scale is fixed once the window is open and
+    // netplay peers agreed on the speed when the session started
+    fn reload_settings(&mut self) {
+        let Some(result) = self.settings.as_mut().and_then(|watcher| watcher.poll()) else {
+            return;
+        };
+
+        let settings = match result {
+            Ok(settings) => settings,
+            Err(e) => {
+                tracing::warn!("config file not reloaded: {:#}", e);
+                return;
+            }
+        };
+
+        let mut applied = Vec::new();
+        let mut needs_restart = Vec::new();
+
+        if let Some(palette) = settings
+            .options
+            .palette
+            .filter(|p| *p != self.config.palette)
+        {
+            self.config.palette = palette;
+            applied.push("palette");
+        }
+
+        if let Some(pattern) = settings
+            .options
+            .pixel_pattern
+            .filter(|p| *p != self.config.pixel_pattern)
+        {
+            self.config.pixel_pattern = pattern;
+            applied.push("pixel-pattern");
+        }
+
+        if let Some(aspect) = settings
+            .options
+            .pixel_aspect
+            .filter(|a| *a != self.config.pixel_aspect)
+        {
+            self.config.pixel_aspect = aspect;
+            applied.push("pixel-aspect");
+        }
+
+        if let Some(filter) = settings.options.filter.filter(|f| *f != self.config.filter) {
+            self.config.filter = filter;
+            applied.push("filter");
+        }
+
+        if let Some(speed) = settings
+            .options
+            .speed
+            .filter(|s| *s != self.config.instructions_per_sec)
+        {
+            if self.netplay.is_some() {
+                needs_restart.push("speed");
+            } else {
+                self.config.instructions_per_sec = speed;
+                if let Some(compare) = self.compare.as_mut() {
+                    compare.config.instructions_per_sec = speed;
+                }
+                applied.push("speed");
+            }
+        }
+
+        if settings.hotkeys != self.config.hotkeys {
+            self.config.hotkeys = settings.hotkeys;
+            applied.push("hotkeys");
+        }
+
+        if settings
+            .options
+            .scale
+            .is_some_and(|s| s != self.config.scale)
+        {
+            needs_restart.push("scale");
+        }
+
+        if settings
+            .options
+            .mode
+            .as_ref()
+            .is_some_and(|m| *m != self.config.mode)
+            || settings.options.quirks != self.config.quirks
+        {
+            needs_restart.push("quirks");
+        }
+
+        if applied.is_empty() && needs_restart.is_empty() {
+            tracing::info!("config file changed, nothing to apply");
+        }
+
+        if !applied.is_empty() {
+            tracing::info!("applied {} from the config file", applied.join(", "));
+
+            if let Some(frontend) = self.frontend.as_ref() {
+                let reload = Output::Reload(self.style(), self.config.hotkeys.clone());
+                let _ = frontend.outputs.send(reload);
+            }
+            self.send_frame();
+        }
+
+        if !needs_restart.is_empty() {
+            tracing::warn!(
+                "{} changed in the config file and takes effect after a restart",
+                needs_restart.join(", ")
+            );
+        }
+    }
+    fn should_exit(&self, started: Instant) -> bool {
+        let frames_done = self
+            .config
+            .exit_after_frames
+            .is_some_and(|frames| self.frame >= frames);
+
+        let time_done = self
+            .config
+            .exit_after_time
+            .is_some_and(|time| started.elapsed() >= time);
+
+        frames_done || time_done
+    }
+    fn restore_autosave(&mut self) {
+        let Some(path) = self.config.autosave.clone() else {
+            return;
+        };
+
+        if !Path::new(&path).exists() {
+            return;
+        }
+
+        match Snapshot::load(&path, &self.machine.cpu) {
+            Ok(snapshot) => {
+                self.restore(snapshot);
+                tracing::info!("restored autosave from {}", path);
+            }
+            Err(e) => tracing::warn!("could not restore autosave {}: {:#}", path, e),
+        }
+    }
+    fn shutdown(&mut self) -> Result<()> {
+        self.audio.stop();
+
+        if let Some(debugger) = self.debugger.as_ref() {
+            debugger.save_session().context("write debugger session")?;
+        }
+
+        if let Some(path) = self.config.export_svg.as_ref() {
+            svg::save(path, &self.displays(), self.config.palette).context("write svg")?;
+            tracing::info!("wrote the last frame to {}", path);
+        }
+
+        if let Some(path) = self.config.autosave.as_ref() {
+            self.snapshot().save(path).context("write autosave")?;
+            tracing::info!("wrote autosave to {}", path);
+        }
+
+        if let Some(file) = self.frame_hashes.as_mut() {
+            file.flush().context("flush frame hash file")?;
+        }
+
+        if let Some(recorder) = self.recorder.as_mut() {
+            recorder.finish().context("flush input recording")?;
+        }
+
+        if let Some(export) = self.export.as_mut() {
+            export.finish().context("flush export")?;
+            tracing::info!(
+                "exported frames to {}",
+                self.config.export.as_deref().unwrap_or_default()
+            );
+        }
+
+        if let Some((coverage, path)) = self.coverage.as_ref().zip(self.config.coverage.as_ref()) {
+            let empty = Program::default();
+            let report = coverage.report(
+                self.config.coverage_format,
+                self.program.as_ref().unwrap_or(&empty),
+                PROGRAM_START_ADDR,
+                &self.config.symbols,
+            );
+            std::fs::write(path, report).context(format!("write coverage {}", path))?;
+            tracing::info!("wrote coverage to {}", path);
+        }
+
+        std::io::stdout().flush().context("flush stdout")
+    }
+    fn process_inputs(&mut self) {
+        let Some(frontend) = self.frontend.as_ref() else {
+            return;
+        };
+
+        let inputs: Vec<Input> = frontend.inputs.try_iter().collect();
+        for input in inputs {
+            self.apply_input(input);
+        }
+    }
+    // key events are routed through netplay when a session is active so they are applied in
+    // lockstep with the peer
+    fn apply_input(&mut self, input: Input) {
+        match input {
+            Input::Key(key, pressed) => {
+                let idx = key.idx();
+                if pressed {
+                    self.hold_until[idx] = self.frame + self.config.min_key_hold.unwrap_or(0);
+                    self.early_releases[idx] = false;
+                } else if self.frame < self.hold_until[idx] {
+                    self.early_releases[idx] = true;
+                    return;
+                }
+
+                self.record_input(&key, pressed);
+
+                match self.netplay.as_mut() {
+                    Some(netplay) => netplay.queue(key, pressed),
+                    None if pressed => self.machine.keyboard.key_pressed(key),
+                    None => self.machine.keyboard.key_released(key),
+                }
+            }
+            Input::Hotkey(hotkey, pressed) => self.apply_hotkey(hotkey, pressed),
+            Input::Macro(name, true) => self.play_macro(&name),
+            Input::Macro(_, false) => {}
+            Input::Refresh => self.refreshes += 1,
+            Input::Quit => self.stop.store(true, Ordering::Relaxed),
+        }
+    }
+    fn record_input(&mut self, key: &Key, pressed: bool) {
+        let Some(recorder) = self.recorder.as_mut() else {
+            return;
+        };
+
+        let event = KeyEvent {
+            key: key.idx() as u8,
+            pressed,
+        };
+        if let Err(e) = recorder.record(self.frame - self.program_frame, event) {
+            tracing::warn!("input recording stopped: {:#}", e);
+            self.recorder = None;
+        }
+    }
+    // a macro pressed while another plays starts once the other is done
+    fn play_macro(&mut self, name: &str) {
+        let Some(steps) = self.config.hotkeys.macro_for(name) else {
+            return;
+        };
+
+        let start = match self.macro_events.last() {
+            Some((frame, _)) => u64::max(self.frame, frame + 1),
+            None => self.frame,
+        };
+        self.macro_events.extend(
+            steps
+                .events()
+                .into_iter()
+                .map(|(frame, event)| (start + frame, event)),
+        );
+
+        tracing::debug!("playing macro bound to {}", name);
+    }
+    fn macro_frame(&mut self) {
+        let due = self
+            .macro_events
+            .partition_point(|(frame, _)| *frame <= self.frame);
+
+        let events: Vec<KeyEvent> = self.macro_events.drain(..due).map(|(_, e)| e).collect();
+        for event in events {
+            let key = Key::from(event.key as usize);
+            self.apply_input(Input::Key(key, event.pressed));
+        }
+    }
+    // releases the keys let go before their minimum hold once it has passed
+    fn key_hold_frame(&mut self) {
+        for idx in 0..self.early_releases.len() {
+            if self.early_releases[idx] && self.frame >= self.hold_until[idx] {
+                self.early_releases[idx] = false;
+                self.apply_input(Input::Key(Key::from(idx), false));
+            }
+        }
+    }
+    // replays the recorded inputs of the rom and moves on to the next rom of the playlist once
+    // its time is up
+    fn kiosk_frame(&mut self) {
+        let Some(kiosk) = self.kiosk.as_ref() else {
+            return;
+        };
+
+        let entry = kiosk.entry();
+        let frame = self.frame - self.program_frame;
+
+        if frame >= entry.seconds * self.config.timer_hz as u64 {
+            if let Some(kiosk) = self.kiosk.as_mut() {
+                kiosk.advance();
+            }
+            self.play_kiosk_entry();
+            return;
+        }
+
+        if let Some(inputs) = entry.inputs.as_ref() {
+            for event in inputs.events(frame, frame + 1) {
+                self.machine.keyboard.apply(event);
+            }
+        }
+    }
+    fn play_kiosk_entry(&mut self) {
+        let Some(entry) = self.kiosk.as_ref().map(|kiosk| kiosk.entry().clone()) else {
+            return;
+        };
+
+        tracing::info!(
+            "kiosk playing {} for {} seconds",
+            entry.program.name,
+            entry.seconds
+        );
+
+        let mode = entry.mode.as_ref().unwrap_or(&self.config.mode);
+        self.machine
+            .cpu
+            .set_quirks(quirks(mode, &self.config.quirks));
+
+        if let Some(frontend) = self.frontend.as_ref() {
+            let title = format!("chipate - {}", entry.program.name);
+            let _ = frontend.outputs.send(Output::Title(title));
+        }
+
+        self.program = Some(entry.program);
+        self.reset();
+
+        if let Some(inputs) = entry.inputs.as_ref() {
+            for event in inputs.events(0, 1) {
+                self.machine.keyboard.apply(event);
+            }
+        }
+    }
+    fn apply_hotkey(&mut self, hotkey: Hotkey, pressed: bool) {
+        match hotkey {
+            Hotkey::Quit if pressed => self.stop.store(true, Ordering::Relaxed),
+            // reading memory leaves both sides of a comparison or netplay session alone
+            Hotkey::DumpMemory if pressed => match self.dump_memory() {
+                Ok(path) => tracing::info!("hotkey {} wrote {}", hotkey.name(), path.display()),
+                Err(e) => tracing::warn!("hotkey {} failed: {:#}", hotkey.name(), e),
+            },
+            Hotkey::ExportSvg if pressed => match self.screenshot() {
+                Ok(path) => tracing::info!("hotkey {} wrote {}", hotkey.name(), path.display()),
+                Err(e) => tracing::warn!("hotkey {} failed: {:#}", hotkey.name(), e),
+            },
+            // only changes how the display is drawn
+            Hotkey::PixelGrid => {
+                if pressed {
+                    self.config.pixel_grid = self.config.pixel_grid.next();
+                    tracing::info!("hotkey {} {}", hotkey.name(), self.config.pixel_grid);
+
+                    if let Some(frontend) = self.frontend.as_ref() {
+                        let reload = Output::Reload(self.style(), self.config.hotkeys.clone());
+                        let _ = frontend.outputs.send(reload);
+                    }
+                    self.send_frame();
+                }
+            }
+            // changing the machine on only one side of a comparison or netplay session would
+            // desync it
+            _ if self.compare.is_some() || self.netplay.is_some() => {
+                tracing::debug!(
+                    "{} is unavailable while comparing or netplaying",
+                    hotkey.name()
+                )
+            }
+            Hotkey::Rewind => self.rewinding = pressed && self.rewind.is_some(),
+            _ if !pressed => {}
+            Hotkey::Pause | Hotkey::Reset | Hotkey::SaveState | Hotkey::LoadState => {
+                let command = match hotkey {
+                    Hotkey::Pause if self.paused => Command::Resume,
+                    Hotkey::Pause => Command::Pause,
+                    Hotkey::Reset => Command::Reset,
+                    Hotkey::SaveState => Command::SaveState,
+                    _ => Command::LoadState,
+                };
+
+                match self.handle_command(command) {
+                    Ok(_) => tracing::info!("hotkey {}", hotkey.name()),
+                    Err(e) => tracing::warn!("hotkey {} failed: {}", hotkey.name(), e),
+                }
+
+                self.audio
+                    .update(self.machine.cpu.is_sound_playable() && !self.paused);
+            }
+            Hotkey::Quit | Hotkey::DumpMemory | Hotkey::ExportSvg => {}
+        }
+    }
+    // dumps go in the data directory of the rom, named after the frame they were taken on
+    fn dump_memory(&self) -> Result<PathBuf> {
+        let program = self.program.as_ref().context("no rom is loaded")?;
+        let dir = RomData::locate(program)?.dumps_dir();
+        std::fs::create_dir_all(&dir)
+            .context(format!("create directory {}", dir.to_string_lossy()))?;
+
+        let path = dir.join(format!("frame-{}.bin", self.frame));
+        dump::save(&path, &self.machine.cpu, &self.machine.memory, 0, RAM_SIZE)?;
+
+        Ok(path)
+    }
+    // screenshots go beside the dumps, named the same way
+    fn screenshot(&self) -> Result<PathBuf> {
+        let program = self.program.as_ref().context("no rom is loaded")?;
+        let dir = RomData::locate(program)?.screenshots_dir();
+        std::fs::create_dir_all(&dir)
+            .context(format!("create directory {}", dir.to_string_lossy()))?;
+
+        let path = dir.join(format!("frame-{}.svg", self.frame));
+        svg::save(&path, &self.displays(), self.config.palette)?;
+
+        Ok(path)
+    }
+    // saved states go in the data directory of the rom, a file for each slot
+    fn state_slot_path(&self) -> Result<PathBuf> {
+        let program = self.program.as_ref().context("no rom is loaded")?;
+        let data = RomData::locate(program)?;
+        data.create()?;
+
+        Ok(data.state_slot_path(self.config.state_slot))
+    }
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            cpu: self.machine.cpu.clone(),
+            memory: self.machine.memory.clone(),
+            display: self.machine.display.clone(),
+        }
+    }
+    fn restore(&mut self, snapshot: Snapshot) {
+        self.machine.cpu = snapshot.cpu;
+        self.machine.memory = snapshot.memory;
+        self.machine.display = snapshot.display;
+    }
+    fn record_frame(&mut self) {
+        if let Some(rewind) = self.rewind.as_mut() {
+            rewind.push(&Snapshot {
+                cpu: self.machine.cpu.clone(),
+                memory: self.machine.memory.clone(),
+                display: self.machine.display.clone(),
+            });
+        }
+    }
+    // one line per frame of the frame number and the hash of the registers, memory and display, two
+    // runs that should be identical can be diffed to find the first frame they differ on
+    fn hash_frame(&mut self) -> Result<()> {
+        if self.frame_hashes.is_none() {
+            return Ok(());
+        }
+
+        let hash = self.snapshot().hash();
+        if let Some(file) = self.frame_hashes.as_mut() {
+            writeln!(file, "{} {:016x}", self.frame, hash).context("write frame hash")?;
+        }
+
+        Ok(())
+    }
+    fn export_frame(&mut self) -> Result<()> {
+        let seconds = self.frame as f64 / self.config.timer_hz as f64;
+        if let Some(export) = self.export.as_mut() {
+            export
+                .frame(self.frame, seconds, &self.machine.display)
+                .context("write export")?;
+        }
+
+        Ok(())
+    }
+    // restores the frame before the newest one, the newest is the state the machine is already in
+    fn step_back(&mut self) {
+        let Some(rewind) = self.rewind.as_mut() else {
+            return;
+        };
+
+        // the oldest frame stays so there is always a state to continue from
+        if rewind.len() < 2 {
+            self.rewinding = false;
+            return;
+        }
+
+        rewind.pop();
+
+        let result = rewind
+            .pop()
+            .context("rewind history is empty")
+            .and_then(|state| Snapshot::decode(&state, &self.machine.cpu));
+
+        match result {
+            Ok(snapshot) => {
+                // the restored frame stays in the history so the next step back continues from it
+                rewind.push(&snapshot);
+                tracing::trace!(
+                    "rewound to frame {}, {} frames of history in {} bytes",
+                    self.frame.saturating_sub(1),
+                    rewind.len(),
+                    rewind.size_in_bytes()
+                );
+
+                self.restore(snapshot);
+                self.frame = self.frame.saturating_sub(1);
+            }
+            Err(e) => {
+                tracing::error!("could not rewind: {:#}", e);
+                self.rewinding = false;
+            }
+        }
+    }
+    // sleeps until the deadline, waking early when input arrives so an idle machine reacts to
+    // key presses right away, returns true when it woke for input
+    fn wait_until(&mut self, deadline: Instant) -> bool {
+        let timeout = deadline.saturating_duration_since(Instant::now());
+
+        let Some(frontend) = self.frontend.as_ref() else {
+            std::thread::sleep(timeout);
+            return false;
+        };
+
+        match frontend.inputs.recv_timeout(timeout) {
+            Ok(input) => {
+                self.apply_input(input);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+    // breakpoints have to see every instruction so the debugger keeps the machine running
+    fn is_idle(&self) -> bool {
+        if self.debugger.is_some()
+            || !self
+                .machine
+                .cpu
+                .is_idle(&self.machine.memory, &self.machine.keyboard)
+        {
+            return false;
+        }
+
+        self.compare.as_ref().map_or(true, |compare| {
+            compare
+                .machine
+                .cpu
+                .is_idle(&compare.machine.memory, &self.machine.keyboard)
+        })
+    }
+    // the rom is named as it was loaded, a playlist or a loaded rom changes it
+    #[cfg(feature = "discord")]
+    fn update_presence(&mut self) {
+        if let Some(presence) = self.presence.as_mut() {
+            let rom = self.program.as_ref().map_or("chipate", |p| p.name.as_str());
+            presence.update(rom, self.paused);
+        }
+    }
+    // the display of the machine beside the one it is compared with
+    fn displays(&self) -> Vec<DisplayState> {
+        let mut displays = vec![self.machine.display.clone()];
+        if let Some(compare) = self.compare.as_ref() {
+            displays.push(compare.machine.display.clone());
+        }
+
+        displays
+    }
+    fn send_frame(&mut self) {
+        if self.frontend.is_none() && self.sinks.is_empty() {
+            return;
+        }
+
+        let displays = self.displays();
+        let bell = self.config.visual_bell.filter(|_| self.audio.is_playing());
+
+        self.sinks.present(&displays, self.config.palette, bell);
+
+        if let Some(frontend) = self.frontend.as_ref() {
+            // the render thread only goes away after the emulation thread has finished
+            let _ = frontend.outputs.send(Output::Frame(displays, bell));
+
+            if let Some(heatmap) = self.heatmap.as_ref() {
+                let view = DebugView::new(
+                    &self.machine.cpu,
+                    &self.machine.memory,
+                    heatmap,
+                    &self.config.symbols,
+                );
+                let _ = frontend.outputs.send(Output::Debug(Box::new(view)));
+            }
+        }
+    }
+    fn process_commands(&mut self) {
+        let requests: Vec<Request> = self.commands.try_iter().collect();
+
+        for request in requests {
+            tracing::debug!("handling command {:?}", request.command);
+
+            let result = self.handle_command(request.command);
+            if request.reply.send(result).is_err() {
+                tracing::debug!("command sender went away before the reply was sent");
+            }
+        }
+
+        // a reset or a restored state can start or silence the sound
+        self.audio
+            .update(self.machine.cpu.is_sound_playable() && !self.paused);
+    }
+    fn handle_command(&mut self, command: Command) -> Result<String, String> {
+        // changing the machine on only one side of a comparison or netplay session would desync
+        // it, the same as the hotkeys for these
+        let changes_machine = matches!(
+            command,
+            Command::Reset | Command::LoadRom(_) | Command::SaveState | Command::LoadState
+        );
+        if changes_machine && (self.compare.is_some() || self.netplay.is_some()) {
+            return Err(String::from("unavailable while comparing or netplaying"));
+        }
+
+        match command {
+            Command::Pause => {
+                self.paused = true;
+                self.last_frame = None;
+            }
+            Command::Resume => self.paused = false,
+            Command::Reset => self.reset(),
+            Command::LoadRom(program) => {
+                // counts from the previous rom would be reported against this one
+                if self.coverage.is_some() {
+                    self.coverage = Some(Coverage::default());
+                }
+                self.program = Some(program);
+                self.reset();
+            }
+            Command::SaveState => {
+                let path = self.state_slot_path().map_err(|e| format!("{:#}", e))?;
+                self.snapshot()
+                    .save(&path)
+                    .map_err(|e| format!("{:#}", e))?;
+                tracing::info!("saved state to {}", path.display());
+            }
+            Command::LoadState => {
+                let path = self.state_slot_path().map_err(|e| format!("{:#}", e))?;
+                if !path.exists() {
+                    return Err(format!("no saved state in slot {}", self.config.state_slot));
+                }
+
+                let snapshot =
+                    Snapshot::load(&path, &self.machine.cpu).map_err(|e| format!("{:#}", e))?;
+                self.restore(snapshot);
+            }
+            Command::Registers => return Ok(self.registers_json()),
+        }
+
+        Ok(format!("{{\"paused\":{}}}", self.paused))
+    }
+    // registers, stack and the instructions leading up to now for diagnosing a stuck run
+    fn state_report(&self) -> String {
+        let symbols = &self.config.symbols;
+
+        let mut lines = vec![
+            format!("state at frame {}", self.frame),
+            format!(
+                "pc={} i={} dt={:#04x} st={:#04x}",
+                symbols.format_address(self.machine.cpu.prog_counter()),
+                symbols.format_address(self.machine.cpu.index()),
+                self.machine.cpu.delay_timer(),
+                self.machine.cpu.sound_timer()
+            ),
+        ];
+
+        for row in 0..2 {
+            let regs: Vec<String> = (0..8)
+                .map(|col| row * 8 + col)
+                .map(|idx| format!("v{:x}={:#04x}", idx, self.machine.cpu.v(idx)))
+                .collect();
+            lines.push(regs.join(" "));
+        }
+
+        let stack: Vec<String> = self
+            .machine
+            .cpu
+            .stack()
+            .iter()
+            .map(|address| symbols.format_address(*address))
+            .collect();
+        lines.push(format!("stack=[{}]", stack.join(", ")));
+
+        lines.push(String::from("recent instructions, oldest first:"));
+        for (address, instruction) in self.machine.cpu.history() {
+            lines.push(format!(
+                "  {} {}",
+                symbols.format_address(address),
+                instruction.with_symbols(symbols)
+            ));
+        }
+
+        lines.join("\n")
+    }
+    fn registers_json(&self) -> String {
+        let vs: Vec<String> = (0..16)
+            .map(|idx| self.machine.cpu.v(idx).to_string())
+            .collect();
+
+        format!(
+            "{{\"pc\":{},\"i\":{},\"v\":[{}],\"dt\":{},\"st\":{},\"paused\":{}}}",
+            self.machine.cpu.prog_counter(),
+            self.machine.cpu.index(),
+            vs.join(","),
+            self.machine.cpu.delay_timer(),
+            self.machine.cpu.sound_timer(),
+            self.paused
+        )
+    }
+    fn break_at_start(&mut self) -> Action {
+        let should_break = self.debugger.as_ref().is_some_and(|debugger| {
+            debugger.should_break(&self.machine.cpu, &self.machine.memory, None)
+        });
+
+        if !should_break {
+            return Action::Continue;
+        }
+
+        self.send_frame();
+
+        match self.debugger.as_mut() {
+            Some(debugger) => debugger.prompt(
+                &mut self.machine.cpu,
+                &mut self.machine.memory,
+                &mut self.freezes,
+                None,
+            ),
+            None => Action::Continue,
+        }
+    }
+    // netplay runs a fixed number of instructions per frame so both peers stay in lockstep
+    // regardless of how fast either host is, otherwise instructions are paced individually
+    fn run_frame(&mut self) -> Result<Action> {
+        let Some(netplay) = self.netplay.as_mut() else {
+            return Ok(Action::Continue);
+        };
+
+        for event in netplay.exchange(self.frame)? {
+            self.machine.keyboard.apply(event);
+        }
+
+        let instructions_per_frame =
+            u16::max(1, self.config.instructions_per_sec / self.config.timer_hz);
+
+        for _ in 0..instructions_per_frame {
+            if self.step()? == Action::Quit {
+                return Ok(Action::Quit);
+            }
+        }
+
+        Ok(Action::Continue)
+    }
+    fn on_frame(&mut self) {
+        let now = Instant::now();
+        if let Some(last_frame) = self.last_frame.replace(now) {
+            metrics::histogram!("chipate_frame_seconds").record(now - last_frame);
+        }
+
+        self.machine.vblank();
+        self.audio.update(self.machine.cpu.is_sound_playable());
+
+        self.frame += 1;
+        self.observers.vblank(self.frame, &self.machine.display);
+
+        if let Some(heatmap) = self.heatmap.as_mut() {
+            heatmap.frame(&self.machine.memory);
+        }
+
+        if let Some(server) = self.websocket.as_mut() {
+            server.publish(&self.machine.display);
+
+            for event in server.key_events() {
+                self.machine.keyboard.apply(event);
+            }
+        }
+
+        let Some(compare) = self.compare.as_mut() else {
+            return;
+        };
+
+        compare.machine.vblank();
+
+        if self.diverged || self.machine.display == compare.machine.display {
+            return;
+        }
+
+        self.diverged = true;
+
+        tracing::warn!(
+            "displays diverged at frame {}, pc {:#05x} ({:?}) vs {:#05x} ({:?})",
+            self.frame,
+            self.machine.cpu.prog_counter(),
+            self.config.mode,
+            compare.machine.cpu.prog_counter(),
+            compare.config.mode
+        );
+
+        if let Some(frontend) = self.frontend.as_ref() {
+            let title = format!("chipate - diverged at frame {}", self.frame);
+            let _ = frontend.outputs.send(Output::Title(title));
+        }
+    }
+    fn step(&mut self) -> Result<Action> {
+        // a cpu waiting for the vblank executes nothing and should not break again where it stopped
+        let waiting = self.machine.cpu.is_waiting_for_vblank();
+
+        if let Some(coverage) = self.coverage.as_mut().filter(|_| !waiting) {
+            coverage.record(self.machine.cpu.prog_counter());
+        }
+
+        if let Some(heatmap) = self.heatmap.as_mut().filter(|_| !waiting) {
+            heatmap.executed(self.machine.cpu.prog_counter());
+        }
+
+        let draw = self.machine.step();
+
+        for freeze in &self.freezes {
+            freeze.apply(&mut self.machine.cpu, &mut self.machine.memory);
+        }
+
+        self.audio.update(self.machine.cpu.is_sound_playable());
+
+        if let Some(fault) = self.machine.cpu.take_fault() {
+            if self.config.strict {
+                return Err(fault.into());
+            }
+        }
+
+        if let Some(compare) = self.compare.as_mut() {
+            compare.machine.cpu.tick(
+                &mut compare.machine.memory,
+                &mut compare.machine.display,
+                &compare.machine.font,
+                &self.machine.keyboard,
+            );
+
+            for freeze in &compare.freezes {
+                freeze.apply(&mut compare.machine.cpu, &mut compare.machine.memory);
+            }
+        }
+
+        let should_break = !waiting
+            && self.debugger.as_ref().is_some_and(|debugger| {
+                debugger.should_break(&self.machine.cpu, &self.machine.memory, draw.as_ref())
+            });
+
+        if !should_break {
+            return Ok(Action::Continue);
+        }
+
+        self.send_frame();
+
+        Ok(match self.debugger.as_mut() {
+            Some(debugger) => debugger.prompt(
+                &mut self.machine.cpu,
+                &mut self.machine.memory,
+                &mut self.freezes,
+                draw.as_ref(),
+            ),
+            None => Action::Continue,
+        })
+    }
+    #[cfg_attr(not(any(feature = "sdl", feature = "pixels")), allow(dead_code))]
+    fn num_displays(&self) -> u32 {
+        if self.compare.is_some() {
+            2
+        } else {
+            1
+        }
+    }
+}
+
+// quirks named in the config file are changed from the ones of the mode
+fn quirks(mode: &Mode, overrides: &[(String, bool)]) -> Quirks {
+    let mut quirks = Quirks::from(mode);
+    for (name, value) in overrides {
+        quirks.set(name, *value);
+    }
+
+    quirks
+}
+
+// save states only hold what differs between machines so the cpu quirks do not affect the export
+pub fn export_state_json(path: impl AsRef<Path>) -> Result<String> {
+    Ok(Snapshot::load(path, &CPU::default())?.to_json())
+}
+
+// the json an export or another emulator wrote as a save state that can be loaded as an autosave
+pub fn import_state_json(json: impl AsRef<Path>, state: impl AsRef<Path>) -> Result<()> {
+    let text = std::fs::read_to_string(json.as_ref())
+        .context(format!("read file {}", json.as_ref().to_string_lossy()))?;
+
+    Snapshot::from_json(&text, &CPU::default())
+        .context(format!("import {}", json.as_ref().to_string_lossy()))?
+        .save(state)
+}
+
+// every save state in a directory by name, each followed by its thumbnail
+pub fn list_states(dir: impl AsRef<Path>) -> Result<String> {
+    let dir = dir.as_ref();
+    let mut paths: Vec<PathBuf> = match std::fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "c8st"))
+            .collect(),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+        Err(e) => return Err(e).context(format!("read directory {}", dir.to_string_lossy())),
+    };
+    paths.sort();
+
+    if paths.is_empty() {
+        return Ok(format!("no save states in {}\n", dir.to_string_lossy()));
+    }
+
+    let mut text = String::new();
+    for path in paths {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let bytes =
+            std::fs::read(&path).context(format!("read file {}", path.to_string_lossy()))?;
+
+        match state::read_thumbnail(&bytes) {
+            Ok(thumbnail) => text.push_str(&format!("{}\n{}", name, thumbnail.render())),
+            Err(e) => text.push_str(&format!("{}\n  unreadable: {}\n", name, e)),
+        }
+    }
+
+    Ok(text)
 }
@@ -1,50 +1,1421 @@
 use anyhow::Context;
 use chipate::{
-    core::{cpu::Mode, Font, Program},
-    Config, Emu,
+    audio::{AudioBackend, AudioSink, Bell},
+    bezel::{Bezel, Viewport},
+    core::{
+        analysis::{self, Analysis},
+        bench::Bench,
+        cheat::Cheat,
+        coverage::CoverageFormat,
+        cpu::Mode,
+        demo,
+        disasm::Disassembly,
+        fuzz::{self, FuzzConfig},
+        octo,
+        profile::Profile,
+        regions::RegionMap,
+        symbols::SymbolTable,
+        test_pattern,
+        trace::{self, TraceConfig},
+        Font, Program,
+    },
+    debugger::{DebugSession, DebuggerConfig, DrawBreakpoint, OpcodePattern, ScreenRect},
+    handle::EmuHandle,
+    keymap::Hotkeys,
+    kiosk::Playlist,
+    netplay::{Netplay, Session},
+    palette::Palette,
+    settings::{self, Options, Settings},
+    storage::RomData,
+    websocket::DisplayServer,
+    Config, Emu, EmuError, ExportFormat, Filter, Frontend, PixelAspect, PixelGrid, Renderer,
+    Resolution, TerminalGraphics, VisualBell, PROGRAM_START_ADDR,
+};
+use clap::{builder::PossibleValuesParser, CommandFactory, Parser, Subcommand, ValueEnum};
+use clap_complete::Shell;
+use std::{
+    fs::File,
+    io::BufWriter,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    time::{Duration, Instant},
 };
-use clap::Parser;
 use tracing::level_filters::LevelFilter;
-use tracing_subscriber::EnvFilter;
+use tracing_subscriber::{fmt::writer::BoxMakeWriter, EnvFilter};
+
+const DEFAULT_SCALE: u32 = 10;
+
+const SOURCE_POLL_INTERVAL: Duration = Duration::from_millis(250);
 
 #[derive(Parser, Debug)]
-#[command()]
+#[command(subcommand_negates_reqs = true, after_help = EXIT_CODES)]
 struct Args {
-    #[arg(short, long)]
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[arg(long, global = true, value_enum, default_value_t = LogFormat::Pretty)]
+    log_format: LogFormat,
+    #[arg(long, global = true, env = "CHIPATE_ROM_DIR", value_name = "PATH")]
+    rom_dir: Option<String>,
+    #[arg(long, global = true, env = "CHIPATE_CONFIG", value_name = "PATH")]
+    config: Option<String>,
+    #[arg(
+        long,
+        env = "CHIPATE_PROFILE",
+        value_name = "vip|chip48|schip|xochip|modern"
+    )]
+    profile: Option<Profile>,
+    #[arg(long, env = "CHIPATE_PROFILE_NAME", value_name = "NAME")]
+    profile_name: Option<String>,
+    #[arg(long)]
+    no_detect: bool,
+    #[arg(short, long, value_name = "classic|chip48|schip|xochip|modern")]
     mode: Option<Mode>,
     #[arg(short, long)]
-    rom: String,
-    #[arg(short, long, default_value_t = 700)]
-    instructions_per_second: u16,
+    rom: Option<String>,
+    #[arg(
+        long,
+        value_name = "PATH",
+        conflicts_with_all = ["rom", "host", "connect", "compare"]
+    )]
+    playlist: Option<String>,
+    #[arg(long, value_name = "S", default_value_t = 60, value_parser = clap::value_parser!(u64).range(1..), requires = "playlist")]
+    kiosk_seconds: u64,
+    #[arg(long, value_name = "PATH")]
+    record_inputs: Option<String>,
+    #[arg(long, value_name = "FRAMES", value_parser = clap::value_parser!(u64).range(1..))]
+    min_key_hold: Option<u64>,
+    #[arg(long, value_name = "PATH", requires = "rom")]
+    patch: Option<String>,
+    #[arg(
+        short,
+        long,
+        env = "CHIPATE_SPEED",
+        value_parser = clap::value_parser!(u16).range(1..)
+    )]
+    instructions_per_second: Option<u16>,
+    #[arg(long, default_value_t = 60, value_parser = clap::value_parser!(u16).range(1..))]
+    timer_hz: u16,
+    #[arg(long)]
+    break_on_draw: bool,
+    #[arg(long, value_name = "X,Y,W,H")]
+    break_on_draw_rect: Option<ScreenRect>,
+    #[arg(long)]
+    break_on_collision: bool,
+    #[arg(short, long = "break", value_name = "ADDRESS|SYMBOL")]
+    breakpoints: Vec<String>,
+    #[arg(long = "break-opcode", value_name = "PATTERN")]
+    opcode_breakpoints: Vec<OpcodePattern>,
+    #[arg(long)]
+    debug: bool,
+    #[arg(long)]
+    no_debug_session: bool,
+    #[arg(long)]
+    debug_window: bool,
+    #[arg(long)]
+    symbols: Option<String>,
+    #[arg(long, value_name = "PATH")]
+    regions: Option<String>,
+    #[arg(long = "cheat", value_name = "ADDRESS=VALUE")]
+    cheats: Vec<Cheat>,
+    #[arg(long = "freeze", value_name = "ADDRESS=VALUE")]
+    freezes: Vec<Cheat>,
+    #[arg(long)]
+    seed: Option<u64>,
+    #[arg(long, value_name = "classic|chip48|schip|xochip|modern")]
+    compare: Option<Mode>,
+    #[arg(long, value_name = "ADDRESS", conflicts_with = "connect")]
+    host: Option<String>,
+    #[arg(long, value_name = "ADDRESS")]
+    connect: Option<String>,
+    #[arg(long)]
+    headless: bool,
+    #[arg(long, value_name = "ADDRESS")]
+    websocket: Option<String>,
+    #[arg(long, value_name = "PATH")]
+    autosave: Option<Option<String>>,
+    #[arg(long, value_name = "N", default_value_t = 1, value_parser = clap::value_parser!(u8).range(1..=9))]
+    state_slot: u8,
+    #[arg(long, value_name = "PATH")]
+    frame_hashes: Option<String>,
+    #[arg(long, value_name = "PATH")]
+    export: Option<String>,
+    #[arg(
+        long,
+        value_name = "cast|text",
+        default_value = "cast",
+        requires = "export"
+    )]
+    export_format: ExportFormat,
+    #[arg(long, value_name = "PATH")]
+    export_svg: Option<String>,
+    #[arg(long, value_name = "PATH")]
+    coverage: Option<String>,
+    #[arg(
+        long,
+        value_name = "text|disasm",
+        default_value = "text",
+        requires = "coverage"
+    )]
+    coverage_format: CoverageFormat,
+    #[arg(long, value_name = "PATH")]
+    export_state_json: Option<String>,
+    #[arg(long, value_name = "PATH", conflicts_with = "export_state_json")]
+    import_state_json: Option<String>,
+    #[arg(long, requires = "rom")]
+    list_states: bool,
+    #[arg(long, value_name = "S", value_parser = parse_seconds)]
+    rewind: Option<Duration>,
+    #[arg(long, value_name = "N")]
+    exit_after_frames: Option<u64>,
+    #[arg(long, value_name = "S", value_parser = parse_seconds)]
+    exit_after_seconds: Option<Duration>,
+    #[arg(long)]
+    strict: bool,
+    #[arg(long, value_name = "border|invert")]
+    visual_bell: Option<VisualBell>,
+    #[arg(long)]
+    mute: bool,
+    #[arg(long, env = "CHIPATE_AUDIO", value_name = "sdl|bell")]
+    audio: Option<AudioBackend>,
+    #[arg(long, value_name = "SAMPLES", value_parser = clap::value_parser!(u16).range(16..))]
+    audio_buffer: Option<u16>,
+    #[arg(long, value_name = "PERCENT", value_parser = clap::value_parser!(u8).range(1..=100))]
+    rumble: Option<u8>,
+    #[arg(
+        long,
+        env = "CHIPATE_PALETTE",
+        value_name = "classic|high-contrast|colorblind"
+    )]
+    palette: Option<Palette>,
+    #[arg(long, env = "CHIPATE_SCALE", value_parser = clap::value_parser!(u32).range(1..=64))]
+    scale: Option<u32>,
+    #[arg(long)]
+    pixel_pattern: bool,
+    #[arg(long, env = "CHIPATE_PIXEL_ASPECT", value_name = "square|stretch|W:H")]
+    pixel_aspect: Option<PixelAspect>,
+    #[arg(long, env = "CHIPATE_FILTER", value_name = "nearest|linear")]
+    filter: Option<Filter>,
+    #[arg(long, value_name = "off|lines|rulers", default_value_t = PixelGrid::Off)]
+    pixel_grid: PixelGrid,
+    #[arg(long, value_name = "PATH")]
+    bezel: Option<String>,
+    #[arg(long, value_name = "X,Y,W,H", requires = "bezel")]
+    bezel_viewport: Option<Viewport>,
+    #[arg(long, value_name = "64x32|64x48|64x64")]
+    resolution: Option<Resolution>,
+    #[arg(
+        long,
+        env = "CHIPATE_RENDERER",
+        value_name = "auto|accelerated|software",
+        default_value = "auto"
+    )]
+    renderer: Renderer,
+    #[arg(long)]
+    vsync: bool,
+    #[arg(long, value_name = "sdl|tui|pixels")]
+    frontend: Option<Frontend>,
+    #[arg(long, value_name = "auto|kitty|sixel|blocks", default_value = "auto")]
+    terminal_graphics: TerminalGraphics,
+    #[arg(long)]
+    terminal_mirror: bool,
+    #[cfg(feature = "http-api")]
+    #[arg(long, value_name = "ADDRESS")]
+    http: Option<String>,
+    #[cfg(feature = "prometheus")]
+    #[arg(long, value_name = "ADDRESS")]
+    metrics: Option<std::net::SocketAddr>,
+    #[cfg(feature = "discord")]
+    #[arg(long, value_name = "CLIENT_ID")]
+    discord: Option<String>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    Analyze {
+        rom: String,
+        #[arg(long)]
+        trace: Option<String>,
+    },
+    Disasm {
+        rom: String,
+        #[arg(long)]
+        symbols: Option<String>,
+        #[arg(long, value_name = "PATH")]
+        regions: Option<String>,
+        #[arg(long)]
+        annotate: bool,
+    },
+    Bench {
+        rom: String,
+        #[arg(long, default_value_t = 5_000_000)]
+        instructions: u64,
+        #[arg(short, long, value_name = "classic|chip48|schip|xochip|modern")]
+        mode: Option<Mode>,
+        #[arg(long)]
+        jit: bool,
+    },
+    Trace {
+        rom: String,
+        #[arg(long, default_value_t = 60)]
+        frames: u64,
+        #[arg(short, long, value_name = "classic|chip48|schip|xochip|modern")]
+        mode: Option<Mode>,
+        #[arg(short, long, value_parser = clap::value_parser!(u16).range(1..))]
+        instructions_per_second: Option<u16>,
+        #[arg(long, default_value_t = 60, value_parser = clap::value_parser!(u16).range(1..))]
+        timer_hz: u16,
+        #[arg(long, default_value_t = 0)]
+        seed: u64,
+        #[arg(short, long, value_name = "PATH")]
+        output: Option<String>,
+    },
+    Fuzz {
+        seeds: Vec<String>,
+        #[arg(long, default_value_t = 1000)]
+        runs: u64,
+        #[arg(long, default_value_t = 10_000)]
+        instructions: u64,
+        #[arg(long)]
+        seed: Option<u64>,
+        #[arg(short, long, value_name = "classic|chip48|schip|xochip|modern")]
+        mode: Option<Mode>,
+        #[arg(long, value_name = "DIR", default_value = "fuzz-crashes")]
+        crashes: String,
+    },
+    TraceDiff {
+        a: String,
+        b: String,
+        #[arg(short = 'C', long, default_value_t = 3)]
+        context: usize,
+    },
+    DataDir {
+        rom: String,
+    },
+    Dev {
+        source: String,
+    },
+    DisplayTest {
+        #[arg(
+            long,
+            env = "CHIPATE_PALETTE",
+            value_name = "classic|high-contrast|colorblind",
+            default_value = "classic"
+        )]
+        palette: Palette,
+        #[arg(long, env = "CHIPATE_SCALE", default_value_t = DEFAULT_SCALE, value_parser = clap::value_parser!(u32).range(1..=64))]
+        scale: u32,
+        #[arg(long)]
+        pixel_pattern: bool,
+        #[arg(long, value_name = "square|stretch|W:H", default_value = "square")]
+        pixel_aspect: PixelAspect,
+        #[arg(long, value_name = "nearest|linear", default_value = "nearest")]
+        filter: Filter,
+        #[arg(long, value_name = "64x32|64x48|64x64", default_value = "64x32")]
+        resolution: Resolution,
+        #[arg(long, value_name = "S", value_parser = parse_seconds)]
+        exit_after_seconds: Option<Duration>,
+    },
+    InputTest {
+        #[arg(
+            long,
+            env = "CHIPATE_PALETTE",
+            value_name = "classic|high-contrast|colorblind",
+            default_value = "classic"
+        )]
+        palette: Palette,
+        #[arg(long, env = "CHIPATE_SCALE", default_value_t = DEFAULT_SCALE, value_parser = clap::value_parser!(u32).range(1..=64))]
+        scale: u32,
+        #[arg(long, value_name = "S", value_parser = parse_seconds)]
+        exit_after_seconds: Option<Duration>,
+    },
+    SoundTest {
+        #[arg(long, value_name = "border|invert")]
+        visual_bell: Option<VisualBell>,
+        #[arg(long, value_name = "S", value_parser = parse_seconds)]
+        exit_after_seconds: Option<Duration>,
+    },
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+    #[command(hide = true)]
+    Completions {
+        shell: Shell,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum ConfigCommand {
+    Init {
+        path: Option<String>,
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum LogFormat {
+    Pretty,
+    Json,
 }
 
-fn main() -> anyhow::Result<()> {
-    tracing_subscriber::fmt()
-        .pretty()
+const EXIT_CODES: &str = "Exit codes:
+  0  success
+  1  any other error
+  2  the rom could not be loaded
+  3  illegal op code
+  4  stack overflow or underflow
+  5  fuzzed programs crashed the interpreter or traces differ";
+
+// marks errors that get their own exit code, anything not marked exits with 1
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Failure {
+    RomLoad,
+    TestSuite,
+}
+
+impl std::fmt::Display for Failure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Failure::RomLoad => write!(f, "load rom"),
+            Failure::TestSuite => write!(f, "test suite failed"),
+        }
+    }
+}
+
+fn exit_code(error: &anyhow::Error) -> u8 {
+    match error.downcast_ref::<Failure>() {
+        Some(Failure::RomLoad) => return 2,
+        Some(Failure::TestSuite) => return 5,
+        None => {}
+    }
+
+    // faults reach here as the error the library turned them into, often with context around it
+    match error.downcast_ref::<EmuError>().map(EmuError::root) {
+        Some(EmuError::IllegalOpcode { .. }) => 3,
+        Some(EmuError::StackOverflow { .. } | EmuError::StackUnderflow { .. }) => 4,
+        _ => 1,
+    }
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+
+    // the terminal frontend and mirror draw on stdout and traces are written to it when no output
+    // is given so logs go to stderr where they can be redirected
+    let stdout_taken = args.frontend.unwrap_or_default() == Frontend::Tui
+        || args.terminal_mirror
+        || matches!(args.command, Some(Command::Trace { output: None, .. }));
+    let log_writer = if stdout_taken {
+        BoxMakeWriter::new(std::io::stderr)
+    } else {
+        BoxMakeWriter::new(std::io::stdout)
+    };
+
+    let filter = EnvFilter::builder()
+        .with_default_directive(LevelFilter::INFO.into())
+        .from_env_lossy();
+    // cranelift logs every function the jit defines
+    #[cfg(feature = "jit")]
+    let filter = filter.add_directive("cranelift_jit=warn".parse().unwrap());
+    // generated programs are full of op codes the cpu warns about
+    let filter = match args.command {
+        Some(Command::Fuzz { .. }) => {
+            filter.add_directive("chipate::core::cpu=error".parse().unwrap())
+        }
+        _ => filter,
+    };
+
+    let subscriber = tracing_subscriber::fmt()
         .with_level(true)
         .with_target(true)
         .with_file(true)
         .with_line_number(true)
         .with_thread_ids(true)
         .with_thread_names(true)
-        .with_env_filter(
-            EnvFilter::builder()
-                .with_default_directive(LevelFilter::INFO.into())
-                .from_env_lossy(),
-        )
-        .init();
+        .with_writer(log_writer)
+        .with_env_filter(filter);
 
-    let args = Args::parse();
+    match args.log_format {
+        LogFormat::Pretty => subscriber.pretty().init(),
+        LogFormat::Json => subscriber.json().init(),
+    }
+
+    let result = match args.command {
+        Some(Command::Analyze { rom, trace }) => analyze(resolve_rom(rom, &args.rom_dir), trace),
+        Some(Command::Disasm {
+            rom,
+            symbols,
+            regions,
+            annotate,
+        }) => disasm(resolve_rom(rom, &args.rom_dir), symbols, regions, annotate),
+        Some(Command::Bench {
+            rom,
+            instructions,
+            mode,
+            jit,
+        }) => bench(resolve_rom(rom, &args.rom_dir), instructions, mode, jit),
+        Some(Command::Trace {
+            rom,
+            frames,
+            mode,
+            instructions_per_second,
+            timer_hz,
+            seed,
+            output,
+        }) => trace(
+            resolve_rom(rom, &args.rom_dir),
+            frames,
+            mode,
+            instructions_per_second,
+            timer_hz,
+            seed,
+            output,
+        ),
+        Some(Command::Fuzz {
+            seeds,
+            runs,
+            instructions,
+            seed,
+            mode,
+            crashes,
+        }) => {
+            let config = FuzzConfig {
+                runs,
+                instructions,
+                seed: seed.unwrap_or_else(rand::random),
+                mode,
+            };
+            fuzz(seeds, &config, crashes)
+        }
+        Some(Command::TraceDiff { a, b, context }) => trace_diff(a, b, context),
+        Some(Command::DataDir { rom }) => data_dir(resolve_rom(rom, &args.rom_dir)),
+        Some(Command::DisplayTest {
+            palette,
+            scale,
+            pixel_pattern,
+            pixel_aspect,
+            filter,
+            resolution,
+            exit_after_seconds,
+        }) => {
+            let style = TestStyle {
+                palette,
+                scale,
+                pixel_pattern,
+                pixel_aspect,
+                filter,
+                resolution,
+                visual_bell: None,
+            };
+            test_emu(
+                test_pattern::display(resolution.height()),
+                style,
+                Profile::default().instructions_per_sec(),
+                exit_after_seconds,
+            )
+            .run()
+            .map_err(anyhow::Error::from)
+        }
+        Some(Command::InputTest {
+            palette,
+            scale,
+            exit_after_seconds,
+        }) => {
+            let style = TestStyle {
+                palette,
+                scale,
+                pixel_pattern: false,
+                pixel_aspect: PixelAspect::Square,
+                filter: Filter::Nearest,
+                resolution: Resolution::Standard,
+                visual_bell: None,
+            };
+            test_emu(
+                test_pattern::keypad(),
+                style,
+                test_pattern::KEYPAD_INSTRUCTIONS_PER_SEC,
+                exit_after_seconds,
+            )
+            .run()
+            .map_err(anyhow::Error::from)
+        }
+        Some(Command::SoundTest {
+            visual_bell,
+            exit_after_seconds,
+        }) => sound_test(visual_bell, exit_after_seconds),
+        Some(Command::Config {
+            command: ConfigCommand::Init { path, force },
+        }) => config_init(path, force),
+        Some(Command::Completions { shell }) => completions(shell, config_path(args.config)),
+        // the run options all apply to the program assembled from the source
+        Some(Command::Dev { .. }) | None => run(args),
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {:?}", e);
+            ExitCode::from(exit_code(&e))
+        }
+    }
+}
+
+fn analyze(rom: String, trace: Option<String>) -> anyhow::Result<()> {
+    let program = Program::from_file(rom).context(Failure::RomLoad)?;
+
+    let mut analysis = Analysis::new(&program, PROGRAM_START_ADDR);
+    if let Some(trace) = trace {
+        analysis = analysis.with_trace(analysis::read_trace(trace).context("load trace")?);
+    }
+
+    print!("{}", analysis);
+
+    Ok(())
+}
+
+fn disasm(
+    rom: String,
+    symbols: Option<String>,
+    regions: Option<String>,
+    annotate: bool,
+) -> anyhow::Result<()> {
+    let program = Program::from_file(rom).context(Failure::RomLoad)?;
+    let symbols = load_symbols(symbols)?;
+
+    let mut program_regions = RegionMap::builtin(program.data().len());
+    program_regions.merge(&load_regions(regions)?);
+
+    let disassembly = Disassembly::new(&program, PROGRAM_START_ADDR)
+        .with_symbols(&symbols)
+        .with_regions(program_regions);
+    let disassembly = match annotate {
+        true => disassembly.with_annotations(),
+        false => disassembly,
+    };
+
+    print!("{}", disassembly);
+
+    Ok(())
+}
+
+fn bench(rom: String, instructions: u64, mode: Option<Mode>, jit: bool) -> anyhow::Result<()> {
+    let program = Program::from_file(rom).context(Failure::RomLoad)?;
+    let mode = mode.unwrap_or_default();
 
+    let bench = Bench::run(&program, &mode, instructions)?;
+    #[cfg(feature = "jit")]
+    let bench = if jit {
+        bench.with_jit(&program, &mode)?
+    } else {
+        bench
+    };
+    #[cfg(not(feature = "jit"))]
+    if jit {
+        anyhow::bail!("--jit needs chipate built with the jit feature");
+    }
+    print!("{}", bench);
+
+    Ok(())
+}
+
+// without an output the trace goes to stdout while the logs stay on stderr
+fn trace(
+    rom: String,
+    frames: u64,
+    mode: Option<Mode>,
+    instructions_per_second: Option<u16>,
+    timer_hz: u16,
+    seed: u64,
+    output: Option<String>,
+) -> anyhow::Result<()> {
+    let program = Program::from_file(rom).context(Failure::RomLoad)?;
+
+    // the speed and quirks default to the ones of the detected profile as they do when running
+    let profile = Profile::detect(&program)
+        .map(|detection| detection.profile)
+        .unwrap_or_default();
+    let config = TraceConfig {
+        mode: mode.unwrap_or_else(|| profile.mode()),
+        instructions_per_sec: instructions_per_second
+            .unwrap_or_else(|| profile.instructions_per_sec()),
+        timer_hz,
+        seed,
+        frames,
+    };
+
+    let instructions = match output {
+        Some(path) => {
+            let file = File::create(&path).context(format!("create {}", path))?;
+            trace::run(&program, &config, &mut BufWriter::new(file))?
+        }
+        None => trace::run(&program, &config, &mut std::io::stdout().lock())?,
+    };
+
+    tracing::info!(
+        "traced {} instructions over {} frames",
+        instructions,
+        config.frames
+    );
+
+    Ok(())
+}
+
+// crashing programs are saved as roms named after their run so they can be run again
+fn fuzz(seeds: Vec<String>, config: &FuzzConfig, crashes: String) -> anyhow::Result<()> {
+    let seeds = seeds
+        .into_iter()
+        .map(Program::from_file)
+        .collect::<chipate::Result<Vec<Program>>>()
+        .context(Failure::RomLoad)?;
+
+    tracing::info!(
+        "fuzzing {} programs with seed {}, pass --seed {} to repeat",
+        config.runs,
+        config.seed,
+        config.seed
+    );
+
+    let report = fuzz::run(config, &seeds);
+
+    println!(
+        "{} runs, {} instructions, {} faulted, {} crashed",
+        report.runs,
+        report.instructions,
+        report.faults,
+        report.crashes.len()
+    );
+
+    if report.crashes.is_empty() {
+        return Ok(());
+    }
+
+    std::fs::create_dir_all(&crashes).context(format!("create directory {}", crashes))?;
+    for crash in &report.crashes {
+        let path = PathBuf::from(&crashes).join(format!("crash-{}-{}.ch8", config.seed, crash.run));
+        std::fs::write(&path, &crash.program)
+            .context(format!("write {}", path.to_string_lossy()))?;
+
+        println!(
+            "run {} in {:?} mode panicked: {}, saved to {}",
+            crash.run,
+            crash.mode,
+            crash.message,
+            path.to_string_lossy()
+        );
+    }
+
+    Err(
+        anyhow::anyhow!("{} programs crashed the interpreter", report.crashes.len())
+            .context(Failure::TestSuite),
+    )
+}
+
+fn trace_diff(a: String, b: String, context: usize) -> anyhow::Result<()> {
+    let read = |path: &str| std::fs::read_to_string(path).context(format!("read {}", path));
+
+    match trace::diff(&read(&a)?, &read(&b)?, context)? {
+        Some(report) => {
+            print!("{}", report);
+            return Err(anyhow::anyhow!("traces differ").context(Failure::TestSuite));
+        }
+        None => println!("traces are identical"),
+    }
+
+    Ok(())
+}
+
+fn data_dir(rom: String) -> anyhow::Result<()> {
+    let program = Program::from_file(rom).context(Failure::RomLoad)?;
+
+    println!("{}", RomData::locate(&program)?.dir().to_string_lossy());
+
+    Ok(())
+}
+
+// how the generated roms of the test subcommands are shown
+#[derive(Clone, Copy, Debug)]
+struct TestStyle {
+    palette: Palette,
+    scale: u32,
+    pixel_pattern: bool,
+    pixel_aspect: PixelAspect,
+    filter: Filter,
+    resolution: Resolution,
+    visual_bell: Option<VisualBell>,
+}
+
+fn test_emu(
+    program: Program,
+    style: TestStyle,
+    instructions_per_sec: u16,
+    exit_after_time: Option<Duration>,
+) -> Emu {
     let config = Config {
-        mode: args.mode.unwrap_or_default(),
-        instructions_per_sec: args.instructions_per_second,
+        mode: Mode::default(),
+        quirks: Vec::new(),
+        instructions_per_sec,
+        timer_hz: 60,
         font: Font::default(),
+        symbols: SymbolTable::default(),
+        cheats: Vec::new(),
+        freezes: Vec::new(),
+        debugger: DebuggerConfig::default(),
+        debug_window: false,
+        seed: None,
+        compare: None,
+        headless: false,
+        autosave: None,
+        state_slot: 1,
+        frame_hashes: None,
+        export: None,
+        export_format: ExportFormat::default(),
+        export_svg: None,
+        coverage: None,
+        coverage_format: CoverageFormat::default(),
+        record_inputs: None,
+        exit_after_frames: None,
+        exit_after_time,
+        strict: true,
+        visual_bell: style.visual_bell,
+        audio: AudioBackend::default(),
+        audio_buffer: None,
+        rumble: None,
+        palette: style.palette,
+        pixel_pattern: style.pixel_pattern,
+        pixel_aspect: style.pixel_aspect,
+        filter: style.filter,
+        pixel_grid: PixelGrid::Off,
+        bezel: None,
+        scale: style.scale,
+        resolution: Some(style.resolution),
+        rewind: None,
+        renderer: Renderer::default(),
+        vsync: false,
+        frontend: Frontend::default(),
+        terminal_graphics: TerminalGraphics::default(),
+        terminal_mirror: false,
+        min_key_hold: None,
+        hotkeys: Hotkeys::default(),
+        config_file: None,
+        profile_name: None,
+    };
+
+    let mut emu = Emu::new(config);
+    emu.load_program(program);
+
+    emu
+}
+
+fn sound_test(
+    visual_bell: Option<VisualBell>,
+    exit_after_time: Option<Duration>,
+) -> anyhow::Result<()> {
+    let style = TestStyle {
+        palette: Palette::default(),
+        scale: 10,
+        pixel_pattern: false,
+        pixel_aspect: PixelAspect::Square,
+        filter: Filter::Nearest,
+        resolution: Resolution::Standard,
+        visual_bell,
+    };
+
+    let mut emu = test_emu(
+        test_pattern::sound(),
+        style,
+        Profile::default().instructions_per_sec(),
+        exit_after_time,
+    );
+
+    // logging every transition with the time since the last one shows whether the sound timer
+    // runs at the rate the rom expects
+    let mut bell = Bell;
+    let mut last = Instant::now();
+    emu.set_audio_sink(move |playing| {
+        let now = Instant::now();
+        tracing::info!(
+            "sound {} after {}ms",
+            if playing { "started" } else { "stopped" },
+            (now - last).as_millis()
+        );
+        last = now;
+
+        if playing {
+            bell.sound_started();
+        }
+    });
+
+    Ok(emu.run()?)
+}
+
+// relative paths that do not exist from the working directory are looked up in the rom directory
+fn resolve_rom(rom: String, rom_dir: &Option<String>) -> String {
+    match rom_dir {
+        Some(dir) if Path::new(&rom).is_relative() && !Path::new(&rom).exists() => {
+            Path::new(dir).join(rom).to_string_lossy().into_owned()
+        }
+        _ => rom,
+    }
+}
+
+// a config file given explicitly has to exist, the one in the config directory is optional
+fn config_path(path: Option<String>) -> Option<PathBuf> {
+    match path {
+        Some(path) => Some(PathBuf::from(path)),
+        None => Settings::default_path().filter(|path| path.exists()),
+    }
+}
+
+// writes the template with the defaults to the default config path unless given another one
+fn config_init(path: Option<String>, force: bool) -> anyhow::Result<()> {
+    let path = match path {
+        Some(path) => PathBuf::from(path),
+        None => Settings::default_path().context("no config directory on this platform")?,
+    };
+
+    if path.exists() && !force {
+        anyhow::bail!(
+            "{} already exists, pass --force to overwrite it",
+            path.to_string_lossy()
+        );
+    }
+
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir)
+            .context(format!("create directory {}", dir.to_string_lossy()))?;
+    }
+
+    let defaults = Options {
+        palette: Some(Palette::default()),
+        pixel_pattern: Some(false),
+        pixel_aspect: Some(PixelAspect::default()),
+        filter: Some(Filter::default()),
+        scale: Some(DEFAULT_SCALE),
+        ..Options::default()
+    };
+    std::fs::write(&path, settings::template(&defaults, &Hotkeys::default()))
+        .context(format!("write {}", path.to_string_lossy()))?;
+
+    println!("wrote {}", path.to_string_lossy());
+
+    Ok(())
+}
+
+// options offer the values their value name lists, e.g. classic|schip, leaving out placeholders
+// like W:H, and --profile-name the profiles in the config file when the completions are generated
+fn completions(shell: Shell, config_file: Option<PathBuf>) -> anyhow::Result<()> {
+    let profiles: Vec<String> = load_settings(config_file.as_ref())?
+        .profiles
+        .into_keys()
+        .collect();
+
+    let mut command = with_possible_values(Args::command());
+    if !profiles.is_empty() {
+        command = command.mut_arg("profile_name", |arg| {
+            arg.value_parser(PossibleValuesParser::new(profiles))
+        });
+    }
+
+    clap_complete::generate(shell, &mut command, "chipate", &mut std::io::stdout());
+
+    Ok(())
+}
+
+fn with_possible_values(mut command: clap::Command) -> clap::Command {
+    let listed: Vec<(String, Vec<String>)> = command
+        .get_arguments()
+        .filter(|arg| arg.get_possible_values().is_empty())
+        .filter_map(|arg| match arg.get_value_names() {
+            Some([name]) if name.contains('|') => Some((
+                arg.get_id().to_string(),
+                name.split('|')
+                    .filter(|value| !value.chars().any(|c| c.is_ascii_uppercase()))
+                    .map(String::from)
+                    .collect(),
+            )),
+            _ => None,
+        })
+        .collect();
+
+    for (id, values) in listed {
+        command = command.mut_arg(id, |arg| {
+            arg.value_parser(PossibleValuesParser::new(values))
+        });
+    }
+
+    let subcommands: Vec<String> = command
+        .get_subcommands()
+        .map(|subcommand| subcommand.get_name().to_string())
+        .collect();
+    for name in subcommands {
+        command = command.mut_subcommand(name, with_possible_values);
+    }
+
+    command
+}
+
+fn load_settings(path: Option<&PathBuf>) -> anyhow::Result<Settings> {
+    match path {
+        Some(path) => Settings::load(path).context("load config file"),
+        None => Ok(Settings::default()),
+    }
+}
+
+// a path that cannot be resolved is kept as given so loading it reports why
+fn canonical_path(path: String) -> String {
+    std::fs::canonicalize(&path)
+        .map(|canonical| canonical.to_string_lossy().into_owned())
+        .unwrap_or(path)
+}
+
+// files a session names may have been moved or deleted since, they are left out of the session
+fn existing_file(path: String) -> Option<String> {
+    if Path::new(&path).exists() {
+        Some(path)
+    } else {
+        tracing::warn!("debugger session file {} no longer exists", path);
+        None
+    }
+}
+
+fn load_symbols(path: Option<String>) -> anyhow::Result<SymbolTable> {
+    match path {
+        Some(path) => SymbolTable::from_file(path).context("load symbols"),
+        None => Ok(SymbolTable::default()),
+    }
+}
+
+fn load_regions(path: Option<String>) -> anyhow::Result<RegionMap> {
+    match path {
+        Some(path) => RegionMap::from_file(path).context("load regions"),
+        None => Ok(RegionMap::default()),
+    }
+}
+
+fn run(args: Args) -> anyhow::Result<()> {
+    if let Some(path) = args.export_state_json {
+        println!("{}", chipate::export_state_json(path)?);
+        return Ok(());
+    }
+
+    // written beside the json so it can be passed to --autosave
+    if let Some(path) = args.import_state_json {
+        let state = Path::new(&path).with_extension("c8st");
+        chipate::import_state_json(&path, &state)?;
+        println!("wrote {}", state.to_string_lossy());
+        return Ok(());
+    }
+
+    let break_on_draw =
+        if args.break_on_draw || args.break_on_draw_rect.is_some() || args.break_on_collision {
+            Some(DrawBreakpoint {
+                rect: args.break_on_draw_rect,
+                collision_only: args.break_on_collision,
+            })
+        } else {
+            None
+        };
+
+    let source = match &args.command {
+        Some(Command::Dev { source }) => Some(source.clone()),
+        _ => None,
+    };
+    if source.is_some() && (args.rom.is_some() || args.playlist.is_some()) {
+        anyhow::bail!(
+            "dev runs the program assembled from the source, it takes no rom or playlist"
+        );
+    }
+
+    // a rom directory means the user knows what they want to run so only a first run without
+    // either gets the demo
+    let playlist = args
+        .playlist
+        .map(|path| Playlist::load(path, args.kiosk_seconds))
+        .transpose()
+        .context(Failure::RomLoad)?;
+
+    let is_demo = args.rom.is_none() && playlist.is_none() && source.is_none();
+    let program = match (args.rom, playlist.as_ref(), source.as_ref()) {
+        (_, _, Some(source)) => octo::assemble_file(source).context(Failure::RomLoad)?,
+        (Some(rom), _, _) => {
+            let program =
+                Program::from_file(resolve_rom(rom, &args.rom_dir)).context(Failure::RomLoad)?;
+            match args.patch {
+                Some(patch) => program.patch(patch).context(Failure::RomLoad)?,
+                None => program,
+            }
+        }
+        (None, Some(playlist), _) => playlist.entry(0).program.clone(),
+        (None, None, None) if args.rom_dir.is_none() => {
+            tracing::info!("no rom given, showing the demo, run a rom with --rom PATH");
+            demo::program()
+        }
+        (None, None, None) => anyhow::bail!("missing rom, pass one with --rom PATH"),
+    };
+
+    if args.list_states {
+        let data = RomData::locate(&program)?;
+        print!("{}", chipate::list_states(data.dir())?);
+        return Ok(());
+    }
+
+    // anything set explicitly takes precedence over what the profile expands to
+    let profile = match args.profile {
+        Some(profile) => profile,
+        // the roms of a playlist are run in the mode their entry names
+        None if args.no_detect || playlist.is_some() => Profile::default(),
+        None => match Profile::detect(&program) {
+            Some(detection) => {
+                tracing::info!(
+                    "detected {:?} profile, {}",
+                    detection.profile,
+                    detection.reason
+                );
+                detection.profile
+            }
+            None => Profile::default(),
+        },
+    };
+
+    let config_file = config_path(args.config);
+    let mut settings = load_settings(config_file.as_ref())?;
+    if let Some(name) = args.profile_name.as_deref() {
+        settings = settings
+            .select_profile(name)
+            .context("select profile from the config file")?;
+    }
+
+    let mut mode = args
+        .mode
+        .or(settings.options.mode.clone())
+        .unwrap_or_else(|| profile.mode());
+    let mut instructions_per_sec = args
+        .instructions_per_second
+        .or(settings.options.speed)
+        .unwrap_or_else(|| {
+            if is_demo {
+                demo::INSTRUCTIONS_PER_SEC
+            } else {
+                profile.instructions_per_sec()
+            }
+        });
+    let mut timer_hz = args.timer_hz;
+    let mut seed = args.seed;
+    let mut resolution = args.resolution.or_else(|| profile.resolution());
+
+    // a viewport only fits the bezel it was given with
+    let bezel = match args.bezel {
+        Some(path) => Some((path, args.bezel_viewport)),
+        None => settings
+            .options
+            .bezel
+            .clone()
+            .map(|path| (path, settings.options.bezel_viewport)),
+    };
+    let bezel = match bezel {
+        Some((path, viewport)) => Some(Bezel::open(&path, viewport).context("load bezel")?),
+        None => None,
+    };
+
+    // the host decides the settings that affect execution and the client adopts them
+    // peers only agree on the mode so quirks changed from it would make them diverge
+    if (args.host.is_some() || args.connect.is_some()) && !settings.options.quirks.is_empty() {
+        anyhow::bail!("quirks from the config file can not be used with netplay");
+    }
+    if (args.host.is_some() || args.connect.is_some()) && !settings.freezes.is_empty() {
+        anyhow::bail!("freezes from the config file can not be used with netplay");
+    }
+    if (args.host.is_some() || args.connect.is_some()) && !args.cheats.is_empty() {
+        anyhow::bail!("--cheat can not be used with netplay");
+    }
+    if (args.host.is_some() || args.connect.is_some()) && !args.freezes.is_empty() {
+        anyhow::bail!("--freeze can not be used with netplay");
+    }
+
+    let netplay = match (args.host, args.connect) {
+        (Some(addr), _) => {
+            let session_seed = seed.unwrap_or_else(rand::random);
+            seed = Some(session_seed);
+
+            let session = Session::new(
+                session_seed,
+                mode.clone(),
+                instructions_per_sec,
+                timer_hz,
+                resolution.unwrap_or_else(|| Resolution::detect(&program)),
+                &program,
+            );
+            Some(Netplay::host(addr, &session).context("host netplay session")?)
+        }
+        (None, Some(addr)) => {
+            let (netplay, session) =
+                Netplay::connect(addr, &program).context("join netplay session")?;
+
+            mode = session.mode;
+            instructions_per_sec = session.instructions_per_sec;
+            timer_hz = session.timer_hz;
+            resolution = Some(session.resolution);
+            seed = Some(session.seed);
+
+            Some(netplay)
+        }
+        (None, None) => None,
+    };
+
+    // without a path the autosave goes in the data directory of the rom
+    let autosave = match args.autosave {
+        Some(Some(path)) => Some(path),
+        Some(None) => {
+            let data = RomData::locate(&program)?;
+            data.create()?;
+            Some(data.autosave_path().to_string_lossy().into_owned())
+        }
+        None => None,
     };
 
-    let program = Program::from_file(args.rom).context("load rom")?;
+    // a freeze given on the command line replaces the one from the config file for the same target
+    let mut freezes = settings.freezes;
+    for freeze in args.freezes {
+        freezes.retain(|f| f.target != freeze.target);
+        freezes.push(freeze);
+    }
+
+    // debugging a rom starts with the breakpoints and files its last session ended with, what is
+    // given on the command line is added to them
+    let mut breakpoints = args.breakpoints;
+    let mut opcode_breakpoints = args.opcode_breakpoints;
+    // stored canonical so a session restores them from any working directory
+    let mut symbols_file = args.symbols.map(canonical_path);
+    let mut regions_file = args.regions.map(canonical_path);
+    let debugging = args.debug
+        || break_on_draw.is_some()
+        || !breakpoints.is_empty()
+        || !opcode_breakpoints.is_empty();
+    let debug_session = if debugging && !args.no_debug_session {
+        let data = RomData::locate(&program)?;
+        data.create()?;
+        let path = data.debugger_session_path();
+
+        if path.exists() {
+            let session = DebugSession::load(&path).context("load debugger session")?;
+            symbols_file = symbols_file.or(session.symbols.and_then(existing_file));
+            regions_file = regions_file.or(session.regions.and_then(existing_file));
+            breakpoints.extend(session.breakpoints);
+            for pattern in session.opcode_breakpoints {
+                if !opcode_breakpoints.contains(&pattern) {
+                    opcode_breakpoints.push(pattern);
+                }
+            }
+            tracing::info!("restored debugger session from {}", path.to_string_lossy());
+        }
+
+        Some(path.to_string_lossy().into_owned())
+    } else {
+        None
+    };
+
+    let config = Config {
+        mode,
+        quirks: settings.options.quirks,
+        instructions_per_sec,
+        timer_hz,
+        font: Font::default(),
+        symbols: load_symbols(symbols_file.clone())?,
+        cheats: args.cheats,
+        freezes,
+        seed,
+        compare: args.compare,
+        headless: args.headless,
+        autosave,
+        state_slot: args.state_slot,
+        frame_hashes: args.frame_hashes,
+        export: args.export,
+        export_format: args.export_format,
+        export_svg: args.export_svg,
+        coverage: args.coverage,
+        coverage_format: args.coverage_format,
+        record_inputs: args.record_inputs,
+        exit_after_frames: args.exit_after_frames,
+        exit_after_time: args.exit_after_seconds,
+        strict: args.strict,
+        visual_bell: args.visual_bell,
+        audio: args.audio.or(settings.options.audio).unwrap_or_default(),
+        audio_buffer: args.audio_buffer.or(settings.options.audio_buffer),
+        rumble: args.rumble.or(settings.options.rumble),
+        palette: args
+            .palette
+            .or(settings.options.palette)
+            .unwrap_or_default(),
+        pixel_pattern: args.pixel_pattern || settings.options.pixel_pattern.unwrap_or_default(),
+        pixel_aspect: args
+            .pixel_aspect
+            .or(settings.options.pixel_aspect)
+            .unwrap_or_default(),
+        filter: args.filter.or(settings.options.filter).unwrap_or_default(),
+        pixel_grid: args.pixel_grid,
+        bezel,
+        scale: args
+            .scale
+            .or(settings.options.scale)
+            .unwrap_or(DEFAULT_SCALE),
+        resolution,
+        rewind: args.rewind,
+        renderer: args.renderer,
+        vsync: args.vsync,
+        frontend: args.frontend.unwrap_or_default(),
+        terminal_graphics: args.terminal_graphics,
+        terminal_mirror: args.terminal_mirror,
+        min_key_hold: args.min_key_hold.or(settings.options.min_key_hold),
+        hotkeys: settings.hotkeys,
+        config_file,
+        profile_name: args.profile_name,
+        debugger: DebuggerConfig {
+            break_on_draw,
+            breakpoints,
+            opcode_breakpoints,
+            regions: load_regions(regions_file.clone())?,
+            break_at_start: args.debug,
+            session: debug_session,
+            symbols_file,
+            regions_file,
+        },
+        debug_window: args.debug_window,
+    };
 
     let mut emu = Emu::new(config);
     emu.load_program(program);
-    emu.run()
+    if let Some(playlist) = playlist {
+        emu.set_playlist(playlist);
+    }
+    if args.mute {
+        emu.set_audio_sink(|_| {});
+    }
+    if let Some(netplay) = netplay {
+        emu.set_netplay(netplay);
+    }
+
+    if let Some(addr) = args.websocket {
+        emu.set_websocket(DisplayServer::bind(addr).context("start websocket server")?);
+    }
+
+    #[cfg(feature = "prometheus")]
+    if let Some(addr) = args.metrics {
+        metrics_exporter_prometheus::PrometheusBuilder::new()
+            .with_http_listener(addr)
+            .install()
+            .context("start prometheus exporter")?;
+
+        tracing::info!("serving prometheus metrics on {}", addr);
+    }
+
+    #[cfg(feature = "http-api")]
+    if let Some(addr) = args.http {
+        chipate::http::serve(addr, emu.handle()).context("start http server")?;
+    }
+
+    #[cfg(feature = "discord")]
+    if let Some(client_id) = args.discord {
+        let presence =
+            chipate::discord::Presence::start(&client_id).context("start discord rich presence")?;
+        emu.set_presence(presence);
+    }
+
+    // installed before sdl starts so sdl leaves the signals alone, a second signal exits right
+    // away for when the loop is blocked on the debugger prompt or a netplay peer
+    let handle = emu.handle();
+    ctrlc::set_handler(move || {
+        if handle.is_stopping() {
+            std::process::exit(130);
+        }
+
+        tracing::info!("shutting down");
+        handle.stop();
+    })
+    .context("install signal handler")?;
+
+    #[cfg(unix)]
+    emu.dump_state_on_sigusr1()?;
+
+    if let Some(source) = source {
+        let handle = emu.handle();
+        std::thread::spawn(move || watch_source(&source, &handle));
+    }
+
+    Ok(emu.run()?)
+}
+
+// reassembles the source every time it is saved and loads the program in place of the running
+// one, a source that does not assemble leaves the last program running
+fn watch_source(path: &str, handle: &EmuHandle) {
+    let modified = || std::fs::metadata(path).and_then(|m| m.modified()).ok();
+
+    let mut last = modified();
+    while !handle.is_stopping() {
+        std::thread::sleep(SOURCE_POLL_INTERVAL);
+
+        let current = modified();
+        if current == last {
+            continue;
+        }
+        last = current;
+
+        match octo::assemble_file(path).and_then(|program| handle.load_rom(program)) {
+            Ok(()) => tracing::info!("reloaded {}", path),
+            Err(e) => tracing::error!("{} not reloaded: {:#}", path, e),
+        }
+    }
+}
+
+fn parse_seconds(value: &str) -> Result<Duration, String> {
+    let seconds = value.parse::<f64>().map_err(|e| e.to_string())?;
+
+    Duration::try_from_secs_f64(seconds).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chipate::core::cpu::Fault;
+
+    #[test]
+    fn faults_exit_with_their_own_codes() {
+        let wrapped = |fault: Fault| {
+            let error = EmuError::Context {
+                context: String::from("run"),
+                error: Box::new(EmuError::from(fault)),
+            };
+            anyhow::Error::from(error).context("emulate")
+        };
+
+        let unknown = Fault::UnknownOpCode {
+            address: 0x200,
+            op_code: 0xFFFF,
+        };
+        assert_eq!(exit_code(&wrapped(unknown)), 3);
+        assert_eq!(
+            exit_code(&wrapped(Fault::StackOverflow { address: 0x200 })),
+            4
+        );
+        assert_eq!(
+            exit_code(&wrapped(Fault::StackUnderflow { address: 0x200 })),
+            4
+        );
+        assert_eq!(
+            exit_code(&anyhow::Error::from(EmuError::IllegalOpcode {
+                address: 0x200,
+                op_code: 0xFFFF
+            })),
+            3
+        );
+        assert_eq!(
+            exit_code(&anyhow::anyhow!("traces differ").context(Failure::TestSuite)),
+            5
+        );
+        assert_eq!(
+            exit_code(&anyhow::anyhow!("missing").context(Failure::RomLoad)),
+            2
+        );
+        assert_eq!(exit_code(&anyhow::anyhow!("anything else")), 1);
+    }
 }
@@ -0,0 +1,272 @@
+use crate::{
+    core::{cpu::Mode, memory::checksum, Program},
+    error::{Context, EmuError, Result},
+    Key, Resolution,
+};
+
+use std::{
+    io::{Read, Write},
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+};
+
+const MAGIC: [u8; 4] = *b"C8NP";
+
+const VERSION: u8 = 4;
+
+const SESSION_LEN: usize = 23;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Role {
+    Host,
+    Client,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KeyEvent {
+    pub key: u8,
+    pub pressed: bool,
+}
+
+// settings the host dictates so both machines execute identically
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Session {
+    pub seed: u64,
+    pub mode: Mode,
+    pub instructions_per_sec: u16,
+    pub timer_hz: u16,
+    pub resolution: Resolution,
+    pub rom_checksum: u32,
+}
+
+impl Session {
+    pub fn new(
+        seed: u64,
+        mode: Mode,
+        instructions_per_sec: u16,
+        timer_hz: u16,
+        resolution: Resolution,
+        program: &Program,
+    ) -> Self {
+        Self {
+            seed,
+            mode,
+            instructions_per_sec,
+            timer_hz,
+            resolution,
+            rom_checksum: checksum(program.data()),
+        }
+    }
+    fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(SESSION_LEN);
+        bytes.extend_from_slice(&MAGIC);
+        bytes.push(VERSION);
+        bytes.extend_from_slice(&self.seed.to_be_bytes());
+        bytes.push(match self.mode {
+            Mode::Classic => 0,
+            Mode::Modern => 1,
+            Mode::Chip48 => 2,
+            Mode::Schip => 3,
+            Mode::XoChip => 4,
+        });
+        bytes.extend_from_slice(&self.instructions_per_sec.to_be_bytes());
+        bytes.extend_from_slice(&self.timer_hz.to_be_bytes());
+        bytes.push(match self.resolution {
+            Resolution::Standard => 0,
+            Resolution::Tall => 1,
+            Resolution::Hires => 2,
+        });
+        bytes.extend_from_slice(&self.rom_checksum.to_be_bytes());
+        bytes
+    }
+    fn decode(bytes: &[u8; SESSION_LEN]) -> Result<Self> {
+        if bytes[0..4] != MAGIC {
+            return Err(EmuError::Network(String::from(
+                "peer is not a chipate netplay host",
+            )));
+        }
+
+        if bytes[4] != VERSION {
+            return Err(EmuError::Network(format!(
+                "unsupported netplay version {}",
+                bytes[4]
+            )));
+        }
+
+        let mode = match bytes[13] {
+            0 => Mode::Classic,
+            1 => Mode::Modern,
+            2 => Mode::Chip48,
+            3 => Mode::Schip,
+            4 => Mode::XoChip,
+            value => {
+                return Err(EmuError::Network(format!(
+                    "unsupported netplay mode {}",
+                    value
+                )))
+            }
+        };
+
+        let resolution = match bytes[18] {
+            0 => Resolution::Standard,
+            1 => Resolution::Tall,
+            2 => Resolution::Hires,
+            value => {
+                return Err(EmuError::Network(format!(
+                    "unsupported netplay resolution {}",
+                    value
+                )))
+            }
+        };
+
+        // both pace the emulation by dividing with them
+        let instructions_per_sec = u16::from_be_bytes(bytes[14..16].try_into()?);
+        if instructions_per_sec == 0 {
+            return Err(EmuError::Network(String::from(
+                "unsupported netplay speed of 0 instructions per second",
+            )));
+        }
+
+        let timer_hz = u16::from_be_bytes(bytes[16..18].try_into()?);
+        if timer_hz == 0 {
+            return Err(EmuError::Network(String::from(
+                "unsupported netplay timer frequency of 0 hz",
+            )));
+        }
+
+        Ok(Self {
+            seed: u64::from_be_bytes(bytes[5..13].try_into()?),
+            mode,
+            instructions_per_sec,
+            timer_hz,
+            resolution,
+            rom_checksum: u32::from_be_bytes(bytes[19..23].try_into()?),
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct Netplay {
+    role: Role,
+    stream: TcpStream,
+    pending: Vec<KeyEvent>,
+}
+
+impl Netplay {
+    pub fn host(addr: impl ToSocketAddrs, session: &Session) -> Result<Self> {
+        let listener = TcpListener::bind(addr).context("bind netplay listener")?;
+        tracing::info!(
+            "waiting for netplay client on {}",
+            listener.local_addr().context("netplay listener address")?
+        );
+
+        let (mut stream, peer) = listener.accept().context("accept netplay client")?;
+        stream.set_nodelay(true)?;
+
+        stream.write_all(&session.encode())?;
+
+        let mut ack = [0_u8; 1];
+        stream.read_exact(&mut ack).context("read netplay ack")?;
+        if ack[0] != 1 {
+            return Err(EmuError::Network(format!(
+                "netplay client {} rejected the session",
+                peer
+            )));
+        }
+
+        tracing::info!("netplay client connected from {}", peer);
+
+        Ok(Self {
+            role: Role::Host,
+            stream,
+            pending: Vec::new(),
+        })
+    }
+    // the client adopts the session of the host, which is rejected if the roms do not match
+    pub fn connect(addr: impl ToSocketAddrs, program: &Program) -> Result<(Self, Session)> {
+        let mut stream = TcpStream::connect(addr).context("connect to netplay host")?;
+        stream.set_nodelay(true)?;
+
+        let mut bytes = [0_u8; SESSION_LEN];
+        stream
+            .read_exact(&mut bytes)
+            .context("read netplay session")?;
+
+        let session = Session::decode(&bytes)?;
+
+        if session.rom_checksum != checksum(program.data()) {
+            stream.write_all(&[0])?;
+            return Err(EmuError::Network(String::from(
+                "netplay host is running a different rom",
+            )));
+        }
+
+        stream.write_all(&[1])?;
+
+        tracing::info!("connected to netplay host {}", stream.peer_addr()?);
+
+        Ok((
+            Self {
+                role: Role::Client,
+                stream,
+                pending: Vec::new(),
+            },
+            session,
+        ))
+    }
+    pub fn queue(&mut self, key: Key, pressed: bool) {
+        self.pending.push(KeyEvent {
+            key: usize::from(key) as u8,
+            pressed,
+        });
+    }
+    // sends the local events for the frame and waits for the events of the peer, both sides
+    // return the host events followed by the client events so key state stays identical
+    pub fn exchange(&mut self, frame: u64) -> Result<Vec<KeyEvent>> {
+        // anything past what fits in a single frame message is sent with the next frame
+        let count = usize::min(self.pending.len(), u8::MAX as usize);
+        let local: Vec<KeyEvent> = self.pending.drain(..count).collect();
+
+        let mut bytes = Vec::with_capacity(9 + local.len() * 2);
+        bytes.extend_from_slice(&frame.to_be_bytes());
+        bytes.push(local.len() as u8);
+        for event in &local {
+            bytes.push(event.key);
+            bytes.push(event.pressed as u8);
+        }
+
+        self.stream
+            .write_all(&bytes)
+            .context("send netplay frame")?;
+
+        let mut header = [0_u8; 9];
+        self.stream
+            .read_exact(&mut header)
+            .context("read netplay frame")?;
+
+        let peer_frame = u64::from_be_bytes(header[0..8].try_into()?);
+        if peer_frame != frame {
+            return Err(EmuError::Network(format!(
+                "netplay desync, local frame {} but peer sent frame {}",
+                frame, peer_frame
+            )));
+        }
+
+        let mut events = vec![0_u8; header[8] as usize * 2];
+        self.stream
+            .read_exact(&mut events)
+            .context("read netplay events")?;
+
+        let remote: Vec<KeyEvent> = events
+            .chunks(2)
+            .map(|e| KeyEvent {
+                key: e[0] & 0xF,
+                pressed: e[1] != 0,
+            })
+            .collect();
+
+        Ok(match self.role {
+            Role::Host => local.into_iter().chain(remote).collect(),
+            Role::Client => remote.into_iter().chain(local).collect(),
+        })
+    }
+}
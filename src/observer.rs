@@ -0,0 +1,38 @@
+use crate::DisplayState;
+
+// notified at every frame boundary, the emulated vblank, once the timers have ticked and with the
+// display as the frame left it. closures taking the frame number and the display can be used as
+// an observer as well
+pub trait FrameObserver: Send {
+    fn vblank(&mut self, frame: u64, display: &DisplayState);
+}
+
+impl<F: FnMut(u64, &DisplayState) + Send> FrameObserver for F {
+    fn vblank(&mut self, frame: u64, display: &DisplayState) {
+        self(frame, display)
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct Observers {
+    observers: Vec<Box<dyn FrameObserver>>,
+}
+
+impl Observers {
+    pub(crate) fn add(&mut self, observer: Box<dyn FrameObserver>) {
+        self.observers.push(observer);
+    }
+    pub(crate) fn vblank(&mut self, frame: u64, display: &DisplayState) {
+        for observer in self.observers.iter_mut() {
+            observer.vblank(frame, display);
+        }
+    }
+}
+
+impl std::fmt::Debug for Observers {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Observers")
+            .field("observers", &self.observers.len())
+            .finish()
+    }
+}
@@ -0,0 +1,101 @@
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Color {
+    pub const BLACK: Color = Color::rgb(0, 0, 0);
+    pub const WHITE: Color = Color::rgb(255, 255, 255);
+    pub const RED: Color = Color::rgb(255, 0, 0);
+    pub const GRAY: Color = Color::rgb(128, 128, 128);
+
+    pub const fn rgb(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b }
+    }
+}
+
+// contrast ratios are the wcag 2 ratios of the foreground and the accent against the background,
+// the colorblind preset uses the okabe-ito orange and sky blue which stay apart for every type of
+// color vision deficiency
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Palette {
+    // 21:1, accent 5.25:1
+    #[default]
+    Classic,
+    // 19.56:1, accent 16.75:1
+    HighContrast,
+    // 9.32:1, accent 9.1:1
+    Colorblind,
+}
+
+impl Palette {
+    pub const ALL: [Palette; 3] = [Palette::Classic, Palette::HighContrast, Palette::Colorblind];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Palette::Classic => "classic",
+            Palette::HighContrast => "high-contrast",
+            Palette::Colorblind => "colorblind",
+        }
+    }
+    pub fn background(&self) -> Color {
+        Color::BLACK
+    }
+    pub fn foreground(&self) -> Color {
+        match self {
+            Palette::Classic => Color::WHITE,
+            Palette::HighContrast => Color::rgb(255, 255, 0),
+            Palette::Colorblind => Color::rgb(230, 159, 0),
+        }
+    }
+    // used for anything drawn on top of the display such as the visual bell border
+    pub fn accent(&self) -> Color {
+        match self {
+            Palette::Classic => Color::RED,
+            Palette::HighContrast => Color::rgb(0, 255, 255),
+            Palette::Colorblind => Color::rgb(86, 180, 233),
+        }
+    }
+    pub fn contrast_ratio(&self) -> f64 {
+        contrast_ratio(self.foreground(), self.background())
+    }
+}
+
+impl FromStr for Palette {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Palette::ALL
+            .into_iter()
+            .find(|palette| palette.name() == s)
+            .ok_or_else(|| {
+                format!(
+                    "invalid palette '{}': expected classic, high-contrast or colorblind",
+                    s
+                )
+            })
+    }
+}
+
+pub fn contrast_ratio(a: Color, b: Color) -> f64 {
+    let (a, b) = (luminance(a), luminance(b));
+    (a.max(b) + 0.05) / (a.min(b) + 0.05)
+}
+
+fn luminance(color: Color) -> f64 {
+    let channel = |value: u8| {
+        let value = value as f64 / 255.0;
+        if value <= 0.03928 {
+            value / 12.92
+        } else {
+            ((value + 0.055) / 1.055).powf(2.4)
+        }
+    };
+
+    0.2126 * channel(color.r) + 0.7152 * channel(color.g) + 0.0722 * channel(color.b)
+}
@@ -0,0 +1,199 @@
+// Rewind history is kept as encoded save states. Most frames only touch a handful of bytes so
+// every frame is stored as the xor of its state with the frame before it, run length encoded, and
+// only every KEYFRAME_INTERVAL frames, or more often for a short history, a full state is kept.
+//
+// A delta is a list of runs, each a big endian u16 count of unchanged bytes followed by a byte
+// count and that many xored bytes.
+
+use crate::state::Snapshot;
+
+use std::collections::VecDeque;
+
+const KEYFRAME_INTERVAL: usize = 60;
+
+#[derive(Clone, Debug)]
+enum Frame {
+    Key(Vec<u8>),
+    Delta(Vec<u8>),
+}
+
+#[derive(Debug)]
+pub(crate) struct Rewind {
+    frames: VecDeque<Frame>,
+    capacity: usize,
+    // the newest state in full, deltas are applied to it when stepping back
+    latest: Vec<u8>,
+    since_key: usize,
+    keyframe_interval: usize,
+}
+
+impl Rewind {
+    pub(crate) fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+
+        // dropping the oldest keyframe drops the deltas after it as well, a short history keeps
+        // keyframes often enough that at least half of it is left
+        Self {
+            frames: VecDeque::new(),
+            capacity,
+            latest: Vec::new(),
+            since_key: 0,
+            keyframe_interval: KEYFRAME_INTERVAL.min(capacity / 2).max(1),
+        }
+    }
+    pub(crate) fn push(&mut self, snapshot: &Snapshot) {
+        let state = snapshot.encode();
+
+        // the stack depth and display height change the length so those states are kept whole
+        let frame = if self.frames.is_empty()
+            || self.since_key >= self.keyframe_interval
+            || state.len() != self.latest.len()
+        {
+            self.since_key = 0;
+            Frame::Key(state.clone())
+        } else {
+            Frame::Delta(encode_delta(&self.latest, &state))
+        };
+
+        self.since_key += 1;
+        self.frames.push_back(frame);
+        self.latest = state;
+
+        // history is dropped a keyframe at a time so the oldest frame can always be rebuilt
+        while self.frames.len() > self.capacity {
+            self.frames.pop_front();
+            while matches!(self.frames.front(), Some(Frame::Delta(_))) {
+                self.frames.pop_front();
+            }
+        }
+    }
+    // removes the newest frame and returns its state, the frame before it becomes the newest
+    pub(crate) fn pop(&mut self) -> Option<Vec<u8>> {
+        let frame = self.frames.pop_back()?;
+        let state = std::mem::take(&mut self.latest);
+
+        self.latest = match frame {
+            Frame::Delta(delta) => apply_delta(&state, &delta),
+            Frame::Key(_) => self.rebuild_latest(),
+        };
+
+        self.since_key = self
+            .frames
+            .iter()
+            .rev()
+            .position(|frame| matches!(frame, Frame::Key(_)))
+            .map_or(0, |idx| idx + 1);
+
+        Some(state)
+    }
+    pub(crate) fn len(&self) -> usize {
+        self.frames.len()
+    }
+    pub(crate) fn size_in_bytes(&self) -> usize {
+        self.frames
+            .iter()
+            .map(|frame| match frame {
+                Frame::Key(bytes) | Frame::Delta(bytes) => bytes.len(),
+            })
+            .sum()
+    }
+    // replays the deltas after the last keyframe once the newer keyframe has been removed
+    fn rebuild_latest(&self) -> Vec<u8> {
+        let Some(start) = self
+            .frames
+            .iter()
+            .rposition(|frame| matches!(frame, Frame::Key(_)))
+        else {
+            return Vec::new();
+        };
+
+        let mut state = Vec::new();
+        for frame in self.frames.range(start..) {
+            state = match frame {
+                Frame::Key(bytes) => bytes.clone(),
+                Frame::Delta(delta) => apply_delta(&state, delta),
+            };
+        }
+
+        state
+    }
+}
+
+fn encode_delta(old: &[u8], new: &[u8]) -> Vec<u8> {
+    let mut delta = Vec::new();
+    let mut idx = 0;
+
+    while idx < new.len() {
+        let start = idx;
+        while idx < new.len() && idx - start < u16::MAX as usize && old[idx] == new[idx] {
+            idx += 1;
+        }
+
+        if idx == new.len() {
+            break;
+        }
+
+        delta.extend_from_slice(&((idx - start) as u16).to_be_bytes());
+
+        let len_at = delta.len();
+        delta.push(0);
+
+        while idx < new.len() && delta[len_at] < u8::MAX && old[idx] != new[idx] {
+            delta.push(old[idx] ^ new[idx]);
+            delta[len_at] += 1;
+            idx += 1;
+        }
+    }
+
+    delta
+}
+
+// xor is its own inverse so the same delta steps forwards or backwards between two states
+fn apply_delta(state: &[u8], delta: &[u8]) -> Vec<u8> {
+    let mut state = state.to_vec();
+    let mut idx = 0;
+    let mut at = 0;
+
+    while at + 3 <= delta.len() {
+        idx += u16::from_be_bytes([delta[at], delta[at + 1]]) as usize;
+        let len = delta[at + 2] as usize;
+        at += 3;
+
+        for byte in &delta[at..at + len] {
+            state[idx] ^= byte;
+            idx += 1;
+        }
+
+        at += len;
+    }
+
+    state
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        core::{cpu::CPU, memory::RAM},
+        DisplayState,
+    };
+
+    #[test]
+    fn short_history_keeps_at_least_half_its_frames() {
+        let mut rewind = Rewind::new(30);
+        let mut snapshot = Snapshot {
+            cpu: CPU::default(),
+            memory: RAM::default(),
+            display: DisplayState::default(),
+        };
+
+        for frame in 0..200u16 {
+            snapshot.memory.write(0x300, frame as u8);
+            rewind.push(&snapshot);
+            if frame >= 30 {
+                assert!(rewind.len() >= 15, "{} frames at {}", rewind.len(), frame);
+            }
+            assert!(rewind.len() <= 30);
+        }
+    }
+}
@@ -0,0 +1,1061 @@
+// The sdl window, drawn with the sdl renderer. sdl has to stay on the main thread so the emulation
+// runs on its own thread instead, that way a stalled window only delays what is shown and never
+// how fast the cpu runs. An application with its own sdl window can embed the emulation instead,
+// it passes its events on and draws the display into a texture of its own.
+
+use crate::{
+    audio::AudioSink,
+    bezel::Bezel,
+    core::gfx::{Font, GLYPH_WIDTH},
+    debug_view::DebugView,
+    error::{bail, EmuError, Result},
+    image::Image,
+    keymap::{self, Hotkeys},
+    palette::{Color, Palette},
+    Config, DisplayState, Filter, Input, Output, PixelGrid, Renderer, Style, VisualBell,
+    DISPLAY_PIXELS_HEIGHT, DISPLAY_PIXELS_WIDTH,
+};
+
+use sdl2::{
+    audio::{AudioCallback, AudioDevice, AudioSpecDesired},
+    controller::GameController,
+    event::{Event, WindowEvent},
+    keyboard::Keycode,
+    pixels::{self, PixelFormatEnum},
+    rect::Rect,
+    render::{BlendMode, Canvas, RenderTarget, Texture},
+    video, EventPump,
+};
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{Receiver, RecvTimeoutError, Sender},
+        Arc,
+    },
+    thread::ScopedJoinHandle,
+    time::{Duration, Instant},
+};
+
+impl From<Color> for pixels::Color {
+    fn from(color: Color) -> Self {
+        pixels::Color::RGB(color.r, color.g, color.b)
+    }
+}
+
+// the debug window is drawn small and scaled up by this much
+const DEBUG_WINDOW_SCALE: u32 = 3;
+
+const SAMPLE_RATE: i32 = 44_100;
+
+const TONE_HZ: f32 = 440.0;
+
+const TONE_VOLUME: f32 = 0.2;
+
+// the motors stop on their own after this long unless told to run again, so a controller left
+// rumbling by a stalled window soon goes quiet
+const RUMBLE_MS: u32 = 1000;
+
+const RUMBLE_REFRESH: Duration = Duration::from_millis(500);
+
+pub(crate) struct Window {
+    canvas: Canvas<video::Window>,
+    event_pump: EventPump,
+    // closing it leaves the emulation running in the main window
+    debug: Option<DebugWindow>,
+    rumble: Option<Rumble>,
+    bezel: Option<Bezel>,
+    // false when vsync was asked for but the display cannot pace frames with it
+    pub(crate) vsync: bool,
+}
+
+impl Window {
+    // the size is in display pixels, the window is scaled up from it
+    pub(crate) fn open(config: &Config, width: u32, height: u32) -> Result<Self> {
+        let sdl_context = match sdl2::init() {
+            Err(msg) => return Err(EmuError::Sdl(msg)),
+            Ok(ctx) => ctx,
+        };
+
+        let video_subsystem = match sdl_context.video() {
+            Err(msg) => return Err(EmuError::Sdl(msg)),
+            Ok(video_subsystem) => video_subsystem,
+        };
+
+        let (pixel_width, pixel_height) =
+            config
+                .pixel_aspect
+                .pixel_size(config.scale, DISPLAY_PIXELS_WIDTH as u32, height);
+        let vsync = config.vsync;
+
+        // zero when the driver does not know, e.g. without a real display
+        let refresh_rate = match video_subsystem.current_display_mode(0) {
+            Ok(mode) => mode.refresh_rate,
+            Err(e) => {
+                tracing::debug!("could not read the display refresh rate: {}", e);
+                0
+            }
+        };
+
+        let size = match config.bezel.as_ref() {
+            Some(bezel) => (bezel.width, bezel.height),
+            None => (width * pixel_width, height * pixel_height),
+        };
+
+        // building a canvas consumes the window so a failed attempt needs a new one
+        let build_canvas = |software: bool| -> Result<Canvas<video::Window>> {
+            let window = match video_subsystem
+                .window("chipate", size.0, size.1)
+                .position_centered()
+                .build()
+            {
+                Err(msg) => return Err(EmuError::Sdl(msg.to_string())),
+                Ok(window) => window,
+            };
+
+            let mut builder = window.into_canvas();
+            builder = if software {
+                builder.software()
+            } else {
+                builder.accelerated()
+            };
+            if vsync {
+                builder = builder.present_vsync();
+            }
+
+            match builder.build() {
+                Err(msg) => Err(EmuError::Sdl(msg.to_string())),
+                Ok(canvas) => Ok(canvas),
+            }
+        };
+
+        let canvas = match config.renderer {
+            Renderer::Accelerated => build_canvas(false)?,
+            Renderer::Software => build_canvas(true)?,
+            Renderer::Auto => build_canvas(false).or_else(|e| {
+                tracing::warn!("accelerated renderer unavailable, using software: {}", e);
+                build_canvas(true)
+            })?,
+        };
+
+        tracing::debug!("using {} renderer", canvas.info().name);
+
+        // pacing off a renderer that does not wait for the refresh would run frames as fast as
+        // they can be presented
+        let presents_vsync = canvas.info().flags
+            & sdl2::sys::SDL_RendererFlags::SDL_RENDERER_PRESENTVSYNC as u32
+            != 0;
+        if vsync && (!presents_vsync || refresh_rate <= 0) {
+            tracing::warn!("display does not support vsync, pacing frames with the timer clock");
+        } else if vsync && refresh_rate != config.timer_hz as i32 {
+            tracing::warn!(
+                "display refreshes at {}hz, timers will run at that rate instead of {}hz",
+                refresh_rate,
+                config.timer_hz
+            );
+        }
+
+        let event_pump = match sdl_context.event_pump() {
+            Err(msg) => return Err(EmuError::Sdl(msg)),
+            Ok(event_pump) => event_pump,
+        };
+
+        let debug = match config.debug_window {
+            true => Some(DebugWindow::open(&video_subsystem, &canvas)?),
+            false => None,
+        };
+
+        let rumble = config
+            .rumble
+            .and_then(|intensity| Rumble::open(&sdl_context, intensity));
+
+        Ok(Self {
+            vsync: vsync && presents_vsync && refresh_rate > 0,
+            canvas,
+            event_pump,
+            debug,
+            rumble,
+            bezel: config.bezel.clone(),
+        })
+    }
+    // tells the window when the sound plays so it can rumble the game controller along with it
+    pub(crate) fn rumble_sink(&self) -> Option<impl AudioSink> {
+        let playing = Arc::clone(&self.rumble.as_ref()?.playing);
+        Some(move |on: bool| playing.store(on, Ordering::Relaxed))
+    }
+    // forwards input to the emulation thread and draws the frames it sends back until the
+    // emulation thread has finished
+    pub(crate) fn present<T>(
+        &mut self,
+        style: Style,
+        hotkeys: &Hotkeys,
+        outputs: &Receiver<Output>,
+        inputs: &Sender<Input>,
+        emulation: &ScopedJoinHandle<T>,
+    ) {
+        let vsync = self.vsync;
+        let Self {
+            canvas,
+            event_pump,
+            debug,
+            rumble,
+            bezel,
+            ..
+        } = self;
+
+        let mut style = style;
+        let mut hotkeys = hotkeys.clone();
+        let mut frame = None;
+
+        loop {
+            if let Some(rumble) = rumble.as_mut() {
+                rumble.update();
+            }
+
+            for event in event_pump.poll_iter() {
+                let input = match event {
+                    Event::Quit { .. } => Some(Input::Quit),
+                    // with two windows open sdl only sends quit once both are closed
+                    Event::Window {
+                        window_id,
+                        win_event: WindowEvent::Close,
+                        ..
+                    } => match debug.as_ref() {
+                        Some(window) if window.id() == window_id => {
+                            *debug = None;
+                            None
+                        }
+                        _ => Some(Input::Quit),
+                    },
+                    Event::KeyDown {
+                        keycode: Some(keycode),
+                        repeat: false,
+                        ..
+                    } => key_name(keycode)
+                        .and_then(|name| Input::from_host_key(&hotkeys, &name, true)),
+                    Event::KeyUp {
+                        keycode: Some(keycode),
+                        ..
+                    } => key_name(keycode)
+                        .and_then(|name| Input::from_host_key(&hotkeys, &name, false)),
+                    _ => None,
+                };
+
+                let Some(input) = input else {
+                    continue;
+                };
+
+                if inputs.send(input).is_err() {
+                    return;
+                }
+            }
+
+            // presenting blocks until the display refreshes so the window is redrawn every refresh,
+            // with the last frame when the emulation has not sent a new one
+            if vsync {
+                if emulation.is_finished() {
+                    return;
+                }
+
+                let palette = style.palette;
+                let latest = latest_frame(&mut style, &mut hotkeys, outputs.try_iter(), |output| {
+                    show(canvas, debug, palette, output)
+                });
+                if let Some(latest) = latest {
+                    frame = Some(latest);
+                }
+
+                match frame.as_ref() {
+                    Some((displays, bell)) => {
+                        render(canvas, style, displays, *bell, bezel.as_ref())
+                    }
+                    None => canvas.present(),
+                }
+
+                if inputs.send(Input::Refresh).is_err() {
+                    return;
+                }
+
+                continue;
+            }
+
+            let output = match outputs.recv_timeout(Duration::from_millis(5)) {
+                Ok(output) => output,
+                // the channels live in the emu so a panic on the emulation thread does not close them
+                Err(RecvTimeoutError::Timeout) if !emulation.is_finished() => continue,
+                Err(_) => return,
+            };
+
+            let outputs = std::iter::once(output).chain(outputs.try_iter());
+            let palette = style.palette;
+            let latest = latest_frame(&mut style, &mut hotkeys, outputs, |output| {
+                show(canvas, debug, palette, output)
+            });
+            if let Some((displays, bell)) = latest {
+                render(canvas, style, &displays, bell, bezel.as_ref());
+            }
+        }
+    }
+}
+
+fn set_title(canvas: &mut Canvas<video::Window>, title: String) {
+    if let Err(e) = canvas.window_mut().set_title(&title) {
+        tracing::error!("set window title error: {}", e);
+    }
+}
+
+// the outputs other than frames and reloads
+fn show(
+    canvas: &mut Canvas<video::Window>,
+    debug: &mut Option<DebugWindow>,
+    palette: Palette,
+    output: Output,
+) {
+    match output {
+        Output::Title(title) => set_title(canvas, title),
+        Output::Debug(view) => {
+            if let Some(debug) = debug.as_mut() {
+                debug.render(&view, palette);
+            }
+        }
+        _ => {}
+    }
+}
+
+// the buzzer as a square wave, the device plays silence between sounds rather than pausing so a
+// sound starts as soon as the buffer being played runs out
+pub(crate) struct Tone {
+    _device: AudioDevice<SquareWave>,
+}
+
+impl Tone {
+    // a smaller buffer starts and stops the sound sooner but underruns on a busy system
+    pub(crate) fn open(buffer: Option<u16>) -> Result<(Self, impl AudioSink)> {
+        let sdl_context = match sdl2::init() {
+            Err(msg) => return Err(EmuError::Sdl(msg)),
+            Ok(ctx) => ctx,
+        };
+
+        let audio_subsystem = match sdl_context.audio() {
+            Err(msg) => return Err(EmuError::Sdl(msg)),
+            Ok(audio_subsystem) => audio_subsystem,
+        };
+
+        let driver = audio_subsystem.current_audio_driver();
+        let spec = AudioSpecDesired {
+            freq: Some(SAMPLE_RATE),
+            channels: Some(1),
+            samples: Some(buffer.unwrap_or_else(|| default_buffer(driver))),
+        };
+
+        let playing = Arc::new(AtomicBool::new(false));
+        let wave_playing = Arc::clone(&playing);
+        let device = match audio_subsystem.open_playback(None, &spec, |obtained| {
+            tracing::debug!(
+                "playing audio with {} at {}hz in buffers of {} samples",
+                driver,
+                obtained.freq,
+                obtained.samples
+            );
+            SquareWave {
+                playing: wave_playing,
+                phase: 0.0,
+                step: TONE_HZ / obtained.freq as f32,
+            }
+        }) {
+            Err(msg) => return Err(EmuError::Sdl(msg)),
+            Ok(device) => device,
+        };
+        device.resume();
+
+        let sink = move |on: bool| playing.store(on, Ordering::Relaxed);
+        Ok((Self { _device: device }, sink))
+    }
+}
+
+// samples per buffer when none is configured, 512 is under a frame at 44.1khz
+fn default_buffer(driver: &str) -> u16 {
+    match driver {
+        // sound servers mix in a buffer of their own and crackle when fed smaller ones
+        "pulseaudio" | "pipewire" | "directsound" => 1024,
+        _ => 512,
+    }
+}
+
+struct SquareWave {
+    playing: Arc<AtomicBool>,
+    phase: f32,
+    step: f32,
+}
+
+impl AudioCallback for SquareWave {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        let playing = self.playing.load(Ordering::Relaxed);
+        for sample in out.iter_mut() {
+            *sample = match playing {
+                false => 0.0,
+                true if self.phase < 0.5 => TONE_VOLUME,
+                true => -TONE_VOLUME,
+            };
+            self.phase = (self.phase + self.step) % 1.0;
+        }
+    }
+}
+
+// the motors of the first game controller that has them, run while the sound plays
+struct Rumble {
+    controller: GameController,
+    strength: u16,
+    playing: Arc<AtomicBool>,
+    // when the motors were last told to run
+    started: Option<Instant>,
+}
+
+impl Rumble {
+    // the intensity is a percentage, no controller only means no rumble
+    fn open(sdl_context: &sdl2::Sdl, intensity: u8) -> Option<Self> {
+        let subsystem = match sdl_context.game_controller() {
+            Err(msg) => {
+                tracing::warn!("could not open game controllers, not rumbling: {}", msg);
+                return None;
+            }
+            Ok(subsystem) => subsystem,
+        };
+
+        let count = match subsystem.num_joysticks() {
+            Err(msg) => {
+                tracing::warn!("could not list game controllers, not rumbling: {}", msg);
+                return None;
+            }
+            Ok(count) => count,
+        };
+
+        for index in (0..count).filter(|&index| subsystem.is_game_controller(index)) {
+            let controller = match subsystem.open(index) {
+                Err(e) => {
+                    tracing::debug!("could not open game controller {}: {}", index, e);
+                    continue;
+                }
+                Ok(controller) => controller,
+            };
+
+            if !controller.has_rumble() {
+                tracing::debug!("game controller {} cannot rumble", controller.name());
+                continue;
+            }
+
+            tracing::debug!("rumbling {} while the sound plays", controller.name());
+
+            return Some(Self {
+                controller,
+                strength: (u16::MAX as u32 * intensity as u32 / 100) as u16,
+                playing: Arc::new(AtomicBool::new(false)),
+                started: None,
+            });
+        }
+
+        tracing::warn!("no game controller that can rumble is connected");
+        None
+    }
+    fn update(&mut self) {
+        let playing = self.playing.load(Ordering::Relaxed);
+
+        let result = match (playing, self.started) {
+            (true, Some(started)) if started.elapsed() < RUMBLE_REFRESH => return,
+            (true, _) => {
+                self.started = Some(Instant::now());
+                self.controller
+                    .set_rumble(self.strength, self.strength, RUMBLE_MS)
+            }
+            (false, Some(_)) => {
+                self.started = None;
+                self.controller.set_rumble(0, 0, 0)
+            }
+            (false, None) => return,
+        };
+
+        // an unplugged controller keeps failing until the window closes
+        if let Err(e) = result {
+            tracing::debug!("rumble error: {}", e);
+        }
+    }
+}
+
+// a second window beside the main one showing what the cpu is doing
+struct DebugWindow {
+    canvas: Canvas<video::Window>,
+}
+
+impl DebugWindow {
+    fn open(video_subsystem: &sdl2::VideoSubsystem, main: &Canvas<video::Window>) -> Result<Self> {
+        // sized to the panel once the first one is drawn
+        let (x, y) = main.window().position();
+        let (width, height) = main.window().size();
+        let window = match video_subsystem
+            .window("chipate debug", width, height)
+            .position(x + width as i32, y)
+            .build()
+        {
+            Err(msg) => return Err(EmuError::Sdl(msg.to_string())),
+            Ok(window) => window,
+        };
+
+        match window.into_canvas().build() {
+            Err(msg) => Err(EmuError::Sdl(msg.to_string())),
+            Ok(canvas) => Ok(Self { canvas }),
+        }
+    }
+    fn id(&self) -> u32 {
+        self.canvas.window().id()
+    }
+    fn render(&mut self, view: &DebugView, palette: Palette) {
+        let panel = view.render(palette);
+        let (width, height) = (panel.width as u32, panel.height as u32);
+
+        let size = (width * DEBUG_WINDOW_SCALE, height * DEBUG_WINDOW_SCALE);
+        if self.canvas.window().size() != size {
+            if let Err(e) = self.canvas.window_mut().set_size(size.0, size.1) {
+                tracing::error!("resize debug window error: {}", e);
+            }
+        }
+
+        // the text is too small to read smoothed
+        sdl2::hint::set("SDL_RENDER_SCALE_QUALITY", "nearest");
+
+        let texture_creator = self.canvas.texture_creator();
+        let mut texture =
+            match texture_creator.create_texture_static(PixelFormatEnum::RGB24, width, height) {
+                Ok(texture) => texture,
+                Err(e) => {
+                    tracing::error!("create texture error: {}", e);
+                    return;
+                }
+            };
+
+        let rgb: Vec<u8> = panel
+            .pixels
+            .iter()
+            .flat_map(|color| [color.r, color.g, color.b])
+            .collect();
+        if let Err(e) = texture.update(None, &rgb, panel.width * 3) {
+            tracing::error!("update texture error: {}", e);
+            return;
+        }
+
+        if let Err(msg) = self.canvas.copy(&texture, None, None) {
+            tracing::error!("copy texture error: {}", msg);
+        }
+        self.canvas.present();
+    }
+}
+
+// the emulation as a part of an application that owns the window, the event loop and the canvas
+pub struct Embedded<'a, 'scope> {
+    style: Style,
+    hotkeys: Hotkeys,
+    outputs: &'a Receiver<Output>,
+    inputs: &'a Sender<Input>,
+    emulation: &'a ScopedJoinHandle<'scope, Result<()>>,
+    frame: Option<(Vec<DisplayState>, Option<VisualBell>)>,
+    title: Option<String>,
+}
+
+impl<'a, 'scope> Embedded<'a, 'scope> {
+    pub(crate) fn new(
+        style: Style,
+        hotkeys: Hotkeys,
+        outputs: &'a Receiver<Output>,
+        inputs: &'a Sender<Input>,
+        emulation: &'a ScopedJoinHandle<'scope, Result<()>>,
+    ) -> Self {
+        Self {
+            style,
+            hotkeys,
+            outputs,
+            inputs,
+            emulation,
+            frame: None,
+            title: None,
+        }
+    }
+    // takes what the emulation has sent since the last poll, true when there is a new frame
+    pub fn poll(&mut self) -> bool {
+        let Self {
+            style,
+            hotkeys,
+            outputs,
+            title,
+            ..
+        } = self;
+        // there is no debug window to show a debug view in
+        let latest = latest_frame(style, hotkeys, outputs.try_iter(), |output| {
+            if let Output::Title(latest) = output {
+                *title = Some(latest)
+            }
+        });
+
+        match latest {
+            Some(frame) => {
+                self.frame = Some(frame);
+                true
+            }
+            None => false,
+        }
+    }
+    // key events are passed on as keypad keys or hotkeys, true when the event was one of them
+    pub fn handle_event(&self, event: &Event) -> bool {
+        let input = match event {
+            Event::KeyDown {
+                keycode: Some(keycode),
+                repeat: false,
+                ..
+            } => {
+                key_name(*keycode).and_then(|name| Input::from_host_key(&self.hotkeys, &name, true))
+            }
+            Event::KeyUp {
+                keycode: Some(keycode),
+                ..
+            } => key_name(*keycode)
+                .and_then(|name| Input::from_host_key(&self.hotkeys, &name, false)),
+            _ => None,
+        };
+
+        match input {
+            Some(input) => self.inputs.send(input).is_ok(),
+            None => false,
+        }
+    }
+    // the latest title the emulation asked for, e.g. the rom a playlist moved on to
+    pub fn title(&self) -> Option<&str> {
+        self.title.as_deref()
+    }
+    // the size in texels a texture has to be at least to hold the display, it changes when a rom
+    // for another resolution is loaded
+    pub fn texture_size(&self) -> Option<(u32, u32)> {
+        self.frame.as_ref().map(|(displays, _)| {
+            let image = Image::new(self.style.palette, displays, None);
+            (image.width as u32, image.height as u32)
+        })
+    }
+    // fills the top left of the texture with the latest frame, the rest is left as it was
+    pub fn update_texture(&self, texture: &mut Texture) -> Result<()> {
+        let Some((displays, bell)) = self.frame.as_ref() else {
+            return Ok(());
+        };
+        let image = Image::new(self.style.palette, displays, *bell);
+
+        let query = texture.query();
+        if query.width < image.width as u32 || query.height < image.height as u32 {
+            bail!(
+                "texture is {}x{}, the display needs {}x{}",
+                query.width,
+                query.height,
+                image.width,
+                image.height
+            );
+        }
+
+        let texel = |color: &Color| -> Result<Vec<u8>> {
+            let (r, g, b) = (color.r as u32, color.g as u32, color.b as u32);
+            let packed = match query.format {
+                PixelFormatEnum::RGB24 => return Ok(vec![color.r, color.g, color.b]),
+                PixelFormatEnum::RGB888 | PixelFormatEnum::ARGB8888 => {
+                    0xFF << 24 | r << 16 | g << 8 | b
+                }
+                PixelFormatEnum::RGBA8888 => r << 24 | g << 16 | b << 8 | 0xFF,
+                PixelFormatEnum::ABGR8888 => 0xFF << 24 | b << 16 | g << 8 | r,
+                format => bail!(
+                    "texture format {:?} is not supported: expected RGB24, RGB888, ARGB8888, \
+                     RGBA8888 or ABGR8888",
+                    format
+                ),
+            };
+            Ok(packed.to_ne_bytes().to_vec())
+        };
+        let colors = image.colors.iter().map(texel).collect::<Result<Vec<_>>>()?;
+
+        let bytes: Vec<u8> = image
+            .pixels
+            .iter()
+            .flat_map(|pixel| colors[*pixel as usize].iter().copied())
+            .collect();
+        texture
+            .update(
+                Rect::new(0, 0, image.width as u32, image.height as u32),
+                &bytes,
+                image.width * colors[0].len(),
+            )
+            .map_err(|e| EmuError::Sdl(format!("update texture: {}", e)))?;
+
+        Ok(())
+    }
+    // the texture is scaled to the destination with the filter sdl created it with
+    pub fn draw<T: RenderTarget>(
+        &self,
+        canvas: &mut Canvas<T>,
+        texture: &mut Texture,
+        dest: Rect,
+    ) -> Result<()> {
+        let Some((width, height)) = self.texture_size() else {
+            return Ok(());
+        };
+
+        self.update_texture(texture)?;
+        if let Err(msg) = canvas.copy(texture, Rect::new(0, 0, width, height), dest) {
+            return Err(EmuError::Sdl(msg));
+        }
+
+        Ok(())
+    }
+    // asks the emulation to stop, the host keeps polling until it has finished
+    pub fn quit(&self) {
+        let _ = self.inputs.send(Input::Quit);
+    }
+    pub fn is_finished(&self) -> bool {
+        self.emulation.is_finished()
+    }
+}
+
+// sdl names keys like A, 1, F5 and Return
+fn key_name(keycode: Keycode) -> Option<String> {
+    let name = match keycode {
+        Keycode::Return => String::from("enter"),
+        keycode => keycode.name().to_lowercase(),
+    };
+
+    keymap::is_host_key(&name).then_some(name)
+}
+
+// only the latest frame is worth drawing when the window has fallen behind
+fn latest_frame(
+    style: &mut Style,
+    hotkeys: &mut Hotkeys,
+    outputs: impl Iterator<Item = Output>,
+    mut show: impl FnMut(Output),
+) -> Option<(Vec<DisplayState>, Option<VisualBell>)> {
+    let mut frame = None;
+    for output in outputs {
+        match output {
+            Output::Frame(displays, bell) => frame = Some((displays, bell)),
+            Output::Reload(reloaded_style, reloaded_hotkeys) => {
+                *style = reloaded_style;
+                *hotkeys = reloaded_hotkeys;
+            }
+            output => show(output),
+        }
+    }
+
+    frame
+}
+
+// displays are laid out left to right when more than one is rendered, scaled into the viewport
+// of the bezel when there is one
+fn render(
+    canvas: &mut Canvas<video::Window>,
+    style: Style,
+    displays: &[DisplayState],
+    bell: Option<VisualBell>,
+    bezel: Option<&Bezel>,
+) {
+    let (background, foreground) = match bell {
+        Some(VisualBell::Invert) => (style.palette.foreground(), style.palette.background()),
+        _ => (style.palette.background(), style.palette.foreground()),
+    };
+
+    let scale = style.scale;
+
+    // loading a rom for another resolution changes the height of the display
+    let display_height = displays
+        .first()
+        .map_or(DISPLAY_PIXELS_HEIGHT, |d| d.height()) as u32;
+    let (pixel_width, pixel_height) =
+        style
+            .pixel_aspect
+            .pixel_size(scale, DISPLAY_PIXELS_WIDTH as u32, display_height);
+
+    let width = DISPLAY_PIXELS_WIDTH as u32 * pixel_width;
+    let height = display_height * pixel_height;
+    let size = (width * displays.len() as u32, height);
+    let window_size = bezel.map_or(size, |bezel| (bezel.width, bezel.height));
+    if canvas.window().size() != window_size {
+        if let Err(e) = canvas.window_mut().set_size(window_size.0, window_size.1) {
+            tracing::error!("resize window error: {}", e);
+        }
+    }
+
+    match bezel {
+        Some(bezel) => draw_bezel(canvas, bezel, background, size),
+        None => {
+            canvas.set_draw_color(background);
+            canvas.clear();
+        }
+    }
+
+    for (i, display) in displays.iter().enumerate() {
+        let offset = i as i32 * width as i32;
+
+        // a smoothed display is drawn as a texture the size of the display that sdl scales up,
+        // rects are still drawn when it cannot be
+        let smoothed = style.filter == Filter::Linear
+            && draw_smoothed(
+                canvas,
+                display,
+                (background, foreground),
+                Rect::new(offset, 0, width, height),
+            );
+
+        canvas.set_draw_color(foreground);
+
+        for c in 0..display.width() {
+            for r in 0..display.height() {
+                let idx = (r as i32 * display.width() as i32) + c as i32;
+
+                if display.read_pixel(idx as u16) {
+                    // window is a factor of the pixel size larger than display state grid
+                    let x = c as i32 * pixel_width as i32 + offset;
+                    let y = r as i32 * pixel_height as i32;
+
+                    let rect = Rect::new(x, y, pixel_width, pixel_height);
+                    if !smoothed {
+                        if let Err(msg) = canvas.fill_rect(rect) {
+                            tracing::error!("fill rect error: {}", msg);
+                        }
+                    }
+
+                    // a diagonal notch keeps lit pixels apart from unlit ones without relying on
+                    // color alone
+                    if style.pixel_pattern {
+                        canvas.set_draw_color(background);
+                        let (right, bottom) = (pixel_width as i32 - 1, pixel_height as i32 - 1);
+                        if let Err(msg) = canvas.draw_line((x, y + bottom), (x + right, y)) {
+                            tracing::error!("draw line error: {}", msg);
+                        }
+                        canvas.set_draw_color(foreground);
+                    }
+                }
+            }
+        }
+
+        if style.pixel_grid != PixelGrid::Off {
+            draw_grid(
+                canvas,
+                style.pixel_grid,
+                display,
+                offset,
+                (pixel_width, pixel_height),
+                style.palette.accent(),
+            );
+        }
+
+        if i > 0 {
+            canvas.set_draw_color(Color::GRAY);
+            if let Err(msg) = canvas.draw_line((offset, 0), (offset, height as i32)) {
+                tracing::error!("draw line error: {}", msg);
+            }
+        }
+
+        if bell == Some(VisualBell::Border) {
+            canvas.set_draw_color(style.palette.accent());
+            for inset in 0..u32::max(1, scale * 2 / 5) as i32 {
+                let rect = Rect::new(
+                    offset + inset,
+                    inset,
+                    width - 2 * inset as u32,
+                    height - 2 * inset as u32,
+                );
+                if let Err(msg) = canvas.draw_rect(rect) {
+                    tracing::error!("draw rect error: {}", msg);
+                }
+            }
+        }
+    }
+
+    if bezel.is_some() {
+        canvas.set_viewport(None);
+        if let Err(msg) = canvas.set_scale(1.0, 1.0) {
+            tracing::error!("set scale error: {}", msg);
+        }
+    }
+
+    canvas.present();
+}
+
+// faint lines between the pixels with every eighth one stronger, left out when the pixels are too
+// small for lines between them to leave anything of the pixels visible
+fn draw_grid(
+    canvas: &mut Canvas<video::Window>,
+    grid: PixelGrid,
+    display: &DisplayState,
+    offset: i32,
+    (pixel_width, pixel_height): (u32, u32),
+    accent: Color,
+) {
+    if pixel_width < 3 || pixel_height < 3 {
+        return;
+    }
+
+    let (width, height) = (display.width() as i32, display.height() as i32);
+    let (pixel_width, pixel_height) = (pixel_width as i32, pixel_height as i32);
+    let line_color = |line: i32| {
+        let alpha = if line % 8 == 0 { 96 } else { 40 };
+        pixels::Color::RGBA(Color::GRAY.r, Color::GRAY.g, Color::GRAY.b, alpha)
+    };
+
+    canvas.set_blend_mode(BlendMode::Blend);
+
+    for column in 1..width {
+        let x = offset + column * pixel_width;
+        canvas.set_draw_color(line_color(column));
+        if let Err(msg) = canvas.draw_line((x, 0), (x, height * pixel_height)) {
+            tracing::error!("draw line error: {}", msg);
+        }
+    }
+
+    for row in 1..height {
+        let y = row * pixel_height;
+        canvas.set_draw_color(line_color(row));
+        if let Err(msg) = canvas.draw_line((offset, y), (offset + width * pixel_width, y)) {
+            tracing::error!("draw line error: {}", msg);
+        }
+    }
+
+    // the strong lines are numbered in hex inside the first pixels past them, columns along the
+    // top edge and rows down the left
+    if grid == PixelGrid::Rulers {
+        let font = Font::default();
+        let size = i32::max(1, pixel_width.min(pixel_height) / 4);
+
+        let mut rects = Vec::new();
+        for column in (0..width).step_by(8) {
+            let at = (offset + column * pixel_width + size, size);
+            number_rects(&mut rects, &font, column as u8, at, size);
+        }
+        for row in (8..height).step_by(8) {
+            let at = (offset + size, row * pixel_height + size);
+            number_rects(&mut rects, &font, row as u8, at, size);
+        }
+
+        canvas.set_draw_color(pixels::Color::RGBA(accent.r, accent.g, accent.b, 192));
+        if let Err(msg) = canvas.fill_rects(&rects) {
+            tracing::error!("fill rects error: {}", msg);
+        }
+    }
+
+    canvas.set_blend_mode(BlendMode::None);
+}
+
+// the lit pixels of the number written in hex with the glyphs of the font, a pixel apart
+fn number_rects(rects: &mut Vec<Rect>, font: &Font, number: u8, (x, y): (i32, i32), size: i32) {
+    let digits = format!("{:X}", number);
+    let advance = (GLYPH_WIDTH as i32 + 1) * size;
+
+    for (idx, digit) in digits.chars().filter_map(|c| c.to_digit(16)).enumerate() {
+        let left = x + idx as i32 * advance;
+        for (row, bits) in font.glyph(digit as u8).iter().enumerate() {
+            for column in 0..GLYPH_WIDTH as i32 {
+                if bits & (0x80 >> column) != 0 {
+                    let top = y + row as i32 * size;
+                    rects.push(Rect::new(
+                        left + column * size,
+                        top,
+                        size as u32,
+                        size as u32,
+                    ));
+                }
+            }
+        }
+    }
+}
+
+// draws the bezel over the whole window and leaves the canvas drawing the display into the
+// viewport as though the viewport were a window of the given size
+fn draw_bezel(
+    canvas: &mut Canvas<video::Window>,
+    bezel: &Bezel,
+    background: Color,
+    (width, height): (u32, u32),
+) {
+    canvas.set_draw_color(Color::BLACK);
+    canvas.clear();
+
+    let texture_creator = canvas.texture_creator();
+    match texture_creator.create_texture_static(PixelFormatEnum::RGBA32, bezel.width, bezel.height)
+    {
+        Ok(mut texture) => {
+            texture.set_blend_mode(BlendMode::Blend);
+            if let Err(e) = texture.update(None, &bezel.rgba, bezel.width as usize * 4) {
+                tracing::error!("update texture error: {}", e);
+            } else if let Err(msg) = canvas.copy(&texture, None, None) {
+                tracing::error!("copy texture error: {}", msg);
+            }
+        }
+        Err(e) => tracing::error!("create texture error: {}", e),
+    }
+
+    let viewport = bezel.viewport;
+    canvas.set_viewport(Rect::new(
+        viewport.x as i32,
+        viewport.y as i32,
+        viewport.width,
+        viewport.height,
+    ));
+
+    let scale = (
+        viewport.width as f32 / width as f32,
+        viewport.height as f32 / height as f32,
+    );
+    if let Err(msg) = canvas.set_scale(scale.0, scale.1) {
+        tracing::error!("set scale error: {}", msg);
+    }
+
+    canvas.set_draw_color(background);
+    if let Err(msg) = canvas.fill_rect(Rect::new(0, 0, width, height)) {
+        tracing::error!("fill rect error: {}", msg);
+    }
+}
+
+// sdl picks the filter for a texture from the hint when the texture is created
+fn draw_smoothed(
+    canvas: &mut Canvas<video::Window>,
+    display: &DisplayState,
+    (background, foreground): (Color, Color),
+    dest: Rect,
+) -> bool {
+    sdl2::hint::set("SDL_RENDER_SCALE_QUALITY", "linear");
+
+    let (width, height) = (display.width() as u32, display.height() as u32);
+    let texture_creator = canvas.texture_creator();
+    let mut texture = match texture_creator.create_texture_static(
+        pixels::PixelFormatEnum::RGB24,
+        width,
+        height,
+    ) {
+        Ok(texture) => texture,
+        Err(e) => {
+            tracing::error!("create texture error: {}", e);
+            return false;
+        }
+    };
+
+    let rgb: Vec<u8> = (0..width * height)
+        .flat_map(|idx| {
+            let color = match display.read_pixel(idx as u16) {
+                true => foreground,
+                false => background,
+            };
+            [color.r, color.g, color.b]
+        })
+        .collect();
+    if let Err(e) = texture.update(None, &rgb, width as usize * 3) {
+        tracing::error!("update texture error: {}", e);
+        return false;
+    }
+
+    if let Err(msg) = canvas.copy(&texture, None, dest) {
+        tracing::error!("copy texture error: {}", msg);
+        return false;
+    }
+
+    true
+}
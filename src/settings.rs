@@ -0,0 +1,540 @@
+// The config file is a small subset of toml, sections of key = value lines with # starting a
+// comment, strings are quoted while numbers and booleans are not:
+//
+//   [display]
+//   palette = "high-contrast"
+//   pixel-pattern = true
+//   pixel-aspect = "2:1"
+//   scale = 8
+//
+//   [emulation]
+//   mode = "schip"
+//   quirk.clipping = false
+//   speed = 1000
+//   min-key-hold = 3
+//
+//   [audio]
+//   backend = "sdl"
+//   buffer = 256
+//   rumble = 60
+//
+//   [profile.crt]
+//   palette = "classic"
+//   pixel-pattern = true
+//
+//   [hotkeys]
+//   pause = "space"
+//   reset = "none"
+//
+//   [freeze]
+//   0x3a0 = 9
+//   v4 = 0xff
+//
+//   [macros]
+//   m = "5:3, 6"
+//
+// Each key of [macros] is a host key that plays the keypad presses of its value when pressed, the
+// way they are written is explained in keymap.rs.
+//
+// Each key of [freeze] is an address or register the value is written to after every instruction,
+// for keeping lives or time from running out.
+//
+// Profiles bundle any of the display, emulation and audio options under a name and are chosen with
+// --profile-name, what a profile sets replaces the options outside of it. The quirks are those of
+// the mode with each quirk.NAME turning one of shift, memory, clipping, vf_reset, display_wait or
+// vip_routines on or off.
+//
+// It is read from CHIPATE_CONFIG or --config when given, otherwise from config.toml in the
+// platform config directory, e.g. ~/.config/chipate on linux, when that exists. Options given on
+// the command line take precedence over the file. `chipate config init` writes one with every
+// option explained to start from.
+//
+// The file is watched while running and the palette, pixel pattern, pixel aspect, speed, hotkeys
+// and macros are applied as soon as it changes, the scale, mode and quirks only take effect on the
+// next start.
+
+use crate::{
+    audio::AudioBackend,
+    bezel::Viewport,
+    core::{
+        cheat::Cheat,
+        cpu::{Mode, Quirks},
+    },
+    error::{bail, Context, EmuError, Result},
+    keymap::{self, Hotkey, Hotkeys},
+    palette::Palette,
+    Filter, PixelAspect,
+};
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::{Duration, Instant, SystemTime},
+};
+
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Options {
+    pub palette: Option<Palette>,
+    pub pixel_pattern: Option<bool>,
+    pub pixel_aspect: Option<PixelAspect>,
+    pub filter: Option<Filter>,
+    pub bezel: Option<String>,
+    pub bezel_viewport: Option<Viewport>,
+    pub scale: Option<u32>,
+    pub mode: Option<Mode>,
+    pub quirks: Vec<(String, bool)>,
+    pub speed: Option<u16>,
+    pub min_key_hold: Option<u64>,
+    pub audio: Option<AudioBackend>,
+    pub audio_buffer: Option<u16>,
+    pub rumble: Option<u8>,
+}
+
+impl Options {
+    // the section an option belongs in outside of a profile
+    fn section(key: &str) -> Option<&'static str> {
+        match key {
+            "palette" | "pixel-pattern" | "pixel-aspect" | "filter" | "bezel"
+            | "bezel-viewport" | "scale" => Some("display"),
+            "mode" | "speed" | "min-key-hold" => Some("emulation"),
+            "backend" | "buffer" | "rumble" => Some("audio"),
+            key if key.starts_with("quirk.") => Some("emulation"),
+            _ => None,
+        }
+    }
+    fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "palette" => self.palette = Some(value.parse()?),
+            "pixel-pattern" => self.pixel_pattern = Some(parse_bool(key, value)?),
+            "pixel-aspect" => self.pixel_aspect = Some(value.parse()?),
+            "filter" => self.filter = Some(value.parse()?),
+            "bezel" => self.bezel = Some(String::from(value)),
+            "bezel-viewport" => self.bezel_viewport = Some(value.parse()?),
+            "scale" => self.scale = Some(parse_number(key, value, 1..=64)?),
+            "mode" => self.mode = Some(value.parse()?),
+            "speed" => self.speed = Some(parse_number(key, value, 1..=u16::MAX)?),
+            "min-key-hold" => self.min_key_hold = Some(parse_number(key, value, 1..=u64::MAX)?),
+            "backend" => self.audio = Some(value.parse()?),
+            "buffer" => self.audio_buffer = Some(parse_number(key, value, 16..=u16::MAX)?),
+            "rumble" => self.rumble = Some(parse_number(key, value, 1..=100)?),
+            _ => {
+                let name = key.strip_prefix("quirk.").unwrap_or_default();
+                if !Quirks::NAMES.contains(&name) {
+                    return Err(format!(
+                        "invalid quirk '{}': expected {}",
+                        name,
+                        Quirks::NAMES.join(", ")
+                    ));
+                }
+
+                let value = parse_bool(key, value)?;
+                self.quirks.retain(|(quirk, _)| quirk != name);
+                self.quirks.push((String::from(name), value));
+            }
+        }
+
+        Ok(())
+    }
+    fn overlay(&mut self, profile: &Options) {
+        self.palette = profile.palette.or(self.palette);
+        self.pixel_pattern = profile.pixel_pattern.or(self.pixel_pattern);
+        self.pixel_aspect = profile.pixel_aspect.or(self.pixel_aspect);
+        self.filter = profile.filter.or(self.filter);
+        // a profile with its own bezel does not keep the viewport of another
+        if profile.bezel.is_some() {
+            self.bezel = profile.bezel.clone();
+            self.bezel_viewport = profile.bezel_viewport;
+        }
+        self.scale = profile.scale.or(self.scale);
+        self.mode = profile.mode.clone().or(self.mode.take());
+        self.speed = profile.speed.or(self.speed);
+        self.min_key_hold = profile.min_key_hold.or(self.min_key_hold);
+        self.audio = profile.audio.or(self.audio);
+        self.audio_buffer = profile.audio_buffer.or(self.audio_buffer);
+        self.rumble = profile.rumble.or(self.rumble);
+
+        for (name, value) in &profile.quirks {
+            self.quirks.retain(|(quirk, _)| quirk != name);
+            self.quirks.push((name.clone(), *value));
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Settings {
+    pub options: Options,
+    pub hotkeys: Hotkeys,
+    pub profiles: BTreeMap<String, Options>,
+    pub freezes: Vec<Cheat>,
+}
+
+impl Settings {
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("chipate").join("config.toml"))
+    }
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        tracing::debug!("loading settings from path: {:?}", path.as_ref());
+
+        let text = std::fs::read_to_string(path.as_ref())
+            .context(format!("read {}", path.as_ref().to_string_lossy()))?;
+
+        Self::parse(&text).context(format!("parse {}", path.as_ref().to_string_lossy()))
+    }
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut settings = Self::default();
+        let mut section = String::new();
+
+        for (idx, line) in text.lines().enumerate() {
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                section = String::from(name.trim());
+                continue;
+            }
+
+            let result = match line.split_once('=') {
+                Some((key, value)) => unquote(value.trim())
+                    .and_then(|value| settings.set(&section, key.trim(), value)),
+                None => Err(format!("expected key = value but found '{}'", line)),
+            };
+
+            if let Err(e) = result {
+                bail!("line {}: {}", idx + 1, e);
+            }
+        }
+
+        settings.hotkeys.validate().map_err(EmuError::Invalid)?;
+
+        Ok(settings)
+    }
+    // the options of the named profile replace the ones outside of it
+    pub fn select_profile(mut self, name: &str) -> Result<Self> {
+        let Some(profile) = self.profiles.get(name) else {
+            let names: Vec<&str> = self.profiles.keys().map(String::as_str).collect();
+            if names.is_empty() {
+                bail!("no profile named '{}', the config file has none", name);
+            }
+            bail!(
+                "no profile named '{}', expected one of {}",
+                name,
+                names.join(", ")
+            );
+        };
+
+        self.options.overlay(profile);
+
+        Ok(self)
+    }
+    fn set(&mut self, section: &str, key: &str, value: &str) -> Result<(), String> {
+        if let Some(name) = section.strip_prefix("profile.") {
+            if Options::section(key).is_none() {
+                return Err(format!("unknown setting '{}' in [{}]", key, section));
+            }
+            return self
+                .profiles
+                .entry(String::from(name))
+                .or_default()
+                .set(key, value);
+        }
+
+        match section {
+            "display" | "emulation" | "audio" => match Options::section(key) {
+                Some(expected) if expected == section => self.options.set(key, value),
+                _ => Err(format!("unknown setting '{}' in [{}]", key, section)),
+            },
+            "hotkeys" => self.hotkeys.bind(key.parse::<Hotkey>()?, value),
+            "macros" => self.hotkeys.bind_macro(key, value.parse()?),
+            "freeze" => {
+                let freeze = format!("{}={}", key, value).parse::<Cheat>()?;
+                self.freezes.retain(|f| f.target != freeze.target);
+                self.freezes.push(freeze);
+                Ok(())
+            }
+            "" => Err(format!("'{}' is outside of a section", key)),
+            _ => Err(format!("unknown section [{}]", section)),
+        }
+    }
+}
+
+// a config file explaining every option, the display options with a default are written with it
+// and the rest are left commented out as examples since setting them would override detection
+pub fn template(defaults: &Options, hotkeys: &Hotkeys) -> String {
+    let mut text = String::from(
+        "# chipate config file\n\
+         #\n\
+         # Options given on the command line take precedence over the ones here. The palette,\n\
+         # pixel pattern, pixel aspect, speed, hotkeys and macros are applied as soon as this\n\
+         # file is saved while chipate runs, the rest on the next start.\n\
+         \n\
+         [display]\n\
+         # classic, high-contrast or colorblind, each drawn on black:\n",
+    );
+
+    for palette in Palette::ALL {
+        let _ = writeln!(
+            text,
+            "#   {:<14} {} with a {} accent",
+            palette.name(),
+            hex(palette.foreground()),
+            hex(palette.accent())
+        );
+    }
+    write_option(
+        &mut text,
+        "palette",
+        defaults.palette.map(|p| quote(p.name())),
+        "\"classic\"",
+    );
+
+    text.push_str("# a notch in every lit pixel so they stand apart without relying on color\n");
+    write_option(
+        &mut text,
+        "pixel-pattern",
+        defaults.pixel_pattern.map(|p| p.to_string()),
+        "true",
+    );
+
+    text.push_str(
+        "# the shape of a pixel as its width to its height, e.g. \"2:1\", or \"stretch\" to fill a\n\
+         # 4:3 screen like a television\n",
+    );
+    write_option(
+        &mut text,
+        "pixel-aspect",
+        defaults.pixel_aspect.map(|a| quote(&a.to_string())),
+        "\"stretch\"",
+    );
+
+    text.push_str(
+        "# how the display is scaled up to the window, \"nearest\" for sharp pixels or \"linear\"\n\
+         # to smooth them, only the sdl frontend can smooth\n",
+    );
+    write_option(
+        &mut text,
+        "filter",
+        defaults.filter.map(|f| quote(&f.to_string())),
+        "\"linear\"",
+    );
+
+    text.push_str(
+        "# a png drawn behind the display with the sdl frontend, the window takes its size and the\n\
+         # display is scaled into the viewport, x,y,width,height in pixels of the image, or the\n\
+         # whole image without one\n\
+         # bezel = \"vip.png\"\n\
+         # bezel-viewport = \"96,80,640,320\"\n",
+    );
+
+    text.push_str("# how many screen pixels wide a display pixel is, from 1 to 64\n");
+    write_option(
+        &mut text,
+        "scale",
+        defaults.scale.map(|s| s.to_string()),
+        "8",
+    );
+
+    text.push_str(
+        "\n[emulation]\n\
+         # classic, chip48, schip, xochip or modern, detected from the rom when not set\n",
+    );
+    text.push_str(
+        "# mode = \"schip\"\n\
+         # instructions per second from 1 to 65535, detected from the rom when not set\n\
+         # speed = 700\n\
+         # frames a tapped key is held down for at least, for roms that miss quick taps\n\
+         # min-key-hold = 3\n",
+    );
+
+    let _ = writeln!(
+        text,
+        "# quirks turn single behaviors of the mode on or off: {}",
+        Quirks::NAMES.join(", ")
+    );
+    for name in Quirks::NAMES {
+        let _ = writeln!(text, "# quirk.{} = true", name);
+    }
+
+    text.push_str(
+        "\n[audio]\n\
+         # sdl plays a tone through the audio device, bell rings the terminal bell instead\n\
+         # backend = \"bell\"\n\
+         # samples per buffer from 16, a smaller buffer starts and stops the sound sooner but may\n\
+         # crackle, 1024 with pulseaudio, pipewire or directsound and 512 elsewhere when not set\n\
+         # buffer = 256\n\
+         # percent of full strength to rumble a connected game controller with while the sound\n\
+         # plays, with the sdl frontend only\n\
+         # rumble = 60\n",
+    );
+
+    text.push_str(
+        "\n# a [profile.NAME] section holds any of the options above and replaces them when chosen\n\
+         # with --profile-name NAME\n\
+         # [profile.crt]\n\
+         # palette = \"classic\"\n\
+         # pixel-aspect = \"stretch\"\n\
+         \n\
+         [hotkeys]\n\
+         # a letter, a digit, f1 to f12, escape, backspace, tab, space or enter, or \"none\" to\n\
+         # leave the hotkey unbound\n",
+    );
+    for hotkey in Hotkey::ALL {
+        let key = hotkeys.key(hotkey).unwrap_or("none");
+        let _ = writeln!(text, "{} = {}", hotkey.name(), quote(key));
+    }
+
+    text.push_str(
+        "#\n\
+         # The keypad is on the left of the keyboard in the same shape as the cosmac vip keypad,\n\
+         # a hotkey bound to one of these keys takes it from the keypad:\n",
+    );
+    for row in ["1234", "qwer", "asdf", "zxcv"] {
+        let keys: Vec<String> = row.chars().map(String::from).collect();
+        let keypad: Vec<String> = row
+            .chars()
+            .filter_map(|c| keymap::keypad_key(&c.to_string()))
+            .map(|key| format!("{:X}", key.idx()))
+            .collect();
+        let _ = writeln!(text, "#   {}  ->  {}", keys.join(" "), keypad.join(" "));
+    }
+
+    text.push_str(
+        "\n# each key is a host key that plays a sequence of keypad presses, steps are separated by\n\
+         # commas and hold the keypad keys joined with + for the frames after the colon, - holds\n\
+         # nothing, e.g. hold 5 for three frames and then press 6\n\
+         # [macros]\n\
+         # m = \"5:3, 6\"\n",
+    );
+
+    text.push_str(
+        "\n# each key is an address or register that is written with the value after every\n\
+         # instruction, e.g. to keep the lives of a game from running out\n\
+         # [freeze]\n\
+         # 0x3a0 = 9\n\
+         # v4 = 0xff\n",
+    );
+
+    text
+}
+
+fn write_option(text: &mut String, key: &str, value: Option<String>, example: &str) {
+    let _ = match value {
+        Some(value) => writeln!(text, "{} = {}", key, value),
+        None => writeln!(text, "# {} = {}", key, example),
+    };
+}
+
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value)
+}
+
+fn hex(color: crate::palette::Color) -> String {
+    format!("#{:02x}{:02x}{:02x}", color.r, color.g, color.b)
+}
+
+// notices the config file changing by its modification time, which is checked at most once a
+// second as it is polled from the emulation loop, the profile chosen at start stays chosen
+#[derive(Debug)]
+pub(crate) struct Watcher {
+    path: PathBuf,
+    profile: Option<String>,
+    modified: Option<SystemTime>,
+    checked: Instant,
+}
+
+impl Watcher {
+    pub(crate) fn new(path: PathBuf, profile: Option<String>) -> Self {
+        Self {
+            modified: modified(&path),
+            path,
+            profile,
+            checked: Instant::now(),
+        }
+    }
+    // the settings in the file when it changed since the last check
+    pub(crate) fn poll(&mut self) -> Option<Result<Settings>> {
+        if self.checked.elapsed() < WATCH_INTERVAL {
+            return None;
+        }
+        self.checked = Instant::now();
+
+        let modified = modified(&self.path);
+        if modified == self.modified {
+            return None;
+        }
+        self.modified = modified;
+
+        let settings = Settings::load(&self.path);
+        Some(match self.profile.as_deref() {
+            Some(name) => settings.and_then(|s| s.select_profile(name)),
+            None => settings,
+        })
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+fn parse_bool(key: &str, value: &str) -> Result<bool, String> {
+    match value {
+        "true" => Ok(true),
+        "false" => Ok(false),
+        _ => Err(format!(
+            "invalid {} '{}': expected true or false",
+            key, value
+        )),
+    }
+}
+
+fn parse_number<T: FromStr + PartialOrd + std::fmt::Display>(
+    key: &str,
+    value: &str,
+    range: RangeInclusive<T>,
+) -> Result<T, String> {
+    match value.parse::<T>() {
+        Ok(number) if range.contains(&number) => Ok(number),
+        _ => Err(format!(
+            "invalid {} '{}': expected a number from {} to {}",
+            key,
+            value,
+            range.start(),
+            range.end()
+        )),
+    }
+}
+
+// a # inside a quoted value is part of the value
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    for (idx, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            '#' if !quoted => return &line[..idx],
+            _ => {}
+        }
+    }
+
+    line
+}
+
+// numbers and booleans are the only values left bare
+fn unquote(value: &str) -> Result<&str, String> {
+    if let Some(quoted) = value.strip_prefix('"') {
+        return quoted
+            .strip_suffix('"')
+            .ok_or_else(|| format!("expected a closing quote in '{}'", value));
+    }
+
+    if !value.is_empty() && value.chars().all(|c| c.is_ascii_alphanumeric()) {
+        Ok(value)
+    } else {
+        Err(format!("expected a quoted value but found '{}'", value))
+    }
+}
@@ -0,0 +1,46 @@
+use crate::{palette::Palette, DisplayState, VisualBell};
+
+// sent every frame the frontend is sent, on the emulation thread, so a run can draw to more places
+// than its frontend at once. sinks get the frames of a headless run as well. closures taking the
+// displays, the palette and the visual bell can be used as a sink as well
+pub trait DisplaySink: Send {
+    fn present(&mut self, displays: &[DisplayState], palette: Palette, bell: Option<VisualBell>);
+}
+
+impl<F: FnMut(&[DisplayState], Palette, Option<VisualBell>) + Send> DisplaySink for F {
+    fn present(&mut self, displays: &[DisplayState], palette: Palette, bell: Option<VisualBell>) {
+        self(displays, palette, bell)
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct Sinks {
+    sinks: Vec<Box<dyn DisplaySink>>,
+}
+
+impl Sinks {
+    pub(crate) fn add(&mut self, sink: Box<dyn DisplaySink>) {
+        self.sinks.push(sink);
+    }
+    pub(crate) fn is_empty(&self) -> bool {
+        self.sinks.is_empty()
+    }
+    pub(crate) fn present(
+        &mut self,
+        displays: &[DisplayState],
+        palette: Palette,
+        bell: Option<VisualBell>,
+    ) {
+        for sink in self.sinks.iter_mut() {
+            sink.present(displays, palette, bell);
+        }
+    }
+}
+
+impl std::fmt::Debug for Sinks {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Sinks")
+            .field("sinks", &self.sinks.len())
+            .finish()
+    }
+}
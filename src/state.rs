@@ -0,0 +1,522 @@
+// Save states are stored as a small binary file, every number is big endian:
+//   "C8ST" followed by a version byte
+//   sections until the end of the file, each a four byte tag, a u32 length and the contents
+//
+// Sections in version 3:
+//   "CPU " program counter and index as u16, then v0 through vf, delay and sound timers, stack
+//          depth as a byte followed by a u16 per address, oldest first
+//   "RAM " all of memory
+//   "DISP" the display height in pixels followed by the display packed eight pixels to a byte,
+//          most significant bit first, row by row
+//   "THMB" a thumbnail of the display at half its size to tell states apart without loading
+//          them, its width and height in pixels followed by the pixels packed as for "DISP", a
+//          thumbnail pixel is lit when any of the four display pixels it covers is
+//
+// Readers skip sections they do not know so new sections can be added without a new version,
+// the version only changes when an existing section changes and newer versions are rejected.
+// Versions 1 and 2 have no sections, the same fields follow the version byte in the order above
+// and version 1 omits the display height since it is always 32.
+//
+// Quirks, symbols and the random number generator are not saved, they come from the cpu the
+// state is restored into.
+//
+// To move a session to or from another emulator a state can also be written as json, a layout
+// other tools can produce from their own states without knowing this binary format:
+//   {
+//     "version": 3,
+//     "cpu": {"pc": 512, "i": 0, "v": [v0 through vf], "dt": 0, "st": 0, "stack": [oldest first]},
+//     "memory": "all of memory as two lowercase hex digits a byte",
+//     "display": {"width": 64, "height": 32, "rows": ["0110...", one string of 0 and 1 a row]}
+//   }
+// Importing ignores the version and any field it does not know, and memory shorter than 4096
+// bytes is zero filled so a state from a machine with less memory still loads.
+
+use crate::{
+    core::{
+        cpu::CPU,
+        memory::{RAM, RAM_SIZE},
+    },
+    error::{bail, Context, Result},
+    json::Value,
+    DisplayState, DISPLAY_PIXELS_HEIGHT, DISPLAY_PIXELS_WIDTH, MAX_DISPLAY_PIXELS_HEIGHT,
+};
+
+use std::path::Path;
+
+const MAGIC: [u8; 4] = *b"C8ST";
+
+const VERSION: u8 = 3;
+
+const CPU_SECTION: [u8; 4] = *b"CPU ";
+
+const RAM_SECTION: [u8; 4] = *b"RAM ";
+
+const DISPLAY_SECTION: [u8; 4] = *b"DISP";
+
+const THUMBNAIL_SECTION: [u8; 4] = *b"THMB";
+
+#[derive(Clone, Debug)]
+pub(crate) struct Snapshot {
+    pub(crate) cpu: CPU,
+    pub(crate) memory: RAM,
+    pub(crate) display: DisplayState,
+}
+
+impl Snapshot {
+    pub(crate) fn load(path: impl AsRef<Path>, cpu: &CPU) -> Result<Self> {
+        let bytes = std::fs::read(path.as_ref())
+            .context(format!("read file {}", path.as_ref().to_string_lossy()))?;
+
+        Self::decode(&bytes, cpu)
+    }
+    pub(crate) fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        std::fs::write(path.as_ref(), self.encode())
+            .context(format!("write file {}", path.as_ref().to_string_lossy()))
+    }
+    pub(crate) fn to_json(&self) -> String {
+        let vs: Vec<String> = (0..16).map(|idx| self.cpu.v(idx).to_string()).collect();
+        let stack: Vec<String> = self.cpu.stack().iter().map(u16::to_string).collect();
+        let memory: String = self
+            .memory
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+
+        let width = self.display.width() as u16;
+        let rows: Vec<String> = (0..self.display.height() as u16)
+            .map(|row| {
+                let pixels: String = (0..width)
+                    .map(|col| match self.display.read_pixel(row * width + col) {
+                        true => '1',
+                        false => '0',
+                    })
+                    .collect();
+                format!("\"{}\"", pixels)
+            })
+            .collect();
+
+        format!(
+            "{{\"version\":{},\"cpu\":{{\"pc\":{},\"i\":{},\"v\":[{}],\"dt\":{},\"st\":{},\"stack\":[{}]}},\"memory\":\"{}\",\"display\":{{\"width\":{},\"height\":{},\"rows\":[{}]}}}}",
+            VERSION,
+            self.cpu.prog_counter(),
+            self.cpu.index(),
+            vs.join(","),
+            self.cpu.delay_timer(),
+            self.cpu.sound_timer(),
+            stack.join(","),
+            memory,
+            width,
+            self.display.height(),
+            rows.join(",")
+        )
+    }
+    pub(crate) fn from_json(text: &str, cpu: &CPU) -> Result<Self> {
+        let json = Value::parse(text).context("parse json")?;
+
+        let state = json.get("cpu").context("state has no cpu")?;
+        let mut cpu = cpu.clone();
+        cpu.set_prog_counter(json_number(state, "pc")?);
+        cpu.set_index(json_number(state, "i")?);
+
+        let vs = json_array(state, "v")?;
+        if vs.len() != 16 {
+            bail!("invalid v: expected 16 registers, got {}", vs.len());
+        }
+        for (idx, value) in vs.iter().enumerate() {
+            cpu.set_v(idx, json_integer(value, "v")?);
+        }
+
+        cpu.set_timers(json_number(state, "dt")?, json_number(state, "st")?);
+
+        let stack = json_array(state, "stack")?
+            .iter()
+            .map(|address| json_integer(address, "stack"))
+            .collect::<Result<Vec<u16>>>()?;
+        cpu.set_stack(&stack);
+
+        let hex = json
+            .get("memory")
+            .and_then(Value::as_str)
+            .context("state has no memory")?;
+        if hex.len() % 2 != 0 || hex.len() > RAM_SIZE * 2 {
+            bail!(
+                "invalid memory: expected at most {} bytes as pairs of hex digits",
+                RAM_SIZE
+            );
+        }
+        let bytes = (0..hex.len())
+            .step_by(2)
+            .map(|idx| {
+                hex.get(idx..idx + 2)
+                    .and_then(|digits| u8::from_str_radix(digits, 16).ok())
+                    .context("invalid memory: expected hex digits")
+            })
+            .collect::<Result<Vec<u8>>>()?;
+        let mut memory = RAM::new();
+        memory.write_block(0, &bytes);
+
+        let screen = json.get("display").context("state has no display")?;
+        let rows = json_array(screen, "rows")?;
+        if rows.is_empty() || rows.len() > MAX_DISPLAY_PIXELS_HEIGHT as usize {
+            bail!("unsupported display height {}", rows.len());
+        }
+        let mut display = DisplayState::with_height(rows.len() as u8);
+        for (y, row) in rows.iter().enumerate() {
+            let pixels = row
+                .as_str()
+                .context("invalid display row: expected a string")?;
+            if pixels.len() != DISPLAY_PIXELS_WIDTH as usize {
+                bail!("unsupported display width {}", pixels.len());
+            }
+
+            for (x, pixel) in pixels.chars().enumerate() {
+                let lit = match pixel {
+                    '0' => false,
+                    '1' => true,
+                    _ => bail!("invalid display pixel '{}': expected 0 or 1", pixel),
+                };
+                display.set_pixel(x as u8, y as u8, lit);
+            }
+        }
+
+        Ok(Self {
+            cpu,
+            memory,
+            display,
+        })
+    }
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(64 + RAM_SIZE + self.display.num_pixels() as usize / 8);
+        bytes.extend_from_slice(&MAGIC);
+        bytes.push(VERSION);
+
+        write_section(&mut bytes, CPU_SECTION, &self.encode_cpu());
+        write_section(&mut bytes, RAM_SECTION, self.memory.as_slice());
+        write_section(&mut bytes, DISPLAY_SECTION, &self.encode_display());
+        write_section(&mut bytes, THUMBNAIL_SECTION, &self.thumbnail().encode());
+
+        bytes
+    }
+    fn encode_cpu(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&self.cpu.prog_counter().to_be_bytes());
+        bytes.extend_from_slice(&self.cpu.index().to_be_bytes());
+        bytes.extend((0..16).map(|idx| self.cpu.v(idx)));
+        bytes.push(self.cpu.delay_timer());
+        bytes.push(self.cpu.sound_timer());
+
+        // the depth is a byte, past MAX_STACK_DEPTH a rom without --strict can keep calling so
+        // only the newest entries are kept rather than saving a depth that does not match them
+        let stack = self.cpu.stack();
+        let stack = &stack[stack.len().saturating_sub(u8::MAX as usize)..];
+        bytes.push(stack.len() as u8);
+        for address in stack {
+            bytes.extend_from_slice(&address.to_be_bytes());
+        }
+
+        bytes
+    }
+    fn encode_display(&self) -> Vec<u8> {
+        let num_pixels = self.display.num_pixels() as usize;
+
+        let mut bytes = vec![self.display.height()];
+        bytes.resize(1 + num_pixels / 8, 0);
+        for idx in 0..num_pixels {
+            if self.display.read_pixel(idx as u16) {
+                bytes[1 + idx / 8] |= 0x80 >> (idx % 8);
+            }
+        }
+
+        bytes
+    }
+    pub(crate) fn thumbnail(&self) -> Thumbnail {
+        Thumbnail::new(&self.display)
+    }
+    // fnv-1a over the encoded state, it is spelled out rather than using the std hasher so the
+    // same state hashes the same with every build and on every platform
+    pub(crate) fn hash(&self) -> u64 {
+        self.encode()
+            .iter()
+            .fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+                (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+            })
+    }
+    pub(crate) fn decode(bytes: &[u8], cpu: &CPU) -> Result<Self> {
+        let mut reader = Reader { bytes };
+
+        if reader.take(4)? != MAGIC {
+            bail!("not a chipate save state");
+        }
+
+        let version = reader.take(1)?[0];
+        match version {
+            1 | 2 => return decode_unsectioned(reader, version, cpu),
+            VERSION => {}
+            _ => bail!("unsupported save state version {}", version),
+        }
+
+        let mut snapshot = None;
+        let mut memory = None;
+        let mut display = None;
+
+        while !reader.bytes.is_empty() {
+            let tag = reader.take(4)?;
+            let len = u32::from_be_bytes(reader.take(4)?.try_into()?) as usize;
+            let mut section = Reader {
+                bytes: reader.take(len)?,
+            };
+
+            match tag.try_into()? {
+                CPU_SECTION => snapshot = Some(read_cpu(&mut section, cpu)?),
+                RAM_SECTION => memory = Some(read_memory(&mut section)?),
+                DISPLAY_SECTION => {
+                    let height = section.take(1)?[0];
+                    display = Some(read_display(&mut section, height)?);
+                }
+                // the thumbnail is only read when listing states
+                THUMBNAIL_SECTION => {}
+                _ => tracing::debug!(
+                    "skipping unknown save state section {}",
+                    String::from_utf8_lossy(tag)
+                ),
+            }
+        }
+
+        Ok(Self {
+            cpu: snapshot.context("save state has no cpu section")?,
+            memory: memory.context("save state has no memory section")?,
+            display: display.context("save state has no display section")?,
+        })
+    }
+}
+
+// the thumbnail of an encoded state, states saved before thumbnails were added get one made from
+// their display
+pub(crate) fn read_thumbnail(bytes: &[u8]) -> Result<Thumbnail> {
+    let mut reader = Reader { bytes };
+
+    if reader.take(4)? == MAGIC && reader.take(1)?[0] == VERSION {
+        while !reader.bytes.is_empty() {
+            let tag = reader.take(4)?;
+            let len = u32::from_be_bytes(reader.take(4)?.try_into()?) as usize;
+            let section = reader.take(len)?;
+
+            if tag == THUMBNAIL_SECTION {
+                return Thumbnail::decode(section);
+            }
+        }
+    }
+
+    Ok(Snapshot::decode(bytes, &CPU::default())?.thumbnail())
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Thumbnail {
+    width: usize,
+    height: usize,
+    pixels: Vec<bool>,
+}
+
+impl Thumbnail {
+    fn new(display: &DisplayState) -> Self {
+        let (display_width, display_height) = (display.width() as usize, display.height() as usize);
+        let (width, height) = (display_width / 2, display_height / 2);
+
+        let pixels = (0..width * height)
+            .map(|idx| {
+                let (x, y) = (idx % width * 2, idx / width * 2);
+                [(0, 0), (1, 0), (0, 1), (1, 1)]
+                    .iter()
+                    .any(|(dx, dy)| display.read_pixel(((y + dy) * display_width + x + dx) as u16))
+            })
+            .collect();
+
+        Self {
+            width,
+            height,
+            pixels,
+        }
+    }
+    fn encode(&self) -> Vec<u8> {
+        let mut bytes = vec![self.width as u8, self.height as u8];
+        bytes.resize(2 + self.pixels.len().div_ceil(8), 0);
+        for (idx, _) in self.pixels.iter().enumerate().filter(|(_, on)| **on) {
+            bytes[2 + idx / 8] |= 0x80 >> (idx % 8);
+        }
+
+        bytes
+    }
+    fn decode(bytes: &[u8]) -> Result<Self> {
+        let mut reader = Reader { bytes };
+        let size = reader.take(2)?;
+        let (width, height) = (size[0] as usize, size[1] as usize);
+
+        let packed = reader.take((width * height).div_ceil(8))?;
+        let pixels = (0..width * height)
+            .map(|idx| packed[idx / 8] & (0x80 >> (idx % 8)) != 0)
+            .collect();
+
+        Ok(Self {
+            width,
+            height,
+            pixels,
+        })
+    }
+    fn pixel(&self, x: usize, y: usize) -> bool {
+        y < self.height && self.pixels[y * self.width + x]
+    }
+    // two rows of pixels to a line of half blocks inside a frame so a blank display still shows
+    pub(crate) fn render(&self) -> String {
+        let border = format!("+{}+\n", "-".repeat(self.width));
+
+        let mut text = border.clone();
+        for y in (0..self.height).step_by(2) {
+            text.push('|');
+            text.extend(
+                (0..self.width).map(|x| match (self.pixel(x, y), self.pixel(x, y + 1)) {
+                    (true, true) => '█',
+                    (true, false) => '▀',
+                    (false, true) => '▄',
+                    (false, false) => ' ',
+                }),
+            );
+            text.push_str("|\n");
+        }
+        text.push_str(&border);
+
+        text
+    }
+}
+
+fn json_array<'a>(object: &'a Value, name: &str) -> Result<&'a [Value]> {
+    object
+        .get(name)
+        .and_then(Value::as_array)
+        .context(format!("state has no {} array", name))
+}
+
+fn json_number<T: TryFrom<u64>>(object: &Value, name: &str) -> Result<T> {
+    let value = object.get(name).context(format!("state has no {}", name))?;
+    json_integer(value, name)
+}
+
+fn json_integer<T: TryFrom<u64>>(value: &Value, name: &str) -> Result<T> {
+    value
+        .as_u64()
+        .and_then(|n| T::try_from(n).ok())
+        .context(format!(
+            "invalid {}: expected a whole number that fits in {} bits",
+            name,
+            std::mem::size_of::<T>() * 8
+        ))
+}
+
+fn write_section(bytes: &mut Vec<u8>, tag: [u8; 4], contents: &[u8]) {
+    bytes.extend_from_slice(&tag);
+    bytes.extend_from_slice(&(contents.len() as u32).to_be_bytes());
+    bytes.extend_from_slice(contents);
+}
+
+fn decode_unsectioned(mut reader: Reader, version: u8, cpu: &CPU) -> Result<Snapshot> {
+    let cpu = read_cpu(&mut reader, cpu)?;
+    let memory = read_memory(&mut reader)?;
+
+    let height = match version {
+        1 => DISPLAY_PIXELS_HEIGHT,
+        _ => reader.take(1)?[0],
+    };
+
+    let display = read_display(&mut reader, height)?;
+
+    Ok(Snapshot {
+        cpu,
+        memory,
+        display,
+    })
+}
+
+fn read_cpu(reader: &mut Reader, cpu: &CPU) -> Result<CPU> {
+    let mut cpu = cpu.clone();
+    cpu.set_prog_counter(reader.u16()?);
+    cpu.set_index(reader.u16()?);
+
+    for (idx, value) in reader.take(16)?.iter().enumerate() {
+        cpu.set_v(idx, *value);
+    }
+
+    let timers = reader.take(2)?;
+    cpu.set_timers(timers[0], timers[1]);
+
+    let depth = reader.take(1)?[0];
+    let stack = (0..depth)
+        .map(|_| reader.u16())
+        .collect::<Result<Vec<u16>>>()?;
+    cpu.set_stack(&stack);
+
+    Ok(cpu)
+}
+
+fn read_memory(reader: &mut Reader) -> Result<RAM> {
+    let mut memory = RAM::new();
+    memory.write_block(0, reader.take(RAM_SIZE)?);
+
+    Ok(memory)
+}
+
+fn read_display(reader: &mut Reader, height: u8) -> Result<DisplayState> {
+    if height == 0 || height > MAX_DISPLAY_PIXELS_HEIGHT {
+        bail!("unsupported display height {}", height);
+    }
+
+    let mut display = DisplayState::with_height(height);
+    let num_pixels = display.num_pixels() as usize;
+    let pixels = reader.take(num_pixels / 8)?;
+    for idx in 0..num_pixels {
+        display.write_pixel(idx as u16, pixels[idx / 8] & (0x80 >> (idx % 8)) != 0);
+    }
+
+    Ok(display)
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.bytes.len() < len {
+            bail!("save state is truncated");
+        }
+
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+
+        Ok(taken)
+    }
+    fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_be_bytes(self.take(2)?.try_into()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deep_stack_restores_as_saved() {
+        let mut cpu = CPU::default();
+        let stack: Vec<u16> = (0..300).map(|depth| 0x200 + depth * 2).collect();
+        cpu.set_stack(&stack);
+
+        let snapshot = Snapshot {
+            cpu,
+            memory: RAM::default(),
+            display: DisplayState::default(),
+        };
+        let encoded = snapshot.encode();
+        let decoded = Snapshot::decode(&encoded, &CPU::default()).unwrap();
+
+        assert_eq!(decoded.cpu.stack(), &stack[300 - u8::MAX as usize..]);
+        assert_eq!(decoded.encode(), encoded);
+    }
+}
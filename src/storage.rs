@@ -0,0 +1,80 @@
+// Everything chipate keeps for a rom lives in a directory named after the checksum of the rom so
+// it follows the rom when it is renamed or moved:
+//   <data dir>/chipate/roms/<checksum>/
+//     autosave.c8st   the save state written on exit
+//     slot<n>.c8st    save states written with the save-state hotkey, one per slot
+//     flags.bin       schip flag registers
+//     dumps/          memory dumps taken with the dump-memory hotkey
+//     screenshots/    svgs of the display taken with the export-svg hotkey
+//     movies/         recorded input movies
+//     settings.toml   settings that apply to the rom only
+//     debugger.txt    breakpoints and files of the last debugging session
+//
+// The data dir is CHIPATE_DATA_DIR when set, otherwise the platform data directory, e.g.
+// ~/.local/share on linux.
+
+use crate::{
+    core::{memory::checksum, Program},
+    error::{Context, Result},
+};
+
+use std::path::{Path, PathBuf};
+
+const DATA_DIR_ENV: &str = "CHIPATE_DATA_DIR";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RomData {
+    dir: PathBuf,
+}
+
+impl RomData {
+    pub fn locate(program: &Program) -> Result<Self> {
+        let root = match std::env::var_os(DATA_DIR_ENV) {
+            Some(dir) => PathBuf::from(dir),
+            None => dirs::data_dir()
+                .context("no data directory on this platform, set CHIPATE_DATA_DIR")?
+                .join("chipate"),
+        };
+
+        Ok(Self::in_dir(root, program))
+    }
+    pub fn in_dir(root: impl AsRef<Path>, program: &Program) -> Self {
+        Self {
+            dir: root
+                .as_ref()
+                .join("roms")
+                .join(format!("{:08x}", checksum(program.data()))),
+        }
+    }
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+    pub fn create(&self) -> Result<()> {
+        std::fs::create_dir_all(&self.dir)
+            .context(format!("create directory {}", self.dir.to_string_lossy()))
+    }
+    pub fn autosave_path(&self) -> PathBuf {
+        self.dir.join("autosave.c8st")
+    }
+    pub fn state_slot_path(&self, slot: u8) -> PathBuf {
+        self.dir.join(format!("slot{}.c8st", slot))
+    }
+    pub fn flags_path(&self) -> PathBuf {
+        self.dir.join("flags.bin")
+    }
+    pub fn dumps_dir(&self) -> PathBuf {
+        self.dir.join("dumps")
+    }
+    pub fn screenshots_dir(&self) -> PathBuf {
+        self.dir.join("screenshots")
+    }
+    pub fn movies_dir(&self) -> PathBuf {
+        self.dir.join("movies")
+    }
+    pub fn settings_path(&self) -> PathBuf {
+        self.dir.join("settings.toml")
+    }
+    pub fn debugger_session_path(&self) -> PathBuf {
+        self.dir.join("debugger.txt")
+    }
+}
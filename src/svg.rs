@@ -0,0 +1,76 @@
+// Writes the displays as an svg of one rect per run of lit pixels in a row on a rect of the
+// background color, in the colors of the palette. The view box is in pixels so the image scales
+// to any size without blurring, the width and height only set the size it opens at.
+
+use crate::{
+    error::{Context, Result},
+    image::{Image, OFF},
+    palette::{Color, Palette},
+    DisplayState,
+};
+
+use std::{fmt::Write, path::Path};
+
+// the size of a pixel when the svg is opened without being scaled
+const PIXEL_SIZE: usize = 10;
+
+pub(crate) fn render(displays: &[DisplayState], palette: Palette) -> String {
+    let image = Image::new(palette, displays, None);
+
+    let mut svg = String::new();
+    let _ = writeln!(
+        svg,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" viewBox=\"0 0 {} {}\" \
+         shape-rendering=\"crispEdges\">",
+        image.width * PIXEL_SIZE,
+        image.height * PIXEL_SIZE,
+        image.width,
+        image.height
+    );
+    let _ = writeln!(
+        svg,
+        "<rect width=\"{}\" height=\"{}\" fill=\"{}\"/>",
+        image.width,
+        image.height,
+        hex(image.colors[OFF as usize])
+    );
+
+    for y in 0..image.height {
+        let mut x = 0;
+        while x < image.width {
+            let pixel = image.pixel(x, y);
+            let start = x;
+            while x < image.width && image.pixel(x, y) == pixel {
+                x += 1;
+            }
+
+            if pixel != OFF {
+                let _ = writeln!(
+                    svg,
+                    "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"1\" fill=\"{}\"/>",
+                    start,
+                    y,
+                    x - start,
+                    hex(image.colors[pixel as usize])
+                );
+            }
+        }
+    }
+
+    svg.push_str("</svg>\n");
+
+    svg
+}
+
+pub(crate) fn save(
+    path: impl AsRef<Path>,
+    displays: &[DisplayState],
+    palette: Palette,
+) -> Result<()> {
+    std::fs::write(path.as_ref(), render(displays, palette))
+        .context(format!("write file {}", path.as_ref().to_string_lossy()))
+}
+
+fn hex(color: Color) -> String {
+    format!("#{:02x}{:02x}{:02x}", color.r, color.g, color.b)
+}
@@ -0,0 +1,500 @@
+// Draws the display in the terminal, two pixels to a character cell using the upper half block
+// with the top pixel as the foreground and the bottom pixel as the background color.
+//
+// Most terminals only report key presses, a key counts as held until no repeat for it has
+// arrived for RELEASE_AFTER. Terminals that support the kitty keyboard protocol report releases
+// and are used as is.
+
+use crate::{
+    error::{Context, Result},
+    image::{Image, ACCENT, OFF, ON},
+    keymap::{self, Hotkey, Hotkeys},
+    palette::{self, Palette},
+    sink::DisplaySink,
+    DisplayState, Input, Key, Output, Style, TerminalGraphics, VisualBell,
+};
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use crossterm::{
+    cursor,
+    event::{
+        self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, KeyboardEnhancementFlags,
+        PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+    },
+    queue,
+    style::{Color, Print, ResetColor, SetBackgroundColor, SetForegroundColor},
+    terminal,
+};
+use std::{
+    io::Write,
+    sync::mpsc::{Receiver, Sender},
+    thread::ScopedJoinHandle,
+    time::{Duration, Instant},
+};
+
+// longer than the delay before most terminals start repeating a held key
+const RELEASE_AFTER: Duration = Duration::from_millis(250);
+
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+// the largest payload the kitty protocol accepts in a single escape sequence
+const KITTY_CHUNK_LEN: usize = 4096;
+
+// used when the terminal does not report its size in pixels
+const SIXEL_FALLBACK_SCALE: usize = 8;
+
+// puts the terminal into raw mode on the alternate screen and restores it when dropped
+#[derive(Debug)]
+pub(crate) struct Terminal {
+    reports_releases: bool,
+    graphics: TerminalGraphics,
+}
+
+impl Terminal {
+    pub(crate) fn enter(graphics: TerminalGraphics) -> Result<Self> {
+        let graphics = match graphics {
+            TerminalGraphics::Auto => detect_graphics(),
+            graphics => graphics,
+        };
+        tracing::debug!("drawing the terminal with {:?}", graphics);
+
+        terminal::enable_raw_mode().context("enable raw mode")?;
+
+        let mut stdout = std::io::stdout();
+        crossterm::execute!(stdout, terminal::EnterAlternateScreen, cursor::Hide)
+            .context("enter alternate screen")?;
+
+        let reports_releases = terminal::supports_keyboard_enhancement().unwrap_or(false);
+        if reports_releases {
+            crossterm::execute!(
+                stdout,
+                PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::REPORT_EVENT_TYPES)
+            )
+            .context("enable key release events")?;
+        }
+
+        Ok(Self {
+            reports_releases,
+            graphics,
+        })
+    }
+    pub(crate) fn present<T>(
+        &mut self,
+        style: Style,
+        hotkeys: &Hotkeys,
+        outputs: &Receiver<Output>,
+        inputs: &Sender<Input>,
+        emulation: &ScopedJoinHandle<T>,
+    ) {
+        if let Err(e) = self.run(style, hotkeys, outputs, inputs, emulation) {
+            tracing::error!("terminal error: {:#}", e);
+            let _ = inputs.send(Input::Quit);
+        }
+    }
+    fn run<T>(
+        &mut self,
+        style: Style,
+        hotkeys: &Hotkeys,
+        outputs: &Receiver<Output>,
+        inputs: &Sender<Input>,
+        emulation: &ScopedJoinHandle<T>,
+    ) -> Result<()> {
+        let mut style = style;
+        let mut hotkeys = hotkeys.clone();
+        let mut keys = HeldKeys::default();
+        let mut drawn: Option<(Vec<DisplayState>, Option<VisualBell>)> = None;
+
+        loop {
+            let mut timeout = POLL_INTERVAL;
+            while event::poll(timeout)? {
+                timeout = Duration::ZERO;
+
+                if let Event::Key(event) = event::read()? {
+                    for input in keys.apply(event, &hotkeys, Instant::now()) {
+                        if inputs.send(input).is_err() {
+                            return Ok(());
+                        }
+                    }
+                }
+            }
+
+            if !self.reports_releases {
+                for input in keys.expire(Instant::now()) {
+                    if inputs.send(input).is_err() {
+                        return Ok(());
+                    }
+                }
+            }
+
+            // only the latest frame is worth drawing when the terminal has fallen behind
+            let mut frame = None;
+            for output in outputs.try_iter() {
+                match output {
+                    Output::Frame(displays, bell) => frame = Some((displays, bell)),
+                    Output::Title(title) => {
+                        queue!(std::io::stdout(), terminal::SetTitle(title))?;
+                    }
+                    // only the sdl frontend opens a debug window
+                    Output::Debug(_) => {}
+                    // the frame sent after a reload is drawn even though it has not changed
+                    Output::Reload(reloaded_style, reloaded_hotkeys) => {
+                        style = reloaded_style;
+                        hotkeys = reloaded_hotkeys;
+                        drawn = None;
+                    }
+                }
+            }
+
+            match frame {
+                Some(frame) if drawn.as_ref() != Some(&frame) => {
+                    draw(self.graphics, &Image::new(style.palette, &frame.0, frame.1))?;
+                    drawn = Some(frame);
+                }
+                _ if emulation.is_finished() => return Ok(()),
+                _ => {}
+            }
+        }
+    }
+}
+
+// draws the frames another frontend presents in the terminal as well, the keyboard is left to
+// that frontend so the terminal is not put in raw mode
+pub(crate) struct Mirror {
+    graphics: TerminalGraphics,
+    drawn: Option<(Vec<DisplayState>, Palette, Option<VisualBell>)>,
+}
+
+impl Mirror {
+    pub(crate) fn enter(graphics: TerminalGraphics) -> Result<Self> {
+        let graphics = match graphics {
+            TerminalGraphics::Auto => detect_graphics(),
+            graphics => graphics,
+        };
+        tracing::debug!("mirroring to the terminal with {:?}", graphics);
+
+        crossterm::execute!(
+            std::io::stdout(),
+            terminal::EnterAlternateScreen,
+            cursor::Hide
+        )
+        .context("enter alternate screen")?;
+
+        Ok(Self {
+            graphics,
+            drawn: None,
+        })
+    }
+}
+
+impl DisplaySink for Mirror {
+    fn present(&mut self, displays: &[DisplayState], palette: Palette, bell: Option<VisualBell>) {
+        let frame = (displays.to_vec(), palette, bell);
+        if self.drawn.as_ref() == Some(&frame) {
+            return;
+        }
+
+        if let Err(e) = draw(self.graphics, &Image::new(palette, displays, bell)) {
+            tracing::warn!("terminal mirror error: {:#}", e);
+        }
+        self.drawn = Some(frame);
+    }
+}
+
+impl Drop for Mirror {
+    fn drop(&mut self) {
+        let mut stdout = std::io::stdout();
+        if self.graphics == TerminalGraphics::Kitty {
+            let _ = write!(stdout, "\x1b_Ga=d,d=I,i=1,q=2\x1b\\");
+        }
+        let _ = crossterm::execute!(
+            stdout,
+            ResetColor,
+            cursor::Show,
+            terminal::LeaveAlternateScreen
+        );
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        let mut stdout = std::io::stdout();
+        if self.reports_releases {
+            let _ = crossterm::execute!(stdout, PopKeyboardEnhancementFlags);
+        }
+        if self.graphics == TerminalGraphics::Kitty {
+            let _ = write!(stdout, "\x1b_Ga=d,d=I,i=1,q=2\x1b\\");
+        }
+        let _ = crossterm::execute!(
+            stdout,
+            ResetColor,
+            cursor::Show,
+            terminal::LeaveAlternateScreen
+        );
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+// when each key or hotkey was last pressed or repeated, hotkeys are held like keypad keys so a
+// held rewind keeps rewinding and repeats of the other hotkeys do not fire them again, the same
+// goes for the host keys of macros
+#[derive(Debug, Default)]
+struct HeldKeys {
+    keys: [Option<Instant>; 16],
+    hotkeys: [Option<Instant>; Hotkey::ALL.len()],
+    macros: Vec<(String, Instant)>,
+}
+
+impl HeldKeys {
+    fn apply(&mut self, event: KeyEvent, hotkeys: &Hotkeys, now: Instant) -> Vec<Input> {
+        let pressed = event.kind != KeyEventKind::Release;
+
+        if event.code == KeyCode::Char('c') && event.modifiers.contains(KeyModifiers::CONTROL) {
+            return vec![Input::Quit];
+        }
+
+        let Some(input) =
+            key_name(event.code).and_then(|name| Input::from_host_key(hotkeys, &name, pressed))
+        else {
+            return Vec::new();
+        };
+
+        let held = match &input {
+            Input::Key(key, _) => &mut self.keys[usize::from(key.clone())],
+            Input::Hotkey(hotkey, _) => &mut self.hotkeys[*hotkey as usize],
+            Input::Macro(name, _) => {
+                let was_held = self.macros.iter().any(|(held, _)| held == name);
+                self.macros.retain(|(held, _)| held != name);
+                if pressed {
+                    self.macros.push((name.clone(), now));
+                }
+                return match pressed && !was_held {
+                    true => vec![input],
+                    false => Vec::new(),
+                };
+            }
+            _ => return vec![input],
+        };
+
+        let was_held = held.is_some();
+        *held = pressed.then_some(now);
+        match was_held == pressed {
+            true => Vec::new(),
+            false => vec![input],
+        }
+    }
+    fn expire(&mut self, now: Instant) -> Vec<Input> {
+        let expired = |held: &mut Option<Instant>| {
+            let expired = held.is_some_and(|at| now - at >= RELEASE_AFTER);
+            if expired {
+                *held = None;
+            }
+            expired
+        };
+
+        self.macros.retain(|(_, at)| now - *at < RELEASE_AFTER);
+
+        let keys = (0..self.keys.len())
+            .filter(|idx| expired(&mut self.keys[*idx]))
+            .map(|idx| Input::Key(Key::from(idx), false))
+            .collect::<Vec<Input>>();
+
+        let hotkeys = Hotkey::ALL
+            .into_iter()
+            .filter(|hotkey| expired(&mut self.hotkeys[*hotkey as usize]))
+            .map(|hotkey| Input::Hotkey(hotkey, false));
+
+        keys.into_iter().chain(hotkeys).collect()
+    }
+}
+
+fn key_name(code: KeyCode) -> Option<String> {
+    let name = match code {
+        KeyCode::Char(' ') => String::from("space"),
+        KeyCode::Char(c) => c.to_ascii_lowercase().to_string(),
+        KeyCode::F(n) => format!("f{}", n),
+        KeyCode::Esc => String::from("escape"),
+        KeyCode::Backspace => String::from("backspace"),
+        KeyCode::Tab => String::from("tab"),
+        KeyCode::Enter => String::from("enter"),
+        _ => return None,
+    };
+
+    keymap::is_host_key(&name).then_some(name)
+}
+
+fn draw(graphics: TerminalGraphics, image: &Image) -> Result<()> {
+    let mut stdout = std::io::stdout().lock();
+    queue!(stdout, cursor::MoveTo(0, 0))?;
+
+    match graphics {
+        TerminalGraphics::Kitty => draw_kitty(&mut stdout, image)?,
+        TerminalGraphics::Sixel => draw_sixel(&mut stdout, image)?,
+        _ => draw_blocks(&mut stdout, image)?,
+    }
+
+    Ok(stdout.flush()?)
+}
+
+// colors are only sent when they change so a frame costs little more than the characters
+fn draw_blocks(out: &mut impl Write, image: &Image) -> Result<()> {
+    let mut colors = None;
+
+    for row in 0..image.height / 2 {
+        queue!(out, cursor::MoveTo(0, row as u16))?;
+
+        for x in 0..image.width {
+            let cell = (image.pixel(x, row * 2), image.pixel(x, row * 2 + 1));
+            if colors != Some(cell) {
+                queue!(
+                    out,
+                    SetForegroundColor(rgb(image.colors[cell.0 as usize])),
+                    SetBackgroundColor(rgb(image.colors[cell.1 as usize]))
+                )?;
+                colors = Some(cell);
+            }
+
+            queue!(out, Print('▀'))?;
+        }
+    }
+
+    queue!(out, ResetColor)?;
+
+    Ok(())
+}
+
+// the image is sent as raw rgb in chunks and scaled by the terminal to the same cells the block
+// characters would take up, reusing the image and placement ids replaces the previous frame
+fn draw_kitty(out: &mut impl Write, image: &Image) -> Result<()> {
+    let mut rgb = Vec::with_capacity(image.pixels.len() * 3);
+    for pixel in &image.pixels {
+        let color = image.colors[*pixel as usize];
+        rgb.extend_from_slice(&[color.r, color.g, color.b]);
+    }
+
+    let encoded = BASE64.encode(rgb);
+    let chunks: Vec<&[u8]> = encoded.as_bytes().chunks(KITTY_CHUNK_LEN).collect();
+
+    for (idx, chunk) in chunks.iter().enumerate() {
+        let more = (idx + 1 < chunks.len()) as u8;
+        if idx == 0 {
+            write!(
+                out,
+                "\x1b_Ga=T,f=24,s={},v={},c={},r={},i=1,p=1,q=2,C=1,m={};",
+                image.width,
+                image.height,
+                image.width,
+                image.height / 2,
+                more
+            )?;
+        } else {
+            write!(out, "\x1b_Gm={};", more)?;
+        }
+
+        out.write_all(chunk)?;
+        write!(out, "\x1b\\")?;
+    }
+
+    Ok(())
+}
+
+// sixel images are not scaled by the terminal so every pixel is drawn as a square as large as a
+// character cell is wide, six rows at a time with one pass per color
+fn draw_sixel(out: &mut impl Write, image: &Image) -> Result<()> {
+    let scale = match terminal::window_size() {
+        Ok(size) if size.width > 0 && size.columns > 0 => {
+            usize::max(1, size.width as usize / size.columns as usize)
+        }
+        _ => SIXEL_FALLBACK_SCALE,
+    };
+
+    let (width, height) = (image.width * scale, image.height * scale);
+
+    write!(out, "\x1bPq\"1;1;{};{}", width, height)?;
+    for (idx, color) in image.colors.iter().enumerate() {
+        let percent = |c: u8| c as usize * 100 / 255;
+        write!(
+            out,
+            "#{};2;{};{};{}",
+            idx,
+            percent(color.r),
+            percent(color.g),
+            percent(color.b)
+        )?;
+    }
+
+    for band in (0..height).step_by(6) {
+        for color in [OFF, ON, ACCENT] {
+            let sixel = |x: usize| {
+                (0..6)
+                    .filter(|bit| band + bit < height)
+                    .filter(|bit| image.pixel(x / scale, (band + bit) / scale) == color)
+                    .fold(0_u8, |sixel, bit| sixel | 1 << bit)
+            };
+
+            if (0..width).all(|x| sixel(x) == 0) {
+                continue;
+            }
+
+            write!(out, "#{}", color)?;
+
+            // runs of the same sixel are written once with a repeat count
+            let mut x = 0;
+            while x < width {
+                let value = sixel(x);
+                let mut run = 1;
+                while x + run < width && sixel(x + run) == value {
+                    run += 1;
+                }
+
+                match run {
+                    1..=3 => {
+                        for _ in 0..run {
+                            out.write_all(&[63 + value])?;
+                        }
+                    }
+                    _ => write!(out, "!{}{}", run, (63 + value) as char)?,
+                }
+
+                x += run;
+            }
+
+            write!(out, "$")?;
+        }
+
+        write!(out, "-")?;
+    }
+
+    write!(out, "\x1b\\")?;
+
+    Ok(())
+}
+
+// terminals set these when they can show kitty images, sixel support cannot be read from the
+// environment reliably so it is only used when the terminal type names it
+fn detect_graphics() -> TerminalGraphics {
+    let var = |name: &str| std::env::var(name).unwrap_or_default();
+
+    let term = var("TERM");
+    let program = var("TERM_PROGRAM");
+
+    if std::env::var_os("KITTY_WINDOW_ID").is_some()
+        || term == "xterm-kitty"
+        || term == "xterm-ghostty"
+        || program == "WezTerm"
+        || program == "ghostty"
+    {
+        TerminalGraphics::Kitty
+    } else if term.contains("sixel") || term.starts_with("mlterm") || term == "foot" {
+        TerminalGraphics::Sixel
+    } else {
+        TerminalGraphics::Blocks
+    }
+}
+
+fn rgb(color: palette::Color) -> Color {
+    Color::Rgb {
+        r: color.r,
+        g: color.g,
+        b: color.b,
+    }
+}
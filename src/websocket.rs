@@ -0,0 +1,202 @@
+// Streams the display to browser clients and accepts key events from them.
+//
+// Server to client messages are binary, the first byte is the message type:
+//   0x00 full frame, followed by the width and height in pixels and the pixels packed eight to a
+//        byte, most significant bit first, row by row
+//   0x01 diff, followed by big endian u16 indexes of every pixel that toggled since the last frame
+//
+// Client to server messages are text of the form `down X` or `up X` where X is a keypad key in
+// hex, e.g. `down a`.
+
+use crate::{
+    error::{Context, EmuError, Result},
+    netplay::KeyEvent,
+    DisplayState,
+};
+
+use std::{
+    io::ErrorKind,
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+use tungstenite::{Error, Message};
+
+const FULL_FRAME: u8 = 0x00;
+
+const DIFF: u8 = 0x01;
+
+#[derive(Debug)]
+pub struct DisplayServer {
+    clients: Arc<Mutex<Vec<Sender<Vec<u8>>>>>,
+    full_frame: Arc<Mutex<Vec<u8>>>,
+    key_events: Receiver<KeyEvent>,
+    last: DisplayState,
+}
+
+impl DisplayServer {
+    pub fn bind(addr: impl ToSocketAddrs) -> Result<Self> {
+        let listener = TcpListener::bind(addr).context("bind websocket listener")?;
+        tracing::info!(
+            "streaming display over websocket on {}",
+            listener
+                .local_addr()
+                .context("websocket listener address")?
+        );
+
+        let clients = Arc::new(Mutex::new(Vec::new()));
+        let full_frame = Arc::new(Mutex::new(encode_full(&DisplayState::default())));
+        let (key_sender, key_events) = mpsc::channel();
+
+        let accept_clients = Arc::clone(&clients);
+        let accept_full_frame = Arc::clone(&full_frame);
+
+        std::thread::Builder::new()
+            .name(String::from("websocket"))
+            .spawn(move || {
+                for stream in listener.incoming() {
+                    match stream {
+                        Ok(stream) => {
+                            let (frame_sender, frames) = mpsc::channel();
+
+                            let Ok(mut clients) = accept_clients.lock() else {
+                                continue;
+                            };
+
+                            let initial = accept_full_frame
+                                .lock()
+                                .map(|f| f.clone())
+                                .unwrap_or_default();
+
+                            clients.push(frame_sender);
+                            drop(clients);
+
+                            let key_sender = key_sender.clone();
+                            std::thread::spawn(move || {
+                                if let Err(e) = serve_client(stream, initial, frames, key_sender) {
+                                    tracing::debug!("websocket client disconnected: {}", e);
+                                }
+                            });
+                        }
+                        Err(e) => tracing::error!("websocket accept error: {}", e),
+                    }
+                }
+            })
+            .context("spawn websocket thread")?;
+
+        Ok(Self {
+            clients,
+            full_frame,
+            key_events,
+            last: DisplayState::default(),
+        })
+    }
+    // sends the pixels that changed since the last publish to every connected client
+    pub fn publish(&mut self, display: &DisplayState) {
+        if *display == self.last {
+            return;
+        }
+
+        // clients have to resize when the resolution changes so they get a full frame instead
+        let message = if display.height() == self.last.height() {
+            let mut diff = vec![DIFF];
+            for idx in 0..display.num_pixels() {
+                if display.read_pixel(idx) != self.last.read_pixel(idx) {
+                    diff.extend_from_slice(&idx.to_be_bytes());
+                }
+            }
+            diff
+        } else {
+            encode_full(display)
+        };
+
+        self.last = display.clone();
+
+        // the client list stays locked while the full frame is replaced so a client that is
+        // connecting receives either the old frame and this diff or the new frame alone
+        if let Ok(mut clients) = self.clients.lock() {
+            if let Ok(mut full_frame) = self.full_frame.lock() {
+                *full_frame = encode_full(display);
+            }
+
+            clients.retain(|client| client.send(message.clone()).is_ok());
+        }
+    }
+    pub fn key_events(&self) -> Vec<KeyEvent> {
+        self.key_events.try_iter().collect()
+    }
+}
+
+fn encode_full(display: &DisplayState) -> Vec<u8> {
+    let mut bytes = vec![FULL_FRAME, display.width(), display.height()];
+    bytes.resize(3 + display.num_pixels() as usize / 8, 0);
+
+    for idx in 0..display.num_pixels() {
+        if display.read_pixel(idx) {
+            bytes[3 + idx as usize / 8] |= 0x80 >> (idx % 8);
+        }
+    }
+
+    bytes
+}
+
+fn serve_client(
+    stream: TcpStream,
+    initial: Vec<u8>,
+    frames: Receiver<Vec<u8>>,
+    key_events: Sender<KeyEvent>,
+) -> Result<()> {
+    let peer = stream.peer_addr()?;
+
+    let mut socket = tungstenite::accept(stream)
+        .map_err(|e| EmuError::Network(format!("websocket handshake: {}", e)))?;
+    tracing::info!("websocket client connected from {}", peer);
+
+    // a short read timeout lets a single thread interleave reading keys and writing frames
+    socket
+        .get_mut()
+        .set_read_timeout(Some(Duration::from_millis(5)))?;
+
+    socket.send(Message::Binary(initial))?;
+
+    loop {
+        for frame in frames.try_iter() {
+            socket.send(Message::Binary(frame))?;
+        }
+
+        match socket.read() {
+            Ok(Message::Text(text)) => match parse_key_event(&text) {
+                Some(event) => {
+                    if key_events.send(event).is_err() {
+                        return Ok(());
+                    }
+                }
+                None => tracing::warn!("invalid websocket key message: {}", text),
+            },
+            Ok(Message::Close(_)) => return Ok(()),
+            Ok(_) => {}
+            Err(Error::Io(e))
+                if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+fn parse_key_event(text: &str) -> Option<KeyEvent> {
+    let (action, key) = text.trim().split_once(' ')?;
+
+    let pressed = match action {
+        "down" => true,
+        "up" => false,
+        _ => return None,
+    };
+
+    let key = u8::from_str_radix(key.trim(), 16)
+        .ok()
+        .filter(|k| *k < 16)?;
+
+    Some(KeyEvent { key, pressed })
+}